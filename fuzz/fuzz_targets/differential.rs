@@ -0,0 +1,103 @@
+//! Serializes arbitrary values with rust-fr (in an arbitrary config) and with ciborium as the
+//! reference and checks that both decode back to the value. Run it with
+//! `cargo fuzz run differential` from the root of the repository.
+#![no_main]
+
+use std::collections::BTreeMap;
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use rust_fr::{
+    config::{Config, StringFraming},
+    deserializer,
+    protocol::format::Delimiter,
+    serializer,
+};
+use serde::{Deserialize, Serialize};
+
+// the same values as the `differential` test in src/lib.rs.
+#[derive(Debug, Clone, PartialEq, Arbitrary, Serialize, Deserialize)]
+enum Value {
+    Unit,
+    Bool(bool),
+    I8(i8),
+    I64(i64),
+    U16(u16),
+    U64(u64),
+    I128(i128),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    Str(String),
+    Bytes(Vec<u8>),
+    Option(Option<Box<Value>>),
+    Seq(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+    IntMap(BTreeMap<u32, Value>),
+    Tuple(u8, String),
+    Struct {
+        a: Option<u16>,
+        b: String,
+        c: Vec<bool>,
+    },
+}
+
+impl Value {
+    // Whether the value has a string with the byte of the String delimiter or bytes with the
+    // byte of the Byte delimiter, which end them early.
+    fn collides(&self) -> bool {
+        let str = |s: &String| s.bytes().any(|b| b == Delimiter::String as u8);
+        match self {
+            Value::Str(s) | Value::Tuple(_, s) | Value::Struct { b: s, .. } => str(s),
+            Value::Bytes(bytes) => bytes.contains(&(Delimiter::Byte as u8)),
+            Value::Option(value) => value.as_ref().is_some_and(|v| v.collides()),
+            Value::Seq(values) => values.iter().any(Value::collides),
+            Value::Map(map) => map.iter().any(|(k, v)| str(k) || v.collides()),
+            Value::IntMap(map) => map.values().any(Value::collides),
+            _ => false,
+        }
+    }
+
+    // How deeply the value is nested.
+    fn depth(&self) -> usize {
+        let children = match self {
+            Value::Option(Some(value)) => value.depth(),
+            Value::Seq(values) => values.iter().map(Value::depth).max().unwrap_or(0),
+            Value::Map(map) => map.values().map(Value::depth).max().unwrap_or(0),
+            Value::IntMap(map) => map.values().map(Value::depth).max().unwrap_or(0),
+            _ => 0,
+        };
+        children + 1
+    }
+}
+
+fuzz_target!(|input: (u16, Value)| {
+    let (header, value) = input;
+    let Ok(config) = Config::from_header(header) else {
+        return;
+    };
+    // ciborium limits how deeply values are nested.
+    if value.depth() > 64 {
+        return;
+    }
+    // NaN isn't equal to itself; such values are only checked to decode.
+    let comparable = value == value;
+
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&value, &mut bytes).unwrap();
+    let reference: Value = ciborium::from_reader(bytes.as_slice()).unwrap();
+    assert!(!comparable || reference == value);
+
+    let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+    let decoded = deserializer::from_bytes_with_config::<Value>(&bytes, &config);
+    // delimited strings and bytes aren't escaped; this is the one known class of values that
+    // is mangled, so it's left out to find the others.
+    if config.string_framing == StringFraming::Delimited && value.collides() {
+        return;
+    }
+    let decoded = decoded.unwrap_or_else(|e| panic!("{value:?} with {config:?}: {e}"));
+    assert!(
+        !comparable || decoded == value,
+        "{value:?} with {config:?} decoded as {decoded:?}"
+    );
+});
@@ -0,0 +1,191 @@
+//! ### Analyze
+//! A module for finding out whether the values of a type can collide with the delimiters of
+//! the format before adopting the delimited framing of strings (see
+//! [`Config::string_framing`]), as data of format versions before 7 does. It writes strings
+//! and bytes up to a delimiter byte (0x86 after strings and 0x87 after bytes) which they don't
+//! escape, so a string whose UTF-8 has the byte 0x86 (e.g. `"Ć"`) or bytes with the byte 0x87
+//! end early and fail to decode (or, worse, decode to another value). Every other value has a
+//! fixed width or a length and is read by it, so e.g. a `u8` of 134 next to a delimiter is
+//! never taken for one. With the default framing, which prefixes strings with their length,
+//! or escaped strings every string and bytes decodes back.
+//!
+//! - [`analyze`] lists the places in a type (from its [`Schema`]) that can hold such a string
+//!   or bytes, and the names of fields and variants that have a delimiter byte themselves
+//!   (they are strings in the data with the field names or in the self-describing mode).
+//! - [`collisions`] serializes sample values and returns the ones that don't decode back.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{
+//!     analyze,
+//!     config::{Config, StringFraming},
+//! };
+//!
+//! #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+//! struct Human {
+//!     name: String,
+//!     age: u8,
+//! }
+//!
+//! let report = analyze::analyze::<Vec<Human>>().unwrap();
+//! assert_eq!(report.to_string(), "$[].name: a string that may contain the byte 0x86\n");
+//!
+//! let samples = [
+//!     Human { name: "Ayush".to_string(), age: 134 },
+//!     Human { name: "Ćwiek".to_string(), age: 19 },
+//! ];
+//! let delimited = Config {
+//!     string_framing: StringFraming::Delimited,
+//!     ..Config::default()
+//! };
+//! assert_eq!(analyze::collisions(&samples, &delimited).unwrap(), [1]);
+//! assert!(analyze::collisions(&samples, &Config::default()).unwrap().is_empty());
+//! ```
+
+use std::fmt;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    config::Config,
+    deserializer,
+    error::Error,
+    protocol::format::Delimiter,
+    schema::{Schema, Variant},
+    serializer,
+};
+
+/// The places in a type that can collide with a delimiter; see [`analyze`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub risks: Vec<Risk>,
+}
+
+impl Report {
+    /// Whether no value of the type can collide with a delimiter.
+    pub fn is_safe(&self) -> bool {
+        self.risks.is_empty()
+    }
+
+    fn push(&mut self, path: String, kind: RiskKind) {
+        self.risks.push(Risk { path, kind });
+    }
+
+    /// The path of the field or variant `name` in `path`, noting the name if it has the byte
+    /// of the string delimiter.
+    fn named(&mut self, name: &'static str, path: &str) -> String {
+        let path = format!("{path}.{name}");
+        if name.as_bytes().contains(&STRING) {
+            self.push(path.clone(), RiskKind::Name(name));
+        }
+        path
+    }
+}
+
+impl fmt::Display for Report {
+    /// A line per risk.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.risks
+            .iter()
+            .try_for_each(|risk| writeln!(f, "{}: {}", risk.path, risk.kind))
+    }
+}
+
+/// A place in a type that can collide with a delimiter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Risk {
+    /// Where the value is, e.g. `$.users[].name`: `.name` is a field (or a variant), `[]` the
+    /// elements of a sequence, `[i]` an element of a tuple and `{key}` and `{value}` the keys
+    /// and values of a map.
+    pub path: String,
+    pub kind: RiskKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RiskKind {
+    /// A string, which may contain the byte of [`Delimiter::String`]; ASCII strings never do.
+    Str,
+    /// Bytes, which may contain the byte of [`Delimiter::Byte`].
+    Bytes,
+    /// The name of a field or variant with the byte of [`Delimiter::String`] in its UTF-8.
+    Name(&'static str),
+}
+
+impl fmt::Display for RiskKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RiskKind::Str => write!(f, "a string that may contain the byte {STRING:#x}"),
+            RiskKind::Bytes => write!(f, "bytes that may contain the byte {BYTE:#x}"),
+            RiskKind::Name(name) => write!(f, "the name {name:?} contains the byte {STRING:#x}"),
+        }
+    }
+}
+
+// The bytes of the delimiters that end strings and bytes.
+const STRING: u8 = Delimiter::String as u8;
+const BYTE: u8 = Delimiter::Byte as u8;
+
+/// The places in `T` that can collide with a delimiter; see the [module](self) docs. Types
+/// without a [`Schema`] (e.g. recursive types) fail with the error of [`Schema::of`].
+pub fn analyze<T: DeserializeOwned>() -> Result<Report, Error> {
+    let mut report = Report::default();
+    walk(&Schema::of::<T>()?, "$".to_string(), &mut report);
+    Ok(report)
+}
+
+fn walk(schema: &Schema, path: String, report: &mut Report) {
+    match schema {
+        Schema::Str => report.push(path, RiskKind::Str),
+        Schema::Bytes => report.push(path, RiskKind::Bytes),
+        Schema::Option(inner) => walk(inner, path, report),
+        Schema::Seq(element) => walk(element, format!("{path}[]"), report),
+        Schema::Tuple(elements) => tuple(elements, &path, report),
+        Schema::Map(key, value) => {
+            walk(key, format!("{path}{{key}}"), report);
+            walk(value, format!("{path}{{value}}"), report);
+        }
+        Schema::Struct { fields, .. } => self::fields(fields, &path, report),
+        Schema::Enum { variants, .. } => {
+            for (name, variant) in variants {
+                let path = report.named(name, &path);
+                match variant {
+                    Variant::Unit => {}
+                    Variant::Newtype(inner) => walk(inner, path, report),
+                    Variant::Tuple(elements) => tuple(elements, &path, report),
+                    Variant::Struct(fields) => self::fields(fields, &path, report),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn tuple(elements: &[Schema], path: &str, report: &mut Report) {
+    for (i, element) in elements.iter().enumerate() {
+        walk(element, format!("{path}[{i}]"), report);
+    }
+}
+
+fn fields(fields: &[(&'static str, Schema)], path: &str, report: &mut Report) {
+    for (name, schema) in fields {
+        let path = report.named(name, path);
+        walk(schema, path, report);
+    }
+}
+
+/// The indices of the `samples` whose data (serialized with `config`) doesn't decode back to
+/// them, e.g. because of a string with the byte 0x86. Errors while serializing are returned.
+pub fn collisions<T>(samples: &[T], config: &Config) -> Result<Vec<usize>, Error>
+where
+    T: Serialize + DeserializeOwned + PartialEq,
+{
+    let mut collisions = Vec::new();
+    for (i, sample) in samples.iter().enumerate() {
+        let bytes = serializer::to_bytes_with_config(sample, config)?;
+        match deserializer::from_bytes_with_config::<T>(&bytes, config) {
+            Ok(decoded) if decoded == *sample => {}
+            _ => collisions.push(i),
+        }
+    }
+    Ok(collisions)
+}
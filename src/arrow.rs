@@ -0,0 +1,546 @@
+//! ### Arrow
+//! A module for converting between the columnar sequences of rust-fr (see
+//! [`columnar`]) and the `RecordBatch`es of Apache Arrow, so rust-fr can carry
+//! the batches of an Arrow-based analytics pipeline (e.g. one that ends in Parquet files)
+//! between services. Only available with the `arrow` feature.
+//!
+//! [`to_bytes`] (or [`serialize`] inside a larger value) writes the rows of a batch column by
+//! column, each row being a map from the names of the columns to the values of the row. The
+//! data is the same as that of a `Vec` of structs with fields of those names and types, so a
+//! peer without Arrow reads it with `#[serde(with = "rust_fr::columnar")]`. [`from_bytes`] (or
+//! [`BatchSeed`]) reads it back into a batch of a given schema.
+//!
+//! - Boolean: bool; Int8 to Int64: i8 to i64; UInt8 to UInt64: u8 to u64; Float32, Float64:
+//!   f32, f64
+//! - Utf8, LargeUtf8: a string; Binary, LargeBinary: bytes
+//! - a nullable column: an `Option` of the value
+//!
+//! Columns of other types are rejected.
+//!
+//! ### Example
+//! ```rust
+//! use std::sync::Arc;
+//!
+//! use arrow_array::{Float64Array, RecordBatch, StringArray, UInt32Array};
+//! use arrow_schema::{DataType, Field, Schema};
+//! use rust_fr::{arrow, config::Config, deserializer};
+//!
+//! let schema = Arc::new(Schema::new(vec![
+//!     Field::new("id", DataType::UInt32, false),
+//!     Field::new("venue", DataType::Utf8, false),
+//!     Field::new("price", DataType::Float64, true),
+//! ]));
+//! let batch = RecordBatch::try_new(
+//!     schema.clone(),
+//!     vec![
+//!         Arc::new(UInt32Array::from(vec![1, 2])),
+//!         Arc::new(StringArray::from(vec!["XLON", "XNYS"])),
+//!         Arc::new(Float64Array::from(vec![Some(101.5), None])),
+//!     ],
+//! )
+//! .unwrap();
+//!
+//! let bytes = arrow::to_bytes(&batch, &Config::default()).unwrap();
+//! assert_eq!(arrow::from_bytes(&bytes, schema).unwrap(), batch);
+//!
+//! // the same data as a sequence of structs written with `columnar`.
+//! #[derive(Debug, serde::Deserialize, PartialEq)]
+//! struct Trade {
+//!     id: u32,
+//!     venue: String,
+//!     price: Option<f64>,
+//! }
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Trades(#[serde(with = "rust_fr::columnar")] Vec<Trade>);
+//!
+//! let trades = deserializer::from_bytes::<Trades>(&bytes).unwrap().0;
+//! assert_eq!(trades[1], Trade { id: 2, venue: "XNYS".to_string(), price: None });
+//! ```
+
+use std::fmt;
+
+use arrow_array::{
+    builder::{
+        ArrayBuilder, BinaryBuilder, BooleanBuilder, Float32Builder, Float64Builder, Int16Builder,
+        Int32Builder, Int64Builder, Int8Builder, LargeBinaryBuilder, LargeStringBuilder,
+        StringBuilder, UInt16Builder, UInt32Builder, UInt64Builder, UInt8Builder,
+    },
+    cast::AsArray,
+    types::{
+        Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, UInt16Type,
+        UInt32Type, UInt64Type, UInt8Type,
+    },
+    Array, ArrayRef, OffsetSizeTrait, RecordBatch, RecordBatchOptions,
+};
+use arrow_schema::{DataType, Field, SchemaRef};
+use serde::{
+    de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    ser::{self, SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::{columnar, config::Config, deserializer, error::Error, serializer};
+
+/// Serialize the rows of `batch` column by column, as [`columnar`] does with
+/// a sequence of structs; e.g. with `#[serde(serialize_with = "rust_fr::arrow::serialize")]`.
+pub fn serialize<S: Serializer>(batch: &RecordBatch, serializer: S) -> Result<S::Ok, S::Error> {
+    if let Some(field) = batch
+        .schema_ref()
+        .fields()
+        .iter()
+        .find(|field| !supported(field.data_type()))
+    {
+        return Err(ser::Error::custom(unsupported(field)));
+    }
+    serializer.serialize_newtype_struct(columnar::NAME, &Rows(batch))
+}
+
+/// Serialize the rows of `batch` column by column with the given config; see the
+/// [module](self) docs.
+pub fn to_bytes(batch: &RecordBatch, config: &Config) -> Result<Vec<u8>, Error> {
+    struct Batch<'a>(&'a RecordBatch);
+
+    impl Serialize for Batch<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serialize(self.0, serializer)
+        }
+    }
+
+    serializer::to_bytes_with_config(&Batch(batch), config)
+}
+
+/// Deserialize the rows of a columnar sequence into a batch of `schema`; the data is decoded
+/// according to the config in its header. Every row must have a value for every column of the
+/// schema.
+pub fn from_bytes(bytes: &[u8], schema: SchemaRef) -> Result<RecordBatch, Error> {
+    deserializer::from_bytes_seed(bytes, BatchSeed::new(schema))
+}
+
+/// Deserializes the rows of a columnar sequence (e.g. a field of a larger value) into a batch
+/// of a given schema; see [`from_bytes`].
+#[derive(Debug, Clone)]
+pub struct BatchSeed {
+    schema: SchemaRef,
+}
+
+impl BatchSeed {
+    /// Deserialize into a batch of `schema`.
+    pub fn new(schema: SchemaRef) -> Self {
+        Self { schema }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for BatchSeed {
+    type Value = RecordBatch;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<RecordBatch, D::Error> {
+        let columns = self
+            .schema
+            .fields()
+            .iter()
+            .map(|field| Column::new(field).ok_or_else(|| de::Error::custom(unsupported(field))))
+            .collect::<Result<Vec<_>, D::Error>>()?;
+        let mut table = Table {
+            schema: &self.schema,
+            columns,
+            rows: 0,
+        };
+        deserializer.deserialize_newtype_struct(columnar::NAME, &mut table)?;
+        let arrays = table.columns.iter_mut().map(Column::finish).collect();
+        let options = RecordBatchOptions::new().with_row_count(Some(table.rows));
+        RecordBatch::try_new_with_options(self.schema.clone(), arrays, &options)
+            .map_err(de::Error::custom)
+    }
+}
+
+fn supported(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Boolean
+            | DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::Utf8
+            | DataType::LargeUtf8
+            | DataType::Binary
+            | DataType::LargeBinary
+    )
+}
+
+fn unsupported(field: &Field) -> String {
+    format!(
+        "the column {} has the Arrow type {}, which rust-fr doesn't convert",
+        field.name(),
+        field.data_type()
+    )
+}
+
+// The rows of a batch as a sequence of maps.
+struct Rows<'a>(&'a RecordBatch);
+
+impl Serialize for Rows<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.num_rows()))?;
+        for row in 0..self.0.num_rows() {
+            seq.serialize_element(&Row(self.0, row))?;
+        }
+        seq.end()
+    }
+}
+
+// A row of a batch: the names of the columns to the values of the row.
+struct Row<'a>(&'a RecordBatch, usize);
+
+impl Serialize for Row<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Row(batch, row) = *self;
+        let mut map = serializer.serialize_map(Some(batch.num_columns()))?;
+        for (field, array) in batch.schema_ref().fields().iter().zip(batch.columns()) {
+            let cell = Cell(array.as_ref(), row);
+            match field.is_nullable() {
+                true => map.serialize_entry(field.name(), &array.is_valid(row).then_some(cell))?,
+                false => map.serialize_entry(field.name(), &cell)?,
+            }
+        }
+        map.end()
+    }
+}
+
+// The value of a row in a column that isn't null.
+struct Cell<'a>(&'a dyn Array, usize);
+
+impl Serialize for Cell<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Cell(array, row) = *self;
+        match array.data_type() {
+            DataType::Boolean => serializer.serialize_bool(array.as_boolean().value(row)),
+            DataType::Int8 => serializer.serialize_i8(array.as_primitive::<Int8Type>().value(row)),
+            DataType::Int16 => {
+                serializer.serialize_i16(array.as_primitive::<Int16Type>().value(row))
+            }
+            DataType::Int32 => {
+                serializer.serialize_i32(array.as_primitive::<Int32Type>().value(row))
+            }
+            DataType::Int64 => {
+                serializer.serialize_i64(array.as_primitive::<Int64Type>().value(row))
+            }
+            DataType::UInt8 => {
+                serializer.serialize_u8(array.as_primitive::<UInt8Type>().value(row))
+            }
+            DataType::UInt16 => {
+                serializer.serialize_u16(array.as_primitive::<UInt16Type>().value(row))
+            }
+            DataType::UInt32 => {
+                serializer.serialize_u32(array.as_primitive::<UInt32Type>().value(row))
+            }
+            DataType::UInt64 => {
+                serializer.serialize_u64(array.as_primitive::<UInt64Type>().value(row))
+            }
+            DataType::Float32 => {
+                serializer.serialize_f32(array.as_primitive::<Float32Type>().value(row))
+            }
+            DataType::Float64 => {
+                serializer.serialize_f64(array.as_primitive::<Float64Type>().value(row))
+            }
+            DataType::Utf8 => serializer.serialize_str(array.as_string::<i32>().value(row)),
+            DataType::LargeUtf8 => serializer.serialize_str(array.as_string::<i64>().value(row)),
+            DataType::Binary => serializer.serialize_bytes(array.as_binary::<i32>().value(row)),
+            DataType::LargeBinary => {
+                serializer.serialize_bytes(array.as_binary::<i64>().value(row))
+            }
+            data_type => Err(ser::Error::custom(format!(
+                "the Arrow type {data_type} isn't supported"
+            ))),
+        }
+    }
+}
+
+// A builder of the array of a column along with its field and the number of values in it.
+struct Column<'s> {
+    field: &'s Field,
+    builder: Builder,
+    len: usize,
+}
+
+enum Builder {
+    Bool(BooleanBuilder),
+    I8(Int8Builder),
+    I16(Int16Builder),
+    I32(Int32Builder),
+    I64(Int64Builder),
+    U8(UInt8Builder),
+    U16(UInt16Builder),
+    U32(UInt32Builder),
+    U64(UInt64Builder),
+    F32(Float32Builder),
+    F64(Float64Builder),
+    Str(StringBuilder),
+    LargeStr(LargeStringBuilder),
+    Bytes(BinaryBuilder),
+    LargeBytes(LargeBinaryBuilder),
+}
+
+impl<'s> Column<'s> {
+    // The column of `field`, if its type is supported.
+    fn new(field: &'s Field) -> Option<Self> {
+        let builder = match field.data_type() {
+            DataType::Boolean => Builder::Bool(BooleanBuilder::new()),
+            DataType::Int8 => Builder::I8(Int8Builder::new()),
+            DataType::Int16 => Builder::I16(Int16Builder::new()),
+            DataType::Int32 => Builder::I32(Int32Builder::new()),
+            DataType::Int64 => Builder::I64(Int64Builder::new()),
+            DataType::UInt8 => Builder::U8(UInt8Builder::new()),
+            DataType::UInt16 => Builder::U16(UInt16Builder::new()),
+            DataType::UInt32 => Builder::U32(UInt32Builder::new()),
+            DataType::UInt64 => Builder::U64(UInt64Builder::new()),
+            DataType::Float32 => Builder::F32(Float32Builder::new()),
+            DataType::Float64 => Builder::F64(Float64Builder::new()),
+            DataType::Utf8 => Builder::Str(StringBuilder::new()),
+            DataType::LargeUtf8 => Builder::LargeStr(LargeStringBuilder::new()),
+            DataType::Binary => Builder::Bytes(BinaryBuilder::new()),
+            DataType::LargeBinary => Builder::LargeBytes(LargeBinaryBuilder::new()),
+            _ => return None,
+        };
+        Some(Self {
+            field,
+            builder,
+            len: 0,
+        })
+    }
+
+    fn builder(&mut self) -> &mut dyn ArrayBuilder {
+        match &mut self.builder {
+            Builder::Bool(builder) => builder,
+            Builder::I8(builder) => builder,
+            Builder::I16(builder) => builder,
+            Builder::I32(builder) => builder,
+            Builder::I64(builder) => builder,
+            Builder::U8(builder) => builder,
+            Builder::U16(builder) => builder,
+            Builder::U32(builder) => builder,
+            Builder::U64(builder) => builder,
+            Builder::F32(builder) => builder,
+            Builder::F64(builder) => builder,
+            Builder::Str(builder) => builder,
+            Builder::LargeStr(builder) => builder,
+            Builder::Bytes(builder) => builder,
+            Builder::LargeBytes(builder) => builder,
+        }
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        self.builder().finish()
+    }
+
+    fn append_null(&mut self) {
+        self.len += 1;
+        match &mut self.builder {
+            Builder::Bool(builder) => builder.append_null(),
+            Builder::I8(builder) => builder.append_null(),
+            Builder::I16(builder) => builder.append_null(),
+            Builder::I32(builder) => builder.append_null(),
+            Builder::I64(builder) => builder.append_null(),
+            Builder::U8(builder) => builder.append_null(),
+            Builder::U16(builder) => builder.append_null(),
+            Builder::U32(builder) => builder.append_null(),
+            Builder::U64(builder) => builder.append_null(),
+            Builder::F32(builder) => builder.append_null(),
+            Builder::F64(builder) => builder.append_null(),
+            Builder::Str(builder) => builder.append_null(),
+            Builder::LargeStr(builder) => builder.append_null(),
+            Builder::Bytes(builder) => builder.append_null(),
+            Builder::LargeBytes(builder) => builder.append_null(),
+        }
+    }
+
+    // Deserialize a value that isn't null into the column.
+    fn append<'de, D: Deserializer<'de>>(&mut self, deserializer: D) -> Result<(), D::Error> {
+        self.len += 1;
+        match &mut self.builder {
+            Builder::Bool(builder) => builder.append_value(bool::deserialize(deserializer)?),
+            Builder::I8(builder) => builder.append_value(i8::deserialize(deserializer)?),
+            Builder::I16(builder) => builder.append_value(i16::deserialize(deserializer)?),
+            Builder::I32(builder) => builder.append_value(i32::deserialize(deserializer)?),
+            Builder::I64(builder) => builder.append_value(i64::deserialize(deserializer)?),
+            Builder::U8(builder) => builder.append_value(u8::deserialize(deserializer)?),
+            Builder::U16(builder) => builder.append_value(u16::deserialize(deserializer)?),
+            Builder::U32(builder) => builder.append_value(u32::deserialize(deserializer)?),
+            Builder::U64(builder) => builder.append_value(u64::deserialize(deserializer)?),
+            Builder::F32(builder) => builder.append_value(f32::deserialize(deserializer)?),
+            Builder::F64(builder) => builder.append_value(f64::deserialize(deserializer)?),
+            Builder::Str(builder) => deserializer.deserialize_str(AppendStr(builder))?,
+            Builder::LargeStr(builder) => deserializer.deserialize_str(AppendStr(builder))?,
+            Builder::Bytes(builder) => deserializer.deserialize_bytes(AppendBytes(builder))?,
+            Builder::LargeBytes(builder) => deserializer.deserialize_bytes(AppendBytes(builder))?,
+        }
+        Ok(())
+    }
+}
+
+// Appends a string to a column without an allocation of its own.
+struct AppendStr<'b, O: OffsetSizeTrait>(&'b mut arrow_array::builder::GenericStringBuilder<O>);
+
+impl<'de, O: OffsetSizeTrait> Visitor<'de> for AppendStr<'_, O> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<(), E> {
+        self.0.append_value(value);
+        Ok(())
+    }
+}
+
+// Appends bytes to a column without an allocation of their own.
+struct AppendBytes<'b, O: OffsetSizeTrait>(&'b mut arrow_array::builder::GenericBinaryBuilder<O>);
+
+impl<'de, O: OffsetSizeTrait> Visitor<'de> for AppendBytes<'_, O> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("bytes")
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<(), E> {
+        self.0.append_value(value);
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+        self.0.append_value(bytes);
+        Ok(())
+    }
+}
+
+// The columns being built and the number of rows read so far.
+struct Table<'s> {
+    schema: &'s SchemaRef,
+    columns: Vec<Column<'s>>,
+    rows: usize,
+}
+
+impl<'de> Visitor<'de> for &mut Table<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of rows")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq.next_element_seed(RowSeed(&mut *self))?.is_some() {
+            self.rows += 1;
+            if let Some(column) = self.columns.iter().find(|c| c.len != self.rows) {
+                return Err(de::Error::custom(format!(
+                    "the row {} doesn't have exactly one value for the column {}",
+                    self.rows - 1,
+                    column.field.name()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Deserializes a row into the columns.
+struct RowSeed<'t, 's>(&'t mut Table<'s>);
+
+impl<'de> DeserializeSeed<'de> for RowSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RowSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a row")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(index) = map.next_key_seed(ColumnIndex(self.0.schema))? {
+            map.next_value_seed(CellSeed(&mut self.0.columns[index]))?;
+        }
+        Ok(())
+    }
+}
+
+// The index of the column whose name is a key of a row.
+struct ColumnIndex<'s>(&'s SchemaRef);
+
+impl<'de> DeserializeSeed<'de> for ColumnIndex<'_> {
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ColumnIndex<'_> {
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("the name of a column")
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> Result<usize, E> {
+        self.0
+            .index_of(name)
+            .map_err(|_| E::custom(format!("the schema has no column {name}")))
+    }
+}
+
+// Deserializes a value of a row into its column: an option if the column is nullable.
+struct CellSeed<'c, 's>(&'c mut Column<'s>);
+
+impl<'de> DeserializeSeed<'de> for CellSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        match self.0.field.is_nullable() {
+            true => deserializer.deserialize_option(self),
+            false => self.0.append(deserializer),
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for CellSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a value of the column {}", self.0.field.name())
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<(), E> {
+        self.0.append_null();
+        Ok(())
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        self.visit_none()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        self.0.append(deserializer)
+    }
+}
@@ -0,0 +1,6 @@
+//! Prints the format specification generated by [`rust_fr::spec::generate`].
+//! The output is what the "format specification" section of the README contains.
+
+fn main() {
+    print!("{}", rust_fr::spec::generate());
+}
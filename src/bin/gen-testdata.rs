@@ -0,0 +1,14 @@
+//! Writes the golden test vectors of [`rust_fr::conformance::vectors`] to `testdata/`: the
+//! payload of every vector as `<name>.bin` and its description as `<name>.json`.
+
+use std::{fs, path::Path};
+
+fn main() -> std::io::Result<()> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+    fs::create_dir_all(&dir)?;
+    for vector in rust_fr::conformance::vectors() {
+        fs::write(dir.join(format!("{}.bin", vector.name)), &vector.bytes)?;
+        fs::write(dir.join(format!("{}.json", vector.name)), vector.to_json())?;
+    }
+    Ok(())
+}
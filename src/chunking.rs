@@ -0,0 +1,225 @@
+//! ### Chunking
+//! A module for splitting serialized data into chunks on content-defined boundaries, so big
+//! payloads (e.g. snapshots) can be stored deduplicated: a small change to the data only
+//! changes the chunks around it, while the other chunks (and their hashes) stay the same. It is
+//! only available with the `digest` feature.
+//!
+//! A boundary is placed where a rolling hash of the bytes before it (a gear hash, as in
+//! FastCDC) matches a mask, so boundaries move along with the data when bytes are inserted or
+//! removed. Every chunk is hashed with a [`Digest`] of the RustCrypto `digest` crate (e.g.
+//! `sha2::Sha256`) and the [`Manifest`] lists the hashes of the chunks in order, which is all
+//! that's needed to put the data back together from a store of chunks. The manifest can itself
+//! be serialized with rust-fr.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{chunking::Chunker, serializer};
+//! use sha2::Sha256;
+//! use std::collections::HashMap;
+//!
+//! let snapshot: Vec<String> = (0..20_000).map(|i| format!("entry {i}")).collect();
+//! let bytes = serializer::to_bytes(&snapshot).unwrap();
+//!
+//! let chunker = Chunker::default();
+//! let (manifest, chunks) = chunker.split::<Sha256>(&bytes);
+//! let mut store = HashMap::new();
+//! for chunk in chunks {
+//!     store.insert(chunk.hash, chunk.data.to_vec());
+//! }
+//!
+//! let restored = manifest
+//!     .reassemble::<Sha256, _>(|hash| store.get(hash).map(Vec::as_slice))
+//!     .unwrap();
+//! assert_eq!(restored, bytes);
+//! ```
+
+use digest::{Digest, Output};
+use serde::{Deserialize, Serialize};
+
+use super::error::Error;
+
+// The gear table: a random u64 for every byte, from splitmix64 so it's the same everywhere.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state = 0x5275_7374_2d66_7221u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Finds the content-defined boundaries of chunks between a minimum and a maximum size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunker {
+    min: usize,
+    avg: usize,
+    max: usize,
+    // The masks below and above the average size; the one below has more bits so chunks are
+    // less likely to be cut early (the normalized chunking of FastCDC).
+    mask_small: u64,
+    mask_large: u64,
+}
+
+impl Chunker {
+    /// Create a chunker for chunks of `min` to `max` bytes and `avg` bytes on average.
+    ///
+    /// # Panics
+    /// If `avg` is not a power of two of at least 4 bytes or `min <= avg <= max` doesn't hold.
+    pub fn new(min: usize, avg: usize, max: usize) -> Self {
+        assert!(
+            avg.is_power_of_two() && avg >= 4,
+            "the average size of chunks must be a power of two of at least 4 bytes"
+        );
+        assert!(
+            min <= avg && avg <= max,
+            "the sizes of chunks must be min <= avg <= max"
+        );
+        let bits = avg.trailing_zeros();
+        Self {
+            min,
+            avg,
+            max,
+            mask_small: !0 << (64 - (bits + 1)),
+            mask_large: !0 << (64 - (bits - 1)),
+        }
+    }
+
+    /// Split `data` into chunks.
+    pub fn chunks<'a>(&self, data: &'a [u8]) -> Chunks<'a> {
+        Chunks {
+            chunker: *self,
+            data,
+        }
+    }
+
+    /// Split `data` into chunks and hash each of them with `D`. It returns the manifest along
+    /// with the chunks; a chunk that occurs more than once is returned every time.
+    pub fn split<'a, D: Digest>(
+        &self,
+        data: &'a [u8],
+    ) -> (Manifest<Output<D>>, Vec<Chunk<'a, Output<D>>>) {
+        let chunks: Vec<_> = self
+            .chunks(data)
+            .map(|data| Chunk {
+                hash: hash::<D>(data),
+                data,
+            })
+            .collect();
+        let manifest = Manifest {
+            len: data.len() as u64,
+            chunks: chunks
+                .iter()
+                .map(|chunk| ChunkRef {
+                    hash: chunk.hash.clone(),
+                    len: chunk.data.len() as u64,
+                })
+                .collect(),
+        };
+        (manifest, chunks)
+    }
+
+    // The length of the chunk at the start of `data`.
+    fn cut(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min {
+            return data.len();
+        }
+        let end = data.len().min(self.max);
+        let normal = self.avg.min(end);
+        let mut hash = 0u64;
+        for (i, byte) in data.iter().enumerate().take(end).skip(self.min) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            let mask = match i < normal {
+                true => self.mask_small,
+                false => self.mask_large,
+            };
+            if hash & mask == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+}
+
+impl Default for Chunker {
+    /// Chunks of 2 KiB to 64 KiB, 8 KiB on average.
+    fn default() -> Self {
+        Self::new(2 * 1024, 8 * 1024, 64 * 1024)
+    }
+}
+
+/// An iterator over the chunks of some data; see [`Chunker::chunks`].
+#[derive(Debug, Clone)]
+pub struct Chunks<'a> {
+    chunker: Chunker,
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let (chunk, rest) = self.data.split_at(self.chunker.cut(self.data));
+        self.data = rest;
+        Some(chunk)
+    }
+}
+
+/// A chunk of data along with its hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk<'a, H> {
+    pub hash: H,
+    pub data: &'a [u8],
+}
+
+/// A chunk in a [`Manifest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRef<H> {
+    pub hash: H,
+    /// The length of the chunk in bytes.
+    pub len: u64,
+}
+
+/// The hashes of the chunks of some data in order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest<H> {
+    /// The length of the data in bytes.
+    pub len: u64,
+    pub chunks: Vec<ChunkRef<H>>,
+}
+
+impl<H: AsRef<[u8]>> Manifest<H> {
+    /// Put the data back together from its chunks, which `fetch` looks up by their hash. Every
+    /// chunk is hashed again with `D` and checked against the manifest; a chunk that can't be
+    /// found fails with [`Error::MissingChunk`] and one that doesn't match with
+    /// [`Error::CorruptChunk`]. The hashes can be of any type that holds their bytes, e.g. the
+    /// [`Output`] of `D` or a `Vec<u8>`.
+    pub fn reassemble<'s, D, F>(&self, mut fetch: F) -> Result<Vec<u8>, Error>
+    where
+        D: Digest,
+        F: FnMut(&H) -> Option<&'s [u8]>,
+    {
+        let mut data = Vec::new();
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            let bytes = fetch(&chunk.hash).ok_or(Error::MissingChunk(index))?;
+            if bytes.len() as u64 != chunk.len || hash::<D>(bytes).as_slice() != chunk.hash.as_ref()
+            {
+                return Err(Error::CorruptChunk(index));
+            }
+            data.extend_from_slice(bytes);
+        }
+        Ok(data)
+    }
+}
+
+fn hash<D: Digest>(data: &[u8]) -> Output<D> {
+    D::digest(data)
+}
@@ -0,0 +1,327 @@
+//! ### Codegen
+//! A module for generating decoders of the format in other languages from a [`Schema`], so
+//! clients (e.g. in a browser) can read payloads without shipping this crate as WASM.
+//!
+//! [`javascript`] emits an ES module whose `decode(bytes)` takes a `Uint8Array` serialized
+//! with the default [`Config`] and returns the value the way `serde_json` would show it:
+//! structs are objects, sequences and tuples are arrays, maps are `Map`s, unit variants are
+//! their names and other variants are objects with their name as the only key. Integers of 64
+//! bits and more are `BigInt`s, bytes are `Uint8Array`s and options and units are `null` when
+//! they're empty. Data with other options is rejected.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{codegen, schema::Schema};
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Human {
+//!     name: String,
+//!     age: u8,
+//! }
+//!
+//! let module = codegen::javascript(&Schema::of::<Human>().unwrap());
+//! assert!(module.contains("export function decode(bytes)"));
+//! ```
+
+use std::{collections::HashMap, fmt::Write};
+
+use super::{
+    config::Config,
+    protocol::format::{Delimiter, FORMAT_VERSION, VARIANT_INDEX_ESCAPE},
+    schema::{Schema, Variant},
+};
+
+/// The reader the generated decoders are built on: the bits of the data least significant
+/// first, as with the default config.
+const READER: &str = r#"class Reader {
+  constructor(bytes) {
+    this.bytes = bytes;
+    this.position = 0;
+  }
+
+  bit() {
+    const byte = this.bytes[this.position >> 3];
+    if (byte === undefined) throw new Error("unexpected end of the data");
+    return (byte >> (this.position++ & 7)) & 1;
+  }
+
+  // An unsigned integer of `width` bits: a number up to 32 bits, a BigInt above.
+  uint(width) {
+    if (width > 32) {
+      let value = 0n;
+      for (let i = 0n; i < BigInt(width); i++) value |= BigInt(this.bit()) << i;
+      return value;
+    }
+    let value = 0;
+    for (let i = 0; i < width; i++) value += this.bit() * 2 ** i;
+    return value;
+  }
+
+  int(width) {
+    const value = this.uint(width);
+    if (width > 32) return BigInt.asIntN(width, value);
+    return value >= 2 ** (width - 1) ? value - 2 ** width : value;
+  }
+
+  float(width) {
+    const view = new DataView(new ArrayBuffer(width / 8));
+    for (let i = 0; i < width / 8; i++) view.setUint8(i, this.uint(8));
+    return width === 32 ? view.getFloat32(0, true) : view.getFloat64(0, true);
+  }
+
+  peek(width) {
+    const position = this.position;
+    if (this.bytes.length * 8 - position < width) return undefined;
+    const value = this.uint(width);
+    this.position = position;
+    return value;
+  }
+
+  expect(token, width, what) {
+    if (this.uint(width) !== token) {
+      throw new Error(`expected ${what} at bit ${this.position - width}`);
+    }
+  }
+
+  // The bytes after their length (u32), starting at the next byte boundary.
+  prefixed() {
+    const length = this.uint(32);
+    this.position = (this.position + 7) & ~7;
+    const start = this.position >> 3;
+    if (start + length > this.bytes.length) throw new Error("unexpected end of the data");
+    this.position = (start + length) * 8;
+    return this.bytes.subarray(start, start + length);
+  }
+
+  str() {
+    return new TextDecoder("utf-8", { fatal: true }).decode(this.prefixed());
+  }
+
+  seq(element) {
+    this.expect(SEQ, 3, "a sequence");
+    const elements = [];
+    while (this.peek(3) !== SEQ) {
+      this.expect(SEQ_VALUE, 3, "an element");
+      elements.push(element(this));
+    }
+    this.position += 3;
+    return elements;
+  }
+
+  map(key, value) {
+    const entries = new Map();
+    while (this.peek(8) !== MAP) {
+      this.expect(MAP_KEY, 3, "a key");
+      const k = key(this);
+      this.expect(MAP_VALUE, 3, "a value");
+      entries.set(k, value(this));
+    }
+    this.position += 8;
+    return entries;
+  }
+
+  variant() {
+    const index = this.uint(8);
+    return index === VARIANT_INDEX_ESCAPE ? this.uint(32) : index;
+  }
+}
+"#;
+
+/// An ES module that decodes the values of `schema`; see the [module](self) docs.
+pub fn javascript(schema: &Schema) -> String {
+    let mut generator = Generator::default();
+    let root = generator.expression(schema);
+    let mut module = format!(
+        "// A decoder of rust-fr payloads (format version {FORMAT_VERSION}) serialized with the \
+         default config.\n// It was generated by `rust_fr::codegen::javascript`; don't edit it.\n\n"
+    );
+    let _ = writeln!(module, "const HEADER = {:#x};", Config::default().header());
+    for (name, delimiter) in [
+        ("UNIT", Delimiter::Unit),
+        ("SEQ", Delimiter::Seq),
+        ("SEQ_VALUE", Delimiter::SeqValue),
+        ("MAP", Delimiter::Map),
+        ("MAP_KEY", Delimiter::MapKey),
+        ("MAP_VALUE", Delimiter::MapValue),
+    ] {
+        let _ = writeln!(module, "const {name} = {:#x};", delimiter.token().value());
+    }
+    let _ = writeln!(
+        module,
+        "const VARIANT_INDEX_ESCAPE = {VARIANT_INDEX_ESCAPE:#x};\n"
+    );
+    module.push_str(READER);
+    for function in generator.functions {
+        module.push('\n');
+        module.push_str(&function);
+    }
+    let _ = write!(
+        module,
+        r#"
+/**
+ * Decode a payload.
+ * @param {{Uint8Array}} bytes
+ */
+export function decode(bytes) {{
+  const r = new Reader(bytes);
+  const header = r.uint(16);
+  if (header !== HEADER) throw new Error(`the header ${{header}} isn't the default config`);
+  return {root};
+}}
+"#
+    );
+    module
+}
+
+// Collects a function per struct and enum; a type name used with different schemas (e.g. a
+// generic struct) gets a function per schema.
+#[derive(Default)]
+struct Generator {
+    functions: Vec<String>,
+    names: HashMap<String, Vec<Schema>>,
+}
+
+impl Generator {
+    /// A JavaScript expression that reads a value of `schema` from the reader `r`.
+    fn expression(&mut self, schema: &Schema) -> String {
+        match schema {
+            Schema::Bool => "r.bit() === 1".to_string(),
+            Schema::I8 => "r.int(8)".to_string(),
+            Schema::I16 => "r.int(16)".to_string(),
+            Schema::I32 => "r.int(32)".to_string(),
+            Schema::I64 => "r.int(64)".to_string(),
+            Schema::I128 => "r.int(128)".to_string(),
+            Schema::U8 => "r.uint(8)".to_string(),
+            Schema::U16 => "r.uint(16)".to_string(),
+            Schema::U32 => "r.uint(32)".to_string(),
+            Schema::U64 => "r.uint(64)".to_string(),
+            Schema::U128 => "r.uint(128)".to_string(),
+            Schema::F32 => "r.float(32)".to_string(),
+            Schema::F64 => "r.float(64)".to_string(),
+            Schema::Char => "String.fromCodePoint(r.uint(32))".to_string(),
+            Schema::Str => "r.str()".to_string(),
+            Schema::Bytes => "r.prefixed().slice()".to_string(),
+            Schema::Unit => "(r.expect(UNIT, 3, \"a unit\"), null)".to_string(),
+            Schema::Option(inner) => format!("(r.bit() === 1 ? {} : null)", self.expression(inner)),
+            Schema::Seq(element) => format!("r.seq((r) => {})", self.expression(element)),
+            Schema::Tuple(elements) => self.tuple(elements),
+            Schema::Map(key, value) => format!(
+                "r.map((r) => {}, (r) => {})",
+                self.expression(key),
+                self.expression(value)
+            ),
+            Schema::Struct { name, fields } => {
+                let (function, new) = self.function(name, schema);
+                if new {
+                    let body = self.fields(fields, "value", "  ");
+                    self.functions.push(format!(
+                        "function {function}(r) {{
+  const value = {{}};
+{body}  return value;
+}}
+"
+                    ));
+                }
+                format!("{function}(r)")
+            }
+            Schema::Enum { name, variants } => {
+                let (function, new) = self.function(name, schema);
+                if new {
+                    let mut body = "  switch (r.variant()) {\n".to_string();
+                    for (index, (variant, content)) in variants.iter().enumerate() {
+                        let value = match content {
+                            Variant::Unit => format!("{variant:?}"),
+                            Variant::Newtype(inner) => {
+                                format!("{{ {variant:?}: {} }}", self.expression(inner))
+                            }
+                            Variant::Tuple(elements) => {
+                                format!("{{ {variant:?}: {} }}", self.tuple(elements))
+                            }
+                            Variant::Struct(fields) => {
+                                let fields = self.fields(fields, "content", "        ");
+                                format!(
+                                    "(() => {{
+        const content = {{}};
+{fields}        return {{ {variant:?}: content }};
+      }})()"
+                                )
+                            }
+                        };
+                        let _ = writeln!(body, "    case {index}:\n      return {value};");
+                    }
+                    body.push_str(
+                        "    default:\n      throw new Error(\"unknown variant\");\n  }\n",
+                    );
+                    self.functions
+                        .push(format!("function {function}(r) {{\n{body}}}\n"));
+                }
+                format!("{function}(r)")
+            }
+        }
+    }
+
+    /// An array of the elements of a tuple, read in order.
+    fn tuple(&mut self, elements: &[Schema]) -> String {
+        let elements = elements
+            .iter()
+            .map(|element| self.expression(element))
+            .collect::<Vec<_>>();
+        format!("[{}]", elements.join(", "))
+    }
+
+    /// Statements (indented by `indent`) that read the entries of a struct into the object
+    /// `target`: a map of the names of the fields to their values.
+    fn fields(&mut self, fields: &[(&'static str, Schema)], target: &str, indent: &str) -> String {
+        let mut cases = String::new();
+        for (field, schema) in fields {
+            let value = self.expression(schema);
+            let _ = writeln!(
+                cases,
+                "case {field:?}:\n  {target}[{field:?}] = {value};\n  break;"
+            );
+        }
+        let lines = format!(
+            "while (r.peek(8) !== MAP) {{
+  r.expect(MAP_KEY, 3, \"a field\");
+  const field = r.str();
+  r.expect(MAP_VALUE, 3, \"a value\");
+  switch (field) {{
+{}    default:
+      throw new Error(`unknown field ${{field}}`);
+  }}
+}}
+r.position += 8;
+",
+            cases.lines().fold(String::new(), |mut indented, line| {
+                let _ = writeln!(indented, "    {line}");
+                indented
+            })
+        );
+        lines.lines().fold(String::new(), |mut indented, line| {
+            let _ = writeln!(indented, "{indent}{line}");
+            indented
+        })
+    }
+
+    /// The name of the function that reads `schema`, a struct or enum named `name`, and
+    /// whether the function still has to be written.
+    fn function(&mut self, name: &str, schema: &Schema) -> (String, bool) {
+        let schemas = self.names.entry(name.to_string()).or_default();
+        let (index, new) = match schemas.iter().position(|known| known == schema) {
+            Some(index) => (index, false),
+            None => {
+                schemas.push(schema.clone());
+                (schemas.len() - 1, true)
+            }
+        };
+        let identifier = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        let function = match index {
+            0 => format!("read{identifier}"),
+            index => format!("read{identifier}{index}"),
+        };
+        (function, new)
+    }
+}
@@ -0,0 +1,588 @@
+//! ### Columnar
+//! A module for writing sequences of structs (e.g. a large batch of records) column by column
+//! instead of row by row: the names of the fields once and then the values of every field of
+//! all the rows one after the other. Values of the same field sit next to each other, which
+//! compresses better and lets a reader go through a single column at a time.
+//!
+//! It is used with `#[serde(with = "rust_fr::columnar")]` on a `Vec` of structs. The rows must
+//! all serialize the same fields (so no `skip_serializing_if`) and both sides must have the
+//! same fields, as the values of unknown fields can't be skipped. Rows that serialize as maps
+//! with string keys (e.g. the rows of an Arrow batch, see the `arrow` feature) are written the
+//! same way, their keys being the names of the fields. Other formats, and rust-fr in the
+//! self-describing mode, write the rows as a plain sequence.
+//!
+//! Every row must write at least a bit (so rows of structs without fields can't be written)
+//! and a reader rejects more rows than the columns have bits before it reads any of them. The
+//! number of rows also counts against
+//! [`Config::max_map_entries`](crate::config::Config::max_map_entries).
+//!
+//! - columns: rows (u32) + fields (u32) + name of every field (a string) + every column
+//! - column: padding + length in bits (u32) + the value of the field in every row
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{deserializer, serializer};
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! struct Trade {
+//!     price: u32,
+//!     volume: u16,
+//!     venue: String,
+//! }
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! struct Batch {
+//!     #[serde(with = "rust_fr::columnar")]
+//!     trades: Vec<Trade>,
+//! }
+//!
+//! let batch = Batch {
+//!     trades: (0..3)
+//!         .map(|i| Trade { price: 100 + i, volume: 5, venue: "XLON".to_string() })
+//!         .collect(),
+//! };
+//! let bytes = serializer::to_bytes(&batch).unwrap();
+//! assert_eq!(deserializer::from_bytes::<Batch>(&bytes).unwrap(), batch);
+//! ```
+
+use std::{borrow::Cow, fmt, marker::PhantomData};
+
+use bitvec::slice::BitSlice;
+use serde::{
+    de::{
+        value::{BorrowedStrDeserializer, StringDeserializer},
+        DeserializeSeed, MapAccess, SeqAccess, Visitor,
+    },
+    forward_to_deserialize_any,
+    ser::{Impossible, SerializeMap, SerializeSeq, SerializeStruct},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::{
+    deserializer::CustomDeserializer, error::Error, protocol::format::WireOrder,
+    serializer::CustomSerializer,
+};
+
+/// The name of the newtype struct the rows are wrapped in so rust-fr recognizes them.
+pub(crate) const NAME: &str = "$rust_fr::private::Columnar";
+
+/// Serialize the rows column by column; see the [module](self) docs.
+pub fn serialize<T, S>(rows: &[T], serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    serializer.serialize_newtype_struct(NAME, rows)
+}
+
+/// Deserialize rows that were serialized column by column; see the [module](self) docs.
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    struct RowsVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for RowsVisitor<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a sequence of structs")
+        }
+
+        fn visit_newtype_struct<D: Deserializer<'de>>(
+            self,
+            deserializer: D,
+        ) -> Result<Self::Value, D::Error> {
+            Vec::deserialize(deserializer)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+            Vec::deserialize(serde::de::value::SeqAccessDeserializer::new(seq))
+        }
+    }
+
+    deserializer.deserialize_newtype_struct(NAME, RowsVisitor(PhantomData))
+}
+
+/// The values of the rows split into a serializer per field.
+pub(crate) struct Columns<'a, O: WireOrder> {
+    pub(crate) rows: usize,
+    pub(crate) names: Vec<Cow<'static, str>>,
+    pub(crate) values: Vec<CustomSerializer<'a, O>>,
+}
+
+/// Split the rows in `value` into columns; `column` gives an empty serializer for a column.
+pub(crate) fn split<'a, T, O, F>(value: &T, column: F) -> Result<Columns<'a, O>, Error>
+where
+    T: ?Sized + Serialize,
+    O: WireOrder,
+    F: Fn() -> CustomSerializer<'a, O>,
+{
+    let mut columns = Columns {
+        rows: 0,
+        names: Vec::new(),
+        values: Vec::new(),
+    };
+    value.serialize(Splitter {
+        columns: &mut columns,
+        column: &column,
+        row: false,
+    })?;
+    Ok(columns)
+}
+
+// Hands the fields of every row to the serializer of their column. The sequence of rows and
+// the rows themselves are the only values it accepts.
+struct Splitter<'s, 'a, O: WireOrder> {
+    columns: &'s mut Columns<'a, O>,
+    column: &'s dyn Fn() -> CustomSerializer<'a, O>,
+    // Whether a row is expected rather than the sequence of rows.
+    row: bool,
+}
+
+// Anything that isn't a sequence of structs.
+macro_rules! not_rows {
+    ($($method:ident($($arg:ty),*)),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<(), Error> {
+                Err(not_rows())
+            }
+        )*
+    };
+}
+
+fn not_rows() -> Error {
+    Error::UnsupportedCall(
+        "`columnar` with anything but a sequence of structs (or maps with string keys)".to_string(),
+    )
+}
+
+impl<'s, 'a, O: WireOrder> Serializer for Splitter<'s, 'a, O> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Row<'s, 'a, O>;
+    type SerializeStruct = Row<'s, 'a, O>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    not_rows! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_none(),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+        serialize_unit_variant(&'static str, u32, &'static str),
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> Result<(), Error> {
+        Err(not_rows())
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), Error> {
+        Err(not_rows())
+    }
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self, Error> {
+        match self.row {
+            true => Err(not_rows()),
+            false => Ok(self),
+        }
+    }
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(not_rows())
+    }
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(not_rows())
+    }
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(not_rows())
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<Row<'s, 'a, O>, Error> {
+        self.serialize_struct("", 0)
+    }
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Row<'s, 'a, O>, Error> {
+        if !self.row {
+            return Err(not_rows());
+        }
+        Ok(Row {
+            columns: self.columns,
+            column: self.column,
+            index: 0,
+        })
+    }
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(not_rows())
+    }
+}
+
+impl<O: WireOrder> SerializeSeq for Splitter<'_, '_, O> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, row: &T) -> Result<(), Error> {
+        row.serialize(Splitter {
+            columns: &mut *self.columns,
+            column: self.column,
+            row: true,
+        })?;
+        self.columns.rows += 1;
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+// A row (a struct or a map with string keys) whose fields are handed to their columns. The
+// first row decides the fields.
+struct Row<'s, 'a, O: WireOrder> {
+    columns: &'s mut Columns<'a, O>,
+    column: &'s dyn Fn() -> CustomSerializer<'a, O>,
+    index: usize,
+}
+
+impl<O: WireOrder> Row<'_, '_, O> {
+    fn different_fields(&self) -> Error {
+        Error::SerializationError(format!(
+            "the row {} of a columnar sequence has other fields than the first row",
+            self.columns.rows
+        ))
+    }
+
+    // Check that `key` is the next field of the row; the first row adds it (as `name()`).
+    fn begin_field(
+        &mut self,
+        key: &str,
+        name: impl FnOnce() -> Cow<'static, str>,
+    ) -> Result<(), Error> {
+        if self.columns.rows == 0 {
+            self.columns.names.push(name());
+            self.columns.values.push((self.column)());
+        }
+        match self.columns.names.get(self.index) {
+            Some(name) if name == key => Ok(()),
+            _ => Err(self.different_fields()),
+        }
+    }
+
+    fn field_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut self.columns.values[self.index])?;
+        self.index += 1;
+        Ok(())
+    }
+
+    fn end_row(self) -> Result<(), Error> {
+        match self.index == self.columns.names.len() {
+            true => Ok(()),
+            false => Err(self.different_fields()),
+        }
+    }
+}
+
+impl<O: WireOrder> SerializeStruct for Row<'_, '_, O> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.begin_field(key, || Cow::Borrowed(key))?;
+        self.field_value(value)
+    }
+
+    fn skip_field(&mut self, _: &'static str) -> Result<(), Error> {
+        Err(self.different_fields())
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.end_row()
+    }
+}
+
+impl<O: WireOrder> SerializeMap for Row<'_, '_, O> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        key.serialize(FieldName(self))
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.field_value(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.end_row()
+    }
+}
+
+// The key of a row that is a map, which must be a string: the name of the field.
+struct FieldName<'r, 's, 'a, O: WireOrder>(&'r mut Row<'s, 'a, O>);
+
+impl<O: WireOrder> Serializer for FieldName<'_, '_, '_, O> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Impossible<(), Error>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    not_rows! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_bytes(&[u8]),
+        serialize_none(),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+        serialize_unit_variant(&'static str, u32, &'static str),
+    }
+
+    fn serialize_str(self, key: &str) -> Result<(), Error> {
+        self.0.begin_field(key, || Cow::Owned(key.to_string()))
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> Result<(), Error> {
+        Err(not_rows())
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<(), Error> {
+        Err(not_rows())
+    }
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(not_rows())
+    }
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(not_rows())
+    }
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(not_rows())
+    }
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(not_rows())
+    }
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(not_rows())
+    }
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct, Error> {
+        Err(not_rows())
+    }
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(not_rows())
+    }
+}
+
+/// The columns of a sequence of structs as they are read: the names of the fields and the bits
+/// of their values that are left.
+pub(crate) struct Table<'de, O: WireOrder> {
+    pub(crate) rows: usize,
+    pub(crate) names: Vec<Cow<'de, str>>,
+    pub(crate) columns: Vec<&'de BitSlice<u8, O>>,
+}
+
+/// Hands out the rows of a [`Table`] as a sequence.
+pub(crate) struct RowsDeserializer<'a, 'de: 'a, O: WireOrder> {
+    pub(crate) deserializer: &'a mut CustomDeserializer<'de, O>,
+    pub(crate) table: Table<'de, O>,
+    pub(crate) index: usize,
+}
+
+impl<'de, O: WireOrder> Deserializer<'de> for RowsDeserializer<'_, 'de, O> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        let value = visitor.visit_seq(&mut self)?;
+        if self.index != self.table.rows {
+            return Err(Error::DeserializationError(
+                "a columnar sequence has more rows than were read".to_string(),
+            ));
+        }
+        if self.table.columns.iter().any(|column| !column.is_empty()) {
+            return Err(Error::DeserializationError(
+                "a column has more values than the columnar sequence has rows".to_string(),
+            ));
+        }
+        Ok(value)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum
+        identifier ignored_any
+    }
+}
+
+impl<'de, O: WireOrder> SeqAccess<'de> for &mut RowsDeserializer<'_, 'de, O> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.index == self.table.rows {
+            return Ok(None);
+        }
+        self.deserializer.tick()?;
+        self.index += 1;
+        seed.deserialize(RowDeserializer {
+            rows: &mut **self,
+            field: 0,
+        })
+        .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.table.rows - self.index)
+    }
+}
+
+// Hands out a row as a map of the names of the fields to the next values of their columns.
+struct RowDeserializer<'r, 'a, 'de: 'a, O: WireOrder> {
+    rows: &'r mut RowsDeserializer<'a, 'de, O>,
+    field: usize,
+}
+
+impl<'de, O: WireOrder> Deserializer<'de> for RowDeserializer<'_, '_, 'de, O> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(&mut self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum
+        identifier ignored_any
+    }
+}
+
+impl<'de, O: WireOrder> MapAccess<'de> for &mut RowDeserializer<'_, '_, 'de, O> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.rows.table.names.get(self.field) {
+            Some(Cow::Borrowed(name)) => seed
+                .deserialize(BorrowedStrDeserializer::new(name))
+                .map(Some),
+            Some(Cow::Owned(name)) => seed
+                .deserialize(StringDeserializer::new(name.clone()))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Read the value from the column of the field, leaving the rest of the column for the
+    /// next row.
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let deserializer = &mut *self.rows.deserializer;
+        let rest = std::mem::replace(&mut deserializer.data, self.rows.table.columns[self.field]);
+        let value = seed.deserialize(&mut *deserializer);
+        self.rows.table.columns[self.field] = std::mem::replace(&mut deserializer.data, rest);
+        self.field += 1;
+        value
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.rows.table.names.len() - self.field)
+    }
+}
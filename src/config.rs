@@ -0,0 +1,330 @@
+//! ### Config
+//! A module for the options that change how data is encoded. The same [`Config`] must be
+//! used to serialize and deserialize a value; see
+//! [`to_bytes_with_config`](crate::serializer::to_bytes_with_config) and
+//! [`from_bytes_with_config`](crate::deserializer::from_bytes_with_config). The options are
+//! written in a header in front of the data (see [`Config::header`]), so decoding with another
+//! config fails with [`Error::ConfigMismatch`] while
+//! [`from_bytes`](crate::deserializer::from_bytes) adopts the config of the header.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{config::Config, deserializer, serializer};
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! #[serde(untagged)]
+//! enum Setting {
+//!     Flag(bool),
+//!     Text(String),
+//! }
+//!
+//! let config = Config {
+//!     self_describing: true,
+//!     ..Config::default()
+//! };
+//! let setting = Setting::Text("on".to_string());
+//! let bytes = serializer::to_bytes_with_config(&setting, &config).unwrap();
+//! let decoded = deserializer::from_bytes_with_config::<Setting>(&bytes, &config).unwrap();
+//! assert_eq!(setting, decoded);
+//! ```
+//!
+//! ### Serde attributes
+//! - `rename`, `rename_all`, `with`, `skip`, `transparent`, `from`, `try_from` and `into`
+//!   work with every config. The error of a `try_from` conversion is returned as
+//!   [`Error::DeserializationError`](crate::error::Error::DeserializationError) prefixed with
+//!   the field (and the struct) it happened in.
+//! - Fields skipped with `skip_serializing_if` need the field names ([`StructKeys::Names`]);
+//!   without them serializing fails. So do fields that are missing from older data and filled
+//!   in by `default`, except at the end of a struct in the self-describing mode.
+//! - Adjacently tagged enums (`#[serde(tag = "t", content = "c")]`) need the field names too.
+//!   Their struct variants are read through `deserialize_any` and need the self-describing
+//!   mode as well.
+//! - Internally tagged enums (`#[serde(tag = "type")]`), untagged enums and `flatten` are
+//!   read through `deserialize_any` and need the self-describing mode; the default mode fails
+//!   with [`Error::SelfDescribingRequired`](crate::error::Error::SelfDescribingRequired).
+//!
+//! ### Map order
+//! The entries of maps are written in the order the map hands them out and read back in the
+//! order they were written; no option reorders them. Maps that keep their insertion order
+//! (e.g. `IndexMap` with its `serde` feature) round-trip with that order in every config. With
+//! [`Config::sorted_int_keys`] the keys are written in the same order too; only keys that are
+//! already in ascending order can be binary searched. The order of a `HashMap` changes from
+//! run to run and so do its bytes; use an ordered map (e.g. a `BTreeMap`) for stable bytes.
+
+use super::error::Error;
+
+/// The options used to serialize and deserialize data. `Config::default()` gives the
+/// format used by [`to_bytes`](crate::serializer::to_bytes) and
+/// [`from_bytes`](crate::deserializer::from_bytes).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Prefix every value with a [`TypeTag`](crate::protocol::format::TypeTag) so the data
+    /// describes itself. This makes the data larger but allows `deserialize_any` (and with it
+    /// `#[serde(untagged)]` and internally tagged enums) as well as skipping values of unknown
+    /// struct fields.
+    pub self_describing: bool,
+    /// Store `u64` and `i64` values in the fewest of 1, 2, 4 or 8 bytes that hold them,
+    /// after a 2 bit width tag. `i64` values are zigzag encoded first so small negative
+    /// numbers stay small too.
+    pub adaptive_ints: bool,
+    /// Fold runs of equal elements in sequences (and tuples) into a single element and
+    /// the number of times it repeats, e.g. for zero-filled buffers or sparse matrices.
+    pub run_length_encoding: bool,
+    /// Write sequences (and tuples) of `u8`, e.g. a plain `Vec<u8>`, like bytes instead of
+    /// element by element. Types that serialize as bytes (see `Serializer::serialize_bytes`)
+    /// are always written like bytes.
+    pub pack_u8_seqs: bool,
+    /// Put a table with the offsets of the entries in front of every map (and struct). Readers
+    /// can then jump straight to an entry, e.g. [`LazyStruct`](crate::deserializer::LazyStruct)
+    /// or when skipping unknown struct fields without the self-describing mode.
+    pub offset_tables: bool,
+    /// Write the keys of maps whose keys are unsigned integers, e.g. a `BTreeMap<u32, V>`, as a
+    /// block in front of the values instead of one by one in front of every value. Every map
+    /// (and struct) starts with a bit telling which layout it has. Keys in ascending order (as
+    /// in a `BTreeMap`) can be binary searched by [`LazyMap`](crate::deserializer::LazyMap).
+    pub sorted_int_keys: bool,
+    /// Write every `bool` as a whole byte (0 or 1) instead of a single bit. The data gets a
+    /// little larger but values following a bool keep their byte alignment, which makes the data
+    /// easier to read by hand or from other languages.
+    pub byte_aligned_bools: bool,
+    /// Whether the names of struct fields are written; see [`StructKeys`].
+    pub struct_keys: StructKeys,
+    /// Write the `Option` fields of structs (and struct variants) that are `None` as nothing at
+    /// all and those that are `Some` without their presence bit, e.g. for sparse records with
+    /// many optional fields. With the field names ([`StructKeys::Names`]) absent fields are
+    /// simply left out. Without them ([`StructKeys::Omit`]) every struct starts with a bitmap
+    /// with a bit per field telling whether it is present, padded to whole bytes; this layout
+    /// doesn't describe itself so it can't be combined with the self-describing mode.
+    pub option_bitmaps: bool,
+    /// Pack the bits into bytes from the most significant bit of a byte down instead of from
+    /// the least significant bit up, e.g. to match an MSB-first bitstream standard. Fields
+    /// that aren't whole bytes (delimiters, type tags, the width tags of adaptive integers)
+    /// are written most significant bit first too. Whole bytes (the header,
+    /// strings, bytes and integers on a byte boundary) keep their values and integers stay
+    /// little endian, so only data with fields that aren't whole bytes changes. The readers
+    /// that work on the raw bits ([`LazyStruct`](crate::deserializer::LazyStruct),
+    /// [`LazyMap`](crate::deserializer::LazyMap), [`to_bitvec`](crate::serializer::to_bitvec)
+    /// and the [`tokens`](crate::tokens) module) only support the default order.
+    pub msb_first: bool,
+    /// Start the value of every struct field on a byte boundary, padding it with zero bits.
+    /// Together with [`Config::byte_aligned_bools`] this puts fixed-width fields (bools,
+    /// integers, floats and chars) at the same byte offset in every record as long as only
+    /// fixed-width fields come before them, so they can be updated in place; see
+    /// [`Config::patchable`] and the [`patch`](crate::patch) module. It needs the values alone
+    /// ([`StructKeys::Omit`]) and can't be combined with the self-describing mode.
+    pub aligned_fields: bool,
+    /// Put the length of every key of a map (and every field name of a struct) in bits (u32)
+    /// in front of the key, which starts on a byte boundary. Readers can then compare the bits
+    /// of keys or skip them without decoding them, e.g. to find duplicate keys with
+    /// [`duplicate_keys`](crate::deserializer::duplicate_keys). Keys written as a block (see
+    /// [`Config::sorted_int_keys`]) have a fixed size and get no length.
+    pub length_prefixed_keys: bool,
+    /// How strings and bytes are framed; see [`StringFraming`].
+    pub string_framing: StringFraming,
+    /// The most entries a map may have while deserializing, e.g. to bound the work spent on
+    /// untrusted data made of millions of tiny keys. A larger map fails with
+    /// [`Error::TooManyMapEntries`] before its extra entries are read; with a block of keys (see
+    /// [`Config::sorted_int_keys`]) or an offset table (see [`Config::offset_tables`]) before any
+    /// entry is read. Structs aren't limited. It only guards the reading side, so it isn't part
+    /// of the header and doesn't have to match the config the data was serialized with.
+    pub max_map_entries: Option<usize>,
+    /// The most levels of containers (sequences, tuples, maps, structs and enums), options and
+    /// newtypes a value may be nested in while deserializing. Deeper data fails with
+    /// [`Error::TooDeep`] instead of overflowing the stack, e.g. untrusted data that nests a
+    /// recursive type (a tree or a linked list) millions of levels deep. A level takes around
+    /// 1.5 KB of stack in optimized builds and up to ten times that in debug builds, so the
+    /// limit should fit the stack of the decoding thread (spawned threads get 2 MB by default).
+    /// Like [`Config::max_map_entries`] it only guards the reading side and isn't part of the
+    /// header.
+    pub max_depth: Option<usize>,
+    /// What to do with the index of a variant the enum doesn't have, e.g. one added by a newer
+    /// producer that long-lived readers must tolerate. Like [`Config::max_map_entries`] it only
+    /// concerns the reading side and isn't part of the header. In the self-describing mode a
+    /// variant whose name isn't the one at its index (e.g. a newer variant at the index of the
+    /// fallback variant) is unknown too.
+    pub on_unknown_variant: UnknownVariant,
+    /// Hand the strings in the keys of maps to the visitor as bytes (`visit_bytes`) without
+    /// checking that they are UTF-8, e.g. for pipelines that treat keys as opaque bytes or
+    /// keys from foreign producers that aren't UTF-8. Keys that are `String`s (or `&str`s)
+    /// still check them themselves, while keys read as bytes (e.g. `&[u8]` or
+    /// `serde_bytes::ByteBuf`) take them as they are. Keys read as bytes are read as strings
+    /// in this mode, so maps whose keys were serialized as bytes can't be read with it; the
+    /// field names of structs are read as usual. Like [`Config::max_map_entries`] it only
+    /// concerns the reading side and isn't part of the header.
+    pub raw_str_keys: bool,
+}
+
+impl Config {
+    /// The names of the options in the order of their bits in the header.
+    pub const OPTIONS: [&'static str; 14] = [
+        "self_describing",
+        "adaptive_ints",
+        "run_length_encoding",
+        "pack_u8_seqs",
+        "offset_tables",
+        "sorted_int_keys",
+        "byte_aligned_bools",
+        "struct_keys = Omit",
+        "option_bitmaps",
+        "msb_first",
+        "aligned_fields",
+        "length_prefixed_keys",
+        "string_framing = Escaped",
+        "string_framing = LengthPrefixed",
+    ];
+
+    /// The options as the header written in front of the data: bit `i` is set if the option
+    /// `Config::OPTIONS[i]` is.
+    pub fn header(&self) -> u16 {
+        self.options()
+            .into_iter()
+            .enumerate()
+            .fold(0, |header, (i, set)| header | (u16::from(set) << i))
+    }
+
+    /// The config described by a header (without limits on the entries of maps or the depth,
+    /// failing on unknown variants and checking string keys). Bits of options this version
+    /// doesn't know (or options that can't be combined) fail with [`Error::InvalidHeader`].
+    pub fn from_header(header: u16) -> Result<Config, Error> {
+        if header >> Config::OPTIONS.len() != 0 {
+            return Err(Error::InvalidHeader(header));
+        }
+        let set = |i: usize| header & (1 << i) != 0;
+        let config = Config {
+            self_describing: set(0),
+            adaptive_ints: set(1),
+            run_length_encoding: set(2),
+            pack_u8_seqs: set(3),
+            offset_tables: set(4),
+            sorted_int_keys: set(5),
+            byte_aligned_bools: set(6),
+            struct_keys: match set(7) {
+                true => StructKeys::Omit,
+                false => StructKeys::Names,
+            },
+            option_bitmaps: set(8),
+            msb_first: set(9),
+            aligned_fields: set(10),
+            length_prefixed_keys: set(11),
+            string_framing: match (set(12), set(13)) {
+                (false, false) => StringFraming::Delimited,
+                (true, false) => StringFraming::Escaped,
+                (false, true) => StringFraming::LengthPrefixed,
+                (true, true) => return Err(Error::InvalidHeader(header)),
+            },
+            max_map_entries: None,
+            max_depth: None,
+            on_unknown_variant: UnknownVariant::Error,
+            raw_str_keys: false,
+        };
+        match config.is_valid() {
+            true => Ok(config),
+            false => Err(Error::InvalidHeader(header)),
+        }
+    }
+
+    /// The profile for records whose fields are updated in place: bools are whole bytes, field
+    /// names are omitted and every field starts on a byte boundary (see
+    /// [`Config::aligned_fields`]). Fixed-width fields then have stable byte offsets.
+    pub fn patchable() -> Config {
+        Config {
+            byte_aligned_bools: true,
+            struct_keys: StructKeys::Omit,
+            aligned_fields: true,
+            ..Config::default()
+        }
+    }
+
+    /// Whether the options can be combined; see [`Config::conflict`].
+    pub(crate) fn is_valid(&self) -> bool {
+        self.conflict().is_none()
+    }
+
+    /// Why the options can't be combined, if they can't: the bitmaps of structs without field
+    /// names (see [`Config::option_bitmaps`]) don't describe themselves and aligned fields (see
+    /// [`Config::aligned_fields`]) need the values alone.
+    pub(crate) fn conflict(&self) -> Option<&'static str> {
+        let omit = self.struct_keys == StructKeys::Omit;
+        if self.option_bitmaps && self.self_describing && omit {
+            return Some(
+                "option bitmaps can't be combined with the self-describing mode when struct \
+                 field names are omitted",
+            );
+        }
+        if self.aligned_fields && (self.self_describing || !omit) {
+            return Some(
+                "aligned fields need omitted struct field names and can't be combined with the \
+                 self-describing mode",
+            );
+        }
+        None
+    }
+
+    fn options(&self) -> [bool; 14] {
+        [
+            self.self_describing,
+            self.adaptive_ints,
+            self.run_length_encoding,
+            self.pack_u8_seqs,
+            self.offset_tables,
+            self.sorted_int_keys,
+            self.byte_aligned_bools,
+            self.struct_keys == StructKeys::Omit,
+            self.option_bitmaps,
+            self.msb_first,
+            self.aligned_fields,
+            self.length_prefixed_keys,
+            self.string_framing == StringFraming::Escaped,
+            self.string_framing == StringFraming::LengthPrefixed,
+        ]
+    }
+}
+
+/// How the fields of structs (and struct variants) are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StructKeys {
+    /// Structs are maps of their field names to their values. Fields can be added, removed
+    /// or reordered as long as the readers skip unknown fields.
+    #[default]
+    Names,
+    /// Structs are their field values one after the other, without the names or any
+    /// delimiters, so both sides must use the very same type. Fields can't be skipped while
+    /// serializing (e.g. with `#[serde(skip_serializing_if = "...")]`). In the self-describing
+    /// mode structs are tuples so they still describe themselves.
+    Omit,
+}
+
+/// How strings and bytes (and the names of fields and variants) are framed. With
+/// [`StringFraming::Delimited`] a string whose UTF-8 has the byte of its delimiter (e.g. `"Ć"`)
+/// or bytes with the byte of theirs end early (see the [`analyze`](crate::analyze) module); the
+/// other two framings write every value as it is. The escaped framing keeps plain text as small
+/// as it was and the length-prefixed one can be read without looking at every byte; compare
+/// them with the `string_framing` playground test. Since format version 7 the default is the
+/// length-prefixed framing, which decodes several times faster than the others for 3 more
+/// bytes per string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StringFraming {
+    /// padding + bytes + delimiter, as the format did up to version 6.
+    Delimited,
+    /// padding + bytes + delimiter, where every byte that is the delimiter or
+    /// [`STRING_ESCAPE`](crate::protocol::format::STRING_ESCAPE) is written after
+    /// [`STRING_ESCAPE`](crate::protocol::format::STRING_ESCAPE).
+    Escaped,
+    /// length (u32) + padding + bytes, without a delimiter.
+    #[default]
+    LengthPrefixed,
+}
+
+/// How unknown variants of enums are deserialized; see [`Config::on_unknown_variant`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownVariant {
+    /// Fail with [`Error::UnknownVariant`].
+    #[default]
+    Error,
+    /// Deserialize the fallback variant of the enum instead: the unit variant marked with
+    /// `#[serde(other)]`. The content of the unknown variant is skipped, so this needs the
+    /// self-describing mode; without it the fallback fails with
+    /// [`Error::SelfDescribingRequired`]. Enums without a fallback variant still fail with
+    /// [`Error::UnknownVariant`].
+    SkipToDefault,
+}
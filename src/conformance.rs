@@ -0,0 +1,428 @@
+//! ### Conformance
+//! A module with the golden test vectors of the format: canonical payloads of a few values
+//! along with a JSON description of each. The `testdata/` directory holds every vector as
+//! `<name>.bin` (the payload) and `<name>.json` (the description) so other implementations
+//! (e.g. JS or Python decoders) can verify themselves against this crate. The files are the
+//! output of [`Vector::to_json`] and can be regenerated with `cargo run --bin gen-testdata`.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::conformance;
+//!
+//! for vector in conformance::vectors() {
+//!     // bytes produced by another implementation for the value in `vector.value`.
+//!     let produced = vector.bytes.clone();
+//!     conformance::check(vector.name, &produced).unwrap();
+//! }
+//! ```
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use serde::Serialize;
+
+use super::{
+    config::{Config, StringFraming, StructKeys},
+    error::Error,
+    protocol::format::FORMAT_VERSION,
+    serializer,
+};
+
+/// A golden test vector: a value, the config it is serialized with and the payload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vector {
+    /// The name of the vector; also the name of its files in `testdata/`.
+    pub name: &'static str,
+    /// What the vector covers.
+    pub description: &'static str,
+    /// The Rust type of the value.
+    pub rust_type: &'static str,
+    /// The value as JSON.
+    pub value: &'static str,
+    /// The config the value is serialized with.
+    pub config: Config,
+    /// The payload.
+    pub bytes: Vec<u8>,
+}
+
+impl Vector {
+    /// Describe the vector as JSON; the payload is included as hex.
+    pub fn to_json(&self) -> String {
+        let Config {
+            self_describing,
+            adaptive_ints,
+            run_length_encoding,
+            pack_u8_seqs,
+            offset_tables,
+            sorted_int_keys,
+            byte_aligned_bools,
+            struct_keys,
+            option_bitmaps,
+            msb_first,
+            aligned_fields,
+            length_prefixed_keys,
+            string_framing,
+            max_map_entries: _,
+            max_depth: _,
+            on_unknown_variant: _,
+            raw_str_keys: _,
+        } = &self.config;
+        let struct_keys = match struct_keys {
+            StructKeys::Names => "names",
+            StructKeys::Omit => "omit",
+        };
+        let string_framing = match string_framing {
+            StringFraming::Delimited => "delimited",
+            StringFraming::Escaped => "escaped",
+            StringFraming::LengthPrefixed => "length_prefixed",
+        };
+        let hex = self.bytes.iter().fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        });
+        format!(
+            r#"{{
+  "name": "{}",
+  "description": "{}",
+  "format_version": {FORMAT_VERSION},
+  "rust_type": "{}",
+  "value": {},
+  "config": {{
+    "self_describing": {self_describing},
+    "adaptive_ints": {adaptive_ints},
+    "run_length_encoding": {run_length_encoding},
+    "pack_u8_seqs": {pack_u8_seqs},
+    "offset_tables": {offset_tables},
+    "sorted_int_keys": {sorted_int_keys},
+    "byte_aligned_bools": {byte_aligned_bools},
+    "struct_keys": "{struct_keys}",
+    "option_bitmaps": {option_bitmaps},
+    "msb_first": {msb_first},
+    "aligned_fields": {aligned_fields},
+    "length_prefixed_keys": {length_prefixed_keys},
+    "string_framing": "{string_framing}"
+  }},
+  "payload": "{}.bin",
+  "hex": "{hex}"
+}}
+"#,
+            self.name, self.description, self.rust_type, self.value, self.name
+        )
+    }
+}
+
+fn vector<T: Serialize>(
+    name: &'static str,
+    description: &'static str,
+    rust_type: &'static str,
+    value: &'static str,
+    config: Config,
+    data: &T,
+) -> Result<Vector, Error> {
+    Ok(Vector {
+        name,
+        description,
+        rust_type,
+        value,
+        bytes: serializer::to_bytes_with_config(data, &config)?,
+        config,
+    })
+}
+
+#[derive(Serialize)]
+struct Human {
+    name: &'static str,
+    age: u8,
+}
+
+#[derive(Serialize)]
+struct Contact {
+    name: &'static str,
+    email: Option<&'static str>,
+    phone: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct Sensor {
+    active: Option<bool>,
+    reading: u32,
+}
+
+#[derive(Serialize)]
+enum Shape {
+    Point,
+    Circle(f32),
+    Rect { w: u16, h: u16 },
+}
+
+/// All the golden test vectors.
+pub fn vectors() -> Vec<Vector> {
+    let self_describing = Config {
+        self_describing: true,
+        ..Config::default()
+    };
+    [
+        vector(
+            "bool",
+            "a single bit",
+            "bool",
+            "true",
+            Config::default(),
+            &true,
+        ),
+        vector(
+            "integers",
+            "little endian integers of every width",
+            "(u8, i16, u32, i64)",
+            "[1, -2, 3, -4]",
+            Config::default(),
+            &(1u8, -2i16, 3u32, -4i64),
+        ),
+        vector(
+            "floats",
+            "little endian IEEE 754 floats",
+            "(f32, f64)",
+            "[1.5, -0.25]",
+            Config::default(),
+            &(1.5f32, -0.25f64),
+        ),
+        vector(
+            "char",
+            "a char as a u32",
+            "char",
+            "\"r\"",
+            Config::default(),
+            &'r',
+        ),
+        vector(
+            "string",
+            "the length, padding and the utf-8 bytes",
+            "(bool, String)",
+            "[true, \"rust-fr\"]",
+            Config::default(),
+            &(true, "rust-fr"),
+        ),
+        vector(
+            "option",
+            "a presence bit in front of the value",
+            "(Option<u8>, Option<u8>)",
+            "[null, 1]",
+            Config::default(),
+            &(None::<u8>, Some(1u8)),
+        ),
+        vector(
+            "seq",
+            "a sequence of u16",
+            "Vec<u16>",
+            "[1, 2, 3]",
+            Config::default(),
+            &vec![1u16, 2, 3],
+        ),
+        vector(
+            "struct",
+            "a struct as a map of field names to values",
+            "struct Human { name: String, age: u8 }",
+            "{\"name\": \"Ayush\", \"age\": 19}",
+            Config::default(),
+            &Human {
+                name: "Ayush",
+                age: 19,
+            },
+        ),
+        vector(
+            "enum",
+            "unit, newtype and struct variants by their index",
+            "Vec<enum Shape { Point, Circle(f32), Rect { w: u16, h: u16 } }>",
+            "[\"Point\", {\"Circle\": 1.0}, {\"Rect\": {\"w\": 2, \"h\": 3}}]",
+            Config::default(),
+            &vec![Shape::Point, Shape::Circle(1.0), Shape::Rect { w: 2, h: 3 }],
+        ),
+        vector(
+            "self_describing",
+            "type tags in front of every value",
+            "Vec<enum Shape { Point, Circle(f32), Rect { w: u16, h: u16 } }>",
+            "[\"Point\", {\"Circle\": 1.0}, {\"Rect\": {\"w\": 2, \"h\": 3}}]",
+            self_describing,
+            &vec![Shape::Point, Shape::Circle(1.0), Shape::Rect { w: 2, h: 3 }],
+        ),
+        vector(
+            "adaptive_ints",
+            "u64 and i64 in as few bytes as they fit",
+            "(u64, i64, u64)",
+            "[7, -300, 4294967296]",
+            Config {
+                adaptive_ints: true,
+                ..Config::default()
+            },
+            &(7u64, -300i64, 1u64 << 32),
+        ),
+        vector(
+            "run_length_encoding",
+            "runs of equal elements as a count and the element",
+            "Vec<u16>",
+            "[1, 0, 0, 0, 0, 2]",
+            Config {
+                run_length_encoding: true,
+                ..Config::default()
+            },
+            &vec![1u16, 0, 0, 0, 0, 2],
+        ),
+        vector(
+            "packed_u8_seqs",
+            "a sequence of u8 packed like bytes",
+            "Vec<u8>",
+            "[1, 2, 3]",
+            Config {
+                pack_u8_seqs: true,
+                ..Config::default()
+            },
+            &vec![1u8, 2, 3],
+        ),
+        vector(
+            "offset_tables",
+            "a struct with the offsets of its fields in front",
+            "struct Human { name: String, age: u8 }",
+            "{\"name\": \"Ayush\", \"age\": 19}",
+            Config {
+                offset_tables: true,
+                ..Config::default()
+            },
+            &Human {
+                name: "Ayush",
+                age: 19,
+            },
+        ),
+        vector(
+            "sorted_int_keys",
+            "a map with its integer keys in front of the values",
+            "BTreeMap<u32, String>",
+            "{\"1\": \"one\", \"2\": \"two\"}",
+            Config {
+                sorted_int_keys: true,
+                ..Config::default()
+            },
+            &BTreeMap::from([(1u32, "one"), (2, "two")]),
+        ),
+        vector(
+            "struct_keys_omit",
+            "a struct as its values without the field names",
+            "struct Human { name: String, age: u8 }",
+            "{\"name\": \"Ayush\", \"age\": 19}",
+            Config {
+                struct_keys: StructKeys::Omit,
+                ..Config::default()
+            },
+            &Human {
+                name: "Ayush",
+                age: 19,
+            },
+        ),
+        vector(
+            "option_bitmaps",
+            "a bitmap of the present fields in front of a struct without field names",
+            "struct Contact { name: String, email: Option<String>, phone: Option<String> }",
+            "{\"name\": \"Ayush\", \"email\": null, \"phone\": \"123\"}",
+            Config {
+                struct_keys: StructKeys::Omit,
+                option_bitmaps: true,
+                ..Config::default()
+            },
+            &Contact {
+                name: "Ayush",
+                email: None,
+                phone: Some("123"),
+            },
+        ),
+        vector(
+            "msb_first",
+            "a struct with the bits packed from the most significant bit of a byte down",
+            "struct Human { name: String, age: u8 }",
+            "{\"name\": \"Ayush\", \"age\": 19}",
+            Config {
+                msb_first: true,
+                ..Config::default()
+            },
+            &Human {
+                name: "Ayush",
+                age: 19,
+            },
+        ),
+        vector(
+            "aligned_fields",
+            "a struct whose fields start on byte boundaries (the patchable profile)",
+            "struct Sensor { active: Option<bool>, reading: u32 }",
+            "{\"active\": true, \"reading\": 7}",
+            Config::patchable(),
+            &Sensor {
+                active: Some(true),
+                reading: 7,
+            },
+        ),
+        vector(
+            "length_prefixed_keys",
+            "a struct whose field names have their lengths in bits in front",
+            "struct Human { name: String, age: u8 }",
+            "{\"name\": \"Ayush\", \"age\": 19}",
+            Config {
+                length_prefixed_keys: true,
+                ..Config::default()
+            },
+            &Human {
+                name: "Ayush",
+                age: 19,
+            },
+        ),
+        vector(
+            "delimited_strings",
+            "padding, the utf-8 bytes and the string delimiter",
+            "(bool, String)",
+            "[true, \"rust-fr\"]",
+            Config {
+                string_framing: StringFraming::Delimited,
+                ..Config::default()
+            },
+            &(true, "rust-fr"),
+        ),
+        vector(
+            "escaped_strings",
+            "a string with the byte of the string delimiter in its UTF-8, escaped",
+            "String",
+            "\"Ćwiczenie\"",
+            Config {
+                string_framing: StringFraming::Escaped,
+                ..Config::default()
+            },
+            &"Ćwiczenie",
+        ),
+        vector(
+            "length_prefixed_strings",
+            "a string with the byte of the string delimiter in its UTF-8, prefixed with its length",
+            "String",
+            "\"Ćwiczenie\"",
+            Config {
+                string_framing: StringFraming::LengthPrefixed,
+                ..Config::default()
+            },
+            &"Ćwiczenie",
+        ),
+    ]
+    .into_iter()
+    .collect::<Result<_, _>>()
+    // the vectors are fixed values that always serialize.
+    .expect("the test vectors serialize")
+}
+
+/// Check the payload another implementation produced for the vector with the given name.
+pub fn check(name: &str, bytes: &[u8]) -> Result<(), Error> {
+    let vector = vectors()
+        .into_iter()
+        .find(|vector| vector.name == name)
+        .ok_or_else(|| Error::UnknownTestVector(name.to_string()))?;
+    if vector.bytes != bytes {
+        return Err(Error::TestVectorMismatch {
+            name: vector.name,
+            expected: vector.bytes,
+            found: bytes.to_vec(),
+        });
+    }
+    Ok(())
+}
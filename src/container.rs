@@ -0,0 +1,1216 @@
+//! ### Container
+//! A module for a simple container of records, e.g. a log: values serialized one after the
+//! other, each prefixed with its length in bytes (u32, little endian).
+//!
+//! - container: length_1 + record_1 + length_2 + record_2 + ...
+//!
+//! Records are framed by their length alone, so a [`ContainerReader`] can hand them out
+//! without decoding them. Every [`Record`] carries its index in the container which keeps the
+//! order when records are decoded out of order, e.g. on a thread pool.
+//!
+//! ### Alignment
+//! A container can align the start of every record to a power of two (e.g. 8 or 4096 bytes)
+//! from the start of the container, so records in a container that is memory mapped can be
+//! referenced through aligned pointers. Zero bytes are put before the length of a record to
+//! pad it; the reader must be given the same alignment (see
+//! [`ContainerWriter::with_alignment`] and [`ContainerReader::with_alignment`]).
+//!
+//! - aligned container: padding_1 + length_1 + record_1 + padding_2 + length_2 + record_2 + ...
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{
+//!     config::Config,
+//!     container::{ContainerReader, ContainerWriter},
+//! };
+//! use std::num::NonZeroUsize;
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! struct Event {
+//!     id: u32,
+//!     name: String,
+//! }
+//!
+//! let config = Config::default();
+//! let mut writer = ContainerWriter::new(&config);
+//! for id in 0..100 {
+//!     writer.push(&Event { id, name: format!("event {id}") }).unwrap();
+//! }
+//! let bytes = writer.into_bytes();
+//!
+//! let threads = NonZeroUsize::new(4).unwrap();
+//! let events = ContainerReader::new(&bytes, &config)
+//!     .decode_parallel::<Event>(threads)
+//!     .unwrap();
+//! assert_eq!(events.len(), 100);
+//! assert!(events.iter().enumerate().all(|(i, event)| event.id == i as u32));
+//! ```
+//!
+//! ### Streams
+//! A [`StreamWriter`] writes records to an `io::Write` (e.g. a connection) between a start
+//! and an end marker, so a [`StreamReader`] can tell a stream that ended cleanly from one that
+//! was cut off, even between two records.
+//!
+//! - stream: STREAM_START + length_1 + record_1 + ... + STREAM_END
+//!
+//! ```rust
+//! use rust_fr::{
+//!     config::Config,
+//!     container::{StreamReader, StreamWriter},
+//!     error::Error,
+//! };
+//!
+//! let config = Config::default();
+//! let mut writer = StreamWriter::new(Vec::new(), &config).unwrap();
+//! writer.push(&"first").unwrap();
+//! writer.push(&"second").unwrap();
+//! let bytes = writer.finish().unwrap();
+//!
+//! let mut reader = StreamReader::new(bytes.as_slice(), &config).unwrap();
+//! assert_eq!(reader.read::<String>().unwrap(), Some("first".to_string()));
+//! assert_eq!(reader.read::<String>().unwrap(), Some("second".to_string()));
+//! assert_eq!(reader.read::<String>().unwrap(), None);
+//!
+//! // without the end marker the stream was cut off.
+//! let mut reader = StreamReader::new(&bytes[..bytes.len() - 4], &config).unwrap();
+//! reader.read::<String>().unwrap();
+//! reader.read::<String>().unwrap();
+//! assert!(matches!(reader.read::<String>(), Err(Error::UnexpectedEOF)));
+//! ```
+//!
+//! A [`StreamReader`] that reads from an untrusted source should limit the size of records
+//! (see [`StreamReader::with_max_record_size`]): a larger record is rejected by its length,
+//! before any of it is read or allocated, and skipped on the next read.
+//!
+//! A server that reads from non-blocking sockets (e.g. with epoll) can feed the bytes of every
+//! read to a [`PartialDecoder`] which hands out records once they are complete.
+//!
+//! ### Seekable compression
+//! A [`SeekableWriter`] compresses a container in frames of whole records and appends a table
+//! with the sizes of the frames, laid out as the
+//! [zstd seekable format](https://github.com/facebook/zstd/tree/dev/contrib/seekable_format):
+//! with a [`FrameCodec`] wrapping zstd (e.g. `Zstd` with the `zstd` feature) the data is a
+//! valid zstd file that `zstd -d` (and other seekable readers) decompress to the container. A
+//! [`SeekableReader`] reads the table and decompresses only the frame of the record it is
+//! asked for, so compressed logs keep their random access.
+//!
+//! - seekable container: frame_1 + frame_2 + ... + seek table
+//! - frame: the compressed records length_1 + record_1 + length_2 + record_2 + ...
+//!
+//! ### Dictionaries
+//! Small records compress poorly one by one: each of them is too short for zstd to find the
+//! repetitions they share with the others. With the `zstd` feature a `DictionaryWriter`
+//! trains a zstd dictionary on a sample of its records, stores it in front of them and
+//! compresses every record on its own with it. A `DictionaryReader` loads the dictionary
+//! once and decompresses the records one at a time, so they stay individually readable.
+//!
+//! - dictionary container: dictionary_length + dictionary + container of compressed records
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    marker::PhantomData,
+    num::NonZeroUsize,
+    task::Poll,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{
+    config::Config,
+    deserializer,
+    error::Error,
+    protocol::format::{STREAM_END, STREAM_START},
+    serializer,
+};
+
+/// Builds a container by serializing records one after the other.
+#[derive(Debug, Clone, Default)]
+pub struct ContainerWriter {
+    bytes: Vec<u8>,
+    config: Config,
+    alignment: usize,
+}
+
+impl ContainerWriter {
+    /// Create an empty container whose records are serialized with the given config.
+    pub fn new(config: &Config) -> Self {
+        Self::with_alignment(config, 1)
+    }
+
+    /// The same as [`ContainerWriter::new`] but the start of every record is aligned to
+    /// `alignment` bytes from the start of the container.
+    ///
+    /// # Panics
+    /// If `alignment` is not a power of two.
+    pub fn with_alignment(config: &Config, alignment: usize) -> Self {
+        assert!(
+            alignment.is_power_of_two(),
+            "the alignment of records must be a power of two"
+        );
+        Self {
+            bytes: Vec::new(),
+            config: config.clone(),
+            alignment,
+        }
+    }
+
+    /// Serialize `value` and append it to the container as the next record.
+    pub fn push<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let record = serializer::to_bytes_with_config(value, &self.config)?;
+        let length = u32::try_from(record.len()).map_err(|_| {
+            Error::SerializationError("the record is too large for a container".to_string())
+        })?;
+        let padding = padding(self.bytes.len(), self.alignment);
+        self.bytes.resize(self.bytes.len() + padding, 0);
+        self.bytes.extend(length.to_le_bytes());
+        self.bytes.extend(record);
+        Ok(())
+    }
+
+    /// The bytes of the container.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// A record of a container along with its index in the container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    /// The index of the record in the container.
+    pub index: usize,
+    /// The serialized record.
+    pub bytes: &'a [u8],
+}
+
+impl<'a> Record<'a> {
+    /// Deserialize the record with the config the container was written with.
+    pub fn decode<T: Deserialize<'a>>(&self, config: &Config) -> Result<T, Error> {
+        deserializer::from_bytes_with_config(self.bytes, config)
+    }
+}
+
+/// Reads the records of a container in order. It is an iterator over the [`Record`]s which
+/// can be decoded anywhere (e.g. on a thread pool) or all at once with
+/// [`ContainerReader::decode_parallel`].
+#[derive(Debug, Clone)]
+pub struct ContainerReader<'a> {
+    bytes: &'a [u8],
+    config: Config,
+    index: usize,
+    alignment: usize,
+    // The position in the container.
+    position: usize,
+}
+
+impl<'a> ContainerReader<'a> {
+    /// Read the container in `bytes` whose records were serialized with the given config.
+    pub fn new(bytes: &'a [u8], config: &Config) -> Self {
+        Self::with_alignment(bytes, config, 1)
+    }
+
+    /// The same as [`ContainerReader::new`] for a container written with
+    /// [`ContainerWriter::with_alignment`].
+    ///
+    /// # Panics
+    /// If `alignment` is not a power of two.
+    pub fn with_alignment(bytes: &'a [u8], config: &Config, alignment: usize) -> Self {
+        assert!(
+            alignment.is_power_of_two(),
+            "the alignment of records must be a power of two"
+        );
+        Self {
+            bytes,
+            config: config.clone(),
+            index: 0,
+            alignment,
+            position: 0,
+        }
+    }
+
+    /// Decode the remaining records on `threads` threads. The records are split into one
+    /// contiguous chunk per thread and the decoded values are returned in container order.
+    pub fn decode_parallel<T>(self, threads: NonZeroUsize) -> Result<Vec<T>, Error>
+    where
+        T: Deserialize<'a> + Send,
+    {
+        let config = self.config.clone();
+        let records = self.collect::<Result<Vec<_>, _>>()?;
+        let chunk_size = records.len().div_ceil(threads.get()).max(1);
+        std::thread::scope(|scope| {
+            let workers = records
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let config = &config;
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|record| record.decode::<T>(config))
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut values = Vec::with_capacity(records.len());
+            for worker in workers {
+                let decoded = worker.join().map_err(|_| {
+                    Error::DeserializationError("a decoding thread panicked".to_string())
+                })?;
+                values.extend(decoded?);
+            }
+            Ok(values)
+        })
+    }
+}
+
+impl<'a> Iterator for ContainerReader<'a> {
+    type Item = Result<Record<'a>, Error>;
+
+    /// Grab the next record from the container.
+    /// - If at end of container; exit.
+    /// - Eat the padding (which must be zero); then the length; then as many bytes as the
+    ///   record.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        let padding = padding(self.position, self.alignment);
+        let Some((padding, rest)) = self.bytes.split_at_checked(padding) else {
+            self.bytes = &[];
+            return Some(Err(Error::UnexpectedEOF));
+        };
+        if padding.iter().any(|&byte| byte != 0) {
+            self.bytes = &[];
+            return Some(Err(Error::DeserializationError(format!(
+                "the padding before the record {} is not zero",
+                self.index
+            ))));
+        }
+        let Some((length, rest)) = rest.split_first_chunk::<4>() else {
+            self.bytes = &[];
+            return Some(Err(Error::UnexpectedEOF));
+        };
+        let length = u32::from_le_bytes(*length) as usize;
+        let Some((bytes, rest)) = rest.split_at_checked(length) else {
+            self.bytes = &[];
+            return Some(Err(Error::UnexpectedEOF));
+        };
+        self.position += padding.len() + 4 + bytes.len();
+        self.bytes = rest;
+        let record = Record {
+            index: self.index,
+            bytes,
+        };
+        self.index += 1;
+        Some(Ok(record))
+    }
+}
+
+// The number of zero bytes to put at `position` so the record after its length starts at a
+// multiple of `alignment`.
+fn padding(position: usize, alignment: usize) -> usize {
+    (alignment - (position + 4) % alignment) % alignment
+}
+
+/// Writes records to an `io::Write` as a stream (see the [module](self) docs). The stream
+/// is only complete once [`StreamWriter::finish`] has written the end marker; a writer that is
+/// dropped before leaves a stream that readers see as cut off.
+#[derive(Debug)]
+pub struct StreamWriter<W: Write> {
+    writer: W,
+    config: Config,
+    position: usize,
+}
+
+impl<W: Write> StreamWriter<W> {
+    /// Start a stream in `writer` whose records are serialized with the given config.
+    pub fn new(writer: W, config: &Config) -> Result<Self, Error> {
+        let mut stream = Self {
+            writer,
+            config: config.clone(),
+            position: 0,
+        };
+        stream.write(&STREAM_START)?;
+        Ok(stream)
+    }
+
+    /// Serialize `value` and write it to the stream as the next record.
+    pub fn push<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let record = serializer::to_bytes_with_config(value, &self.config)?;
+        let length = u32::try_from(record.len())
+            .ok()
+            .filter(|&length| length != STREAM_END)
+            .ok_or_else(|| {
+                Error::SerializationError("the record is too large for a stream".to_string())
+            })?;
+        self.write(&length.to_le_bytes())?;
+        self.write(&record)
+    }
+
+    /// Write the end marker and flush the stream. Returns the writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.write(&STREAM_END.to_le_bytes())?;
+        self.writer.flush().map_err(|source| Error::Io {
+            source,
+            position: self.position,
+        })?;
+        Ok(self.writer)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.writer.write_all(bytes).map_err(|source| Error::Io {
+            source,
+            position: self.position,
+        })?;
+        self.position += bytes.len();
+        Ok(())
+    }
+}
+
+/// Reads the records of a stream (see the [module](self) docs) from an `io::Read` one at a
+/// time. A stream that ends without its end marker, i.e. one that was cut off, fails with
+/// [`Error::UnexpectedEOF`].
+#[derive(Debug)]
+pub struct StreamReader<R: Read> {
+    reader: R,
+    config: Config,
+    position: usize,
+    record: Vec<u8>,
+    finished: bool,
+    max_record_size: usize,
+    // The length of a rejected record that is skipped before the next one is read.
+    rejected: usize,
+}
+
+impl<R: Read> StreamReader<R> {
+    /// Read the start of the stream in `reader` whose records were serialized with the given
+    /// config.
+    pub fn new(reader: R, config: &Config) -> Result<Self, Error> {
+        Self::with_max_record_size(reader, config, usize::MAX)
+    }
+
+    /// The same as [`StreamReader::new`] but records larger than `max` bytes fail with
+    /// [`Error::RecordTooLarge`] without being read. The next read skips the rejected record
+    /// and goes on with the one after it.
+    pub fn with_max_record_size(reader: R, config: &Config, max: usize) -> Result<Self, Error> {
+        let mut stream = Self {
+            reader,
+            config: config.clone(),
+            position: 0,
+            record: Vec::new(),
+            finished: false,
+            max_record_size: max,
+            rejected: 0,
+        };
+        let start = stream.read_array()?;
+        if start != STREAM_START {
+            return Err(Error::InvalidStreamStart(start));
+        }
+        Ok(stream)
+    }
+
+    /// Read and deserialize the next record or `None` at the end of the stream.
+    /// - If at end of stream; exit.
+    /// - Skip the record rejected before (if any).
+    /// - Read the length; reject it if it's over the limit.
+    /// - Read as many bytes as the record.
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Error> {
+        if self.finished {
+            return Ok(None);
+        }
+        if self.rejected > 0 {
+            self.skip_rejected()?;
+        }
+        let length = u32::from_le_bytes(self.read_array()?);
+        if length == STREAM_END {
+            self.finished = true;
+            return Ok(None);
+        }
+        let length = length as usize;
+        if length > self.max_record_size {
+            self.rejected = length;
+            return Err(Error::RecordTooLarge {
+                length,
+                max: self.max_record_size,
+            });
+        }
+        self.record.resize(length, 0);
+        let mut record = std::mem::take(&mut self.record);
+        let read = self.read_exact(&mut record);
+        self.record = record;
+        read?;
+        deserializer::from_bytes_with_config(&self.record, &self.config).map(Some)
+    }
+
+    /// Whether the end marker of the stream has been read.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Read past the rejected record without keeping it.
+    fn skip_rejected(&mut self) -> Result<(), Error> {
+        let length = self.rejected as u64;
+        let skipped = std::io::copy(&mut (&mut self.reader).take(length), &mut std::io::sink())
+            .map_err(|source| Error::Io {
+                source,
+                position: self.position,
+            })?;
+        self.position += skipped as usize;
+        if skipped < length {
+            self.rejected -= skipped as usize;
+            return Err(Error::UnexpectedEOF);
+        }
+        self.rejected = 0;
+        Ok(())
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut bytes = [0u8; N];
+        self.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn read_exact(&mut self, bytes: &mut [u8]) -> Result<(), Error> {
+        self.reader
+            .read_exact(bytes)
+            .map_err(|source| match source.kind() {
+                ErrorKind::UnexpectedEof => Error::UnexpectedEOF,
+                _ => Error::Io {
+                    source,
+                    position: self.position,
+                },
+            })?;
+        self.position += bytes.len();
+        Ok(())
+    }
+}
+
+/// Decodes the records of a stream (see the [module](self) docs) from bytes that arrive in
+/// pieces, e.g. from the partial reads of a non-blocking socket. Unlike a [`StreamReader`] it
+/// doesn't read anything itself: bytes are fed to it as they come and it keeps where it is in
+/// the stream between calls, so a record may be split across any number of reads.
+///
+/// ```rust
+/// use rust_fr::{
+///     config::Config,
+///     container::{PartialDecoder, StreamWriter},
+/// };
+/// use std::task::Poll;
+///
+/// let config = Config::default();
+/// let mut writer = StreamWriter::new(Vec::new(), &config).unwrap();
+/// writer.push(&"first").unwrap();
+/// writer.push(&"second").unwrap();
+/// let bytes = writer.finish().unwrap();
+///
+/// let mut decoder = PartialDecoder::<String>::new(&config);
+/// let mut records = Vec::new();
+/// // one byte per read.
+/// for byte in &bytes {
+///     let mut poll = decoder.feed(std::slice::from_ref(byte));
+///     while let Poll::Ready(record) = poll {
+///         records.push(record.unwrap());
+///         poll = decoder.poll();
+///     }
+/// }
+/// assert_eq!(records, ["first", "second"]);
+/// assert!(decoder.is_finished());
+/// ```
+#[derive(Debug)]
+pub struct PartialDecoder<T> {
+    config: Config,
+    buffer: Vec<u8>,
+    // The bytes at the start of the buffer that have been decoded already.
+    consumed: usize,
+    state: DecoderState,
+    max_record_size: usize,
+    record: PhantomData<fn() -> T>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecoderState {
+    Start,
+    Length,
+    Record(usize),
+    // The bytes of a rejected record that are still to be dropped.
+    Skip(usize),
+    Finished,
+    // The start of the stream was invalid, nothing after it is decoded.
+    Invalid([u8; 4]),
+}
+
+impl<T: DeserializeOwned> PartialDecoder<T> {
+    /// Create a decoder for a stream whose records were serialized with the given config.
+    pub fn new(config: &Config) -> Self {
+        Self::with_max_record_size(config, usize::MAX)
+    }
+
+    /// The same as [`PartialDecoder::new`] but records larger than `max` bytes fail with
+    /// [`Error::RecordTooLarge`] as soon as their length is read. Their bytes are dropped as
+    /// they're fed, never buffered, and decoding goes on with the record after them.
+    pub fn with_max_record_size(config: &Config, max: usize) -> Self {
+        Self {
+            config: config.clone(),
+            buffer: Vec::new(),
+            consumed: 0,
+            state: DecoderState::Start,
+            max_record_size: max,
+            record: PhantomData,
+        }
+    }
+
+    /// Add `bytes` to the ones fed before and decode the next record. It returns
+    /// `Poll::Pending` until all of the record is there. The bytes after the record are kept,
+    /// so call [`PartialDecoder::poll`] until it's pending to decode every record that is
+    /// complete before feeding more.
+    pub fn feed(&mut self, bytes: &[u8]) -> Poll<Result<T, Error>> {
+        if self.consumed > 0 {
+            self.buffer.drain(..self.consumed);
+            self.consumed = 0;
+        }
+        match self.state {
+            DecoderState::Skip(rest) => {
+                let skipped = rest.min(bytes.len());
+                self.state = match rest - skipped {
+                    0 => DecoderState::Length,
+                    rest => DecoderState::Skip(rest),
+                };
+                self.buffer.extend_from_slice(&bytes[skipped..]);
+            }
+            DecoderState::Finished | DecoderState::Invalid(_) => {}
+            _ => self.buffer.extend_from_slice(bytes),
+        }
+        self.poll()
+    }
+
+    /// Decode the next record from the bytes fed so far without feeding more.
+    /// - Check the start of the stream (once).
+    /// - Read the length; reject it if it's over the limit.
+    /// - Decode the record once all of its bytes are there.
+    ///
+    /// It's pending at the end of the stream, see [`PartialDecoder::is_finished`].
+    pub fn poll(&mut self) -> Poll<Result<T, Error>> {
+        loop {
+            match self.state {
+                DecoderState::Start => {
+                    let Some(start) = self.take::<4>() else {
+                        return Poll::Pending;
+                    };
+                    if start != STREAM_START {
+                        self.state = DecoderState::Invalid(start);
+                        continue;
+                    }
+                    self.state = DecoderState::Length;
+                }
+                DecoderState::Length => {
+                    let Some(length) = self.take::<4>() else {
+                        return Poll::Pending;
+                    };
+                    let length = u32::from_le_bytes(length);
+                    if length == STREAM_END {
+                        self.state = DecoderState::Finished;
+                        continue;
+                    }
+                    let length = length as usize;
+                    if length > self.max_record_size {
+                        self.state = DecoderState::Skip(length);
+                        self.skip_buffered();
+                        return Poll::Ready(Err(Error::RecordTooLarge {
+                            length,
+                            max: self.max_record_size,
+                        }));
+                    }
+                    self.state = DecoderState::Record(length);
+                }
+                DecoderState::Record(length) => {
+                    let available = &self.buffer[self.consumed..];
+                    if available.len() < length {
+                        return Poll::Pending;
+                    }
+                    let record = &available[..length];
+                    let value = deserializer::from_bytes_with_config(record, &self.config);
+                    self.consumed += length;
+                    self.state = DecoderState::Length;
+                    return Poll::Ready(value);
+                }
+                DecoderState::Skip(_) => {
+                    self.skip_buffered();
+                    if let DecoderState::Skip(_) = self.state {
+                        return Poll::Pending;
+                    }
+                }
+                DecoderState::Finished => return Poll::Pending,
+                DecoderState::Invalid(start) => {
+                    return Poll::Ready(Err(Error::InvalidStreamStart(start)))
+                }
+            }
+        }
+    }
+
+    /// Whether the end marker of the stream has been decoded. Bytes fed after it are ignored.
+    pub fn is_finished(&self) -> bool {
+        self.state == DecoderState::Finished
+    }
+
+    /// The number of bytes that have been fed but not decoded yet.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() - self.consumed
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self.buffer.get(self.consumed..self.consumed + N)?;
+        let bytes = bytes.try_into().ok()?;
+        self.consumed += N;
+        Some(bytes)
+    }
+
+    // Drop as much of a rejected record as has been buffered.
+    fn skip_buffered(&mut self) {
+        if let DecoderState::Skip(rest) = self.state {
+            let skipped = rest.min(self.buffered());
+            self.consumed += skipped;
+            self.state = match rest - skipped {
+                0 => DecoderState::Length,
+                rest => DecoderState::Skip(rest),
+            };
+        }
+    }
+}
+
+/// Compresses and decompresses the frames of a seekable container (see the
+/// [module](self) docs), e.g. `Zstd` with the `zstd` feature. Every frame is compressed on its
+/// own.
+pub trait FrameCodec {
+    /// Compress the bytes of a frame.
+    fn compress(&self, frame: &[u8]) -> std::io::Result<Vec<u8>>;
+
+    /// Decompress a frame whose decompressed size is `size` bytes.
+    fn decompress(&self, frame: &[u8], size: usize) -> std::io::Result<Vec<u8>>;
+}
+
+/// A [`FrameCodec`] that compresses every frame into a zstd frame, so a seekable container is a
+/// file of the zstd seekable format.
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Zstd {
+    /// The compression level, from 1 to 22; 0 is the default of zstd (3).
+    pub level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl Default for Zstd {
+    fn default() -> Self {
+        Self {
+            level: zstd::DEFAULT_COMPRESSION_LEVEL,
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl FrameCodec for Zstd {
+    fn compress(&self, frame: &[u8]) -> std::io::Result<Vec<u8>> {
+        zstd::bulk::compress(frame, self.level)
+    }
+
+    fn decompress(&self, frame: &[u8], size: usize) -> std::io::Result<Vec<u8>> {
+        zstd::bulk::decompress(frame, size)
+    }
+}
+
+// The magic number of the skippable zstd frame that holds the seek table.
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A5E;
+// The magic number at the end of the seek table.
+const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+// The size of the header of the skippable frame: its magic number and its size (u32).
+const SKIPPABLE_HEADER: usize = 8;
+// The size of the footer of the seek table: the number of frames (u32), the descriptor (u8)
+// and the magic number.
+const SEEK_TABLE_FOOTER: usize = 9;
+// The bit of the descriptor telling that every frame has a checksum in the seek table.
+const CHECKSUM_FLAG: u8 = 0x80;
+
+/// Builds a seekable container (see the [module](self) docs) by serializing records one after
+/// the other and compressing them in frames.
+///
+/// ```rust
+/// use rust_fr::{
+///     config::Config,
+///     container::{ContainerReader, FrameCodec, SeekableReader, SeekableWriter},
+/// };
+///
+/// // stores the frames as they are; a real codec compresses them, e.g. `Zstd`.
+/// struct Stored;
+///
+/// impl FrameCodec for Stored {
+///     fn compress(&self, frame: &[u8]) -> std::io::Result<Vec<u8>> {
+///         Ok(frame.to_vec())
+///     }
+///     fn decompress(&self, frame: &[u8], _size: usize) -> std::io::Result<Vec<u8>> {
+///         Ok(frame.to_vec())
+///     }
+/// }
+///
+/// let config = Config::default();
+/// let mut writer = SeekableWriter::new(Stored, &config, 1024);
+/// let offsets = (0..1000u32)
+///     .map(|i| writer.push(&format!("line {i}")).unwrap())
+///     .collect::<Vec<_>>();
+/// let bytes = writer.into_bytes().unwrap();
+///
+/// let reader = SeekableReader::new(&bytes, Stored, &config).unwrap();
+/// assert!(reader.frames() > 1);
+/// // only the frame of the record is decompressed.
+/// assert_eq!(reader.decode::<String>(offsets[742]).unwrap(), "line 742");
+/// // every frame is a container by itself.
+/// let frame = reader.frame(0).unwrap();
+/// let first = ContainerReader::new(&frame, &config).next().unwrap().unwrap();
+/// assert_eq!(first.decode::<&str>(&config).unwrap(), "line 0");
+/// ```
+#[derive(Debug)]
+pub struct SeekableWriter<C: FrameCodec> {
+    codec: C,
+    config: Config,
+    frame_size: usize,
+    // The records of the frame being filled.
+    frame: Vec<u8>,
+    bytes: Vec<u8>,
+    // The compressed and decompressed sizes of the frames written so far.
+    frames: Vec<(u32, u32)>,
+    // The decompressed size of the frames written so far.
+    position: usize,
+}
+
+impl<C: FrameCodec> SeekableWriter<C> {
+    /// Create an empty seekable container whose records are serialized with the given config
+    /// and compressed by `codec` in frames of about `frame_size` bytes. A frame is cut after
+    /// the record that makes it reach `frame_size`, so records are never split across frames.
+    pub fn new(codec: C, config: &Config, frame_size: usize) -> Self {
+        Self {
+            codec,
+            config: config.clone(),
+            frame_size,
+            frame: Vec::new(),
+            bytes: Vec::new(),
+            frames: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Serialize `value` and append it to the container as the next record. Returns the
+    /// offset of the record in the decompressed container, which finds it again with
+    /// [`SeekableReader::record`].
+    pub fn push<T: Serialize>(&mut self, value: &T) -> Result<usize, Error> {
+        let record = serializer::to_bytes_with_config(value, &self.config)?;
+        let length = u32::try_from(record.len()).map_err(|_| {
+            Error::SerializationError("the record is too large for a container".to_string())
+        })?;
+        let offset = self.position + self.frame.len();
+        self.frame.extend(length.to_le_bytes());
+        self.frame.extend(record);
+        if self.frame.len() >= self.frame_size {
+            self.write_frame()?;
+        }
+        Ok(offset)
+    }
+
+    /// Compress the last frame and append the seek table. Returns the bytes of the container.
+    pub fn into_bytes(mut self) -> Result<Vec<u8>, Error> {
+        self.write_frame()?;
+        let too_large = |_| {
+            Error::SerializationError("the seek table is too large for a container".to_string())
+        };
+        let count = u32::try_from(self.frames.len()).map_err(too_large)?;
+        let size = u32::try_from(self.frames.len() * 8 + SEEK_TABLE_FOOTER).map_err(too_large)?;
+        self.bytes.extend(SKIPPABLE_MAGIC.to_le_bytes());
+        self.bytes.extend(size.to_le_bytes());
+        for (compressed, decompressed) in &self.frames {
+            self.bytes.extend(compressed.to_le_bytes());
+            self.bytes.extend(decompressed.to_le_bytes());
+        }
+        self.bytes.extend(count.to_le_bytes());
+        // no checksums.
+        self.bytes.push(0);
+        self.bytes.extend(SEEKABLE_MAGIC.to_le_bytes());
+        Ok(self.bytes)
+    }
+
+    /// Compress the records of the frame being filled, if any, and append them.
+    fn write_frame(&mut self) -> Result<(), Error> {
+        if self.frame.is_empty() {
+            return Ok(());
+        }
+        let compressed = self
+            .codec
+            .compress(&self.frame)
+            .map_err(|source| Error::Io {
+                source,
+                position: self.bytes.len(),
+            })?;
+        let too_large = |_| {
+            Error::SerializationError("the frame is too large for a seekable container".to_string())
+        };
+        let sizes = (
+            u32::try_from(compressed.len()).map_err(too_large)?,
+            u32::try_from(self.frame.len()).map_err(too_large)?,
+        );
+        self.bytes.extend(compressed);
+        self.frames.push(sizes);
+        self.position += self.frame.len();
+        self.frame.clear();
+        Ok(())
+    }
+}
+
+/// Reads the records of a seekable container (see the [module](self) docs) at random. Only the
+/// seek table is read up front; a frame is decompressed when a record in it is asked for.
+#[derive(Debug)]
+pub struct SeekableReader<'a, C: FrameCodec> {
+    bytes: &'a [u8],
+    codec: C,
+    config: Config,
+    // The compressed and decompressed start of every frame and the end of the last one.
+    starts: Vec<(usize, usize)>,
+}
+
+impl<'a, C: FrameCodec> SeekableReader<'a, C> {
+    /// Read the seek table of the seekable container in `bytes` whose records were serialized
+    /// with the given config and compressed by `codec`. Seek tables with checksums (written by
+    /// other zstd seekable writers) are read too; the checksums aren't checked.
+    pub fn new(bytes: &'a [u8], codec: C, config: &Config) -> Result<Self, Error> {
+        let invalid =
+            |reason: &str| Error::DeserializationError(format!("invalid seek table: {reason}"));
+        let footer = bytes
+            .len()
+            .checked_sub(SEEK_TABLE_FOOTER)
+            .ok_or(Error::UnexpectedEOF)?;
+        let (count, descriptor, magic) = (
+            u32_at(bytes, footer) as usize,
+            bytes[footer + 4],
+            u32_at(bytes, footer + 5),
+        );
+        if magic != SEEKABLE_MAGIC {
+            return Err(invalid(
+                "the data doesn't end with the magic number of a seek table",
+            ));
+        }
+        if descriptor & !CHECKSUM_FLAG != 0 {
+            return Err(invalid("the reserved bits of the descriptor are set"));
+        }
+        let entry = match descriptor & CHECKSUM_FLAG {
+            0 => 8,
+            _ => 12,
+        };
+        let size = count
+            .checked_mul(entry)
+            .and_then(|size| size.checked_add(SEEK_TABLE_FOOTER))
+            .ok_or(Error::UnexpectedEOF)?;
+        let table = bytes
+            .len()
+            .checked_sub(size + SKIPPABLE_HEADER)
+            .ok_or(Error::UnexpectedEOF)?;
+        if u32_at(bytes, table) != SKIPPABLE_MAGIC || u32_at(bytes, table + 4) as usize != size {
+            return Err(invalid(
+                "the seek table isn't a skippable frame of its size",
+            ));
+        }
+        let mut starts = vec![(0, 0)];
+        for index in 0..count {
+            let at = table + SKIPPABLE_HEADER + index * entry;
+            let (compressed, decompressed) = starts[index];
+            starts.push((
+                compressed + u32_at(bytes, at) as usize,
+                decompressed + u32_at(bytes, at + 4) as usize,
+            ));
+        }
+        if starts[count].0 != table {
+            return Err(invalid(
+                "the sizes of the frames don't add up to the data before it",
+            ));
+        }
+        Ok(Self {
+            bytes,
+            codec,
+            config: config.clone(),
+            starts,
+        })
+    }
+
+    /// The number of frames.
+    pub fn frames(&self) -> usize {
+        self.starts.len() - 1
+    }
+
+    /// The size of the decompressed container.
+    pub fn decompressed_len(&self) -> usize {
+        self.starts[self.frames()].1
+    }
+
+    /// The index of the frame that holds the byte at `offset` in the decompressed container,
+    /// found by binary search, or `None` if the container is shorter.
+    pub fn frame_at(&self, offset: usize) -> Option<usize> {
+        if offset >= self.decompressed_len() {
+            return None;
+        }
+        Some(
+            self.starts
+                .partition_point(|(_, decompressed)| *decompressed <= offset)
+                - 1,
+        )
+    }
+
+    /// Decompress the frame `index`: a container of whole records that a [`ContainerReader`]
+    /// reads.
+    pub fn frame(&self, index: usize) -> Result<Vec<u8>, Error> {
+        let (Some(start), Some(end)) = (self.starts.get(index), self.starts.get(index + 1)) else {
+            return Err(Error::UnexpectedEOF);
+        };
+        let size = end.1 - start.1;
+        let frame = self
+            .codec
+            .decompress(&self.bytes[start.0..end.0], size)
+            .map_err(|source| Error::Io {
+                source,
+                position: start.0,
+            })?;
+        if frame.len() != size {
+            return Err(Error::DeserializationError(format!(
+                "the frame {index} decompresses to {} bytes instead of {size}",
+                frame.len()
+            )));
+        }
+        Ok(frame)
+    }
+
+    /// The serialized record at `offset` in the decompressed container (as returned by
+    /// [`SeekableWriter::push`]). Only the frame of the record is decompressed; an offset that
+    /// isn't the start of a record gives garbage or an error.
+    pub fn record(&self, offset: usize) -> Result<Vec<u8>, Error> {
+        let index = self.frame_at(offset).ok_or(Error::UnexpectedEOF)?;
+        let mut frame = self.frame(index)?;
+        let start = offset - self.starts[index].1;
+        let length = frame
+            .get(start..start + 4)
+            .map(|length| u32_at(length, 0) as usize)
+            .ok_or(Error::UnexpectedEOF)?;
+        let end = (start + 4)
+            .checked_add(length)
+            .filter(|end| *end <= frame.len())
+            .ok_or(Error::UnexpectedEOF)?;
+        frame.truncate(end);
+        frame.drain(..start + 4);
+        Ok(frame)
+    }
+
+    /// Deserialize the record at `offset` (see [`SeekableReader::record`]) with the config the
+    /// container was written with.
+    pub fn decode<T: DeserializeOwned>(&self, offset: usize) -> Result<T, Error> {
+        deserializer::from_bytes_with_config(&self.record(offset)?, &self.config)
+    }
+}
+
+/// Builds a container whose records are compressed one by one with a zstd dictionary trained
+/// on a sample of them (see the [module](self) docs). The records are kept in memory until
+/// [`DictionaryWriter::into_bytes`] trains the dictionary and compresses them.
+///
+/// ```rust
+/// use rust_fr::{
+///     config::Config,
+///     container::{DictionaryReader, DictionaryWriter},
+/// };
+///
+/// #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+/// struct Event {
+///     service: String,
+///     level: String,
+///     id: u32,
+/// }
+///
+/// let config = Config::default();
+/// let mut writer = DictionaryWriter::new(&config, 3, 4096);
+/// for id in 0..2000 {
+///     let service = format!("service-{}", id % 7);
+///     writer.push(&Event { service, level: "info".to_string(), id }).unwrap();
+/// }
+/// let bytes = writer.into_bytes().unwrap();
+///
+/// let mut reader = DictionaryReader::new(&bytes, &config).unwrap();
+/// assert!(!reader.dictionary().is_empty());
+/// let first = reader.read::<Event>().unwrap().unwrap();
+/// assert_eq!(first.service, "service-0");
+/// assert_eq!(reader.count(), 1999);
+/// ```
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone)]
+pub struct DictionaryWriter {
+    config: Config,
+    level: i32,
+    dictionary_size: usize,
+    // The serialized records one after the other and the end of every record.
+    records: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+#[cfg(feature = "zstd")]
+impl DictionaryWriter {
+    /// Create an empty container whose records are serialized with the given config and
+    /// compressed at the zstd `level` with a dictionary of at most `dictionary_size` bytes.
+    pub fn new(config: &Config, level: i32, dictionary_size: usize) -> Self {
+        Self {
+            config: config.clone(),
+            level,
+            dictionary_size,
+            records: Vec::new(),
+            ends: Vec::new(),
+        }
+    }
+
+    /// Serialize `value` and append it to the container as the next record.
+    pub fn push<T: Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let record = serializer::to_bytes_with_config(value, &self.config)?;
+        if u32::try_from(record.len()).is_err() {
+            return Err(Error::SerializationError(
+                "the record is too large for a container".to_string(),
+            ));
+        }
+        self.records.extend(record);
+        self.ends.push(self.records.len());
+        Ok(())
+    }
+
+    /// Train the dictionary on a sample of the records and compress every record with it.
+    /// Returns the bytes of the container. Records too few or too small to train a dictionary
+    /// on are compressed without one (the dictionary is empty).
+    pub fn into_bytes(self) -> Result<Vec<u8>, Error> {
+        let records = self
+            .ends
+            .iter()
+            .scan(0, |start, &end| {
+                Some(&self.records[std::mem::replace(start, end)..end])
+            })
+            .collect::<Vec<_>>();
+        // zstd trains best on about 100 times as many bytes as the dictionary; every `step`th
+        // record is sampled so the sample spans the whole container.
+        let target = self.dictionary_size.saturating_mul(100).max(1);
+        let step = self.records.len().div_ceil(target).max(1);
+        let samples = records.iter().step_by(step).collect::<Vec<_>>();
+        let dictionary = match self.dictionary_size {
+            0 => Vec::new(),
+            size => zstd::dict::from_samples(&samples, size).unwrap_or_default(),
+        };
+
+        let failed = |source| Error::Io {
+            source,
+            position: 0,
+        };
+        let mut compressor =
+            zstd::bulk::Compressor::with_dictionary(self.level, &dictionary).map_err(failed)?;
+        let length = u32::try_from(dictionary.len()).map_err(|_| {
+            Error::SerializationError("the dictionary is too large for a container".to_string())
+        })?;
+        let mut bytes = length.to_le_bytes().to_vec();
+        bytes.extend(&dictionary);
+        for record in records {
+            let compressed = compressor.compress(record).map_err(|source| Error::Io {
+                source,
+                position: bytes.len(),
+            })?;
+            let length = u32::try_from(compressed.len()).map_err(|_| {
+                Error::SerializationError("the record is too large for a container".to_string())
+            })?;
+            bytes.extend(length.to_le_bytes());
+            bytes.extend(compressed);
+        }
+        Ok(bytes)
+    }
+}
+
+/// Reads the records of a container written by a [`DictionaryWriter`] in order, decompressing
+/// them one at a time with the dictionary stored in front of them. It is an iterator over the
+/// decompressed records.
+#[cfg(feature = "zstd")]
+pub struct DictionaryReader<'a> {
+    dictionary: &'a [u8],
+    records: ContainerReader<'a>,
+    decompressor: zstd::bulk::Decompressor<'static>,
+    // The position of the compressed records in the data.
+    start: usize,
+}
+
+#[cfg(feature = "zstd")]
+impl<'a> DictionaryReader<'a> {
+    /// Read the dictionary of the container in `bytes` whose records were serialized with the
+    /// given config.
+    pub fn new(bytes: &'a [u8], config: &Config) -> Result<Self, Error> {
+        let (length, rest) = bytes.split_first_chunk::<4>().ok_or(Error::UnexpectedEOF)?;
+        let (dictionary, records) = rest
+            .split_at_checked(u32::from_le_bytes(*length) as usize)
+            .ok_or(Error::UnexpectedEOF)?;
+        let decompressor =
+            zstd::bulk::Decompressor::with_dictionary(dictionary).map_err(|source| Error::Io {
+                source,
+                position: 4,
+            })?;
+        Ok(Self {
+            dictionary,
+            records: ContainerReader::new(records, config),
+            decompressor,
+            start: 4 + dictionary.len(),
+        })
+    }
+
+    /// The dictionary the records are compressed with; empty if they are compressed without
+    /// one.
+    pub fn dictionary(&self) -> &'a [u8] {
+        self.dictionary
+    }
+
+    /// Decompress and deserialize the next record, or `None` at the end of the container.
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Error> {
+        let config = self.records.config.clone();
+        self.next()
+            .map(|record| deserializer::from_bytes_with_config(&record?, &config))
+            .transpose()
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Iterator for DictionaryReader<'_> {
+    type Item = Result<Vec<u8>, Error>;
+
+    /// Grab the next compressed record and decompress it to the size in its zstd frame header
+    /// (records are at most `u32::MAX` bytes).
+    fn next(&mut self) -> Option<Self::Item> {
+        let position = self.start + self.records.position;
+        let record = match self.records.next()? {
+            Ok(record) => record,
+            Err(error) => return Some(Err(error)),
+        };
+        let size = match zstd::zstd_safe::get_frame_content_size(record.bytes) {
+            Ok(Some(size)) if size <= u64::from(u32::MAX) => size as usize,
+            _ => {
+                return Some(Err(Error::DeserializationError(format!(
+                    "the record {} isn't a zstd frame with the size of a record",
+                    record.index
+                ))))
+            }
+        };
+        let decompressed = self
+            .decompressor
+            .decompress(record.bytes, size)
+            .map_err(|source| Error::Io { source, position });
+        Some(decompressed)
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl std::fmt::Debug for DictionaryReader<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("DictionaryReader")
+            .field("dictionary", &self.dictionary.len())
+            .field("records", &self.records)
+            .finish_non_exhaustive()
+    }
+}
+
+// The little endian u32 at `at`, which must be in `bytes`.
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
@@ -5,41 +5,960 @@
 //! To use the deserializer, you need to call the [`from_bytes`] function which takes in
 //! the bytes and a type. The type must implement the `Deserialize` trait from the serde library.
 //! It returns a Result with the deserialized data or an error.
+//!
+//! ### Malformed input
+//! Decoding never panics on malformed input, whether it is truncated, corrupted or crafted:
+//! every read is bounds checked (no indexing or `unwrap` on the data) and a bad payload is
+//! reported as an [`Error`]. Tests feed every truncation and every single bit flip of
+//! payloads in several configs through the decoders to keep it that way. Deeply nested data
+//! still takes stack space for every level (see [`Cancellation`] to bound the time spent).
+//!
+//! ### Borrowing
+//! Strings and bytes are aligned to byte boundaries in the format so they can be borrowed
+//! straight from the input instead of being copied. Types holding `&'de str` or `&'de [u8]`
+//! (marked with `#[serde(borrow)]` where serde needs it) work anywhere in the data, e.g. in
+//! maps or enum variants.
+//!
+//! ```rust
+//! use rust_fr::{deserializer, serializer};
+//! use std::collections::HashMap;
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! enum Body<'a> {
+//!     Text(&'a str),
+//!     Empty,
+//! }
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! struct Message<'a> {
+//!     #[serde(borrow)]
+//!     headers: HashMap<&'a str, &'a str>,
+//!     #[serde(borrow)]
+//!     body: Body<'a>,
+//! }
+//!
+//! let message = Message {
+//!     headers: [("from", "ayush"), ("to", "you")].into_iter().collect(),
+//!     body: Body::Text("hello!"),
+//! };
+//! let bytes = serializer::to_bytes(&message).unwrap();
+//!
+//! // `decoded` borrows all of its strings from `bytes`.
+//! let decoded = deserializer::from_bytes::<Message>(&bytes).unwrap();
+//! assert_eq!(message, decoded);
+//! ```
+//!
+//! ### Smart pointers
+//! `Box<T>`, `Cow<'_, T>` as well as `Rc<T>` and `Arc<T>` (with the `rc` feature of serde)
+//! are written exactly like `T`, without a single extra bit, so they can be swapped for one
+//! another (or for `T`) without changing the data. Shared pointers are written once for every
+//! time they're pointed to and aren't shared anymore once decoded. A `Cow<'a, str>` marked
+//! with `#[serde(borrow)]` borrows from the input like a `&'a str`; without the attribute serde
+//! always decodes it into an owned value.
+//!
+//! ```rust
+//! use rust_fr::{deserializer, serializer};
+//! use std::borrow::Cow;
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! struct Label<'a> {
+//!     #[serde(borrow)]
+//!     text: Cow<'a, str>,
+//!     parent: Option<Box<Label<'a>>>,
+//! }
+//!
+//! let label = Label {
+//!     text: "child".into(),
+//!     parent: Some(Box::new(Label { text: "root".into(), parent: None })),
+//! };
+//! let bytes = serializer::to_bytes(&label).unwrap();
+//! let decoded = deserializer::from_bytes::<Label>(&bytes).unwrap();
+//! assert!(matches!(decoded.text, Cow::Borrowed("child")));
+//! assert_eq!(decoded, label);
+//! ```
+
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    hash::Hash,
+    io::{ErrorKind, Read},
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use bitvec::{prelude as bv, slice::BitSlice, view::BitView};
+use bitvec::{domain::Domain, prelude as bv, slice::BitSlice, view::BitView};
 use serde::{
-    de::{EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess},
+    de::{
+        value::{
+            BorrowedBytesDeserializer, BorrowedStrDeserializer, BytesDeserializer, SeqDeserializer,
+            StringDeserializer,
+        },
+        DeserializeOwned, DeserializeSeed, EnumAccess, IgnoredAny, IntoDeserializer, MapAccess,
+        SeqAccess, VariantAccess,
+    },
     Deserialize, Deserializer,
 };
 
-use super::{error::Error, serializer::Delimiter};
+use super::{
+    columnar::{self, RowsDeserializer, Table},
+    config::{Config, StringFraming, StructKeys, UnknownVariant},
+    encoders::Encoders,
+    error::{Container, Error, PathSegment},
+    protocol::format::{
+        Delimiter, Production, TypeTag, WireOrder, ADAPTIVE_WIDTH_BITS, STRING_ESCAPE,
+        VARIANT_INDEX_ESCAPE,
+    },
+    raw,
+};
 
 // Internal struct that handles the deserialization of the data.
 // It has a few methods that allows us to peek and eat bytes from the data.
-// It also has methods to parse some data into the required type.
+// It also has methods to parse some data into the required type. The bits are in the order
+// `O` (see `Config::msb_first`).
 #[derive(Debug)]
-struct CustomDeserializer<'de> {
-    data: &'de bv::BitSlice<u8, bv::Lsb0>,
+pub(crate) struct CustomDeserializer<'de, O: WireOrder = bv::Lsb0> {
+    pub(crate) data: &'de bv::BitSlice<u8, O>,
+    pub(crate) config: Config,
+    cancellation: Option<Cancellation>,
+    steps: usize,
+    // The bit (in its byte) the data starts at. Padding is relative to it.
+    origin: usize,
+    // The position (as the number of bits left) of a struct field written without its
+    // presence bit and whether it is present; see `Config::option_bitmaps`. Only an option
+    // right at that position takes it: every other value clears it, so e.g. a tuple starting
+    // with an option doesn't hand it down to its first element.
+    implied: Option<(usize, bool)>,
+    // The custom encodings of newtype structs, if any.
+    encoders: Option<Encoders>,
+    // How many containers, options, newtypes and enums the value being read is nested in.
+    depth: usize,
+    // Whether the key of a map is being read and its strings are handed out as bytes; see
+    // `Config::raw_str_keys`.
+    raw_key: bool,
+    // The containers being read, innermost last. The tokens read next belong to the innermost
+    // one, which errors about them name along with its depth.
+    containers: Vec<Container>,
+}
+
+/// The number of decoding steps (elements, keys & string bytes) between two checks
+/// of the [`Cancellation`] token.
+const CANCELLATION_CHECK_INTERVAL: usize = 1024;
+
+/// A token that can stop a running deserialization. It is cheap to clone and all
+/// the clones share the same state, so one clone can be handed to the thread doing the
+/// decoding while another one is kept around to call [`Cancellation::cancel`].
+/// An optional deadline can be attached after which the token counts as cancelled.
+///
+/// The token is checked periodically while decoding; once it trips the deserializer
+/// returns [`Error::Cancelled`].
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl Cancellation {
+    /// Create a token that is only tripped by calling [`Cancellation::cancel`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a token that trips once `deadline` has passed.
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self {
+            cancelled: Arc::default(),
+            deadline: Some(deadline),
+        }
+    }
+
+    /// Create a token that trips once `timeout` has elapsed from now.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self::with_deadline(Instant::now() + timeout)
+    }
+
+    /// Cancel every deserialization observing this token (or one of its clones).
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the token was cancelled or its deadline has passed.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// A reader over an encoded struct (or a map with string keys) that decodes single fields on
+/// demand. Creating it walks over the fields once, skipping their values without decoding them,
+/// so fetching one or two fields of a large record doesn't pay for the rest. Skipping values
+/// needs the self-describing mode (see [`Config::self_describing`]) unless the data has offset
+/// tables (see [`Config::offset_tables`]) to jump from field to field. Structs written
+/// without their field names (see [`StructKeys::Omit`]) can't be read by it; maps with
+/// string keys still can.
+///
+/// ```rust
+/// use rust_fr::{config::Config, deserializer::LazyStruct, serializer};
+///
+/// #[derive(serde::Serialize)]
+/// struct Record {
+///     id: u32,
+///     name: String,
+///     history: Vec<String>,
+/// }
+///
+/// let config = Config {
+///     self_describing: true,
+///     ..Config::default()
+/// };
+/// let record = Record {
+///     id: 7,
+///     name: "ayush".to_string(),
+///     history: vec!["a long history".to_string(); 100],
+/// };
+/// let bytes = serializer::to_bytes_with_config(&record, &config).unwrap();
+///
+/// let lazy = LazyStruct::new(&bytes, &config).unwrap();
+/// assert_eq!(lazy.get::<&str>("name").unwrap(), Some("ayush"));
+/// assert_eq!(lazy.get::<u32>("id").unwrap(), Some(7));
+/// assert_eq!(lazy.get::<u32>("age").unwrap(), None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LazyStruct<'de> {
+    config: Config,
+    // The keys of the fields along with the bits of their (encoded) values.
+    fields: Vec<LazyField<'de>>,
+}
+
+type LazyField<'de, O = bv::Lsb0> = (Cow<'de, str>, &'de BitSlice<u8, O>);
+
+impl<'de> LazyStruct<'de> {
+    /// Walk over the fields of the struct in `bytes` which was serialized with `config`.
+    pub fn new(bytes: &'de [u8], config: &Config) -> Result<Self, Error> {
+        if config.msb_first {
+            return Err(Error::UnsupportedCall(
+                "LazyStruct::new with `Config::msb_first`".to_string(),
+            ));
+        }
+        let mut deserializer = CustomDeserializer::new(bytes, config.clone(), None);
+        deserializer.expect_header()?;
+        deserializer.expect_tag(TypeTag::Map)?;
+        deserializer.open(Container::Map);
+        let fields = match (config.offset_tables, config.self_describing) {
+            (true, _) => deserializer.parse_lazy_fields_from_table()?,
+            (false, true) => deserializer.parse_lazy_fields()?,
+            (false, false) => return Err(Error::SelfDescribingRequired("LazyStruct::new")),
+        };
+        Ok(Self {
+            config: config.clone(),
+            fields,
+        })
+    }
+
+    /// Decode the value of the field with the given key or `None` if there is no such field.
+    /// With option bitmaps (see [`Config::option_bitmaps`]) fields that are `None` aren't
+    /// there either.
+    pub fn get<T>(&self, key: &str) -> Result<Option<T>, Error>
+    where
+        T: Deserialize<'de>,
+    {
+        let Some((_, value)) = self.fields.iter().find(|(field, _)| field == key) else {
+            return Ok(None);
+        };
+        let mut deserializer = CustomDeserializer::new(&[], self.config.clone(), None);
+        deserializer.data = value;
+        if self.config.option_bitmaps {
+            deserializer.implied = Some((value.len(), true));
+        }
+        T::deserialize(&mut deserializer).map(Some)
+    }
+
+    /// The keys of the fields in the order they were serialized.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.fields.iter().map(|(key, _)| key.as_ref())
+    }
+
+    /// The number of fields.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Whether there are no fields.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+/// A reader over an encoded map with sorted integer keys, e.g. a `BTreeMap<u32, V>` (see
+/// [`Config::sorted_int_keys`]), that looks up single entries by binary searching the keys and
+/// decodes only the value it finds.
+/// The values are reached through the offset table (see [`Config::offset_tables`]) or, in the
+/// self-describing mode, by skipping over them once when the reader is created.
+///
+/// ```rust
+/// use rust_fr::{config::Config, deserializer::LazyMap, serializer};
+/// use std::collections::BTreeMap;
+///
+/// let config = Config {
+///     sorted_int_keys: true,
+///     offset_tables: true,
+///     ..Config::default()
+/// };
+/// let names = (0..100u32)
+///     .map(|id| (id * 2, format!("user {id}")))
+///     .collect::<BTreeMap<_, _>>();
+/// let bytes = serializer::to_bytes_with_config(&names, &config).unwrap();
+///
+/// let lazy = LazyMap::new(&bytes, &config).unwrap();
+/// assert_eq!(lazy.get::<&str>(84).unwrap(), Some("user 42"));
+/// assert_eq!(lazy.get::<&str>(85).unwrap(), None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LazyMap<'de> {
+    config: Config,
+    keys: SortedKeys<'de>,
+    // The bits of the (encoded) values.
+    values: Vec<&'de BitSlice<u8, bv::Lsb0>>,
+}
+
+impl<'de> LazyMap<'de> {
+    /// Walk over the map in `bytes` which was serialized with `config`.
+    pub fn new(bytes: &'de [u8], config: &Config) -> Result<Self, Error> {
+        if config.msb_first {
+            return Err(Error::UnsupportedCall(
+                "LazyMap::new with `Config::msb_first`".to_string(),
+            ));
+        }
+        let mut deserializer = CustomDeserializer::new(bytes, config.clone(), None);
+        deserializer.expect_header()?;
+        deserializer.expect_tag(TypeTag::Map)?;
+        deserializer.open(Container::Map);
+        let table = match config.offset_tables {
+            true => Some(deserializer.parse_offset_table()?),
+            false => None,
+        };
+        let keys = match config.sorted_int_keys {
+            true => deserializer.parse_sorted_keys()?,
+            false => None,
+        };
+        let keys = match keys {
+            Some(keys) if keys.sorted => keys,
+            // an empty map never has a block of keys.
+            None if deserializer.peek_token(Production::Map.last())? => SortedKeys {
+                keys: BitSlice::empty(),
+                size: 1,
+                sorted: true,
+            },
+            _ => {
+                return Err(Error::DeserializationError(
+                    "expected a map with sorted integer keys (see `Config::sorted_int_keys`)"
+                        .to_string(),
+                ))
+            }
+        };
+        let values = match (table, config.self_describing) {
+            (Some(table), _) => (0..keys.len())
+                .map(|index| {
+                    let entry = table.entry(index)?;
+                    let value = entry.get(Production::Map.item(1).width()..);
+                    value.ok_or(Error::UnexpectedEOF)
+                })
+                .collect::<Result<Vec<_>, Error>>()?,
+            (None, true) => deserializer.parse_lazy_values(keys.len())?,
+            (None, false) => return Err(Error::SelfDescribingRequired("LazyMap::new")),
+        };
+        Ok(Self {
+            config: config.clone(),
+            keys,
+            values,
+        })
+    }
+
+    /// Decode the value of the entry with the given key or `None` if there is no such entry.
+    /// The keys are binary searched.
+    pub fn get<V>(&self, key: u64) -> Result<Option<V>, Error>
+    where
+        V: Deserialize<'de>,
+    {
+        let (mut low, mut high) = (0, self.values.len());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.keys.key(middle)?.cmp(&key) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => {
+                    let value = *self.values.get(middle).ok_or(Error::UnexpectedEOF)?;
+                    return self.decode(value).map(Some);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// The number of entries.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn decode<T>(&self, data: &'de BitSlice<u8, bv::Lsb0>) -> Result<T, Error>
+    where
+        T: Deserialize<'de>,
+    {
+        let mut deserializer = CustomDeserializer::new(&[], self.config.clone(), None);
+        deserializer.data = data;
+        T::deserialize(&mut deserializer)
+    }
+}
+
+/// The indices of the entries of the map in `bytes` (serialized with `config`) whose key is
+/// the key of an earlier entry, e.g. to reject data that a map would silently collapse. The
+/// keys are compared by their bits without being decoded, so they need their lengths in front
+/// of them (see [`Config::length_prefixed_keys`]) unless they are a block of integer keys
+/// (see [`Config::sorted_int_keys`]). The values are jumped over with an offset table or
+/// skipped in the self-describing mode. Floats are compared by their bits too, so `0.0` and
+/// `-0.0` are different keys.
+///
+/// ```rust
+/// use rust_fr::{config::Config, deserializer, serializer};
+///
+/// // e.g. a map written by another implementation.
+/// struct Pairs(Vec<(&'static str, u32)>);
+///
+/// impl serde::Serialize for Pairs {
+///     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+///         serializer.collect_map(self.0.iter().map(|(key, value)| (key, value)))
+///     }
+/// }
+///
+/// let config = Config {
+///     length_prefixed_keys: true,
+///     offset_tables: true,
+///     ..Config::default()
+/// };
+/// let pairs = Pairs(vec![("a", 1), ("b", 2), ("a", 3)]);
+/// let bytes = serializer::to_bytes_with_config(&pairs, &config).unwrap();
+/// assert_eq!(deserializer::duplicate_keys(&bytes, &config).unwrap(), [2]);
+/// ```
+pub fn duplicate_keys(bytes: &[u8], config: &Config) -> Result<Vec<usize>, Error> {
+    if config.msb_first {
+        return Err(Error::UnsupportedCall(
+            "duplicate_keys with `Config::msb_first`".to_string(),
+        ));
+    }
+    let mut deserializer = CustomDeserializer::new(bytes, config.clone(), None);
+    deserializer.expect_header()?;
+    deserializer.expect_tag(TypeTag::Map)?;
+    deserializer.open(Container::Map);
+    let table = match config.offset_tables {
+        true => Some(deserializer.parse_offset_table()?),
+        false => None,
+    };
+    let block = match config.sorted_int_keys {
+        true => deserializer.parse_sorted_keys()?,
+        false => None,
+    };
+    if let Some(block) = block {
+        return repeated((0..block.len()).map(|index| block.key(index)));
+    }
+    let unprefixed = || {
+        Error::UnsupportedCall("duplicate_keys without `Config::length_prefixed_keys`".to_string())
+    };
+    let mut keys = Vec::new();
+    match (table, config.self_describing) {
+        (Some(table), _) => {
+            for index in 0..table.len() {
+                deserializer.data = table.entry(index)?;
+                let key = deserializer.expect_key(index)?;
+                keys.push(key.ok_or_else(unprefixed)?);
+            }
+        }
+        (None, true) => {
+            while !deserializer.peek_token(Production::Map.last())? {
+                let index = keys.len();
+                let key = deserializer.expect_key(index)?;
+                let key = key.ok_or_else(unprefixed)?;
+                deserializer.eat_bits(key.len())?;
+                deserializer.expect_token(Production::Map.item(1), index)?;
+                deserializer.skip_value()?;
+                keys.push(key);
+            }
+        }
+        (None, false) => return Err(Error::SelfDescribingRequired("duplicate_keys")),
+    }
+    repeated(keys.into_iter().map(Ok))
+}
+
+// The indices of the keys that are the same as an earlier one.
+fn repeated<K: Eq + Hash>(
+    keys: impl Iterator<Item = Result<K, Error>>,
+) -> Result<Vec<usize>, Error> {
+    let mut seen = HashSet::new();
+    let mut repeated = Vec::new();
+    for (index, key) in keys.enumerate() {
+        if !seen.insert(key?) {
+            repeated.push(index);
+        }
+    }
+    Ok(repeated)
+}
+
+/// Reads the entries of a map from an `io::Read` one at a time, e.g. a key-value dump written
+/// by a [`MapWriter`](crate::serializer::MapWriter) that is too large to be held in memory.
+/// Only the bytes of the entry being decoded (and of the last read) are kept.
+///
+/// The entries have no lengths, so an entry is decoded from the bytes read so far and decoded
+/// again with more bytes if they end before it does; the reads grow with the entry so large
+/// entries take few tries. A map that ends before its end, i.e. one that was cut off, fails
+/// with the error of its last entry (e.g. [`Error::UnexpectedEOF`]). Maps with an offset
+/// table or a block of integer keys in front (see [`Config::offset_tables`] and
+/// [`Config::sorted_int_keys`]) can't be read this way.
+///
+/// ```rust
+/// use rust_fr::{config::Config, deserializer::MapReader, serializer};
+/// use std::collections::BTreeMap;
+///
+/// let map = (0..100u16).map(|i| (i.to_string(), vec![i; 3])).collect::<BTreeMap<_, _>>();
+/// let bytes = serializer::to_bytes(&map).unwrap();
+///
+/// let reader = MapReader::<_, String, Vec<u16>>::new(&bytes[..], &Config::default());
+/// let mut count = 0;
+/// for entry in reader {
+///     let (key, value) = entry.unwrap();
+///     assert_eq!(map[&key], value);
+///     count += 1;
+/// }
+/// assert_eq!(count, 100);
+/// ```
+#[derive(Debug)]
+pub struct MapReader<R: Read, K, V> {
+    reader: R,
+    config: Config,
+    buffer: Vec<u8>,
+    // The bits at the start of the buffer that have been decoded already (fewer than 8).
+    bit: usize,
+    // The number of bytes read so far.
+    position: usize,
+    started: bool,
+    // Whether the reader has no more bytes.
+    exhausted: bool,
+    finished: bool,
+    entries: usize,
+    entry: PhantomData<fn() -> (K, V)>,
+}
+
+// The number of bytes read at least at once.
+const READ_SIZE: usize = 8 * 1024;
+
+impl<R: Read, K: DeserializeOwned, V: DeserializeOwned> MapReader<R, K, V> {
+    /// Read the map in `reader` which was serialized with `config`. Nothing is read until the
+    /// first entry is asked for.
+    pub fn new(reader: R, config: &Config) -> Self {
+        Self {
+            reader,
+            config: config.clone(),
+            buffer: Vec::new(),
+            bit: 0,
+            position: 0,
+            started: false,
+            exhausted: false,
+            finished: false,
+            entries: 0,
+            entry: PhantomData,
+        }
+    }
+
+    /// Decode the next entry (or the end of the map) from the bytes read so far.
+    /// - Read the header and the start of the map (once).
+    /// - If at end of map; exit.
+    /// - Read the key and the value of the entry.
+    fn decode(&mut self) -> Result<Option<(K, V)>, Error> {
+        let mut deserializer = CustomDeserializer::new(&self.buffer, self.config.clone(), None);
+        deserializer.data = deserializer
+            .data
+            .get(self.bit..)
+            .ok_or(Error::UnexpectedEOF)?;
+        if !self.started {
+            if self.config.msb_first || self.config.offset_tables {
+                return Err(Error::UnsupportedCall(
+                    "MapReader with `Config::msb_first` or `Config::offset_tables`".to_string(),
+                ));
+            }
+            deserializer.expect_header()?;
+            deserializer.expect_tag(TypeTag::Map)?;
+            if self.config.sorted_int_keys && deserializer.eat_bit()? {
+                return Err(Error::UnsupportedCall(
+                    "MapReader with a block of integer keys".to_string(),
+                ));
+            }
+        }
+        // every call reads an entry of the same map.
+        deserializer.open(Container::Map);
+        let entry = match deserializer.peek_token(Production::Map.last())? {
+            true => {
+                deserializer.eat_token(Production::Map.last())?;
+                None
+            }
+            false => {
+                let index = self.entries;
+                if let Some(max) = self.config.max_map_entries.filter(|max| index >= *max) {
+                    return Err(Error::TooManyMapEntries { max, path: vec![] });
+                }
+                let prefixed = deserializer.expect_key(index)?;
+                let start = deserializer.data;
+                let key = deserializer.deserialize_key(PhantomData::<K>)?;
+                deserializer.check_key(start, prefixed, index)?;
+                deserializer.expect_token(Production::Map.item(1), index)?;
+                let value = V::deserialize(&mut deserializer)
+                    .map_err(|error| error.nested(PathSegment::Entry(index)))?;
+                self.entries += 1;
+                Some((key, value))
+            }
+        };
+        let consumed = self.buffer.len() * 8 - deserializer.data.len();
+        self.buffer.drain(..consumed / 8);
+        self.bit = consumed % 8;
+        self.started = true;
+        Ok(entry)
+    }
+
+    /// Read more bytes, at least as many as are buffered so an entry is decoded a few times at
+    /// most.
+    fn fill(&mut self) -> Result<(), Error> {
+        let start = self.buffer.len();
+        self.buffer.resize(start + start.max(READ_SIZE), 0);
+        let read = loop {
+            match self.reader.read(&mut self.buffer[start..]) {
+                Ok(read) => break read,
+                Err(source) if source.kind() == ErrorKind::Interrupted => {}
+                Err(source) => {
+                    self.buffer.truncate(start);
+                    return Err(Error::Io {
+                        source,
+                        position: self.position,
+                    });
+                }
+            }
+        };
+        self.buffer.truncate(start + read);
+        self.position += read;
+        self.exhausted = read == 0;
+        Ok(())
+    }
+}
+
+impl<R: Read, K: DeserializeOwned, V: DeserializeOwned> Iterator for MapReader<R, K, V> {
+    type Item = Result<(K, V), Error>;
+
+    /// Decode the next entry, reading more bytes while the ones read so far end before it.
+    /// An entry the reader ends in fails with [`Error::UnexpectedEOF`]. The first error ends
+    /// the iteration.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        loop {
+            let result = match self.decode() {
+                Err(error) if is_cut_off(&error) && !self.exhausted => match self.fill() {
+                    Ok(()) => continue,
+                    Err(error) => Err(error),
+                },
+                // the reader has nothing more for the entry.
+                Err(error) if is_cut_off(&error) => Err(Error::UnexpectedEOF),
+                result => result,
+            };
+            self.finished = !matches!(result, Ok(Some(_)));
+            return result.transpose();
+        }
+    }
+}
+
+// Whether the error may come from the data ending early, so more of it may decode.
+fn is_cut_off(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::UnexpectedEOF
+            | Error::NoBit
+            | Error::NoByte
+            | Error::NLargerThanLength(..)
+            | Error::LengthOutOfBounds { .. }
+            | Error::ExpectedDelimiter { found: None, .. }
+    )
 }
 
 /// The function to deserialize (serialized) bytes back into data. `T` must implement the `Deserialize` trait
 /// from the `serde` library. `bytes` is the data to be deserialized. It returns a Result with the deserialized
-/// data or an error.
+/// data or an error. The data is decoded according to the [`Config`] in its header, whichever
+/// it is.
 pub fn from_bytes<'de, T>(bytes: &'de [u8]) -> Result<T, Error>
 where
     T: Deserialize<'de>,
 {
-    let mut deserializer = CustomDeserializer {
-        data: bytes.view_bits(),
-    };
-    let deserialized = T::deserialize(&mut deserializer)?;
-    Ok(deserialized)
+    CustomDeserializer::new(bytes, Config::default(), None).deserialize_root(PhantomData, true)
+}
+
+/// The same as [`from_bytes`] but the data is decoded according to the given [`Config`] which
+/// must be the one the data was serialized with. Otherwise it fails with
+/// [`Error::ConfigMismatch`].
+pub fn from_bytes_with_config<'de, T>(bytes: &'de [u8], config: &Config) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+{
+    CustomDeserializer::new(bytes, config.clone(), None).deserialize_root(PhantomData, false)
+}
+
+/// The same as [`from_bytes`] but the data is deserialized with a `DeserializeSeed`, so state
+/// can be threaded into the decoding: e.g. an interner or an arena the values are put in, or
+/// one of the seeds from the [`seed`](crate::seed) module that reuses an existing collection.
+pub fn from_bytes_seed<'de, S>(bytes: &'de [u8], seed: S) -> Result<S::Value, Error>
+where
+    S: DeserializeSeed<'de>,
+{
+    CustomDeserializer::new(bytes, Config::default(), None).deserialize_root(seed, true)
+}
+
+/// The same as [`from_bytes_seed`] but the data is decoded according to the given [`Config`]
+/// (see [`from_bytes_with_config`]).
+pub fn from_bytes_seed_with_config<'de, S>(
+    bytes: &'de [u8],
+    config: &Config,
+    seed: S,
+) -> Result<S::Value, Error>
+where
+    S: DeserializeSeed<'de>,
+{
+    CustomDeserializer::new(bytes, config.clone(), None).deserialize_root(seed, false)
+}
+
+/// The same as [`from_bytes`] but the data is read from a bit slice, e.g. one returned by
+/// [`to_bitvec`](crate::serializer::to_bitvec) or a part of a larger bit-level protocol. The
+/// data may start at any bit; strings and bytes are only borrowed if it starts on a byte
+/// boundary.
+pub fn from_bitslice<'de, T>(bits: &'de BitSlice<u8, bv::Lsb0>) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+{
+    CustomDeserializer::from_bits(bits, Config::default()).deserialize_root(PhantomData, true)
+}
+
+/// The same as [`from_bitslice`] but the data is decoded according to the given [`Config`].
+pub fn from_bitslice_with_config<'de, T>(
+    bits: &'de BitSlice<u8, bv::Lsb0>,
+    config: &Config,
+) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+{
+    CustomDeserializer::from_bits(bits, config.clone()).deserialize_root(PhantomData, false)
+}
+
+/// The same as [`from_bytes`] but the deserialization observes the given [`Cancellation`]
+/// token and bails out with [`Error::Cancelled`] once it trips. Use this to bound the time spent
+/// decoding untrusted payloads on latency-critical threads.
+pub fn from_bytes_with_cancellation<'de, T>(
+    bytes: &'de [u8],
+    cancellation: &Cancellation,
+) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+{
+    CustomDeserializer::new(bytes, Config::default(), Some(cancellation.clone()))
+        .deserialize_root(PhantomData, true)
+}
+
+/// The same as [`from_bytes_with_config`] but newtype structs with a custom encoding in
+/// `encoders` are read with it. The bytes must have been serialized with the same encoders (see
+/// [`to_bytes_with_encoders`](crate::serializer::to_bytes_with_encoders)).
+pub fn from_bytes_with_encoders<'de, T>(
+    bytes: &'de [u8],
+    config: &Config,
+    encoders: &Encoders,
+) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+{
+    let mut deserializer = CustomDeserializer::new(bytes, config.clone(), None);
+    deserializer.encoders = Some(encoders.clone());
+    deserializer.deserialize_root(PhantomData, false)
 }
 
 impl<'de> CustomDeserializer<'de> {
+    pub(crate) fn new(
+        bytes: &'de [u8],
+        config: Config,
+        cancellation: Option<Cancellation>,
+    ) -> Self {
+        Self {
+            data: bytes.view_bits(),
+            config,
+            cancellation,
+            steps: 0,
+            origin: 0,
+            implied: None,
+            encoders: None,
+            depth: 0,
+            raw_key: false,
+            containers: Vec::new(),
+        }
+    }
+
+    fn from_bits(bits: &'de BitSlice<u8, bv::Lsb0>, config: Config) -> Self {
+        Self {
+            data: bits,
+            config,
+            cancellation: None,
+            steps: 0,
+            origin: bits.as_bitptr().bit().into_inner() as usize,
+            implied: None,
+            encoders: None,
+            depth: 0,
+            raw_key: false,
+            containers: Vec::new(),
+        }
+    }
+
+    /// Read the header (adopting the config it describes or checking it against the config of
+    /// the deserializer) and then the value with `seed`, in the bit order of the config.
+    fn deserialize_root<S>(self, seed: S, adopt: bool) -> Result<S::Value, Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        #[cfg(feature = "metrics")]
+        let (started, bytes) = (std::time::Instant::now(), self.data.len().div_ceil(8));
+        let result = self.deserialize_ordered(seed, adopt);
+        #[cfg(feature = "metrics")]
+        crate::metrics::decoded(&result, bytes, started);
+        result
+    }
+
+    /// Read the header and then the value with `seed` (see
+    /// [`CustomDeserializer::deserialize_root`]).
+    fn deserialize_ordered<S>(mut self, seed: S, adopt: bool) -> Result<S::Value, Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        match adopt {
+            true => self.adopt_header()?,
+            false => self.expect_header()?,
+        }
+        match self.config.msb_first {
+            true => seed.deserialize(&mut self.reorder::<bv::Msb0>()?),
+            false => seed.deserialize(&mut self),
+        }
+    }
+
+    /// The same deserializer with the rest of the data in the bit order `P`. The header is
+    /// made of whole bytes so it reads the same in both orders; the data must start on a
+    /// byte boundary.
+    fn reorder<P: WireOrder>(self) -> Result<CustomDeserializer<'de, P>, Error> {
+        let bytes: &'de [u8] = match self.data.domain() {
+            Domain::Region {
+                head: None, body, ..
+            } => body,
+            _ => {
+                return Err(Error::UnsupportedCall(
+                    "`Config::msb_first` with data that doesn't start on a byte boundary"
+                        .to_string(),
+                ))
+            }
+        };
+        Ok(CustomDeserializer {
+            data: bytes
+                .view_bits::<P>()
+                .get(..self.data.len())
+                .ok_or(Error::UnexpectedEOF)?,
+            config: self.config,
+            cancellation: self.cancellation,
+            steps: self.steps,
+            origin: 0,
+            implied: None,
+            encoders: self.encoders,
+            depth: self.depth,
+            raw_key: self.raw_key,
+            containers: self.containers,
+        })
+    }
+}
+
+impl<'de, O: WireOrder> CustomDeserializer<'de, O> {
+    /// Read the header and check that the data was serialized with the config of the
+    /// deserializer (apart from the options that aren't in the header).
+    pub(crate) fn expect_header(&mut self) -> Result<(), Error> {
+        let found = Config::from_header(self.parse_unsigned::<u16>()?)?;
+        if found.header() != self.config.header() {
+            return Err(Error::ConfigMismatch {
+                expected: self.config.clone(),
+                found,
+            });
+        }
+        Ok(())
+    }
+
+    /// Read the header and decode the data according to the config it describes, keeping the
+    /// reading options of the deserializer (the limits and the handling of unknown variants and
+    /// string keys).
+    fn adopt_header(&mut self) -> Result<(), Error> {
+        self.config = Config {
+            max_map_entries: self.config.max_map_entries,
+            max_depth: self.config.max_depth,
+            on_unknown_variant: self.config.on_unknown_variant,
+            raw_str_keys: self.config.raw_str_keys,
+            ..Config::from_header(self.parse_unsigned::<u16>()?)?
+        };
+        Ok(())
+    }
+
+    /// Read a value nested one level deeper with `read`, failing with [`Error::TooDeep`] past
+    /// [`Config::max_depth`].
+    fn nested<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        if let Some(max) = self.config.max_depth {
+            if self.depth >= max {
+                return Err(Error::TooDeep { max });
+            }
+        }
+        self.depth += 1;
+        let value = read(self);
+        self.depth -= 1;
+        value
+    }
+
+    /// Read the key of a map with `seed`, handing its strings out as bytes if the config says
+    /// so (see [`Config::raw_str_keys`]).
+    fn deserialize_key<K>(&mut self, seed: K) -> Result<K::Value, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let outer = std::mem::replace(&mut self.raw_key, self.config.raw_str_keys);
+        let key = seed.deserialize(&mut *self);
+        self.raw_key = outer;
+        key
+    }
+
+    /// Count a decoding step and check the cancellation token every
+    /// `CANCELLATION_CHECK_INTERVAL` steps.
+    pub(crate) fn tick(&mut self) -> Result<(), Error> {
+        let Some(cancellation) = &self.cancellation else {
+            return Ok(());
+        };
+        self.steps = self.steps.wrapping_add(1);
+        if self.steps.is_multiple_of(CANCELLATION_CHECK_INTERVAL) && cancellation.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        Ok(())
+    }
+
     /// Get 'n' bits from end of the data.
     /// Example: If the data is 0b10101010 and n is 3, the result will be 0b010.
-    fn _peek_n_bits(&self, size: usize) -> Result<&BitSlice<u8>, Error> {
+    fn _peek_n_bits(&self, size: usize) -> Result<&BitSlice<u8, O>, Error> {
         let len = self.data.len();
         if size > len {
             return Err(Error::NLargerThanLength(size, self.data.len()));
@@ -49,88 +968,301 @@ impl<'de> CustomDeserializer<'de> {
 
     /// Get the first byte from the data.
     pub fn peek_byte(&self) -> Result<u8, Error> {
-        let bits = self._peek_n_bits(8)?;
-        let mut byte = 0u8;
-        for (i, bit) in bits.iter().enumerate() {
-            if *bit {
-                byte |= 1 << i;
-            }
-        }
-        Ok(byte)
+        self.peek_bits(8)
+    }
+
+    /// Construct a byte from the first 'n' (at most 8) bits of the data.
+    pub fn peek_bits(&self, n: usize) -> Result<u8, Error> {
+        Ok(O::load(self._peek_n_bits(n)?) as u8)
     }
 
     /// Peek the next token from the data.
     pub fn peek_token(&self, token: Delimiter) -> Result<bool, Error> {
-        let bits = match token {
-            Delimiter::String => self._peek_n_bits(8)?,
-            Delimiter::Byte => self._peek_n_bits(8)?,
-            Delimiter::Map => self._peek_n_bits(8)?,
-            _ => self._peek_n_bits(3)?,
-        };
-        let mut byte = 0u8;
-        for (i, bit) in bits.iter().enumerate() {
-            if *bit {
-                byte |= 1 << i;
-            }
-        }
-        if byte == token as u8 {
-            Ok(true)
-        } else {
-            Ok(false)
+        let token = token.token();
+        if self.data.len() < token.width().bits() {
+            return Err(Error::NLargerThanLength(
+                token.width().bits(),
+                self.data.len(),
+            ));
         }
+        Ok(self.peek_bits(token.width().bits())? == token.value())
     }
 
     /// Grab the next bit from the data and remove it.
     pub fn eat_bit(&mut self) -> Result<bool, Error> {
-        let bit = *self._peek_n_bits(1)?.get(0).ok_or(Error::NoBit)?;
-        self.data = &self.data[1..];
-        Ok(bit)
+        let (bit, rest) = self.data.split_first().ok_or(Error::NoBit)?;
+        self.data = rest;
+        Ok(*bit)
     }
 
     /// Grab the next byte from the data and remove it.
     pub fn eat_byte(&mut self) -> Result<u8, Error> {
         let byte = self.peek_byte()?;
-        self.data = &self.data[8..];
+        self.eat_bits(8)?;
         Ok(byte)
     }
 
+    /// The number of bits taken up by `count` items of `size` bytes each, where `count` was
+    /// read from the data (e.g. a length prefix). All the math on lengths read from the data
+    /// goes through here so a length that overflows or runs past the end of the data is
+    /// an [`Error::LengthOutOfBounds`] instead of a panic or a huge allocation.
+    pub(crate) fn length_bits(&self, count: usize, size: usize) -> Result<usize, Error> {
+        let length = (count as u64).saturating_mul(size as u64);
+        usize::try_from(length)
+            .ok()
+            .and_then(|bytes| bytes.checked_mul(8))
+            .filter(|bits| *bits <= self.data.len())
+            .ok_or(Error::LengthOutOfBounds {
+                length,
+                remaining: self.data.len(),
+            })
+    }
+
     /// Grab the next 'n' bytes from the data and remove them.
     pub fn eat_bytes(&mut self, n: usize) -> Result<Vec<u8>, Error> {
-        let bits = &self.data[..n * 8];
-        let mut bytes = Vec::new();
-        self.data = &self.data[n * 8..];
-        for i in 0..n {
-            let mut byte = 0u8;
-            for (j, bit) in bits[i * 8..(i + 1) * 8].iter().enumerate() {
-                if *bit {
-                    byte |= 1 << j;
+        let bits = self.eat_bits(n.checked_mul(8).ok_or(Error::UnexpectedEOF)?)?;
+        if let Some(bytes) = aligned(bits) {
+            return Ok(bytes.to_vec());
+        }
+        Ok(bits
+            .chunks_exact(8)
+            .map(|byte| O::load(byte) as u8)
+            .collect())
+    }
+
+    /// Grab the next `N` bytes from the data into an array on the stack and remove them.
+    /// Primitives are decoded with it so they don't allocate.
+    pub fn eat_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let bits = self.eat_bits(N * 8)?;
+        let mut bytes = [0u8; N];
+        match aligned(bits) {
+            Some(aligned) => bytes.copy_from_slice(aligned),
+            None => {
+                for (byte, bits) in bytes.iter_mut().zip(bits.chunks_exact(8)) {
+                    *byte = O::load(bits) as u8;
                 }
             }
-            bytes.push(byte);
         }
         Ok(bytes)
     }
 
-    /// Grab the next token from the data and remove it.
-    pub fn eat_token(&mut self, token: Delimiter) -> Result<(), Error> {
-        let bits_to_munch = match token {
-            Delimiter::String => 8,
-            Delimiter::Byte => 8,
-            Delimiter::Map => 8,
-            _ => 3,
-        };
-        if self.data.len() < bits_to_munch {
+    /// Grab the next `n` bits from the data and remove them.
+    fn eat_bits(&mut self, n: usize) -> Result<&'de BitSlice<u8, O>, Error> {
+        if self.data.len() < n {
             return Err(Error::UnexpectedEOF);
         }
-        self.data = &self.data[bits_to_munch..];
+        let (bits, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(bits)
+    }
+
+    /// Skip the padding bits up to the next byte boundary (counted from the start of the data).
+    pub fn eat_padding(&mut self) -> Result<(), Error> {
+        let offset = (self.data.as_bitptr().bit().into_inner() as usize + 8 - self.origin) % 8;
+        self.eat_bits((8 - offset) % 8)?;
         Ok(())
     }
 
-    /// Parser Methods
+    /// Grab the byte-aligned bytes up to the given delimiter and remove them along with
+    /// the delimiter. The bytes are borrowed from the input if they lie on byte boundaries.
+    fn eat_delimited(&mut self, token: Delimiter) -> Result<Cow<'de, [u8]>, Error> {
+        self.eat_padding()?;
+        let start = self.data;
+        let mut length = 0;
+        loop {
+            self.tick()?;
+            if self.peek_token(token)? {
+                self.eat_token(token)?;
+                break;
+            }
+            self.eat_byte()?;
+            length += 1;
+        }
+        let bits = start.get(..length * 8).ok_or(Error::UnexpectedEOF)?;
+        Ok(bytes_of(bits))
+    }
+
+    /// Grab the bytes of a string (or bytes) ended by the given delimiter framed the way
+    /// [`Config::string_framing`] says and remove them along with their framing.
+    fn eat_framed(&mut self, token: Delimiter) -> Result<Cow<'de, [u8]>, Error> {
+        match self.config.string_framing {
+            StringFraming::Delimited => self.eat_delimited(token),
+            StringFraming::Escaped => self.eat_escaped(token),
+            StringFraming::LengthPrefixed => self.eat_prefixed(),
+        }
+    }
+
+    /// Grab the byte-aligned bytes up to the given delimiter, where the byte after a
+    /// [`STRING_ESCAPE`] is never the delimiter, and remove them along with the delimiter.
+    /// The bytes are borrowed from the input if none are escaped and they lie on byte
+    /// boundaries.
+    fn eat_escaped(&mut self, token: Delimiter) -> Result<Cow<'de, [u8]>, Error> {
+        self.eat_padding()?;
+        let start = self.data;
+        let (mut length, mut escapes) = (0, 0);
+        loop {
+            self.tick()?;
+            if self.peek_token(token)? {
+                self.eat_token(token)?;
+                break;
+            }
+            if self.eat_byte()? == STRING_ESCAPE {
+                self.eat_byte()?;
+                length += 1;
+                escapes += 1;
+            }
+            length += 1;
+        }
+        let bits = start.get(..length * 8).ok_or(Error::UnexpectedEOF)?;
+        if escapes == 0 {
+            return Ok(bytes_of(bits));
+        }
+        let mut bytes = Vec::with_capacity(length - escapes);
+        let mut escaped = false;
+        for byte in bits.chunks(8).map(|byte| O::load(byte) as u8) {
+            match !escaped && byte == STRING_ESCAPE {
+                true => escaped = true,
+                false => {
+                    escaped = false;
+                    bytes.push(byte);
+                }
+            }
+        }
+        Ok(Cow::Owned(bytes))
+    }
+
+    /// Grab the bytes after their length (u32) and the padding, and remove them along with
+    /// the length. The bytes are borrowed from the input if they lie on byte boundaries.
+    fn eat_prefixed(&mut self) -> Result<Cow<'de, [u8]>, Error> {
+        let length = self.parse_unsigned::<u32>()? as usize;
+        self.eat_padding()?;
+        let bits = self.eat_bits(self.length_bits(length, 1)?)?;
+        Ok(bytes_of(bits))
+    }
 
-    /// Parses a boolean value from the input.
+    /// Grab the next type tag from the data and remove it.
+    pub fn eat_tag(&mut self) -> Result<TypeTag, Error> {
+        let value = self.peek_bits(TypeTag::WIDTH)?;
+        let tag = TypeTag::from_u8(value).ok_or(Error::InvalidTypeTag(value))?;
+        self.eat_bits(TypeTag::WIDTH)?;
+        Ok(tag)
+    }
+
+    /// Grab the next type tag from the data and remove it if it is the expected one;
+    /// only in the self-describing mode.
+    pub fn expect_tag(&mut self, expected: TypeTag) -> Result<(), Error> {
+        if !self.config.self_describing {
+            return Ok(());
+        }
+        match self.eat_tag()? {
+            found if found == expected => Ok(()),
+            found => Err(Error::UnexpectedTypeTag {
+                expected: expected.name(),
+                found,
+            }),
+        }
+    }
+
+    /// Start reading `container`; the tokens read until [`CustomDeserializer::close`] belong
+    /// to it.
+    pub(crate) fn open(&mut self, container: Container) {
+        self.containers.push(container);
+    }
+
+    /// Stop reading the innermost container.
+    pub(crate) fn close(&mut self) {
+        self.containers.pop();
+    }
+
+    /// Read `container` with `read`, closing it again whether or not that succeeds.
+    fn within<T>(
+        &mut self,
+        container: Container,
+        read: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.open(container);
+        let value = read(self);
+        self.close();
+        value
+    }
+
+    /// The innermost container being read (a standalone value if there is none).
+    fn container(&self) -> &Container {
+        self.containers.last().unwrap_or(&Container::Value)
+    }
+
+    /// Grab the next token of the innermost container from the data and remove it if it is
+    /// the expected one. Otherwise report what was found in its place and where: at which
+    /// `index` of which container, nested in how many others.
+    pub fn expect_token(&mut self, token: Delimiter, index: usize) -> Result<(), Error> {
+        if self.peek_token(token).unwrap_or(false) {
+            return self.eat_token(token);
+        }
+        Err(Error::ExpectedDelimiter {
+            found: self.peek_bits(token.width()).ok(),
+            expected: token,
+            container: self.container().clone(),
+            index,
+            depth: self.containers.len().saturating_sub(1),
+        })
+    }
+
+    /// Grab the next token from the data and remove it.
+    pub fn eat_token(&mut self, token: Delimiter) -> Result<(), Error> {
+        self.eat_bits(token.width())?;
+        Ok(())
+    }
+
+    /// Eat the MAP_KEY_DELIMITER in front of the key of a map entry and, when keys are
+    /// prefixed with their length (see [`Config::length_prefixed_keys`]), the length and the
+    /// padding. The bits of the key are returned in that case; they are left in the data.
+    pub(crate) fn expect_key(
+        &mut self,
+        index: usize,
+    ) -> Result<Option<&'de BitSlice<u8, O>>, Error> {
+        self.expect_token(Production::Map.item(0), index)?;
+        if !self.config.length_prefixed_keys {
+            return Ok(None);
+        }
+        let length = self.parse_unsigned::<u32>()? as usize;
+        self.eat_padding()?;
+        self.data
+            .get(..length)
+            .map(Some)
+            .ok_or(Error::UnexpectedEOF)
+    }
+
+    /// Fail unless the key read since the data was `start` took up exactly the bits of `key`
+    /// (see [`CustomDeserializer::expect_key`]).
+    fn check_key(
+        &self,
+        start: &'de BitSlice<u8, O>,
+        key: Option<&'de BitSlice<u8, O>>,
+        index: usize,
+    ) -> Result<(), Error> {
+        let read = start.len() - self.data.len();
+        match key {
+            Some(key) if key.len() != read => Err(Error::KeyLengthMismatch {
+                index,
+                expected: key.len(),
+                found: read,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // Parser Methods
+
+    /// Parses a boolean value from the input: a bit or, with byte-aligned bools, a byte.
     pub fn parse_bool(&mut self) -> Result<bool, Error> {
-        self.eat_bit()
+        if !self.config.byte_aligned_bools {
+            return self.eat_bit();
+        }
+        match self.parse_unsigned::<u8>()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error::ConversionError),
+        }
     }
     /// Parses an unsigned integer value from the input.
     pub fn parse_unsigned<T>(&mut self) -> Result<T, Error>
@@ -138,7 +1270,7 @@ impl<'de> CustomDeserializer<'de> {
         T: TryFrom<u8> + TryFrom<u16> + TryFrom<u32> + TryFrom<u64>,
     {
         let length = std::mem::size_of::<T>();
-        if self.data.len() < length {
+        if self.data.len() < length * 8 {
             return Err(Error::UnexpectedEOF);
         }
         match length {
@@ -148,26 +1280,15 @@ impl<'de> CustomDeserializer<'de> {
                     .try_into()
                     .map_err(|_| Error::ConversionError)
             }
-            2 => {
-                let bytes = self.eat_bytes(length)?;
-                u16::from_le_bytes([bytes[0], bytes[1]])
-                    .try_into()
-                    .map_err(|_| Error::ConversionError)
-            }
-            4 => {
-                let bytes = self.eat_bytes(length)?;
-                u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-                    .try_into()
-                    .map_err(|_| Error::ConversionError)
-            }
-            8 => {
-                let bytes = self.eat_bytes(length)?;
-                u64::from_le_bytes([
-                    bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-                ])
+            2 => u16::from_le_bytes(self.eat_array()?)
                 .try_into()
-                .map_err(|_| Error::ConversionError)
-            }
+                .map_err(|_| Error::ConversionError),
+            4 => u32::from_le_bytes(self.eat_array()?)
+                .try_into()
+                .map_err(|_| Error::ConversionError),
+            8 => u64::from_le_bytes(self.eat_array()?)
+                .try_into()
+                .map_err(|_| Error::ConversionError),
             _ => Err(Error::InvalidTypeSize),
         }
     }
@@ -177,7 +1298,7 @@ impl<'de> CustomDeserializer<'de> {
         T: TryFrom<i8> + TryFrom<i16> + TryFrom<i32> + TryFrom<i64>,
     {
         let length = std::mem::size_of::<T>();
-        if self.data.len() < length {
+        if self.data.len() < length * 8 {
             return Err(Error::UnexpectedEOF);
         }
         match length {
@@ -187,83 +1308,455 @@ impl<'de> CustomDeserializer<'de> {
                     .try_into()
                     .map_err(|_| Error::ConversionError)
             }
-            2 => {
-                let bytes = self.eat_bytes(length)?;
-                i16::from_le_bytes([bytes[0], bytes[1]])
-                    .try_into()
-                    .map_err(|_| Error::ConversionError)
-            }
-            4 => {
-                let bytes = self.eat_bytes(length)?;
-                i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
-                    .try_into()
-                    .map_err(|_| Error::ConversionError)
-            }
-            8 => {
-                let bytes = self.eat_bytes(length)?;
-                i64::from_le_bytes([
-                    bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-                ])
+            2 => i16::from_le_bytes(self.eat_array()?)
                 .try_into()
-                .map_err(|_| Error::ConversionError)
-            }
+                .map_err(|_| Error::ConversionError),
+            4 => i32::from_le_bytes(self.eat_array()?)
+                .try_into()
+                .map_err(|_| Error::ConversionError),
+            8 => i64::from_le_bytes(self.eat_array()?)
+                .try_into()
+                .map_err(|_| Error::ConversionError),
             _ => Err(Error::InvalidTypeSize),
         }
     }
+    /// Parses a `u64` value from the input, in the adaptive integer mode if it is enabled.
+    pub fn parse_u64(&mut self) -> Result<u64, Error> {
+        if !self.config.adaptive_ints {
+            return self.parse_unsigned::<u64>();
+        }
+        let length = 1 << self.peek_bits(ADAPTIVE_WIDTH_BITS)?;
+        if self.data.len() < ADAPTIVE_WIDTH_BITS + length * 8 {
+            return Err(Error::UnexpectedEOF);
+        }
+        self.eat_bits(ADAPTIVE_WIDTH_BITS)?;
+        Ok(O::load(self.eat_bits(length * 8)?))
+    }
+    /// Parses an `i64` value from the input, in the adaptive integer mode if it is enabled.
+    pub fn parse_i64(&mut self) -> Result<i64, Error> {
+        if !self.config.adaptive_ints {
+            return self.parse_signed::<i64>();
+        }
+        let zigzag = self.parse_u64()?;
+        Ok((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64))
+    }
+    /// Parses an `i128` value from the input.
+    pub fn parse_i128(&mut self) -> Result<i128, Error> {
+        Ok(i128::from_le_bytes(self.eat_array()?))
+    }
+    /// Parses a `u128` value from the input.
+    pub fn parse_u128(&mut self) -> Result<u128, Error> {
+        Ok(u128::from_le_bytes(self.eat_array()?))
+    }
     /// Parses a 32-bit floating point value from the input.
     pub fn parse_f32(&mut self) -> Result<f32, Error> {
-        let bytes = self.eat_bytes(4)?;
-        Ok(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        Ok(f32::from_le_bytes(self.eat_array()?))
     }
     /// Parses a 64-bit floating point value from the input.
     pub fn parse_f64(&mut self) -> Result<f64, Error> {
-        let bytes = self.eat_bytes(8)?;
-        Ok(f64::from_le_bytes([
-            bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-        ]))
+        Ok(f64::from_le_bytes(self.eat_array()?))
     }
     /// Parses a character value from the input.
     pub fn parse_char(&mut self) -> Result<char, Error> {
         let value = self.parse_unsigned::<u32>()?;
-        Ok(std::char::from_u32(value).unwrap())
+        std::char::from_u32(value).ok_or(Error::ConversionError)
     }
 
-    /// Parses a string value from the input.
-    pub fn parse_str(&mut self, bytes: &mut Vec<u8>) -> Result<String, Error> {
-        'byteloop: loop {
-            let byte = self.eat_byte()?;
-            bytes.push(byte);
-            if self.peek_token(Delimiter::String)? {
-                self.eat_token(Delimiter::String)?;
-                break 'byteloop;
+    /// Parses a struct (or struct variant): a map() or, without the field names (see
+    /// [`StructKeys::Omit`]), the values alone. The values are a tuple() in the
+    /// self-describing mode.
+    fn parse_struct<V>(
+        &mut self,
+        container: Container,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.implied = None;
+        match (self.config.struct_keys, self.config.self_describing) {
+            (StructKeys::Names, _) => {
+                self.expect_tag(TypeTag::Map)?;
+                self.parse_map(container, Some(fields), visitor)
+            }
+            (StructKeys::Omit, true) => {
+                self.expect_tag(TypeTag::Seq)?;
+                self.parse_seq(container, Some(fields), visitor)
+            }
+            (StructKeys::Omit, false) => {
+                let bitmap = match self.config.option_bitmaps {
+                    true => Some(self.eat_bits(fields.len().div_ceil(8) * 8)?),
+                    false => None,
+                };
+                self.within(container, |deserializer| {
+                    visitor.visit_seq(FieldsDeserializer {
+                        deserializer,
+                        bitmap,
+                        index: 0,
+                        fields,
+                    })
+                })
             }
         }
-        String::from_utf8(bytes.clone()).map_err(|_| Error::ConversionError)
     }
 
-    /// Parses a byte buffer from the input.
-    pub fn parse_bytes(&mut self, bytes: &mut Vec<u8>) -> Result<(), Error> {
-        loop {
-            if self.peek_token(Delimiter::Byte)? {
-                self.eat_token(Delimiter::Byte)?;
-                break;
+    /// Parses a sequence-like container using the given visitor. For structs, `fields` holds
+    /// the names of their fields which errors of the values are reported with.
+    /// - seq: SEQ_DELIMITER + SEQ_VALUE_DELIMITER + value_1 + SEQ_VALUE_DELIMITER + value_2 + ...
+    ///   + SEQ_DELIMITER
+    /// - packed seq: PACKED_DELIMITER + length (u32) + padding + bytes
+    pub fn parse_seq<V>(
+        &mut self,
+        container: Container,
+        fields: Option<&'static [&'static str]>,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.within(container, |deserializer| {
+            if deserializer.config.pack_u8_seqs
+                && deserializer.peek_token(Production::Packed.first())?
+            {
+                deserializer.expect_token(Production::Packed.first(), 0)?;
+                let length = deserializer.parse_unsigned::<u32>()? as usize;
+                deserializer.eat_padding()?;
+                deserializer.length_bits(length, 1)?;
+                let mut seq = SeqDeserializer::new(deserializer.eat_bytes(length)?.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                return Ok(value);
+            }
+            deserializer.expect_token(Production::Seq.first(), 0)?;
+            let mut seq = SequenceDeserializer::new(deserializer);
+            seq.fields = fields;
+            let value = visitor.visit_seq(&mut seq)?;
+            seq.end()?;
+            Ok(value)
+        })
+    }
+
+    /// Parses a tuple (or tuple struct or tuple variant) of `len` elements using the given
+    /// visitor: a seq() in the self-describing mode and otherwise the elements alone.
+    /// - tuple: value_1 + value_2 + ... + value_len
+    fn parse_tuple<V>(
+        &mut self,
+        container: Container,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.expect_tag(TypeTag::Seq)?;
+        match self.config.self_describing {
+            true => self.parse_seq(container, None, visitor),
+            false => self.within(container, |deserializer| {
+                visitor.visit_seq(TupleDeserializer {
+                    deserializer,
+                    index: 0,
+                    len,
+                })
+            }),
+        }
+    }
+
+    /// Parses a map-like container using the given visitor. For structs, `fields` holds the
+    /// names of the fields known to the visitor; entries with other keys are skipped. With
+    /// option bitmaps, fields that are `None` are left out and those that are `Some` lack
+    /// their presence bit.
+    /// - map: MAP_KEY_DELIMITER + key_1 + MAP_VALUE_DELIMITER + value_1 + ... + MAP_DELIMITER
+    /// - length-prefixed keys: MAP_KEY_DELIMITER + length of key_1 in bits (u32) + padding +
+    ///   key_1 + ...
+    /// - integer keys: key_1 + key_2 + ... + MAP_VALUE_DELIMITER + value_1 + ... + MAP_DELIMITER
+    pub fn parse_map<V>(
+        &mut self,
+        container: Container,
+        fields: Option<&'static [&'static str]>,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.implied = None;
+        self.within(container, |deserializer| {
+            let table = match deserializer.config.offset_tables {
+                true => Some(deserializer.parse_offset_table()?),
+                false => None,
+            };
+            let keys = match deserializer.config.sorted_int_keys {
+                true => deserializer.parse_sorted_keys()?,
+                false => None,
+            };
+            let mut map = MapDeserializer::new(deserializer, fields, table, keys);
+            if let Some(count) = map.known_count() {
+                map.check_entries(count)?;
+            }
+            let value = visitor.visit_map(&mut map)?;
+            map.end()?;
+            Ok(value)
+        })
+    }
+
+    /// Parses the keys of a map along with the bits of their values, skipping the values.
+    fn parse_lazy_fields(&mut self) -> Result<Vec<LazyField<'de, O>>, Error> {
+        self.expect_string_keys()?;
+        let mut fields = Vec::new();
+        while !self.peek_token(Production::Map.last())? {
+            let index = fields.len();
+            let prefixed = self.expect_key(index)?;
+            let start = self.data;
+            self.expect_tag(TypeTag::Str)?;
+            let key = self.parse_str()?;
+            self.check_key(start, prefixed, index)?;
+            self.expect_token(Production::Map.item(1), index)?;
+            let value = self.data;
+            self.skip_value()?;
+            fields.push((key, consumed(value, self.data)?));
+        }
+        self.expect_token(Production::Map.last(), fields.len())?;
+        Ok(fields)
+    }
+
+    /// Parses the keys of a map along with the bits of their values, jumping from entry to
+    /// entry through the offset table.
+    fn parse_lazy_fields_from_table(&mut self) -> Result<Vec<LazyField<'de, O>>, Error> {
+        let table = self.parse_offset_table()?;
+        self.expect_string_keys()?;
+        let mut fields = Vec::with_capacity(table.len());
+        for index in 0..table.len() {
+            let entry = table.entry(index)?;
+            self.data = entry;
+            let prefixed = self.expect_key(index)?;
+            let start = self.data;
+            self.expect_tag(TypeTag::Str)?;
+            let key = self.parse_str()?;
+            self.check_key(start, prefixed, index)?;
+            self.expect_token(Production::Map.item(1), index)?;
+            fields.push((key, self.data));
+        }
+        self.data = table.from(table.len())?;
+        self.expect_token(Production::Map.last(), fields.len())?;
+        Ok(fields)
+    }
+
+    /// Parses the bits of the given number of values of a map with sorted keys, skipping
+    /// the values.
+    fn parse_lazy_values(&mut self, count: usize) -> Result<Vec<&'de BitSlice<u8, O>>, Error> {
+        let mut values = Vec::with_capacity(count);
+        for index in 0..count {
+            self.expect_token(Production::Map.item(1), index)?;
+            let value = self.data;
+            self.skip_value()?;
+            values.push(consumed(value, self.data)?);
+        }
+        self.expect_token(Production::Map.last(), count)?;
+        Ok(values)
+    }
+
+    /// Fail if the keys of the map are a block of integer keys instead of (string) keys in
+    /// front of every value.
+    fn expect_string_keys(&mut self) -> Result<(), Error> {
+        if self.config.sorted_int_keys && self.parse_sorted_keys()?.is_some() {
+            return Err(Error::DeserializationError(
+                "expected a map with string keys but found a block of integer keys".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Parses the bit telling the layout of a map and the block of keys if there is one
+    /// (see [`Config::sorted_int_keys`]).
+    /// - block: 1 + count (u32) + size of a key in bytes (u8) + sorted (u8) + key_1 + key_2 + ...
+    /// - no block: 0
+    pub(crate) fn parse_sorted_keys(&mut self) -> Result<Option<SortedKeys<'de, O>>, Error> {
+        if !self.eat_bit()? {
+            return Ok(None);
+        }
+        let count = self.parse_unsigned::<u32>()? as usize;
+        let size = self.parse_unsigned::<u8>()? as usize;
+        let sorted = self.parse_unsigned::<u8>()? != 0;
+        if ![1, 2, 4, 8].contains(&size) {
+            return Err(Error::InvalidTypeSize);
+        }
+        let keys = self.eat_bits(self.length_bits(count, size)?)?;
+        Ok(Some(SortedKeys { keys, size, sorted }))
+    }
+
+    /// Parses the columns of a sequence of structs (see [`columnar`](crate::columnar)).
+    /// - columns: rows (u32) + fields (u32) + name of every field + every column
+    /// - column: padding + length in bits (u32) + bits
+    fn parse_columns(&mut self) -> Result<Table<'de, O>, Error> {
+        let rows = self.parse_unsigned::<u32>()? as usize;
+        let fields = self.parse_unsigned::<u32>()? as usize;
+        if rows > 0 && fields == 0 {
+            return Err(Error::DeserializationError(format!(
+                "a columnar sequence has {rows} rows without fields"
+            )));
+        }
+        if let Some(max) = self.config.max_map_entries {
+            if rows > max {
+                return Err(Error::TooManyMapEntries { max, path: vec![] });
             }
-            let byte = self.eat_byte()?;
-            bytes.push(byte);
         }
+        let names = (0..fields)
+            .map(|_| self.parse_str())
+            .collect::<Result<Vec<_>, _>>()?;
+        let columns = (0..fields)
+            .map(|_| {
+                self.eat_padding()?;
+                let bits = self.parse_unsigned::<u32>()? as usize;
+                self.eat_bits(bits)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // every row takes at least a bit (see `serialize_columns`), so a count of rows that
+        // the columns can't hold is rejected before the rows are iterated.
+        let bits = columns.iter().map(|column| column.len()).sum::<usize>();
+        if rows > bits {
+            return Err(Error::DeserializationError(format!(
+                "a columnar sequence has {rows} rows but {bits} bits of values"
+            )));
+        }
+        Ok(Table {
+            rows,
+            names,
+            columns,
+        })
+    }
+
+    /// Parses the offset table in front of the entries of a map.
+    /// - table: count (u32) + offset of every entry (u32) + offset of the end (u32)
+    pub(crate) fn parse_offset_table(&mut self) -> Result<OffsetTable<'de, O>, Error> {
+        let count = self.parse_unsigned::<u32>()? as usize;
+        // the offsets of the entries and of the end.
+        self.length_bits(count.saturating_add(1), std::mem::size_of::<u32>())?;
+        let offsets = (0..=count)
+            .map(|_| self.parse_unsigned::<u32>().map(|offset| offset as usize))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(OffsetTable {
+            entries: self.data,
+            offsets,
+        })
+    }
+
+    /// Skip the next value without deserializing it. Only the self-describing mode
+    /// tells where an unknown value ends.
+    pub fn skip_value(&mut self) -> Result<(), Error> {
+        if !self.config.self_describing {
+            return Err(Error::SelfDescribingRequired("skip_value"));
+        }
+        Deserializer::deserialize_any(self, IgnoredAny)?;
         Ok(())
     }
+
+    /// Parses the index (and in the self-describing mode, the name) of an enum variant.
+    pub fn parse_variant(&mut self) -> Result<(u32, Option<Cow<'de, str>>), Error> {
+        let index = match self.parse_unsigned::<u8>()? {
+            VARIANT_INDEX_ESCAPE => self.parse_unsigned::<u32>()?,
+            index => u32::from(index),
+        };
+        match self.config.self_describing {
+            true => Ok((index, Some(self.parse_str()?))),
+            false => Ok((index, None)),
+        }
+    }
+
+    /// Parses a string value from the input. The string is borrowed from the input
+    /// whenever possible.
+    pub fn parse_str(&mut self) -> Result<Cow<'de, str>, Error> {
+        match self.eat_framed(Production::Str.last())? {
+            Cow::Borrowed(bytes) => std::str::from_utf8(bytes)
+                .map(Cow::Borrowed)
+                .map_err(|_| Error::ConversionError),
+            Cow::Owned(bytes) => String::from_utf8(bytes)
+                .map(Cow::Owned)
+                .map_err(|_| Error::ConversionError),
+        }
+    }
+
+    /// Parses a byte buffer from the input. The bytes are borrowed from the input
+    /// whenever possible.
+    pub fn parse_bytes(&mut self) -> Result<Cow<'de, [u8]>, Error> {
+        self.eat_framed(Production::Bytes.last())
+    }
+    /// Parses an embedded payload (see [`RawRustFr`](crate::raw::RawRustFr)) from the input:
+    /// length (u32) + padding + bytes. The bytes are borrowed if they lie on byte boundaries.
+    pub fn parse_raw(&mut self) -> Result<Cow<'de, [u8]>, Error> {
+        self.eat_prefixed()
+    }
 }
 
-impl<'de, 'a> Deserializer<'de> for &'a mut CustomDeserializer<'de> {
+impl<'de, O: WireOrder> Deserializer<'de> for &mut CustomDeserializer<'de, O> {
     type Error = Error;
 
-    /// The data is not self-describing, so we need to use the type to determine how to deserialize it.
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    /// The data is binary, so types with both a readable and a compact representation (e.g.
+    /// `IpAddr`) use the compact one.
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    /// The data is not self-describing by default, so we need to use the type to determine how to
+    /// deserialize it. In the self-describing mode the type tag tells us instead.
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(Error::UnsupportedCall("deserialize_any".to_string()))
+        self.implied = None;
+        if !self.config.self_describing {
+            return Err(Error::SelfDescribingRequired("deserialize_any"));
+        }
+        match self.eat_tag()? {
+            TypeTag::Unit => visitor.visit_unit(),
+            TypeTag::Bool => visitor.visit_bool(self.parse_bool()?),
+            TypeTag::I8 => visitor.visit_i8(self.parse_signed::<i8>()?),
+            TypeTag::I16 => visitor.visit_i16(self.parse_signed::<i16>()?),
+            TypeTag::I32 => visitor.visit_i32(self.parse_signed::<i32>()?),
+            TypeTag::I64 => visitor.visit_i64(self.parse_i64()?),
+            TypeTag::U8 => visitor.visit_u8(self.parse_unsigned::<u8>()?),
+            TypeTag::U16 => visitor.visit_u16(self.parse_unsigned::<u16>()?),
+            TypeTag::U32 => visitor.visit_u32(self.parse_unsigned::<u32>()?),
+            TypeTag::U64 => visitor.visit_u64(self.parse_u64()?),
+            TypeTag::I128 => visitor.visit_i128(self.parse_i128()?),
+            TypeTag::U128 => visitor.visit_u128(self.parse_u128()?),
+            TypeTag::F32 => visitor.visit_f32(self.parse_f32()?),
+            TypeTag::F64 => visitor.visit_f64(self.parse_f64()?),
+            TypeTag::Char => visitor.visit_char(self.parse_char()?),
+            TypeTag::Str => match self.parse_str()? {
+                Cow::Borrowed(str) => visitor.visit_borrowed_str(str),
+                Cow::Owned(string) => visitor.visit_string(string),
+            },
+            TypeTag::Bytes => match self.parse_bytes()? {
+                Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+                Cow::Owned(bytes) => visitor.visit_byte_buf(bytes),
+            },
+            // an embedded payload is described as its bytes.
+            TypeTag::Raw => match self.parse_raw()? {
+                Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+                Cow::Owned(bytes) => visitor.visit_byte_buf(bytes),
+            },
+            TypeTag::None => visitor.visit_none(),
+            TypeTag::Some => self.nested(|deserializer| visitor.visit_some(deserializer)),
+            TypeTag::Seq => {
+                self.nested(|deserializer| deserializer.parse_seq(Container::Seq, None, visitor))
+            }
+            TypeTag::Map => {
+                self.nested(|deserializer| deserializer.parse_map(Container::Map, None, visitor))
+            }
+            // unit variants are described by their name (like a string) while the other
+            // variants are described by a map of their name to their content.
+            TypeTag::UnitVariant => match self.parse_variant()?.1 {
+                Some(Cow::Borrowed(name)) => visitor.visit_borrowed_str(name),
+                Some(Cow::Owned(name)) => visitor.visit_string(name),
+                None => Err(Error::UnsupportedCall("deserialize_any".to_string())),
+            },
+            TypeTag::NewtypeVariant | TypeTag::TupleVariant | TypeTag::StructVariant => {
+                let (_, name) = self.parse_variant()?;
+                self.nested(|deserializer| {
+                    visitor.visit_map(VariantDeserializer { deserializer, name })
+                })
+            }
+        }
     }
 
     // Primitve Types Deserialization. They are serialized as is (LE byte order).
@@ -272,135 +1765,231 @@ impl<'de, 'a> Deserializer<'de> for &'a mut CustomDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
+        self.implied = None;
+        self.expect_tag(TypeTag::Bool)?;
         visitor.visit_bool(self.parse_bool()?)
     }
     fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
+        self.implied = None;
+        self.expect_tag(TypeTag::I8)?;
         visitor.visit_i8(self.parse_signed::<i8>()?)
     }
     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
+        self.implied = None;
+        self.expect_tag(TypeTag::I16)?;
         visitor.visit_i16(self.parse_signed::<i16>()?)
     }
     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
+        self.implied = None;
+        self.expect_tag(TypeTag::I32)?;
         visitor.visit_i32(self.parse_signed::<i32>()?)
     }
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_i64(self.parse_signed::<i64>()?)
+        self.implied = None;
+        self.expect_tag(TypeTag::I64)?;
+        visitor.visit_i64(self.parse_i64()?)
+    }
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.implied = None;
+        self.expect_tag(TypeTag::I128)?;
+        visitor.visit_i128(self.parse_i128()?)
     }
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
+        self.implied = None;
+        self.expect_tag(TypeTag::U8)?;
         visitor.visit_u8(self.parse_unsigned::<u8>()?)
     }
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
+        self.implied = None;
+        self.expect_tag(TypeTag::U16)?;
         visitor.visit_u16(self.parse_unsigned::<u16>()?)
     }
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
+        self.implied = None;
+        self.expect_tag(TypeTag::U32)?;
         visitor.visit_u32(self.parse_unsigned::<u32>()?)
     }
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_u64(self.parse_unsigned::<u64>()?)
+        self.implied = None;
+        self.expect_tag(TypeTag::U64)?;
+        visitor.visit_u64(self.parse_u64()?)
+    }
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.implied = None;
+        self.expect_tag(TypeTag::U128)?;
+        visitor.visit_u128(self.parse_u128()?)
     }
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
+        self.implied = None;
+        self.expect_tag(TypeTag::F32)?;
         visitor.visit_f32(self.parse_f32()?)
     }
     fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
+        self.implied = None;
+        self.expect_tag(TypeTag::F64)?;
         visitor.visit_f64(self.parse_f64()?)
     }
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
+        self.implied = None;
+        self.expect_tag(TypeTag::Char)?;
         visitor.visit_char(self.parse_char()?)
     }
 
-    /// String Deserialization. They are serialized as bytes + STRING_DELIMITER.
+    /// String Deserialization. They are serialized as length (u32) + padding + bytes (see
+    /// [`Config::string_framing`] for the others). The padding aligns the string to a byte
+    /// boundary so it can be borrowed from the input.
+    /// The strings of map keys are handed out as bytes without checking them with
+    /// [`Config::raw_str_keys`].
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        let mut bytes = Vec::new();
-        visitor.visit_str(self.parse_str(&mut bytes)?.as_str())
+        self.implied = None;
+        self.expect_tag(TypeTag::Str)?;
+        if self.raw_key {
+            return match self.eat_framed(Production::Str.last())? {
+                Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+                Cow::Owned(bytes) => visitor.visit_bytes(&bytes),
+            };
+        }
+        match self.parse_str()? {
+            Cow::Borrowed(str) => visitor.visit_borrowed_str(str),
+            Cow::Owned(string) => visitor.visit_str(&string),
+        }
     }
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        let mut bytes = Vec::new();
-        visitor.visit_string(self.parse_str(&mut bytes)?.to_string())
+        self.implied = None;
+        self.expect_tag(TypeTag::Str)?;
+        if self.raw_key {
+            return match self.eat_framed(Production::Str.last())? {
+                Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+                Cow::Owned(bytes) => visitor.visit_byte_buf(bytes),
+            };
+        }
+        match self.parse_str()? {
+            Cow::Borrowed(str) => visitor.visit_borrowed_str(str),
+            Cow::Owned(string) => visitor.visit_string(string),
+        }
     }
 
-    /// Byte Deserialization. They are serialized as bytes + BYTE_DELIMITER.
+    /// Byte Deserialization. They are serialized as length (u32) + padding + bytes.
+    /// The padding aligns the bytes to a byte boundary so they can be borrowed from the input.
+    /// Map keys are read as strings with [`Config::raw_str_keys`].
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        let mut bytes = Vec::new();
-        self.parse_bytes(&mut bytes)?;
-        visitor.visit_bytes(&bytes)
+        self.implied = None;
+        if self.raw_key {
+            return self.deserialize_str(visitor);
+        }
+        self.expect_tag(TypeTag::Bytes)?;
+        match self.parse_bytes()? {
+            Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Cow::Owned(bytes) => visitor.visit_bytes(&bytes),
+        }
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        let mut bytes = Vec::new();
-        self.parse_bytes(&mut bytes)?;
-        visitor.visit_byte_buf(bytes)
+        self.implied = None;
+        if self.raw_key {
+            return self.deserialize_string(visitor);
+        }
+        self.expect_tag(TypeTag::Bytes)?;
+        match self.parse_bytes()? {
+            Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+            Cow::Owned(bytes) => visitor.visit_byte_buf(bytes),
+        }
     }
 
-    /// Option Deserialization. They are serialized as None -> unit(), Some -> self.
+    /// Option Deserialization. They are serialized as None -> 0, Some -> 1 + self.
+    /// In the self-describing mode, they are serialized as NONE_TAG or SOME_TAG + self.
+    /// Struct fields with option bitmaps are nothing or self.
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        match self.peek_token(Delimiter::Unit)? {
-            true => {
-                self.eat_token(Delimiter::Unit)?;
-                visitor.visit_none()
+        if let Some((position, present)) = self.implied.take() {
+            if position == self.data.len() {
+                return match present {
+                    true => self.nested(|deserializer| visitor.visit_some(deserializer)),
+                    false => visitor.visit_none(),
+                };
             }
-            false => visitor.visit_some(self),
+        }
+        if self.config.self_describing {
+            return match self.eat_tag()? {
+                TypeTag::None => visitor.visit_none(),
+                TypeTag::Some => self.nested(|deserializer| visitor.visit_some(deserializer)),
+                found => Err(Error::UnexpectedTypeTag {
+                    expected: "an option",
+                    found,
+                }),
+            };
+        }
+        match self.eat_bit()? {
+            true => self.nested(|deserializer| visitor.visit_some(deserializer)),
+            false => visitor.visit_none(),
         }
     }
-    /// Unit Deserialization. They are serialized as UNIT.
+    /// Unit Deserialization. They are serialized as UNIT (or UNIT_TAG in the self-describing mode).
     fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        match self.peek_token(Delimiter::Unit)? {
-            true => {
-                self.eat_token(Delimiter::Unit)?;
-                visitor.visit_unit()
-            }
-            _ => Err(Error::ExpectedDelimiter(Delimiter::Unit)),
+        self.implied = None;
+        match self.config.self_describing {
+            true => self.expect_tag(TypeTag::Unit)?,
+            false => self.within(Container::Value, |deserializer| {
+                deserializer.expect_token(Production::Unit.first(), 0)
+            })?,
         }
+        visitor.visit_unit()
     }
 
     /// Struct Deserialization.
@@ -413,30 +2002,72 @@ impl<'de, 'a> Deserializer<'de> for &'a mut CustomDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
+        self.implied = None;
         self.deserialize_unit(visitor)
     }
     /// - newtype_struct: self
     fn deserialize_newtype_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_newtype_struct(self)
+        // a newtype struct field is never written without its presence bit.
+        self.implied = None;
+        if name == columnar::NAME && !self.config.self_describing {
+            let table = self.parse_columns()?;
+            return visitor.visit_newtype_struct(RowsDeserializer {
+                deserializer: self,
+                table,
+                index: 0,
+            });
+        }
+        if name == raw::NAME {
+            self.expect_tag(TypeTag::Raw)?;
+            return match self.parse_raw()? {
+                Cow::Borrowed(bytes) => {
+                    visitor.visit_newtype_struct(BorrowedBytesDeserializer::<Error>::new(bytes))
+                }
+                Cow::Owned(bytes) => {
+                    visitor.visit_newtype_struct(BytesDeserializer::<Error>::new(&bytes))
+                }
+            };
+        }
+        let encoder = match self.config.self_describing {
+            true => None,
+            false => self
+                .encoders
+                .as_ref()
+                .and_then(|encoders| encoders.get(name))
+                .cloned(),
+        };
+        match encoder {
+            Some(encoder) => {
+                let length = self.parse_unsigned::<u32>()? as usize;
+                self.eat_padding()?;
+                self.length_bits(length, 1)?;
+                let bytes = self.eat_bytes(length)?;
+                visitor.visit_newtype_struct((encoder.decode)(&bytes)?)
+            }
+            None => self.nested(|deserializer| visitor.visit_newtype_struct(deserializer)),
+        }
     }
-    /// - tuple_struct: seq()
+    /// - tuple_struct: tuple()
     fn deserialize_tuple_struct<V>(
         self,
-        _name: &'static str,
-        _len: usize,
+        name: &'static str,
+        len: usize,
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.implied = None;
+        self.nested(|deserializer| {
+            deserializer.parse_tuple(Container::TupleStruct(name), len, visitor)
+        })
     }
 
     /// Enum Deserialization.
@@ -446,107 +2077,181 @@ impl<'de, 'a> Deserializer<'de> for &'a mut CustomDeserializer<'de> {
     /// - struct_variant: ENUM_DELIMITER + variant_index + struct()
     fn deserialize_enum<V>(
         self,
-        _name: &'static str,
-        _variants: &'static [&'static str],
+        name: &'static str,
+        variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_enum(self)
+        self.implied = None;
+        self.nested(|deserializer| {
+            visitor.visit_enum(EnumDeserializer {
+                deserializer,
+                name,
+                variants,
+                tag: None,
+                unknown: None,
+            })
+        })
     }
 
     /// Seq & Map Deserialization.
-    /// - seq: SEQ_DELIMITER + value_1 + SEQ_VALUE_DELIMITER + value_2 + SEQ_VALUE_DELIMITER + ... + SEQ_DELIMITER
+    /// - seq: SEQ_DELIMITER + SEQ_VALUE_DELIMITER + value_1 + SEQ_VALUE_DELIMITER + value_2 + ...
+    ///   + SEQ_DELIMITER
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        match self.peek_token(Delimiter::Seq)? {
-            true => {
-                self.eat_token(Delimiter::Seq)?;
-                let value = visitor.visit_seq(SequenceDeserializer::new(self))?;
-                if !self.peek_token(Delimiter::Seq)? {
-                    return Err(Error::ExpectedDelimiter(Delimiter::Seq));
-                }
-                self.eat_token(Delimiter::Seq)?;
-                Ok(value)
-            }
-            false => Err(Error::ExpectedDelimiter(Delimiter::Seq)),
-        }
+        self.implied = None;
+        self.expect_tag(TypeTag::Seq)?;
+        self.nested(|deserializer| deserializer.parse_seq(Container::Seq, None, visitor))
     }
-    /// - map: key_1 + MAP_KEY_DELIMITER + value_1 + MAP_VALUE_DELIMITER + ... + MAP_DELIMITER
+    /// - map: MAP_KEY_DELIMITER + key_1 + MAP_VALUE_DELIMITER + value_1 + ... + MAP_DELIMITER
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        let value = visitor.visit_map(MapDeserializer::new(self))?;
-        if !self.peek_token(Delimiter::Map)? {
-            return Err(Error::ExpectedDelimiter(Delimiter::Map));
-        }
-        self.eat_token(Delimiter::Map)?;
-        Ok(value)
+        self.implied = None;
+        self.expect_tag(TypeTag::Map)?;
+        self.nested(|deserializer| deserializer.parse_map(Container::Map, None, visitor))
     }
 
     /// Tuple & Struct Deserialization.
-    /// - tuple: seq()
-    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    /// - tuple: value_1 + value_2 + ... (or seq() in the self-describing mode)
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.implied = None;
+        self.nested(|deserializer| deserializer.parse_tuple(Container::Tuple, len, visitor))
     }
     /// - struct: map()
     fn deserialize_struct<V>(
         self,
-        _name: &'static str,
-        _fields: &'static [&'static str],
+        name: &'static str,
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_map(visitor)
+        self.implied = None;
+        self.nested(|deserializer| {
+            deserializer.parse_struct(Container::Struct(name), fields, visitor)
+        })
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
+        self.implied = None;
         self.deserialize_str(visitor)
     }
 
-    fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(Error::UnsupportedCall(
-            "deserialize_ignored_any".to_string(),
-        ))
+        self.implied = None;
+        self.skip_value()?;
+        visitor.visit_unit()
     }
 }
 
-/// Handles the deserialization of an enum.
+/// Internal struct that handles the deserialization of an enum.
 /// enum() => variant_index + (depends on variant type; handled by VARIANT_ACCESS)
-impl<'de, 'a> EnumAccess<'de> for &'a mut CustomDeserializer<'de> {
+struct EnumDeserializer<'a, 'de: 'a, O: WireOrder> {
+    deserializer: &'a mut CustomDeserializer<'de, O>,
+    name: &'static str,
+    // The names of the variants of the enum.
+    variants: &'static [&'static str],
+    // The type tag of the variant (only in the self-describing mode).
+    tag: Option<TypeTag>,
+    // The index if it is unknown and was handed to the fallback variant.
+    unknown: Option<u32>,
+}
+impl<'de, O: WireOrder> EnumDeserializer<'_, 'de, O> {
+    fn unknown_variant(&self, index: u32) -> Error {
+        Error::UnknownVariant {
+            name: self.name,
+            index,
+            variants: self.variants.len(),
+        }
+    }
+
+    /// Fail if the index was unknown: only the unit fallback variant can stand in for it.
+    fn known(&self) -> Result<(), Error> {
+        match self.unknown {
+            Some(index) => Err(self.unknown_variant(index)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'de, O: WireOrder> EnumAccess<'de> for EnumDeserializer<'_, 'de, O> {
     type Error = Error;
     type Variant = Self;
 
-    /// Get the next variant key from the data and remove it.
-    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    /// Get the next variant key from the data and remove it. An unknown index fails or (see
+    /// [`Config::on_unknown_variant`]) is handed to the fallback variant.
+    fn variant_seed<V>(mut self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
     where
         V: serde::de::DeserializeSeed<'de>,
     {
-        let key = self.parse_unsigned::<u32>()?;
-        Ok((seed.deserialize(key.into_deserializer())?, self))
+        if self.deserializer.config.self_describing {
+            match self.deserializer.eat_tag()? {
+                tag @ (TypeTag::UnitVariant
+                | TypeTag::NewtypeVariant
+                | TypeTag::TupleVariant
+                | TypeTag::StructVariant) => self.tag = Some(tag),
+                found => {
+                    return Err(Error::UnexpectedTypeTag {
+                        expected: "an enum variant",
+                        found,
+                    })
+                }
+            }
+        }
+        let (index, name) = self.deserializer.parse_variant()?;
+        // the self-describing mode also has the name, which tells a newer variant from the
+        // fallback variant at the same index.
+        let known = match (self.variants.get(index as usize), name) {
+            (Some(expected), Some(name)) => *expected == name,
+            (found, _) => found.is_some(),
+        };
+        if !known {
+            self.unknown = Some(index);
+            // derived enums only take an index past their variants if one is `#[serde(other)]`.
+            let past = u64::from(index).max(self.variants.len() as u64);
+            let fallback = match self.deserializer.config.on_unknown_variant {
+                UnknownVariant::Error => None,
+                UnknownVariant::SkipToDefault => seed
+                    .deserialize(IntoDeserializer::<Error>::into_deserializer(past))
+                    .ok(),
+            };
+            return match fallback {
+                Some(value) => Ok((value, self)),
+                None => Err(self.unknown_variant(index)),
+            };
+        }
+        Ok((seed.deserialize(index.into_deserializer())?, self))
     }
 }
-impl<'de, 'a> VariantAccess<'de> for &'a mut CustomDeserializer<'de> {
+impl<'de, O: WireOrder> VariantAccess<'de> for EnumDeserializer<'_, 'de, O> {
     type Error = Error;
 
     /// - unit_variant: variant_index
+    ///
+    /// The fallback variant of an unknown index skips the content of the unknown variant,
+    /// which only the self-describing mode tells the end of.
     fn unit_variant(self) -> Result<(), Self::Error> {
-        Ok(())
+        match (self.unknown, self.tag) {
+            (None, _) | (Some(_), Some(TypeTag::UnitVariant)) => Ok(()),
+            (Some(_), Some(_)) => self.deserializer.skip_value(),
+            (Some(_), None) => Err(Error::SelfDescribingRequired("Config::on_unknown_variant")),
+        }
     }
 
     /// - newtype_variant: variant_index + self
@@ -554,15 +2259,18 @@ impl<'de, 'a> VariantAccess<'de> for &'a mut CustomDeserializer<'de> {
     where
         T: serde::de::DeserializeSeed<'de>,
     {
-        seed.deserialize(self)
+        self.known()?;
+        seed.deserialize(self.deserializer)
     }
 
     /// - tuple_variant: variant_index + tuple() where (tuple() => seq())
-    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.known()?;
+        self.deserializer
+            .parse_tuple(Container::TupleVariant, len, visitor)
     }
 
     /// - struct_variant: variant_index + struct() where (struct() => map())
@@ -574,105 +2282,504 @@ impl<'de, 'a> VariantAccess<'de> for &'a mut CustomDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_struct("", fields, visitor)
+        self.known()?;
+        self.deserializer
+            .parse_struct(Container::StructVariant, fields, visitor)
+    }
+}
+
+/// Internal struct that describes an enum variant with content as a map of the variant name to
+/// its content in `deserialize_any` (only in the self-describing mode).
+struct VariantDeserializer<'a, 'de: 'a, O: WireOrder> {
+    deserializer: &'a mut CustomDeserializer<'de, O>,
+    name: Option<Cow<'de, str>>,
+}
+impl<'de, O: WireOrder> MapAccess<'de> for VariantDeserializer<'_, 'de, O> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: serde::de::DeserializeSeed<'de>,
+    {
+        match self.name.take() {
+            Some(Cow::Borrowed(name)) => seed
+                .deserialize(BorrowedStrDeserializer::new(name))
+                .map(Some),
+            Some(Cow::Owned(name)) => seed.deserialize(StringDeserializer::new(name)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(usize::from(self.name.is_some()))
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.deserializer)
     }
 }
 
 /// Internal struct that handles the deserialization of a sequence.
-/// seq() => SEQ_DELIMITER + value_1 + SEQ_VALUE_DELIMITER + value_2 + SEQ_VALUE_DELIMITER + ... + SEQ_DELIMITER
-struct SequenceDeserializer<'a, 'de: 'a> {
-    deserializer: &'a mut CustomDeserializer<'de>,
-    first: bool,
+/// seq() => SEQ_DELIMITER + SEQ_VALUE_DELIMITER + value_1 + SEQ_VALUE_DELIMITER + value_2 + ...
+/// + SEQ_DELIMITER
+struct SequenceDeserializer<'a, 'de: 'a, O: WireOrder> {
+    deserializer: &'a mut CustomDeserializer<'de, O>,
+    // The names of the fields if the sequence is a struct.
+    fields: Option<&'static [&'static str]>,
+    index: usize,
+    // The remaining repeats of the element of the current run and where it starts
+    // (only in the run-length encoding mode).
+    run: Option<(u32, &'de BitSlice<u8, O>)>,
 }
-impl<'a, 'de> SequenceDeserializer<'a, 'de> {
-    pub fn new(deserializer: &'a mut CustomDeserializer<'de>) -> Self {
+impl<'a, 'de, O: WireOrder> SequenceDeserializer<'a, 'de, O> {
+    pub fn new(deserializer: &'a mut CustomDeserializer<'de, O>) -> Self {
         Self {
             deserializer,
-            first: true,
+            fields: None,
+            index: 0,
+            run: None,
+        }
+    }
+
+    /// Move to the next element in the run-length encoding mode.
+    /// - If in a run; read its element again.
+    /// - If at end of sequence; exit.
+    /// - Eat SEQ_VALUE_DELIMITER or RUN_DELIMITER + count.
+    fn next_group(&mut self) -> Result<bool, Error> {
+        if let Some((remaining @ 1.., element)) = self.run {
+            self.run = Some((remaining - 1, element));
+            self.deserializer.data = element;
+            return Ok(true);
+        }
+        if self.deserializer.peek_token(Production::Seq.last())? {
+            return Ok(false);
+        }
+        if self.deserializer.peek_token(Production::Run.first())? {
+            self.deserializer
+                .expect_token(Production::Run.first(), self.index)?;
+            let count = self.deserializer.parse_unsigned::<u32>()?;
+            if count == 0 {
+                return Err(Error::DeserializationError(format!(
+                    "empty run at index {} of {}",
+                    self.index,
+                    self.deserializer.container()
+                )));
+            }
+            self.run = Some((count - 1, self.deserializer.data));
+            return Ok(true);
         }
+        self.deserializer
+            .expect_token(Production::Seq.item(0), self.index)?;
+        Ok(true)
+    }
+
+    /// Eat the closing SEQ_DELIMITER.
+    pub fn end(self) -> Result<(), Error> {
+        self.deserializer
+            .expect_token(Production::Seq.last(), self.index)
     }
 }
-impl<'de, 'a> SeqAccess<'de> for SequenceDeserializer<'a, 'de> {
+impl<'de, O: WireOrder> SeqAccess<'de> for SequenceDeserializer<'_, 'de, O> {
     type Error = Error;
 
     /// Grab the next element from the data and remove it.
     /// - If at end of sequence; exit.
-    /// - If not first and not at the end of sequence; eat SEQ_VALUE_DELIMITER.
-    /// - Make not first; deserialize next element.
+    /// - Eat SEQ_VALUE_DELIMITER.
+    /// - Deserialize next element; move to the next index.
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: serde::de::DeserializeSeed<'de>,
+    {
+        self.deserializer.tick()?;
+        if self.deserializer.config.run_length_encoding {
+            if !self.next_group()? {
+                return Ok(None);
+            }
+        } else {
+            // if at end of sequence; exit
+            if self.deserializer.peek_token(Production::Seq.last())? {
+                return Ok(None);
+            }
+            // every element follows a SEQ_VALUE_DELIMITER, the first one too, so an element
+            // is never mistaken for the end of the sequence.
+            self.deserializer
+                .expect_token(Production::Seq.item(0), self.index)?;
+        }
+        // deserialize next element; move to the next index
+        let field = self.fields.and_then(|fields| fields.get(self.index));
+        let value = seed
+            .deserialize(&mut *self.deserializer)
+            .map_err(|error| match field {
+                Some(field) => in_field(error, self.deserializer.container(), field)
+                    .nested(PathSegment::Field(field)),
+                None => error.nested(PathSegment::Element(self.index)),
+            })?;
+        self.index += 1;
+        Ok(Some(value))
+    }
+}
+
+/// Say which field of which struct a custom error (e.g. of a `TryFrom` conversion) comes from.
+fn in_field(error: Error, container: &Container, field: &str) -> Error {
+    match error {
+        Error::DeserializationError(message) => {
+            Error::DeserializationError(format!("the field {field} of {container}: {message}"))
+        }
+        error => error,
+    }
+}
+
+/// The bits between `start` and `rest`, where `rest` is what is left of `start` after some
+/// bits were eaten.
+fn consumed<'de, O: WireOrder>(
+    start: &'de BitSlice<u8, O>,
+    rest: &'de BitSlice<u8, O>,
+) -> Result<&'de BitSlice<u8, O>, Error> {
+    let length = start.len().checked_sub(rest.len());
+    length
+        .and_then(|length| start.get(..length))
+        .ok_or(Error::UnexpectedEOF)
+}
+
+/// The bytes under the bits, borrowed if they start and end at byte boundaries.
+fn bytes_of<O: WireOrder>(bits: &BitSlice<u8, O>) -> Cow<'_, [u8]> {
+    match aligned(bits) {
+        Some(bytes) => Cow::Borrowed(bytes),
+        None => Cow::Owned(bits.chunks(8).map(|byte| O::load(byte) as u8).collect()),
+    }
+}
+
+/// The bytes under the bits if they start and end at byte boundaries, so they can be
+/// borrowed (or copied in bulk) instead of being read bit by bit.
+fn aligned<O: WireOrder>(bits: &BitSlice<u8, O>) -> Option<&[u8]> {
+    match bits.domain() {
+        Domain::Region {
+            head: None,
+            body,
+            tail: None,
+        } => Some(body),
+        _ => None,
+    }
+}
+
+/// Internal struct that hands out the elements of a tuple outside the self-describing mode:
+/// as many values as the tuple has elements.
+struct TupleDeserializer<'a, 'de: 'a, O: WireOrder> {
+    deserializer: &'a mut CustomDeserializer<'de, O>,
+    index: usize,
+    len: usize,
+}
+impl<'de, O: WireOrder> SeqAccess<'de> for TupleDeserializer<'_, 'de, O> {
+    type Error = Error;
+
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
     where
         T: serde::de::DeserializeSeed<'de>,
     {
-        // if at end of sequence; exit
-        if self.deserializer.peek_token(Delimiter::Seq)? {
+        if self.index == self.len {
             return Ok(None);
         }
-        // if not first and not at the end of sequence; eat SEQ_VALUE_DELIMITER
-        if !self.first {
-            if !self.deserializer.peek_token(Delimiter::SeqValue)? {
-                return Err(Error::ExpectedDelimiter(Delimiter::SeqValue));
-            }
-            self.deserializer.eat_token(Delimiter::SeqValue)?;
+        self.deserializer.tick()?;
+        self.index += 1;
+        seed.deserialize(&mut *self.deserializer)
+            .map(Some)
+            .map_err(|error| error.nested(PathSegment::Element(self.index - 1)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len - self.index)
+    }
+}
+
+/// Internal struct that hands out the fields of a struct whose field names are omitted
+/// (see [`StructKeys::Omit`]): as many values as the struct has fields. With option bitmaps,
+/// the bitmap tells which fields are present. With aligned fields, every present field starts
+/// on a byte boundary.
+struct FieldsDeserializer<'a, 'de: 'a, O: WireOrder> {
+    deserializer: &'a mut CustomDeserializer<'de, O>,
+    bitmap: Option<&'de BitSlice<u8, O>>,
+    index: usize,
+    fields: &'static [&'static str],
+}
+impl<'de, O: WireOrder> SeqAccess<'de> for FieldsDeserializer<'_, 'de, O> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: serde::de::DeserializeSeed<'de>,
+    {
+        let Some(field) = self.fields.get(self.index) else {
+            return Ok(None);
+        };
+        self.deserializer.tick()?;
+        let present = match self.bitmap {
+            Some(bitmap) => *bitmap.get(self.index).ok_or(Error::UnexpectedEOF)?,
+            None => true,
+        };
+        if present && self.deserializer.config.aligned_fields {
+            self.deserializer.eat_padding()?;
+        }
+        if self.bitmap.is_some() {
+            let position = self.deserializer.data.len();
+            self.deserializer.implied = Some((position, present));
+        }
+        self.index += 1;
+        seed.deserialize(&mut *self.deserializer)
+            .map(Some)
+            .map_err(|error| {
+                in_field(error, self.deserializer.container(), field)
+                    .nested(PathSegment::Field(field))
+            })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len().saturating_sub(self.index))
+    }
+}
+
+/// The offsets of the entries of a map (see [`Config::offset_tables`]).
+#[derive(Debug, Clone)]
+pub(crate) struct OffsetTable<'de, O: WireOrder = bv::Lsb0> {
+    // The data from the first entry on.
+    entries: &'de BitSlice<u8, O>,
+    // The offsets of the entries followed by the offset of the end of the entries.
+    offsets: Vec<usize>,
+}
+impl<'de, O: WireOrder> OffsetTable<'de, O> {
+    /// The number of entries.
+    fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    /// The data from the entry at `index` on (or from the end of the entries on if `index`
+    /// equals the number of entries).
+    fn from(&self, index: usize) -> Result<&'de BitSlice<u8, O>, Error> {
+        let offset = *self.offsets.get(index).ok_or(Error::UnexpectedEOF)?;
+        self.entries.get(offset..).ok_or(Error::UnexpectedEOF)
+    }
+
+    /// The data of the entry at `index`.
+    fn entry(&self, index: usize) -> Result<&'de BitSlice<u8, O>, Error> {
+        let (start, end) = match self.offsets.get(index..index + 2) {
+            Some(&[start, end]) => (start, end),
+            _ => return Err(Error::UnexpectedEOF),
+        };
+        self.entries.get(start..end).ok_or(Error::UnexpectedEOF)
+    }
+}
+
+/// The block of integer keys of a map (see [`Config::sorted_int_keys`]).
+#[derive(Debug, Clone)]
+pub(crate) struct SortedKeys<'de, O: WireOrder = bv::Lsb0> {
+    // The keys one after the other (little endian).
+    keys: &'de BitSlice<u8, O>,
+    // The size of a key in bytes.
+    pub(crate) size: usize,
+    // Whether the keys are in ascending order.
+    sorted: bool,
+}
+impl<O: WireOrder> SortedKeys<'_, O> {
+    /// The number of keys.
+    pub(crate) fn len(&self) -> usize {
+        self.keys.len() / (self.size * 8)
+    }
+
+    /// The key at `index`.
+    pub(crate) fn key(&self, index: usize) -> Result<u64, Error> {
+        let bits = self.size * 8;
+        let key = index
+            .checked_mul(bits)
+            .and_then(|start| self.keys.get(start..start.checked_add(bits)?));
+        Ok(O::load(key.ok_or(Error::UnexpectedEOF)?))
+    }
+
+    /// Deserialize the key at `index` as the unsigned integer type it was written as.
+    fn deserialize<'de, K>(&self, index: usize, seed: K) -> Result<K::Value, Error>
+    where
+        K: serde::de::DeserializeSeed<'de>,
+    {
+        let key = self.key(index)?;
+        match self.size {
+            1 => seed.deserialize((key as u8).into_deserializer()),
+            2 => seed.deserialize((key as u16).into_deserializer()),
+            4 => seed.deserialize((key as u32).into_deserializer()),
+            _ => seed.deserialize(key.into_deserializer()),
         }
-        // make not first; deserialize next element
-        self.first = false;
-        seed.deserialize(&mut *self.deserializer).map(Some)
     }
 }
 
 /// Internal struct that handles the deserialization of a map.
-/// map() => key_1 + MAP_KEY_DELIMITER + value_1 + MAP_VALUE_DELIMITER + ... + MAP_DELIMITER
-struct MapDeserializer<'a, 'de: 'a> {
-    deserializer: &'a mut CustomDeserializer<'de>,
-    first: bool,
+/// map() => MAP_KEY_DELIMITER + key_1 + MAP_VALUE_DELIMITER + value_1 + ... + MAP_DELIMITER
+struct MapDeserializer<'a, 'de: 'a, O: WireOrder> {
+    deserializer: &'a mut CustomDeserializer<'de, O>,
+    fields: Option<&'static [&'static str]>,
+    index: usize,
+    table: Option<OffsetTable<'de, O>>,
+    keys: Option<SortedKeys<'de, O>>,
+    unknown: Option<String>,
+    // The struct field whose value is next.
+    field: Option<&'static str>,
 }
-impl<'a, 'de> MapDeserializer<'a, 'de> {
-    pub fn new(deserializer: &'a mut CustomDeserializer<'de>) -> Self {
+impl<'a, 'de, O: WireOrder> MapDeserializer<'a, 'de, O> {
+    pub fn new(
+        deserializer: &'a mut CustomDeserializer<'de, O>,
+        fields: Option<&'static [&'static str]>,
+        table: Option<OffsetTable<'de, O>>,
+        keys: Option<SortedKeys<'de, O>>,
+    ) -> Self {
         Self {
             deserializer,
-            first: true,
+            fields,
+            index: 0,
+            table,
+            keys,
+            unknown: None,
+            field: None,
+        }
+    }
+
+    /// Read the key in front if it's not among the known struct fields, e.g. a field added
+    /// by a newer writer. Known keys are left in place and remembered as the next field.
+    fn unknown_key(&mut self) -> Result<Option<String>, Error> {
+        let Some(fields) = self.fields else {
+            return Ok(None);
+        };
+        let snapshot = self.deserializer.data;
+        self.deserializer.expect_tag(TypeTag::Str)?;
+        let key = self.deserializer.parse_str()?;
+        if let Some(field) = fields.iter().find(|field| **field == key) {
+            self.deserializer.data = snapshot;
+            self.field = Some(field);
+            return Ok(None);
+        }
+        Ok(Some(key.into_owned()))
+    }
+
+    /// Skip the value of the unknown field `key`; jumping to the next entry with an offset
+    /// table.
+    fn skip_unknown_value(&mut self, key: String) -> Result<(), Error> {
+        if let Some(table) = &self.table {
+            self.deserializer.data = table.from(self.index + 1)?;
+            return Ok(());
+        }
+        self.deserializer
+            .expect_token(Production::Map.item(1), self.index)?;
+        // the limits (e.g. of the depth) still apply to the skipped value.
+        self.deserializer.skip_value().map_err(|error| match error {
+            Error::SelfDescribingRequired(_) => Error::UnknownField(key),
+            error => error,
+        })
+    }
+
+    /// The number of entries if the map tells it up front, in its block of keys or its offset
+    /// table.
+    fn known_count(&self) -> Option<usize> {
+        match (&self.keys, &self.table) {
+            (Some(keys), _) => Some(keys.len()),
+            (None, Some(table)) => Some(table.len()),
+            (None, None) => None,
+        }
+    }
+
+    /// Check that a map with `count` entries stays within the limit of the config (see
+    /// [`Config::max_map_entries`]). Structs aren't limited.
+    fn check_entries(&self, count: usize) -> Result<(), Error> {
+        match self.deserializer.config.max_map_entries {
+            Some(max) if self.fields.is_none() && count > max => {
+                Err(Error::TooManyMapEntries { max, path: vec![] })
+            }
+            _ => Ok(()),
         }
     }
+
+    /// Eat the closing MAP_DELIMITER.
+    pub fn end(self) -> Result<(), Error> {
+        self.deserializer
+            .expect_token(Production::Map.last(), self.index)
+    }
 }
-impl<'de, 'a> MapAccess<'de> for MapDeserializer<'a, 'de> {
+impl<'de, O: WireOrder> MapAccess<'de> for MapDeserializer<'_, 'de, O> {
     type Error = Error;
 
     /// Grab the next key from the data and remove it.
+    /// - If the keys are in a block; deserialize the next one from the block.
     /// - If at end of map; exit.
-    /// - Make not first; deserialize next key_1.
-    /// - Deserialize next value.
-    /// - Eat MAP_KEY_DELIMITER.
+    /// - Eat MAP_KEY_DELIMITER (and the length of the key if keys are prefixed with it).
+    /// - Deserialize next key; an unknown struct field is handed to the visitor by name so
+    ///   `#[serde(deny_unknown_fields)]` can reject it.
+    /// - Check that the key took up its length.
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
     where
         K: serde::de::DeserializeSeed<'de>,
     {
+        self.deserializer.tick()?;
+        if let Some(keys) = &self.keys {
+            if self.index == keys.len() {
+                return Ok(None);
+            }
+            return keys.deserialize(self.index, seed).map(Some);
+        }
         // if at end of map; exit
-        if self.deserializer.peek_token(Delimiter::Map)? {
+        if self.deserializer.peek_token(Production::Map.last())? {
             return Ok(None);
         }
-        // make not first; deserialize next key_1
-        self.first = false;
-        let value = seed.deserialize(&mut *self.deserializer).map(Some)?;
-        if !self.deserializer.peek_token(Delimiter::MapKey)? {
-            return Err(Error::ExpectedDelimiter(Delimiter::MapKey));
-        }
-        self.deserializer.eat_token(Delimiter::MapKey)?;
-        Ok(value)
+        self.check_entries(self.index + 1)?;
+        let prefixed = self.deserializer.expect_key(self.index)?;
+        let start = self.deserializer.data;
+        // deserialize next key
+        let key = match self.unknown_key()? {
+            Some(name) => {
+                let key = seed
+                    .deserialize(name.as_str().into_deserializer())
+                    .map_err(|_: Error| Error::UnknownField(name.clone()))?;
+                self.unknown = Some(name);
+                key
+            }
+            None if self.fields.is_none() => self.deserializer.deserialize_key(seed)?,
+            None => seed.deserialize(&mut *self.deserializer)?,
+        };
+        self.deserializer.check_key(start, prefixed, self.index)?;
+        Ok(Some(key))
     }
 
     /// Grab the next value from the data and remove it.
-    /// - Deserialize next value.
+    /// - Skip the value of an unknown struct field; the visitor ignores it.
     /// - Eat MAP_VALUE_DELIMITER.
+    /// - Deserialize next value; move to the next index.
     /// - Return value.
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::DeserializeSeed<'de>,
     {
-        let value = seed.deserialize(&mut *self.deserializer)?;
-        if !self.deserializer.peek_token(Delimiter::MapValue)? {
-            return Err(Error::ExpectedDelimiter(Delimiter::MapValue));
+        if let Some(key) = self.unknown.take() {
+            self.skip_unknown_value(key)?;
+            self.index += 1;
+            return seed.deserialize(().into_deserializer());
+        }
+        self.deserializer
+            .expect_token(Production::Map.item(1), self.index)?;
+        if self.fields.is_some() && self.deserializer.config.option_bitmaps {
+            self.deserializer.implied = Some((self.deserializer.data.len(), true));
         }
-        self.deserializer.eat_token(Delimiter::MapValue)?;
+        let field = self.field.take();
+        let value = seed
+            .deserialize(&mut *self.deserializer)
+            .map_err(|error| match field {
+                Some(field) => in_field(error, self.deserializer.container(), field)
+                    .nested(PathSegment::Field(field)),
+                None => error.nested(PathSegment::Entry(self.index)),
+            })?;
+        self.index += 1;
         Ok(value)
     }
+
+    /// The number of entries left if the map tells how many it has up front, so visitors
+    /// (e.g. of a `HashMap`) allocate them at once.
+    fn size_hint(&self) -> Option<usize> {
+        self.known_count()
+            .map(|count| count.saturating_sub(self.index))
+    }
 }
@@ -0,0 +1,73 @@
+//! ### Document
+//! A module for an envelope around a value with the metadata services tend to put in front
+//! of it: when it was created, what kind of content it is and the id of its schema. The
+//! envelope is serialized like any other struct, with the value as its last field.
+//!
+//! - document: created_at (nanoseconds since the unix epoch, i64) + content_type (str) +
+//!   schema_id (u32) + payload
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{config::Config, document::Document};
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! struct Reading {
+//!     sensor: u16,
+//!     value: f32,
+//! }
+//!
+//! let config = Config::default();
+//! let document = Document::new("sensor/reading", 3, Reading { sensor: 7, value: 21.5 });
+//! let bytes = document.seal(&config).unwrap();
+//!
+//! let opened = Document::<Reading>::open(&bytes, &config).unwrap();
+//! assert_eq!(opened.content_type, "sensor/reading");
+//! assert_eq!(opened.schema_id, 3);
+//! assert_eq!(opened, document);
+//! ```
+
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use super::{config::Config, deserializer, error::Error, serializer};
+
+/// A value along with when it was created, the type of its content and the id of its schema.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Document<T> {
+    /// When the document was created.
+    #[serde(with = "crate::with::system_time")]
+    pub created_at: SystemTime,
+    /// What the payload is, e.g. a media type.
+    pub content_type: String,
+    /// The id of the schema of the payload.
+    pub schema_id: u32,
+    /// The value.
+    pub payload: T,
+}
+
+impl<T> Document<T> {
+    /// A document around `payload` created now.
+    pub fn new(content_type: impl Into<String>, schema_id: u32, payload: T) -> Self {
+        Self {
+            created_at: SystemTime::now(),
+            content_type: content_type.into(),
+            schema_id,
+            payload,
+        }
+    }
+}
+
+impl<T: Serialize> Document<T> {
+    /// Serialize the document with the given [`Config`].
+    pub fn seal(&self, config: &Config) -> Result<Vec<u8>, Error> {
+        serializer::to_bytes_with_config(self, config)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Document<T> {
+    /// Deserialize a document that was sealed with the given [`Config`].
+    pub fn open(bytes: &'de [u8], config: &Config) -> Result<Self, Error> {
+        deserializer::from_bytes_with_config(bytes, config)
+    }
+}
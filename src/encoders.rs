@@ -0,0 +1,337 @@
+//! ### Encoders
+//! A registry of custom encodings for newtype structs, matched by the name they pass to
+//! `Serializer::serialize_newtype_struct`. Domain types (e.g. decimals or big integers that
+//! serde only knows as strings) can get a compact bespoke encoding this way without forking
+//! the serializer.
+//!
+//! An encoder turns the [`Primitive`] inside the newtype into bytes and a decoder turns the
+//! bytes back into a primitive which is handed to the newtype's `Deserialize` impl. The bytes
+//! are written as their length (u32), padding up to the next byte boundary and the bytes.
+//!
+//! - The same encoders must be registered on both sides; the header doesn't record them.
+//! - Names are only unique within a crate, so every newtype named like a registered one is
+//!   encoded with it.
+//! - Encoders aren't used in the self-describing mode (see
+//!   [`Config::self_describing`](crate::config::Config::self_describing)) since
+//!   `deserialize_any` couldn't tell which decoder to call.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{
+//!     config::Config,
+//!     deserializer,
+//!     encoders::{Encoders, Primitive},
+//!     error::Error,
+//!     serializer,
+//! };
+//!
+//! // a decimal that serde only knows as a string, e.g. "-1234567.891011".
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! struct Decimal(String);
+//!
+//! // as its digits (i64) and the number of them after the point (u8).
+//! let mut encoders = Encoders::new();
+//! encoders.register(
+//!     "Decimal",
+//!     |value| {
+//!         let Primitive::Str(value) = value else {
+//!             return Err(Error::SerializationError("a decimal is a string".to_string()));
+//!         };
+//!         let scale = value.find('.').map_or(0, |point| value.len() - point - 1) as u8;
+//!         let digits = value.replace('.', "").parse::<i64>().map_err(|error| {
+//!             Error::SerializationError(error.to_string())
+//!         })?;
+//!         let mut bytes = digits.to_le_bytes().to_vec();
+//!         bytes.push(scale);
+//!         Ok(bytes)
+//!     },
+//!     |bytes| {
+//!         let (digits, scale) = bytes.split_at(8);
+//!         let digits = i64::from_le_bytes(digits.try_into().unwrap()).to_string();
+//!         let (whole, fraction) = digits.split_at(digits.len() - scale[0] as usize);
+//!         Ok(Primitive::Str(match fraction.is_empty() {
+//!             true => whole.to_string(),
+//!             false => format!("{whole}.{fraction}"),
+//!         }))
+//!     },
+//! );
+//!
+//! let config = Config::default();
+//! let prices = vec![
+//!     Decimal("-1234567.891011".to_string()),
+//!     Decimal("31415926.5358979".to_string()),
+//! ];
+//! let bytes = serializer::to_bytes_with_encoders(&prices, &config, &encoders).unwrap();
+//! let decoded =
+//!     deserializer::from_bytes_with_encoders::<Vec<Decimal>>(&bytes, &config, &encoders).unwrap();
+//! assert_eq!(prices, decoded);
+//! assert!(bytes.len() < serializer::to_bytes(&prices).unwrap().len());
+//! ```
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use serde::{
+    de::Visitor,
+    forward_to_deserialize_any,
+    ser::{self, Impossible},
+    Serialize,
+};
+
+use super::error::Error;
+
+/// The value inside a newtype struct that is handed to an encoder (and returned by a
+/// decoder). Integers and floats are widened to 64 (or 128) bits.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Primitive {
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    I128(i128),
+    U128(u128),
+    F64(f64),
+    Char(char),
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+type Encode = dyn Fn(&Primitive) -> Result<Vec<u8>, Error> + Send + Sync;
+type Decode = dyn Fn(&[u8]) -> Result<Primitive, Error> + Send + Sync;
+
+// The encoder & decoder of a newtype struct.
+#[derive(Clone)]
+pub(crate) struct Encoder {
+    pub(crate) encode: Arc<Encode>,
+    pub(crate) decode: Arc<Decode>,
+}
+
+/// The custom encodings of newtype structs by their names.
+#[derive(Clone, Default)]
+pub struct Encoders {
+    encoders: HashMap<&'static str, Encoder>,
+}
+
+impl Encoders {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encode the newtype structs named `name` with `encode` and decode them with `decode`,
+    /// replacing an earlier encoding of the name.
+    pub fn register<E, D>(&mut self, name: &'static str, encode: E, decode: D) -> &mut Self
+    where
+        E: Fn(&Primitive) -> Result<Vec<u8>, Error> + Send + Sync + 'static,
+        D: Fn(&[u8]) -> Result<Primitive, Error> + Send + Sync + 'static,
+    {
+        self.encoders.insert(
+            name,
+            Encoder {
+                encode: Arc::new(encode),
+                decode: Arc::new(decode),
+            },
+        );
+        self
+    }
+
+    /// Whether newtype structs named `name` have a custom encoding.
+    pub fn contains(&self, name: &str) -> bool {
+        self.encoders.contains_key(name)
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Encoder> {
+        self.encoders.get(name)
+    }
+}
+
+impl fmt::Debug for Encoders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.encoders.keys()).finish()
+    }
+}
+
+/// Capture the primitive a newtype struct wraps.
+pub(crate) fn capture<T: ?Sized + Serialize>(value: &T) -> Result<Primitive, Error> {
+    value.serialize(Capture)
+}
+
+// A serializer that returns the primitive it is given and rejects everything else.
+struct Capture;
+
+fn unsupported<T>() -> Result<T, Error> {
+    Err(Error::SerializationError(
+        "a custom encoder only takes a newtype struct around a primitive".to_string(),
+    ))
+}
+
+impl ser::Serializer for Capture {
+    type Ok = Primitive;
+    type Error = Error;
+    type SerializeSeq = Impossible<Primitive, Error>;
+    type SerializeTuple = Impossible<Primitive, Error>;
+    type SerializeTupleStruct = Impossible<Primitive, Error>;
+    type SerializeTupleVariant = Impossible<Primitive, Error>;
+    type SerializeMap = Impossible<Primitive, Error>;
+    type SerializeStruct = Impossible<Primitive, Error>;
+    type SerializeStructVariant = Impossible<Primitive, Error>;
+
+    // Like the rust-fr serializer the primitive is captured for.
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<Primitive, Error> {
+        Ok(Primitive::Bool(v))
+    }
+    fn serialize_i8(self, v: i8) -> Result<Primitive, Error> {
+        Ok(Primitive::I64(v.into()))
+    }
+    fn serialize_i16(self, v: i16) -> Result<Primitive, Error> {
+        Ok(Primitive::I64(v.into()))
+    }
+    fn serialize_i32(self, v: i32) -> Result<Primitive, Error> {
+        Ok(Primitive::I64(v.into()))
+    }
+    fn serialize_i64(self, v: i64) -> Result<Primitive, Error> {
+        Ok(Primitive::I64(v))
+    }
+    fn serialize_i128(self, v: i128) -> Result<Primitive, Error> {
+        Ok(Primitive::I128(v))
+    }
+    fn serialize_u8(self, v: u8) -> Result<Primitive, Error> {
+        Ok(Primitive::U64(v.into()))
+    }
+    fn serialize_u16(self, v: u16) -> Result<Primitive, Error> {
+        Ok(Primitive::U64(v.into()))
+    }
+    fn serialize_u32(self, v: u32) -> Result<Primitive, Error> {
+        Ok(Primitive::U64(v.into()))
+    }
+    fn serialize_u64(self, v: u64) -> Result<Primitive, Error> {
+        Ok(Primitive::U64(v))
+    }
+    fn serialize_u128(self, v: u128) -> Result<Primitive, Error> {
+        Ok(Primitive::U128(v))
+    }
+    fn serialize_f32(self, v: f32) -> Result<Primitive, Error> {
+        Ok(Primitive::F64(v.into()))
+    }
+    fn serialize_f64(self, v: f64) -> Result<Primitive, Error> {
+        Ok(Primitive::F64(v))
+    }
+    fn serialize_char(self, v: char) -> Result<Primitive, Error> {
+        Ok(Primitive::Char(v))
+    }
+    fn serialize_str(self, v: &str) -> Result<Primitive, Error> {
+        Ok(Primitive::Str(v.to_string()))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Primitive, Error> {
+        Ok(Primitive::Bytes(v.to_vec()))
+    }
+    fn serialize_none(self) -> Result<Primitive, Error> {
+        unsupported()
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<Primitive, Error> {
+        unsupported()
+    }
+    fn serialize_unit(self) -> Result<Primitive, Error> {
+        unsupported()
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Primitive, Error> {
+        unsupported()
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Primitive, Error> {
+        unsupported()
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Primitive, Error> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Primitive, Error> {
+        unsupported()
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        unsupported()
+    }
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        unsupported()
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        unsupported()
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        unsupported()
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        unsupported()
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        unsupported()
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        unsupported()
+    }
+}
+
+// A decoded primitive is handed to the newtype's `Deserialize` impl as a deserializer.
+impl<'de> serde::Deserializer<'de> for Primitive {
+    type Error = Error;
+
+    // Like the rust-fr deserializer the primitive stands in for.
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Primitive::Bool(v) => visitor.visit_bool(v),
+            Primitive::I64(v) => visitor.visit_i64(v),
+            Primitive::U64(v) => visitor.visit_u64(v),
+            Primitive::I128(v) => visitor.visit_i128(v),
+            Primitive::U128(v) => visitor.visit_u128(v),
+            Primitive::F64(v) => visitor.visit_f64(v),
+            Primitive::Char(v) => visitor.visit_char(v),
+            Primitive::Str(v) => visitor.visit_string(v),
+            Primitive::Bytes(v) => visitor.visit_byte_buf(v),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
@@ -1,10 +1,19 @@
 //! ### Error
 //! A module for the error type used in the library. It is a simple enum with a variant for each
-//! error that can occur in the library. It uses `thiserror` internally.
+//! error that can occur in the library. It uses `thiserror` internally. The enum is
+//! `#[non_exhaustive]`, so new errors can be added without a breaking release.
+//!
+//! [`Error::category`] tells what kind of failure an error is (see [`ErrorCategory`]), e.g. so a
+//! loop reading records can skip a corrupt one, set aside one it can't handle and stop when
+//! the reader fails, without matching on the messages.
 
-use super::serializer::Delimiter;
+use super::{
+    config::Config,
+    protocol::format::{Delimiter, TypeTag},
+};
 
 #[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
     #[error("could not get the last bit from the data.")]
     NoBit,
@@ -24,17 +33,309 @@ pub enum Error {
     #[error("calls to {0} are not supported")]
     UnsupportedCall(String),
 
+    #[error("calls to {0} need the self-describing mode (see `Config::self_describing`)")]
+    SelfDescribingRequired(&'static str),
+
     #[error("unexpected end of file")]
     UnexpectedEOF,
 
+    #[error(
+        "a length of {length} bytes read from the data runs past its end ({remaining} bits are \
+         left)"
+    )]
+    LengthOutOfBounds {
+        /// The length (in bytes) that was read, e.g. of a packed sequence or a block of keys.
+        length: u64,
+        /// The number of bits left in the data.
+        remaining: usize,
+    },
+
     #[error("invalid type size")]
     InvalidTypeSize,
 
     #[error("type conversion error")]
     ConversionError,
 
-    #[error("expected delimiter {0}")]
-    ExpectedDelimiter(Delimiter),
+    #[error(
+        "expected delimiter {expected} but found {} at index {index} of {container} at depth \
+         {depth}",
+        found.map_or("the end of the data".to_string(), |bits| format!("{bits:#b}"))
+    )]
+    ExpectedDelimiter {
+        /// The delimiter that was expected.
+        expected: Delimiter,
+        /// The bits that were found instead (as many as the expected delimiter is wide)
+        /// or `None` if the data ended.
+        found: Option<u8>,
+        /// The container that was being deserialized.
+        container: Container,
+        /// The index of the element (or entry) of the container that was being deserialized.
+        index: usize,
+        /// The number of containers the container is nested in (0 for the outermost one).
+        depth: usize,
+    },
+
+    #[error("invalid type tag {0}")]
+    InvalidTypeTag(u8),
+
+    #[error("expected {expected} but found the type tag {found}")]
+    UnexpectedTypeTag {
+        expected: &'static str,
+        found: TypeTag,
+    },
+
+    #[error("unknown field `{0}` is denied or can not be skipped")]
+    UnknownField(String),
+
+    #[error("the deserialization was cancelled")]
+    Cancelled,
+
+    #[error("the header {0:#06x} has options this version doesn't know")]
+    InvalidHeader(u16),
+
+    #[error("the data was serialized with {found:?} but is deserialized with {expected:?}")]
+    ConfigMismatch {
+        /// The config of the deserializer.
+        expected: Config,
+        /// The config in the header of the data.
+        found: Config,
+    },
+
+    #[error("could not read or write the data at byte {position}: {source}")]
+    Io {
+        /// The error of the reader or writer.
+        source: std::io::Error,
+        /// The position in the data (in bytes) of the read or write that failed.
+        position: usize,
+    },
+
+    #[error("a record of {length} bytes is larger than the limit of {max} bytes")]
+    RecordTooLarge {
+        /// The length of the record (in bytes) read from the data.
+        length: usize,
+        /// The limit of the reader.
+        max: usize,
+    },
+
+    #[error("the stream starts with {0:?} instead of the start of a stream")]
+    InvalidStreamStart([u8; 4]),
+
+    #[error("unknown test vector {0}")]
+    UnknownTestVector(String),
+
+    #[error("the test vector {name} expected the bytes {expected:?} but found {found:?}")]
+    TestVectorMismatch {
+        name: &'static str,
+        expected: Vec<u8>,
+        found: Vec<u8>,
+    },
+
+    #[error("invalid fixture: {0}")]
+    InvalidFixture(String),
+
+    #[error("the fixture {name} of format version {version} breaks: {reason}")]
+    FixtureMismatch {
+        name: String,
+        /// The format version the fixture was recorded with.
+        version: u32,
+        /// What changed.
+        reason: String,
+    },
+
+    #[error("the chunk {0} of the manifest is missing")]
+    MissingChunk(usize),
+
+    #[error("the chunk {0} does not match its length or hash in the manifest")]
+    CorruptChunk(usize),
+
+    #[error("the new value of the field {field} has {found} bits instead of {expected}")]
+    PatchSizeMismatch {
+        field: &'static str,
+        /// The number of bits of the value in the data.
+        expected: usize,
+        /// The number of bits of the new value.
+        found: usize,
+    },
+
+    #[error("the map at {} has more than {max} entries", path_to_string(path))]
+    TooManyMapEntries {
+        /// The limit of the config (see [`Config::max_map_entries`]).
+        max: usize,
+        /// Where the map is, from the outermost value in.
+        path: Vec<PathSegment>,
+    },
+
+    #[error("the value is nested more than {max} levels deep")]
+    TooDeep {
+        /// The limit of the config (see [`Config::max_depth`]).
+        max: usize,
+    },
+
+    #[error("the variant {index} of {name} is unknown (it has {variants} variants)")]
+    UnknownVariant {
+        name: &'static str,
+        /// The index read from the data.
+        index: u32,
+        /// The number of variants the enum has.
+        variants: usize,
+    },
+
+    #[error(
+        "the key of the entry {index} of a map takes up {found} bits instead of its length of \
+         {expected} bits"
+    )]
+    KeyLengthMismatch {
+        /// The index of the entry.
+        index: usize,
+        /// The length in front of the key (see [`Config::length_prefixed_keys`]).
+        expected: usize,
+        /// The number of bits the key took up when it was read.
+        found: usize,
+    },
+
+    #[error("the field {field} of {name} is invalid: {reason}")]
+    Invalid {
+        /// The name of the struct (or of the struct variant).
+        name: &'static str,
+        field: &'static str,
+        /// Why the value of the field is invalid.
+        reason: String,
+    },
+}
+
+/// The kind of container a delimiter was expected in. It is used to give context
+/// to [`Error::ExpectedDelimiter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Container {
+    /// A standalone value that is not part of a sequence or a map.
+    Value,
+    Seq,
+    Tuple,
+    TupleStruct(&'static str),
+    TupleVariant,
+    Map,
+    Struct(&'static str),
+    StructVariant,
+}
+
+impl std::fmt::Display for Container {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Container::Value => write!(f, "value"),
+            Container::Seq => write!(f, "seq"),
+            Container::Tuple => write!(f, "tuple"),
+            Container::TupleStruct(name) => write!(f, "tuple struct {name}"),
+            Container::TupleVariant => write!(f, "tuple variant"),
+            Container::Map => write!(f, "map"),
+            Container::Struct(name) => write!(f, "struct {name}"),
+            Container::StructVariant => write!(f, "struct variant"),
+        }
+    }
+}
+
+/// A step on the way from the outermost value to a nested one. It is used to give context to
+/// [`Error::TooManyMapEntries`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// A field of a struct (or of a struct variant).
+    Field(&'static str),
+    /// The element at an index of a sequence or a tuple.
+    Element(usize),
+    /// The value of the entry at an index of a map.
+    Entry(usize),
+}
+
+impl std::fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathSegment::Field(name) => write!(f, ".{name}"),
+            PathSegment::Element(index) => write!(f, "[{index}]"),
+            PathSegment::Entry(index) => write!(f, "{{{index}}}"),
+        }
+    }
+}
+
+/// The path as `$` (the outermost value) followed by its segments, e.g. `$.users[3]{0}` for
+/// the value of the first entry of a map in the fourth element of the field `users`.
+fn path_to_string(path: &[PathSegment]) -> String {
+    path.iter()
+        .fold("$".to_string(), |path, segment| format!("{path}{segment}"))
+}
+
+/// What kind of failure an [`Error`] is; see [`Error::category`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// The data isn't a valid encoding, e.g. it is truncated or garbled. The record can be
+    /// skipped.
+    Corruption,
+    /// The data (or the value) is valid but doesn't match the type, the config or the version
+    /// it is read (or written) with, e.g. a field is missing. The record can be set aside
+    /// until a reader that knows it comes along.
+    SchemaMismatch,
+    /// A limit was hit, e.g. of the depth, of the entries of a map or of the length of a
+    /// record, or the work was cancelled.
+    ResourceLimit,
+    /// The reader or the writer failed; what follows can't be trusted.
+    Io,
+}
+
+impl Error {
+    /// The kind of failure the error is.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::NoBit
+            | Error::NoByte
+            | Error::NLargerThanLength(..)
+            | Error::UnexpectedEOF
+            | Error::LengthOutOfBounds { .. }
+            | Error::InvalidTypeSize
+            | Error::ConversionError
+            | Error::ExpectedDelimiter { .. }
+            | Error::InvalidTypeTag(_)
+            | Error::InvalidStreamStart(_)
+            | Error::InvalidFixture(_)
+            | Error::MissingChunk(_)
+            | Error::CorruptChunk(_)
+            | Error::KeyLengthMismatch { .. } => ErrorCategory::Corruption,
+            Error::SerializationError(_)
+            | Error::DeserializationError(_)
+            | Error::UnsupportedCall(_)
+            | Error::SelfDescribingRequired(_)
+            | Error::UnexpectedTypeTag { .. }
+            | Error::UnknownField(_)
+            | Error::InvalidHeader(_)
+            | Error::ConfigMismatch { .. }
+            | Error::UnknownTestVector(_)
+            | Error::TestVectorMismatch { .. }
+            | Error::FixtureMismatch { .. }
+            | Error::PatchSizeMismatch { .. }
+            | Error::UnknownVariant { .. }
+            | Error::Invalid { .. } => ErrorCategory::SchemaMismatch,
+            Error::Cancelled
+            | Error::RecordTooLarge { .. }
+            | Error::TooManyMapEntries { .. }
+            | Error::TooDeep { .. } => ErrorCategory::ResourceLimit,
+            Error::Io { .. } => ErrorCategory::Io,
+        }
+    }
+
+    /// Whether the records after the one that failed can still be read: every error but the
+    /// failures of the reader (or writer) and cancellations.
+    pub fn is_recoverable(&self) -> bool {
+        !matches!(self, Error::Io { .. } | Error::Cancelled)
+    }
+
+    /// Put `segment` in front of the path of an error that has one, as the error leaves the
+    /// value the segment leads to.
+    pub(crate) fn nested(self, segment: PathSegment) -> Error {
+        match self {
+            Error::TooManyMapEntries { max, mut path } => {
+                path.insert(0, segment);
+                Error::TooManyMapEntries { max, path }
+            }
+            error => error,
+        }
+    }
 }
 
 impl serde::ser::Error for Error {
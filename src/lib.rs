@@ -1,8 +1,82 @@
 //! ### rust-fr
-//! A simple, non-self-describing data-interchange format. It exposes two modules, `serializer`
-//! and `deserializer`, for serializing and deserializing data which contain [`to_bytes`](serializer::to_bytes),
-//! [`from_bytes`](deserializer::from_bytes) functions which do exactly what their names suggest.
+//! A simple, compact data-interchange format (non-self-describing by default). It exposes two
+//! modules, `serializer` and `deserializer`, for serializing and deserializing data which
+//! contain [`to_bytes`](serializer::to_bytes), [`from_bytes`](deserializer::from_bytes)
+//! functions which do exactly what their names suggest.
 //! - The data to be encoded & decoded must implement the `serde::Serialize` and `serde::Deserialize` traits.
+//! - The [`config`] module holds the options (e.g. a self-describing mode) accepted by
+//!   [`to_bytes_with_config`](serializer::to_bytes_with_config) and
+//!   [`from_bytes_with_config`](deserializer::from_bytes_with_config).
+//! - [`to_bitvec`](serializer::to_bitvec) and [`from_bitslice`](deserializer::from_bitslice)
+//!   work on the exact bits of the data (see the re-exported [`bitvec`]) instead of whole bytes.
+//!   The bits are packed least significant bit first unless
+//!   [`Config::msb_first`](config::Config::msb_first) is set.
+//! - [`to_writer`](serializer::to_writer) writes the data to an `io::Write` while it is
+//!   serialized.
+//! - [`MapWriter`](serializer::MapWriter) and [`MapReader`](deserializer::MapReader) write and
+//!   read very large maps (e.g. key-value dumps) one entry at a time.
+//! - The [`seed`] module deserializes into existing collections to reuse their allocations.
+//! - The [`protocol`] module defines the wire format (delimiters, type tags and bit widths)
+//!   shared by both sides.
+//! - The [`tokens`] module reads and writes self-describing data as a stream of events,
+//!   without serde.
+//! - The [`spec`] module generates the format specification from the code.
+//! - The [`overhead`] module has the number of bits the format adds to each construct and
+//!   estimates the size of values; [`overhead::MaxEncodedLen`] bounds the size of types with a
+//!   bounded size at compile time.
+//! - The [`with`] module has compact encodings of `Duration` and `SystemTime` for
+//!   `#[serde(with = "...")]`, and adapters (hex, base64, RFC 3339 timestamps, bitsets) that
+//!   are text in JSON but binary in rust-fr.
+//! - The [`conformance`] module holds the golden test vectors (also in `testdata/`) which
+//!   other implementations can check themselves against.
+//! - The [`analyze`] module reports where the values of a type can collide with the delimiters
+//!   of strings and bytes, and checks sample values for such collisions.
+//! - The [`schema`] module extracts the shape of a type from its `Deserialize` implementation
+//!   and the [`codegen`] module generates decoders in other languages (JavaScript) from it.
+//! - The [`replay`] module records payloads into fixture files per format version and replays
+//!   the files of older releases to catch accidental changes of the wire format.
+//! - The [`encoders`] module registers compact custom encodings of newtype structs (e.g.
+//!   decimals) by their names.
+//! - The [`document`] module wraps a value in an envelope with its creation time, content
+//!   type and schema id.
+//! - The [`container`] module reads and writes many records one after the other, e.g. logs,
+//!   and decodes them in parallel. Its streams mark their start and end so readers can tell a
+//!   finished stream from one that was cut off, and can be decoded from partial reads of a
+//!   non-blocking socket. Its seekable containers are compressed in frames (e.g. with zstd)
+//!   and keep their random access. The `zstd` feature adds the zstd codec of the seekable
+//!   containers and compresses small records one by one with a dictionary trained on them.
+//! - The [`columnar`] module writes sequences of structs column by column, e.g. large batches
+//!   of records. The `arrow` feature adds the `arrow` module, which writes and reads the
+//!   `RecordBatch`es of Apache Arrow the same way.
+//! - The [`patch`] module updates fields of serialized records in place; the
+//!   [`Config::patchable`](config::Config::patchable) profile keeps fixed-width fields at stable
+//!   byte offsets.
+//! - The [`pipeline`] module compresses values and then encrypts them with any authenticated
+//!   cipher behind its `Cipher` trait, and checks them before it decompresses them. The `zstd`
+//!   feature adds its zstd compression.
+//! - The [`precompute`](mod@precompute) module encodes constant strings (e.g. field names) at
+//!   compile time so hot loops copy them instead of encoding them for every record.
+//! - The [`raw`] module embeds payloads that are already serialized (e.g. the body of an
+//!   envelope) so they are neither decoded nor encoded again on the way through.
+//! - The [`rpc`] module exchanges messages over pluggable transports (TCP, Unix sockets, in
+//!   memory or any link that implements its `Transport` or `AsyncTransport` trait). The
+//!   `futures` feature adds `RustFrStream`, a `Stream` of the messages of an `AsyncRead` that
+//!   stops reading while the consumer falls behind.
+//! - The [`validator`] module checks constraints on the fields of structs (e.g. the length of
+//!   a string or the range of a number) while they are serialized.
+//! - The `digest` feature adds `serializer::to_bytes_hashed` which hashes the data with any
+//!   hasher of the RustCrypto `digest` crate while it is serialized, and the `chunking` module
+//!   which splits the data into content-defined chunks for deduplicated storage.
+//! - The `nfc` feature adds `serializer::to_bytes_nfc` which normalizes strings to Unicode NFC
+//!   while they are serialized, so producers that differ in normalization agree on the bytes.
+//! - The `allocator-api2` feature adds `serializer::to_bytes_in` which writes the output into
+//!   a vector of a custom allocator (e.g. an arena), through the `Allocator` trait of
+//!   `allocator-api2` on stable Rust. Only the output buffer is allocated there.
+//! - The `bigint` and `decimal` features add [`with`] adapters that encode the big integers of
+//!   `num-bigint` and the decimals of `rust_decimal` compactly and exactly.
+//! - Decoding malformed input returns an error instead of panicking.
+//! - The crate forbids `unsafe` code. Byte aligned runs of bytes are still copied (or
+//!   borrowed) in bulk through the safe APIs of `bitvec`.
 //!
 //! ### Example
 //! ```rust
@@ -28,13 +102,59 @@
 //! assert_eq!(human, deserialized_human);
 //! ```
 
+#![forbid(unsafe_code)]
+
+pub use bitvec;
+#[cfg(feature = "digest")]
+pub use digest;
+
+pub mod analyze;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "digest")]
+pub mod chunking;
+pub mod codegen;
+pub mod columnar;
+pub mod config;
+pub mod conformance;
+pub mod container;
 pub mod deserializer;
+pub mod document;
+pub mod encoders;
 pub mod error;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod overhead;
+pub mod patch;
+pub mod pipeline;
+pub mod precompute;
+pub mod protocol;
+pub mod raw;
+pub mod replay;
+pub mod rpc;
+pub mod schema;
+pub mod seed;
 pub mod serializer;
+pub mod spec;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod tokens;
+pub mod validator;
+pub mod with;
 
 #[cfg(test)]
 mod tests {
-    use crate::{deserializer, serializer};
+    use crate::{
+        config::Config,
+        conformance,
+        container::{ContainerReader, ContainerWriter},
+        deserializer,
+        encoders::Primitive,
+        error::{Container, Error},
+        serializer::{self, Delimiter},
+        spec,
+        validator::Validator,
+    };
     use serde::{Deserialize, Serialize};
     use std::collections::HashMap;
 
@@ -326,4 +446,7703 @@ mod tests {
         println!("rmp_serde:\t{} bytes", rmp_serde_bytes.len());
         println!("ciborium:\t{} bytes", cir_serde_bytes.len());
     }
+
+    #[test]
+    fn cancellation() {
+        let data: Vec<u32> = (0..10_000).collect();
+        let bytes = serializer::to_bytes(&data).unwrap();
+
+        // an untouched token does not interfere with decoding.
+        let token = deserializer::Cancellation::new();
+        let decoded =
+            deserializer::from_bytes_with_cancellation::<Vec<u32>>(&bytes, &token).unwrap();
+        assert_eq!(data, decoded);
+
+        // a cancelled token (or any of its clones) stops the decoding.
+        let token = deserializer::Cancellation::new();
+        token.clone().cancel();
+        let result = deserializer::from_bytes_with_cancellation::<Vec<u32>>(&bytes, &token);
+        assert!(matches!(result, Err(Error::Cancelled)));
+
+        // so does a deadline that has already passed.
+        let token = deserializer::Cancellation::with_timeout(std::time::Duration::ZERO);
+        let result = deserializer::from_bytes_with_cancellation::<Vec<u32>>(&bytes, &token);
+        assert!(matches!(result, Err(Error::Cancelled)));
+    }
+
+    // A byte buffer that serializes through `serialize_bytes` rather than as a sequence.
+    #[derive(Debug, PartialEq)]
+    struct ByteBuf(Vec<u8>);
+
+    impl Serialize for ByteBuf {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+
+    #[derive(Debug, Serialize)]
+    struct OwnedRecord {
+        id: u8,
+        flag: bool,
+        name: String,
+        tags: HashMap<String, ByteBuf>,
+        kind: RecordKind<'static>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct BorrowedRecord<'a> {
+        id: u8,
+        flag: bool,
+        name: &'a str,
+        #[serde(borrow)]
+        tags: HashMap<&'a str, &'a [u8]>,
+        #[serde(borrow)]
+        kind: RecordKind<'a>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum RecordKind<'a> {
+        Named(&'a str),
+        Anonymous,
+    }
+
+    #[test]
+    fn borrowed_data() {
+        let record = OwnedRecord {
+            id: 7,
+            flag: true,
+            name: "ayush".to_string(),
+            tags: [
+                ("a".to_string(), ByteBuf(vec![1, 2, 3])),
+                ("b".to_string(), ByteBuf(vec![])),
+            ]
+            .into_iter()
+            .collect(),
+            kind: RecordKind::Named("kind"),
+        };
+
+        // Serialize
+        let bytes = serializer::to_bytes(&record).unwrap();
+
+        // Deserialize; every string & byte slice is borrowed from `bytes`.
+        let borrowed = deserializer::from_bytes::<BorrowedRecord>(&bytes).unwrap();
+        assert_eq!(
+            borrowed,
+            BorrowedRecord {
+                id: 7,
+                flag: true,
+                name: "ayush",
+                tags: [("a", &[1u8, 2, 3][..]), ("b", &[][..])]
+                    .into_iter()
+                    .collect(),
+                kind: RecordKind::Named("kind"),
+            }
+        );
+    }
+
+    #[test]
+    fn expected_delimiter_context() {
+        let human = Human {
+            name: "a".to_string(),
+            age: 19,
+        };
+        let mut bytes = serializer::to_bytes(&human).unwrap();
+
+        // the header takes up the first 2 bytes; flip the first bit of the following
+        // MAP_KEY_DELIMITER (0b110) so that it reads 0b111.
+        bytes[2] |= 1;
+        let error = deserializer::from_bytes::<Human>(&bytes).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::ExpectedDelimiter {
+                expected: Delimiter::MapKey,
+                found: Some(0b111),
+                container: Container::Struct("Human"),
+                index: 0,
+                depth: 0,
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "expected delimiter MapKey but found 0b111 at index 0 of struct Human at depth 0"
+        );
+
+        // a struct where a sequence is expected: in the tuple in the sequence.
+        let humans = vec![(vec![1u8], human)];
+        let bytes = serializer::to_bytes(&humans).unwrap();
+        let error = deserializer::from_bytes::<Vec<(Vec<u8>, Vec<u8>)>>(&bytes).unwrap_err();
+        assert!(
+            matches!(
+                error,
+                Error::ExpectedDelimiter {
+                    expected: Delimiter::Seq,
+                    container: Container::Seq,
+                    index: 0,
+                    depth: 2,
+                    ..
+                }
+            ),
+            "{error}"
+        );
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct HumanV2 {
+        name: String,
+        age: u8,
+        email: String,
+    }
+
+    #[test]
+    fn unknown_struct_fields() {
+        let human = HumanV2 {
+            name: "Ayush".to_string(),
+            age: 19,
+            email: "ayush@example.com".to_string(),
+        };
+        let bytes = serializer::to_bytes(&human).unwrap();
+
+        // the values of unknown fields can not be skipped as the data does not describe
+        // itself; the error names the offending field.
+        let error = deserializer::from_bytes::<Human>(&bytes).unwrap_err();
+        assert!(matches!(error, Error::UnknownField(field) if field == "email"));
+    }
+
+    fn self_describing() -> Config {
+        Config {
+            self_describing: true,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn self_describing_compound_types() {
+        let config = self_describing();
+        let compound_types = CompundTypes {
+            a: vec![1, 2, 3],
+            b: [("a".to_string(), 1), ("b".to_string(), 2)]
+                .iter()
+                .cloned()
+                .collect(),
+            c: Some(1),
+            d: None,
+            e: Primitives {
+                a: 1,
+                b: 2,
+                c: 3,
+                d: 4,
+                e: -1,
+                f: -2,
+                g: -3,
+                h: -4,
+                i: 1.0,
+                j: 2.0,
+                k: true,
+                l: 'a',
+                m: "hello".to_string(),
+            },
+            f: vec![AnEnum::A(1), AnEnum::B { a: 1, b: 2 }, AnEnum::C],
+        };
+
+        // Serialize
+        let bytes = serializer::to_bytes_with_config(&compound_types, &config).unwrap();
+
+        // Deserialize
+        let deserialized_compound_types =
+            deserializer::from_bytes_with_config::<CompundTypes>(&bytes, &config).unwrap();
+        assert_eq!(compound_types, deserialized_compound_types);
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(untagged)]
+    enum Setting {
+        Flag(bool),
+        Number(i64),
+        Choice(AnEnum),
+        Text(String),
+        List(Vec<Setting>),
+        Table { key: String, value: Option<u32> },
+    }
+
+    #[test]
+    fn self_describing_untagged_enums() {
+        let config = self_describing();
+        let settings = vec![
+            Setting::Flag(true),
+            Setting::Number(-42),
+            Setting::Text("hello".to_string()),
+            Setting::Table {
+                key: "a".to_string(),
+                value: Some(1),
+            },
+            Setting::Choice(AnEnum::B { a: 1, b: 2 }),
+            Setting::Choice(AnEnum::C),
+            Setting::List(vec![Setting::Flag(false), Setting::Number(7)]),
+        ];
+
+        // Serialize
+        let bytes = serializer::to_bytes_with_config(&settings, &config).unwrap();
+
+        // Deserialize
+        let deserialized_settings =
+            deserializer::from_bytes_with_config::<Vec<Setting>>(&bytes, &config).unwrap();
+        assert_eq!(settings, deserialized_settings);
+
+        // untagged enums need the self-describing mode.
+        let bytes = serializer::to_bytes(&settings).unwrap();
+        assert!(deserializer::from_bytes::<Vec<Setting>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn self_describing_skips_unknown_fields() {
+        let config = self_describing();
+        let human = HumanV2 {
+            name: "Ayush".to_string(),
+            age: 19,
+            email: "ayush@example.com".to_string(),
+        };
+        let bytes = serializer::to_bytes_with_config(&human, &config).unwrap();
+
+        let deserialized_human =
+            deserializer::from_bytes_with_config::<Human>(&bytes, &config).unwrap();
+        assert_eq!(
+            deserialized_human,
+            Human {
+                name: "Ayush".to_string(),
+                age: 19,
+            }
+        );
+    }
+
+    #[test]
+    fn tagged_enums() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(tag = "type")]
+        enum Internal {
+            Struct { x: u8, human: Human },
+            Newtype(Human),
+            Unit,
+            Map { entries: HashMap<String, u16> },
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(tag = "t", content = "c")]
+        enum Adjacent {
+            Newtype(u16),
+            Tuple(u8, Human),
+            Unit,
+            Nested(Box<Adjacent>),
+            Struct { x: u8, human: Human },
+        }
+
+        let human = || Human {
+            name: "Ayush".to_string(),
+            age: 19,
+        };
+
+        // internally tagged enums and adjacently tagged struct variants are read
+        // through `deserialize_any` so they need the self-describing mode.
+        let config = self_describing();
+        for value in [
+            Internal::Struct {
+                x: 1,
+                human: human(),
+            },
+            Internal::Newtype(human()),
+            Internal::Unit,
+            Internal::Map {
+                entries: [("a".to_string(), 1), ("b".to_string(), 2)].into(),
+            },
+        ] {
+            let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<Internal>(&bytes, &config);
+            assert_eq!(decoded.unwrap(), value);
+
+            let bytes = serializer::to_bytes(&value).unwrap();
+            let error = deserializer::from_bytes::<Internal>(&bytes).unwrap_err();
+            assert!(matches!(
+                error,
+                Error::SelfDescribingRequired("deserialize_any")
+            ));
+        }
+
+        for value in [
+            Adjacent::Newtype(1000),
+            Adjacent::Tuple(1, human()),
+            Adjacent::Unit,
+            Adjacent::Nested(Box::new(Adjacent::Unit)),
+            Adjacent::Struct {
+                x: 1,
+                human: human(),
+            },
+        ] {
+            let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<Adjacent>(&bytes, &config);
+            assert_eq!(decoded.unwrap(), value);
+
+            let bytes = serializer::to_bytes(&value).unwrap();
+            let decoded = deserializer::from_bytes::<Adjacent>(&bytes);
+            match value {
+                Adjacent::Struct { .. } => assert!(matches!(
+                    decoded.unwrap_err(),
+                    Error::SelfDescribingRequired("deserialize_any")
+                )),
+                value => assert_eq!(decoded.unwrap(), value),
+            }
+        }
+    }
+
+    #[test]
+    fn spec_matches_readme() {
+        let readme = include_str!("../README.md");
+        assert!(
+            readme.contains(&spec::generate()),
+            "the README is out of date, regenerate it with `cargo run --bin gen-spec`"
+        );
+    }
+
+    #[test]
+    fn adaptive_ints() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Counters {
+            unsigned: Vec<u64>,
+            signed: Vec<i64>,
+        }
+
+        let config = Config {
+            adaptive_ints: true,
+            ..Config::default()
+        };
+        let counters = Counters {
+            unsigned: vec![0, 255, 256, 65_535, 65_536, u32::MAX as u64, u64::MAX],
+            signed: vec![0, -1, 1, -64, 64, -129, i32::MIN as i64, i64::MIN, i64::MAX],
+        };
+        let bytes = serializer::to_bytes_with_config(&counters, &config).unwrap();
+        let decoded = deserializer::from_bytes_with_config::<Counters>(&bytes, &config).unwrap();
+        assert_eq!(counters, decoded);
+
+        // small values only take a byte and a 2 bit width tag (after the 2 byte header).
+        let small = serializer::to_bytes_with_config(&7u64, &config).unwrap();
+        assert_eq!(small.len(), 2 + 2);
+        assert_eq!(
+            serializer::to_bytes_with_config(&-7i64, &config)
+                .unwrap()
+                .len(),
+            2 + 2
+        );
+        assert_eq!(serializer::to_bytes(&7u64).unwrap().len(), 2 + 8);
+
+        // it works along with the self-describing mode too.
+        let config = Config {
+            adaptive_ints: true,
+            ..self_describing()
+        };
+        let bytes = serializer::to_bytes_with_config(&counters, &config).unwrap();
+        let decoded = deserializer::from_bytes_with_config::<Counters>(&bytes, &config).unwrap();
+        assert_eq!(counters, decoded);
+    }
+
+    #[test]
+    fn run_length_encoding() {
+        #[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+        struct Cell {
+            label: String,
+            value: f64,
+            flag: bool,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Sheet {
+            flag: bool,
+            matrix: Vec<Vec<f64>>,
+            cells: Vec<Cell>,
+            labels: Vec<String>,
+            tuple: (u8, u8, u8, u16),
+            empty: Vec<u8>,
+        }
+
+        let config = Config {
+            run_length_encoding: true,
+            ..Config::default()
+        };
+        let mut matrix = vec![vec![0.0; 50]; 50];
+        matrix[3][7] = 1.5;
+        matrix[42][21] = -2.0;
+        let mut cells = vec![Cell::default(); 10];
+        cells[5].label = "five".to_string();
+        let sheet = Sheet {
+            flag: true,
+            matrix,
+            cells,
+            labels: ["a", "a", "b", "", "", "a"].map(String::from).to_vec(),
+            tuple: (3, 3, 3, 3),
+            empty: vec![],
+        };
+
+        let bytes = serializer::to_bytes_with_config(&sheet, &config).unwrap();
+        let decoded = deserializer::from_bytes_with_config::<Sheet>(&bytes, &config).unwrap();
+        assert_eq!(sheet, decoded);
+
+        // the sparse matrix shrinks by more than 90%.
+        let plain = serializer::to_bytes(&sheet.matrix).unwrap();
+        let encoded = serializer::to_bytes_with_config(&sheet.matrix, &config).unwrap();
+        assert!(encoded.len() * 10 < plain.len());
+
+        // it works along with the self-describing mode too.
+        let config = Config {
+            run_length_encoding: true,
+            ..self_describing()
+        };
+        let bytes = serializer::to_bytes_with_config(&sheet, &config).unwrap();
+        let decoded = deserializer::from_bytes_with_config::<Sheet>(&bytes, &config).unwrap();
+        assert_eq!(sheet, decoded);
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Buffers {
+        plain: Vec<u8>,
+        array: [u8; 4],
+        pair: (u8, u8),
+        mixed: (u8, u16, u8),
+        nested: Vec<Vec<u8>>,
+        options: Vec<Option<u8>>,
+        empty: Vec<u8>,
+        strings: Vec<String>,
+    }
+
+    fn buffers() -> Buffers {
+        Buffers {
+            plain: (0..=255).collect(),
+            array: [1, 135, 134, 139],
+            pair: (1, 2),
+            mixed: (1, 1000, 2),
+            nested: vec![vec![1, 2, 3], vec![], vec![135; 3]],
+            options: vec![Some(1), None, Some(5)],
+            empty: vec![],
+            strings: vec!["a".to_string()],
+        }
+    }
+
+    #[test]
+    fn packed_u8_seqs() {
+        let config = Config {
+            pack_u8_seqs: true,
+            ..Config::default()
+        };
+        let bytes = serializer::to_bytes_with_config(&buffers(), &config).unwrap();
+        let decoded = deserializer::from_bytes_with_config::<Buffers>(&bytes, &config).unwrap();
+        assert_eq!(decoded, buffers());
+        assert!(bytes.len() < serializer::to_bytes(&buffers()).unwrap().len());
+
+        // the elements are told by how they serialize rather than by their type: boxed bytes
+        // are packed too while newtype structs around them aren't.
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Byte(u8);
+        let data = (0..100u8).collect::<Vec<_>>();
+        let packed = serializer::to_bytes_with_config(&data, &config).unwrap();
+        let boxed = data.iter().copied().map(Box::new).collect::<Vec<_>>();
+        assert_eq!(
+            serializer::to_bytes_with_config(&boxed, &config).unwrap(),
+            packed
+        );
+        let wrapped = data.iter().copied().map(Byte).collect::<Vec<_>>();
+        let unpacked = serializer::to_bytes_with_config(&wrapped, &config).unwrap();
+        assert!(unpacked.len() > packed.len());
+        let decoded = deserializer::from_bytes_with_config::<Vec<Byte>>(&unpacked, &config);
+        assert_eq!(decoded.unwrap(), wrapped);
+
+        // it works along with the other modes too.
+        let config = Config {
+            pack_u8_seqs: true,
+            run_length_encoding: true,
+            ..self_describing()
+        };
+        let bytes = serializer::to_bytes_with_config(&buffers(), &config).unwrap();
+        let decoded = deserializer::from_bytes_with_config::<Buffers>(&bytes, &config).unwrap();
+        assert_eq!(decoded, buffers());
+    }
+
+    #[test]
+    #[ignore = "playground test; use cargo test -- --nocapture --ignored"]
+    fn length_test_bytes() {
+        let data: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+        let config = Config {
+            pack_u8_seqs: true,
+            ..Config::default()
+        };
+
+        let rust_fr_bytes = serializer::to_bytes(&data).unwrap();
+        let rust_fr_packed_bytes = serializer::to_bytes_with_config(&data, &config).unwrap();
+        let serde_json_bytes = serde_json::to_vec(&data).unwrap();
+        let rmp_serde_bytes = rmp_serde::to_vec(&data).unwrap();
+        let mut cir_serde_bytes = Vec::new();
+        ciborium::ser::into_writer(&data, &mut cir_serde_bytes).unwrap();
+
+        println!("---- Bytes ----");
+        println!("rust_fr:\t{} bytes", rust_fr_bytes.len());
+        println!("rust_fr packed:\t{} bytes", rust_fr_packed_bytes.len());
+        println!("serde_json:\t{} bytes", serde_json_bytes.len());
+        println!("rmp_serde:\t{} bytes", rmp_serde_bytes.len());
+        println!("ciborium:\t{} bytes", cir_serde_bytes.len());
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn arbitrary_payloads() {
+        use crate::testing::{arbitrary_payload, Profile, Value};
+
+        let configs = (0..1 << Config::OPTIONS.len()).filter_map(|header| {
+            let config = Config::from_header(header).ok()?;
+            config.conflict().is_none().then_some(config)
+        });
+        for (i, config) in configs.enumerate() {
+            let profile = Profile {
+                config,
+                max_depth: 4,
+                ..Profile::default()
+            };
+            for seed in (0..20).map(|seed| seed * 1000 + i as u64) {
+                let (bytes, value) = arbitrary_payload(seed, &profile).unwrap();
+                let decoded =
+                    deserializer::from_bytes_with_config::<Value>(&bytes, &profile.config)
+                        .unwrap_or_else(|e| panic!("seed {seed} with {:?}: {e}", profile.config));
+                assert_eq!(decoded, value, "seed {seed} with {:?}", profile.config);
+                assert_eq!(arbitrary_payload(seed, &profile).unwrap().0, bytes);
+            }
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics() {
+        use ::metrics::{
+            Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+            SharedString, Unit,
+        };
+        use std::sync::{Arc, Mutex};
+
+        // adds up every counter and counts the samples of every histogram by name and labels.
+        #[derive(Default)]
+        struct Totals(Arc<Mutex<HashMap<String, u64>>>);
+
+        struct Total(String, Arc<Mutex<HashMap<String, u64>>>);
+
+        impl CounterFn for Total {
+            fn increment(&self, value: u64) {
+                *self.1.lock().unwrap().entry(self.0.clone()).or_default() += value;
+            }
+            fn absolute(&self, value: u64) {
+                self.1.lock().unwrap().insert(self.0.clone(), value);
+            }
+        }
+
+        impl HistogramFn for Total {
+            fn record(&self, _: f64) {
+                self.increment(1);
+            }
+        }
+
+        impl Totals {
+            fn total(&self, key: &Key) -> Arc<Total> {
+                let labels = key
+                    .labels()
+                    .map(|label| format!("{}={}", label.key(), label.value()));
+                let name = format!("{}{:?}", key.name(), labels.collect::<Vec<_>>());
+                Arc::new(Total(name, self.0.clone()))
+            }
+            fn get(&self, name: &str) -> u64 {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .get(name)
+                    .copied()
+                    .unwrap_or_default()
+            }
+        }
+
+        impl Recorder for Totals {
+            fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+                Counter::from_arc(self.total(key))
+            }
+            fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+                Gauge::noop()
+            }
+            fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+                Histogram::from_arc(self.total(key))
+            }
+        }
+
+        let totals = Totals::default();
+        let human = Human {
+            name: "ayush".to_string(),
+            age: 19,
+        };
+        let bytes = ::metrics::with_local_recorder(&totals, || {
+            let bytes = serializer::to_bytes(&human).unwrap();
+            deserializer::from_bytes::<Human>(&bytes).unwrap();
+            let _ = deserializer::from_bytes::<Human>(&bytes[..4]);
+            bytes
+        });
+        assert_eq!(totals.get("rust_fr_encoded_bytes[]"), bytes.len() as u64);
+        assert_eq!(
+            totals.get("rust_fr_decoded_bytes[]"),
+            bytes.len() as u64 + 4
+        );
+        assert_eq!(totals.get("rust_fr_encode_seconds[]"), 1);
+        assert_eq!(totals.get("rust_fr_decode_seconds[]"), 2);
+        assert_eq!(totals.get("rust_fr_encode_errors[]"), 0);
+        assert_eq!(
+            totals.get("rust_fr_decode_errors[\"kind=UnexpectedEOF\"]"),
+            1
+        );
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn hashed_while_serializing() {
+        use digest::{consts::U8, FixedOutput, HashMarker, Output, OutputSizeUser, Update};
+        use sha2::{Digest, Sha256};
+
+        // counts the updates so the test can tell the bytes were fed in pieces.
+        #[derive(Default)]
+        struct Counter(u64);
+
+        impl HashMarker for Counter {}
+        impl OutputSizeUser for Counter {
+            type OutputSize = U8;
+        }
+        impl Update for Counter {
+            fn update(&mut self, _: &[u8]) {
+                self.0 += 1;
+            }
+        }
+        impl FixedOutput for Counter {
+            fn finalize_into(self, out: &mut Output<Self>) {
+                out.copy_from_slice(&self.0.to_le_bytes());
+            }
+        }
+
+        let data: HashMap<String, Vec<String>> = (0..50)
+            .map(|i| (i.to_string(), vec!["rust-fr".to_string(); 50]))
+            .collect();
+        for config in [
+            Config::default(),
+            self_describing(),
+            Config {
+                run_length_encoding: true,
+                pack_u8_seqs: true,
+                ..Config::default()
+            },
+        ] {
+            let (bytes, hash) =
+                serializer::to_bytes_hashed_with_config::<Sha256, _>(&data, &config).unwrap();
+            assert_eq!(
+                bytes,
+                serializer::to_bytes_with_config(&data, &config).unwrap()
+            );
+            assert_eq!(hash, Sha256::digest(&bytes));
+        }
+
+        let (bytes, hash) = serializer::to_bytes_hashed::<Sha256, _>(&data).unwrap();
+        assert_eq!(hash, Sha256::digest(&bytes));
+        let (_, updates) = serializer::to_bytes_hashed::<Counter, _>(&data).unwrap();
+        assert!(u64::from_le_bytes(updates.into()) > 1);
+    }
+
+    #[test]
+    fn lazy_struct() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Record {
+            id: u32,
+            human: Human,
+            tags: HashMap<String, Vec<u8>>,
+            kind: AnEnum,
+            note: Option<String>,
+        }
+
+        let config = self_describing();
+        let record = Record {
+            id: 7,
+            human: Human {
+                name: "Ayush".to_string(),
+                age: 19,
+            },
+            tags: [("a".to_string(), vec![1, 2, 3])].into(),
+            kind: AnEnum::B { a: 1, b: 2 },
+            note: None,
+        };
+        let bytes = serializer::to_bytes_with_config(&record, &config).unwrap();
+
+        let lazy = deserializer::LazyStruct::new(&bytes, &config).unwrap();
+        assert_eq!(lazy.len(), 5);
+        assert_eq!(
+            lazy.keys().collect::<Vec<_>>(),
+            ["id", "human", "tags", "kind", "note"]
+        );
+        assert_eq!(
+            lazy.get::<Human>("human").unwrap(),
+            Some(Human {
+                name: "Ayush".to_string(),
+                age: 19,
+            })
+        );
+        assert_eq!(
+            lazy.get::<AnEnum>("kind").unwrap(),
+            Some(AnEnum::B { a: 1, b: 2 })
+        );
+        assert_eq!(lazy.get::<Option<String>>("note").unwrap(), Some(None));
+        assert_eq!(lazy.get::<u32>("id").unwrap(), Some(7));
+        assert_eq!(lazy.get::<u32>("missing").unwrap(), None);
+        assert!(lazy.get::<String>("id").is_err());
+
+        // the values can't be skipped without the self-describing mode.
+        let bytes = serializer::to_bytes(&record).unwrap();
+        let error = deserializer::LazyStruct::new(&bytes, &Config::default()).unwrap_err();
+        assert!(matches!(error, Error::SelfDescribingRequired(_)));
+    }
+
+    #[test]
+    fn offset_tables() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Wide {
+            a: u8,
+            b: String,
+            c: Vec<Human>,
+            d: HashMap<String, u16>,
+            e: AnEnum,
+            f: Option<Box<Wide>>,
+        }
+
+        let wide = || Wide {
+            a: 1,
+            b: "b".to_string(),
+            c: vec![Human {
+                name: "Ayush".to_string(),
+                age: 19,
+            }],
+            d: [("x".to_string(), 1)].into(),
+            e: AnEnum::B { a: 1, b: 2 },
+            f: None,
+        };
+        let value = Wide {
+            f: Some(Box::new(wide())),
+            ..wide()
+        };
+
+        let config = Config {
+            offset_tables: true,
+            ..Config::default()
+        };
+        let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+        let decoded = deserializer::from_bytes_with_config::<Wide>(&bytes, &config).unwrap();
+        assert_eq!(decoded, value);
+
+        // unknown fields are skipped through the table without the self-describing mode.
+        let human = deserializer::from_bytes_with_config::<Human>(
+            &serializer::to_bytes_with_config(
+                &HumanV2 {
+                    name: "Ayush".to_string(),
+                    email: "ayush@example.com".to_string(),
+                    age: 19,
+                },
+                &config,
+            )
+            .unwrap(),
+            &config,
+        )
+        .unwrap();
+        assert_eq!(human.age, 19);
+
+        // so is the lazy reader.
+        let lazy = deserializer::LazyStruct::new(&bytes, &config).unwrap();
+        assert_eq!(lazy.len(), 6);
+        assert_eq!(
+            lazy.get::<AnEnum>("e").unwrap(),
+            Some(AnEnum::B { a: 1, b: 2 })
+        );
+        assert_eq!(lazy.get::<Option<Wide>>("f").unwrap(), Some(Some(wide())));
+
+        // it works along with the other modes too.
+        let config = Config {
+            offset_tables: true,
+            run_length_encoding: true,
+            pack_u8_seqs: true,
+            ..self_describing()
+        };
+        let bytes = serializer::to_bytes_with_config(&vec![wide(), wide()], &config).unwrap();
+        let decoded = deserializer::from_bytes_with_config::<Vec<Wide>>(&bytes, &config).unwrap();
+        assert_eq!(decoded, vec![wide(), wide()]);
+    }
+
+    #[test]
+    fn container() {
+        let config = Config::default();
+        let humans = (0..50)
+            .map(|age| Human {
+                name: format!("human {age}"),
+                age,
+            })
+            .collect::<Vec<_>>();
+        let mut writer = ContainerWriter::new(&config);
+        for human in &humans {
+            writer.push(human).unwrap();
+        }
+        let bytes = writer.into_bytes();
+
+        // records keep their index, whichever order they are decoded in.
+        let mut records = ContainerReader::new(&bytes, &config)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        records.reverse();
+        for record in records {
+            let human = record.decode::<Human>(&config).unwrap();
+            assert_eq!(human, humans[record.index]);
+        }
+
+        for threads in [1, 3, 64] {
+            let threads = std::num::NonZeroUsize::new(threads).unwrap();
+            let decoded = ContainerReader::new(&bytes, &config)
+                .decode_parallel::<Human>(threads)
+                .unwrap();
+            assert_eq!(decoded, humans);
+        }
+
+        // a truncated container is an error.
+        let error = ContainerReader::new(&bytes[..bytes.len() - 1], &config)
+            .decode_parallel::<Human>(std::num::NonZeroUsize::MIN)
+            .unwrap_err();
+        assert!(matches!(error, Error::UnexpectedEOF));
+    }
+
+    #[test]
+    fn aligned_container() {
+        let config = Config::default();
+        let humans = (0..50)
+            .map(|age| Human {
+                name: "human".repeat(age as usize % 7),
+                age,
+            })
+            .collect::<Vec<_>>();
+        for alignment in [1, 8, 4096] {
+            let mut writer = ContainerWriter::with_alignment(&config, alignment);
+            for human in &humans {
+                writer.push(human).unwrap();
+            }
+            let bytes = writer.into_bytes();
+            let records = ContainerReader::with_alignment(&bytes, &config, alignment)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            for record in &records {
+                let offset = record.bytes.as_ptr() as usize - bytes.as_ptr() as usize;
+                assert_eq!(offset % alignment, 0);
+                assert_eq!(
+                    record.decode::<Human>(&config).unwrap(),
+                    humans[record.index]
+                );
+            }
+            assert_eq!(records.len(), humans.len());
+        }
+
+        // the padding is zero.
+        let mut writer = ContainerWriter::with_alignment(&config, 8);
+        writer.push(&humans[1]).unwrap();
+        writer.push(&humans[2]).unwrap();
+        let mut bytes = writer.into_bytes();
+        assert!(bytes.len() > 8);
+        // 4 bytes of padding + the length + the first record.
+        let end = 8 + u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        assert_ne!((end + 4) % 8, 0, "the second record needs padding");
+        bytes[end] = 1;
+        let error = ContainerReader::with_alignment(&bytes, &config, 8)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert!(matches!(error, Error::DeserializationError(_)));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "miri can't read files")]
+    fn conformance() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        for vector in conformance::vectors() {
+            // the files in testdata are the output of `cargo run --bin gen-testdata`.
+            let bytes = std::fs::read(dir.join(format!("{}.bin", vector.name))).unwrap();
+            let json = std::fs::read_to_string(dir.join(format!("{}.json", vector.name))).unwrap();
+            assert!(conformance::check(vector.name, &bytes).is_ok());
+            assert_eq!(json, vector.to_json());
+            // the description is valid json.
+            let description = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+            assert_eq!(description["name"], vector.name);
+        }
+
+        let vector = &conformance::vectors()[0];
+        let mut bytes = vector.bytes.clone();
+        bytes.push(0);
+        assert!(matches!(
+            conformance::check(vector.name, &bytes),
+            Err(Error::TestVectorMismatch { name, .. }) if name == vector.name
+        ));
+        assert!(matches!(
+            conformance::check("missing", &bytes),
+            Err(Error::UnknownTestVector(name)) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn unit_structs_in_collections() {
+        use std::{collections::BTreeMap, marker::PhantomData};
+
+        #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+        struct User;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Id<T> {
+            value: u32,
+            kind: PhantomData<T>,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Typed {
+            units: Vec<User>,
+            markers: Vec<PhantomData<String>>,
+            values: BTreeMap<u8, User>,
+            keys: BTreeMap<User, u8>,
+            ids: Vec<Id<User>>,
+            last: User,
+        }
+
+        let typed = Typed {
+            units: vec![User; 3],
+            markers: vec![PhantomData; 2],
+            values: [(1, User), (2, User)].into(),
+            keys: [(User, 1)].into(),
+            ids: (0..3)
+                .map(|value| Id {
+                    value,
+                    kind: PhantomData,
+                })
+                .collect(),
+            last: User,
+        };
+
+        // the unit token (010) never reads as the end of a sequence (011) or a sequence value
+        // (100), so unit structs are safe anywhere in a collection.
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                run_length_encoding: true,
+                ..Config::default()
+            },
+        ];
+        for config in configs {
+            let bytes = serializer::to_bytes_with_config(&typed, &config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<Typed>(&bytes, &config).unwrap();
+            assert_eq!(typed, decoded);
+        }
+    }
+
+    #[test]
+    fn sorted_int_keys() {
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Index {
+            names: BTreeMap<u32, String>,
+            nested: BTreeMap<u8, BTreeMap<u64, bool>>,
+            // unsorted keys are a block too while other keys keep the usual layout.
+            scores: HashMap<u16, i8>,
+            labels: BTreeMap<String, u8>,
+            empty: BTreeMap<u32, u8>,
+        }
+
+        // a map serialized in the given order.
+        struct ScoreMap(Vec<(u16, ())>);
+
+        impl Serialize for ScoreMap {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+            }
+        }
+
+        let index = Index {
+            names: (0..50).map(|id| (id * 3, format!("name {id}"))).collect(),
+            nested: (0..5)
+                .map(|i| (i, (0..5).map(|j| (j << 40, j % 2 == 0)).collect()))
+                .collect(),
+            scores: (0..20).map(|i| (i, -(i as i8))).collect(),
+            labels: [("a".to_string(), 1), ("b".to_string(), 2)].into(),
+            empty: BTreeMap::new(),
+        };
+
+        let sorted = Config {
+            sorted_int_keys: true,
+            ..Config::default()
+        };
+        let configs = [
+            sorted.clone(),
+            Config {
+                offset_tables: true,
+                ..sorted.clone()
+            },
+            Config {
+                self_describing: true,
+                adaptive_ints: true,
+                run_length_encoding: true,
+                ..sorted.clone()
+            },
+        ];
+        for config in configs {
+            let bytes = serializer::to_bytes_with_config(&index, &config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<Index>(&bytes, &config).unwrap();
+            assert_eq!(index, decoded);
+        }
+
+        // the keys are written as a block without a delimiter after each of them.
+        let counts = (0..50u32)
+            .map(|i| (i, i as u16))
+            .collect::<BTreeMap<_, _>>();
+        let bytes = serializer::to_bytes_with_config(&counts, &sorted).unwrap();
+        assert!(bytes.len() < serializer::to_bytes(&counts).unwrap().len());
+
+        // the keys are told by how they serialize rather than by their type: boxed and
+        // transparent keys are a block too while newtype structs keep the usual layout.
+        #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+        #[serde(transparent)]
+        struct Id(u32);
+        #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+        struct Wrapped(u32);
+        let boxed = (0..50u32)
+            .map(|i| (Box::new(i), i as u16))
+            .collect::<BTreeMap<_, _>>();
+        let ids = (0..50)
+            .map(|i| (Id(i), i as u16))
+            .collect::<BTreeMap<_, _>>();
+        let wrapped = (0..50)
+            .map(|i| (Wrapped(i), i as u16))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            serializer::to_bytes_with_config(&boxed, &sorted).unwrap(),
+            bytes
+        );
+        assert_eq!(
+            serializer::to_bytes_with_config(&ids, &sorted).unwrap(),
+            bytes
+        );
+        let decoded = deserializer::from_bytes_with_config::<BTreeMap<Id, u16>>(&bytes, &sorted);
+        assert_eq!(decoded.unwrap(), ids);
+        let unblocked = serializer::to_bytes_with_config(&wrapped, &sorted).unwrap();
+        assert!(unblocked.len() > bytes.len());
+        let decoded =
+            deserializer::from_bytes_with_config::<BTreeMap<Wrapped, u16>>(&unblocked, &sorted);
+        assert_eq!(decoded.unwrap(), wrapped);
+
+        // the lazy reader binary searches the keys.
+        let configs = [
+            Config {
+                offset_tables: true,
+                ..sorted.clone()
+            },
+            Config {
+                self_describing: true,
+                ..sorted.clone()
+            },
+        ];
+        for config in configs {
+            let bytes = serializer::to_bytes_with_config(&index.names, &config).unwrap();
+            let lazy = deserializer::LazyMap::new(&bytes, &config).unwrap();
+            assert_eq!(lazy.len(), 50);
+            for (id, name) in &index.names {
+                let id = u64::from(*id);
+                assert_eq!(lazy.get::<&str>(id).unwrap(), Some(name.as_str()));
+                assert_eq!(lazy.get::<&str>(id + 1).unwrap(), None);
+            }
+        }
+        let bytes = serializer::to_bytes_with_config(&index.empty, &sorted).unwrap();
+        let error = deserializer::LazyMap::new(&bytes, &sorted).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::SelfDescribingRequired("LazyMap::new")
+        ));
+        let bytes = serializer::to_bytes_with_config(&index.labels, &sorted).unwrap();
+        assert!(deserializer::LazyMap::new(&bytes, &sorted).is_err());
+        let scores = (0..20u16).rev().map(|i| (i, ())).collect::<Vec<_>>();
+        let bytes = serializer::to_bytes_with_config(&ScoreMap(scores), &sorted).unwrap();
+        assert!(deserializer::LazyMap::new(&bytes, &sorted).is_err());
+    }
+
+    #[test]
+    fn bitvec() {
+        use bitvec::prelude::*;
+
+        let first = (true, "first".to_string(), vec![1u16, 2]);
+        let second = Primitives {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: 4,
+            e: -1,
+            f: -2,
+            g: -3,
+            h: -4,
+            i: 1.0,
+            j: 2.0,
+            k: true,
+            l: 'a',
+            m: "second".to_string(),
+        };
+
+        // a bit-level protocol: 3 bits of its own followed by two payloads.
+        let mut bits = bitvec![u8, Lsb0; 1, 0, 1];
+        let a = serializer::to_bitvec(&first).unwrap();
+        let b = serializer::to_bitvec(&second).unwrap();
+        assert_ne!(a.len() % 8, 0);
+        bits.extend_from_bitslice(&a);
+        bits.extend_from_bitslice(&b);
+
+        let (a, b) = bits[3..].split_at(a.len());
+        let decoded = deserializer::from_bitslice::<(bool, String, Vec<u16>)>(a).unwrap();
+        assert_eq!(decoded, first);
+        let decoded = deserializer::from_bitslice::<Primitives>(b).unwrap();
+        assert_eq!(decoded, second);
+
+        // the bits are those of `to_bytes` without the trailing padding.
+        let config = self_describing();
+        let bits = serializer::to_bitvec_with_config(&second, &config).unwrap();
+        let bytes = serializer::to_bytes_with_config(&second, &config).unwrap();
+        assert_eq!(bits.len().div_ceil(8), bytes.len());
+        assert_eq!(bits.clone().into_vec(), bytes);
+        let decoded =
+            deserializer::from_bitslice_with_config::<Primitives>(&bits, &config).unwrap();
+        assert_eq!(decoded, second);
+    }
+
+    #[test]
+    fn bit_len() {
+        let values = [vec![], vec![1u8], vec![1, 2, 3]];
+        for value in values {
+            let (bytes, bit_len) = serializer::to_bytes_and_bit_len(&value).unwrap();
+            assert_eq!(bytes, serializer::to_bytes(&value).unwrap());
+            assert_eq!(bit_len, serializer::to_bitvec(&value).unwrap().len());
+            assert_eq!(bytes.len(), bit_len.div_ceil(8));
+        }
+
+        // header + SEQ_DELIMITER + SEQ_VALUE_DELIMITER + u8 + SEQ_DELIMITER
+        let config = Config::default();
+        let (bytes, bit_len) =
+            serializer::to_bytes_and_bit_len_with_config(&vec![7u8], &config).unwrap();
+        assert_eq!(bit_len, 16 + 3 + 3 + 8 + 3);
+        assert_eq!(bytes.len(), 5);
+    }
+
+    #[test]
+    fn reused_buffer() {
+        let mut buffer = Vec::with_capacity(1024);
+        let pointer = buffer.as_ptr();
+        for i in 0..10u32 {
+            let value = (format!("message {i}"), i, vec![i.to_string(); 10]);
+            serializer::to_bytes_into(&value, &mut buffer).unwrap();
+            assert_eq!(buffer, serializer::to_bytes(&value).unwrap());
+            // the allocation of the buffer is reused.
+            assert_eq!(buffer.as_ptr(), pointer);
+            let decoded = deserializer::from_bytes::<(String, u32, Vec<String>)>(&buffer).unwrap();
+            assert_eq!(value, decoded);
+        }
+
+        let config = self_describing();
+        serializer::to_bytes_into_with_config(&vec![1u8], &mut buffer, &config).unwrap();
+        assert_eq!(
+            buffer,
+            serializer::to_bytes_with_config(&vec![1u8], &config).unwrap()
+        );
+    }
+
+    #[test]
+    fn truncated_primitives() {
+        let bytes = serializer::to_bytes(&(u64::MAX, -1.5f64)).unwrap();
+        let decoded = deserializer::from_bytes::<(u64, f64)>(&bytes).unwrap();
+        assert_eq!(decoded, (u64::MAX, -1.5));
+
+        // primitives cut short fail instead of reading past the end of the data.
+        assert!(matches!(
+            deserializer::from_bytes::<u32>(&[0, 0, 1, 2]),
+            Err(Error::UnexpectedEOF)
+        ));
+        assert!(matches!(
+            deserializer::from_bytes::<f64>(&[0; 7]),
+            Err(Error::UnexpectedEOF)
+        ));
+        assert!(matches!(
+            deserializer::from_bytes::<(u64, f64)>(&bytes[..bytes.len() - 2]),
+            Err(Error::UnexpectedEOF)
+        ));
+    }
+
+    #[test]
+    fn seeds() {
+        use crate::seed::{MapSeed, VecSeed};
+
+        let config = self_describing();
+        let mut names = Vec::<String>::with_capacity(16);
+        let mut counts = HashMap::with_capacity(16);
+        let (names_pointer, counts_capacity) = (names.as_ptr(), counts.capacity());
+        for frame in 0..5 {
+            let frame_names = (0..frame).map(|i| format!("name {i}")).collect::<Vec<_>>();
+            let bytes = serializer::to_bytes_with_config(&frame_names, &config).unwrap();
+            deserializer::from_bytes_seed_with_config(&bytes, &config, VecSeed::into(&mut names))
+                .unwrap();
+            assert_eq!(names, frame_names);
+
+            let frame_counts = (0..frame).map(|i| (i, i * 2)).collect::<HashMap<u8, u8>>();
+            let bytes = serializer::to_bytes_with_config(&frame_counts, &config).unwrap();
+            deserializer::from_bytes_seed_with_config(&bytes, &config, MapSeed::into(&mut counts))
+                .unwrap();
+            assert_eq!(counts, frame_counts);
+        }
+        // the collections kept their allocations.
+        assert_eq!(names.as_ptr(), names_pointer);
+        assert_eq!(counts.capacity(), counts_capacity);
+
+        let bytes = serializer::to_bytes_with_config(&vec![true; 3], &config).unwrap();
+        let seed = VecSeed::<bool>::with_capacity(32);
+        let decoded = deserializer::from_bytes_seed_with_config(&bytes, &config, seed).unwrap();
+        assert_eq!(decoded, vec![true; 3]);
+        assert!(decoded.capacity() >= 32);
+
+        // a count read from the data makes room for 1 MiB of elements at most.
+        struct Lying<T>(std::marker::PhantomData<T>);
+        impl<T> Iterator for Lying<T> {
+            type Item = T;
+            fn next(&mut self) -> Option<T> {
+                None
+            }
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (u32::MAX as usize, Some(u32::MAX as usize))
+            }
+        }
+        use serde::de::{value, DeserializeSeed};
+        let mut names = Vec::<String>::new();
+        let lying =
+            value::SeqDeserializer::<_, value::Error>::new(Lying::<&str>(Default::default()));
+        VecSeed::into(&mut names).deserialize(lying).unwrap();
+        assert!(names.capacity() * std::mem::size_of::<String>() <= 1024 * 1024);
+        let mut counts = HashMap::<u8, u8>::new();
+        let lying =
+            value::MapDeserializer::<_, value::Error>::new(Lying::<(u8, u8)>(Default::default()));
+        MapSeed::into(&mut counts).deserialize(lying).unwrap();
+        assert!(counts.capacity() <= 1024 * 1024);
+    }
+
+    #[test]
+    fn stateful_seed() {
+        use serde::de::{DeserializeSeed, SeqAccess, Visitor};
+
+        // turns the names in a sequence into ids, the same name into the same id.
+        #[derive(Default)]
+        struct Interner(HashMap<String, u32>);
+
+        impl<'de> DeserializeSeed<'de> for &mut Interner {
+            type Value = Vec<u32>;
+
+            fn deserialize<D: serde::Deserializer<'de>>(self, de: D) -> Result<Vec<u32>, D::Error> {
+                de.deserialize_seq(self)
+            }
+        }
+
+        impl<'de> Visitor<'de> for &mut Interner {
+            type Value = Vec<u32>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a sequence of names")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u32>, A::Error> {
+                let mut ids = Vec::new();
+                while let Some(name) = seq.next_element::<&str>()? {
+                    let next = self.0.len() as u32;
+                    ids.push(*self.0.entry(name.to_string()).or_insert(next));
+                }
+                Ok(ids)
+            }
+        }
+
+        let mut interner = Interner::default();
+        let bytes = serializer::to_bytes(&vec!["a", "b", "a"]).unwrap();
+        let ids = deserializer::from_bytes_seed(&bytes, &mut interner).unwrap();
+        assert_eq!(ids, [0, 1, 0]);
+        // the state is kept from one message to the next.
+        let config = self_describing();
+        let bytes = serializer::to_bytes_with_config(&vec!["c", "b"], &config).unwrap();
+        let ids = deserializer::from_bytes_seed(&bytes, &mut interner).unwrap();
+        assert_eq!(ids, [2, 1]);
+        assert_eq!(interner.0.len(), 3);
+    }
+
+    #[test]
+    fn byte_aligned_bools() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Flags {
+            enabled: bool,
+            name: String,
+            bits: Vec<bool>,
+            maybe: Option<bool>,
+        }
+
+        let flags = Flags {
+            enabled: true,
+            name: "flags".to_string(),
+            bits: vec![true, false, true],
+            maybe: Some(false),
+        };
+        let config = Config {
+            byte_aligned_bools: true,
+            ..Config::default()
+        };
+        let configs = [
+            config.clone(),
+            Config {
+                run_length_encoding: true,
+                ..self_describing()
+            },
+        ];
+        for config in configs {
+            let config = Config {
+                byte_aligned_bools: true,
+                ..config
+            };
+            let bytes = serializer::to_bytes_with_config(&flags, &config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<Flags>(&bytes, &config).unwrap();
+            assert_eq!(flags, decoded);
+        }
+
+        // a bool is a whole byte after the header.
+        let [low, high] = config.header().to_le_bytes();
+        assert_eq!(
+            serializer::to_bytes_with_config(&true, &config).unwrap(),
+            [low, high, 1]
+        );
+        assert_eq!(
+            serializer::to_bytes_with_config(&false, &config).unwrap(),
+            [low, high, 0]
+        );
+        assert!(matches!(
+            deserializer::from_bytes_with_config::<bool>(&[low, high, 2], &config),
+            Err(Error::ConversionError)
+        ));
+    }
+
+    #[test]
+    fn stats() {
+        use crate::serializer::TypeTag;
+
+        let value = (
+            vec![0u16; 10],
+            "text".to_string(),
+            Some(()),
+            vec![1u8, 2, 3],
+        );
+        let (bytes, stats) = serializer::to_bytes_and_stats(&value, &Config::default()).unwrap();
+        assert_eq!(bytes, serializer::to_bytes(&value).unwrap());
+        assert_eq!(stats.bits, serializer::to_bitvec(&value).unwrap().len());
+        // the vectors; the tuple is its values alone.
+        assert_eq!(stats.delimiter(&Delimiter::Seq), 4);
+        assert_eq!(stats.delimiter(&Delimiter::SeqValue), 10 + 3);
+        // the string is prefixed with its length instead of ending with a delimiter.
+        assert_eq!(stats.delimiter(&Delimiter::String), 0);
+        assert_eq!(stats.delimiter(&Delimiter::Unit), 1);
+        assert_eq!(stats.values(TypeTag::U16), 10);
+        assert_eq!(stats.values(TypeTag::U8), 3);
+        assert_eq!(stats.values(TypeTag::Str), 1);
+        assert_eq!(stats.values(TypeTag::Unit), 1);
+        assert_eq!(stats.values(TypeTag::Some), 1);
+        assert_eq!(stats.delimiter_bits(), 4 * 3 + 13 * 3 + 3);
+
+        // the options show up in the counts.
+        let config = Config {
+            run_length_encoding: true,
+            pack_u8_seqs: true,
+            ..Config::default()
+        };
+        let (bytes, stats) = serializer::to_bytes_and_stats(&value, &config).unwrap();
+        assert_eq!(
+            bytes,
+            serializer::to_bytes_with_config(&value, &config).unwrap()
+        );
+        assert_eq!(stats.delimiter(&Delimiter::Run), 1);
+        assert_eq!(stats.delimiter(&Delimiter::Packed), 1);
+        assert_eq!(stats.delimiter(&Delimiter::Seq), 2);
+        // the only group is a run, which starts with RUN_DELIMITER instead.
+        assert_eq!(stats.delimiter(&Delimiter::SeqValue), 0);
+        assert_eq!(stats.values(TypeTag::U16), 1);
+        assert_eq!(stats.values(TypeTag::U8), 3);
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    struct UnitStruct;
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    struct NewtypeStruct(u16);
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    struct TupleStruct(u32, String, i8);
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    enum Variants {
+        Unit,
+        Newtype(u8),
+        Tuple(u32, bool),
+        Struct { a: Option<u8>, b: Vec<u32> },
+    }
+
+    // Every type of the serde data model, nested in the ways that used to be ambiguous.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    struct DataModel {
+        bool: bool,
+        i8: i8,
+        i16: i16,
+        i32: i32,
+        i64: i64,
+        i128: i128,
+        u8: u8,
+        u16: u16,
+        u32: u32,
+        u64: u64,
+        u128: u128,
+        f32: f32,
+        f64: f64,
+        char: char,
+        string: String,
+        #[serde(with = "serde_bytes_compat")]
+        bytes: Vec<u8>,
+        option: Option<u8>,
+        unit: (),
+        unit_struct: UnitStruct,
+        variants: Vec<Variants>,
+        newtype_struct: NewtypeStruct,
+        seq: Vec<Vec<u32>>,
+        tuple: (u32, u8, String),
+        tuple_struct: TupleStruct,
+        map: HashMap<u8, (u32, Option<u8>)>,
+        nested_options: Vec<Option<Option<u8>>>,
+        unit_options: Vec<Option<()>>,
+        unit_struct_options: Vec<Option<UnitStruct>>,
+        seq_options: Vec<Option<Vec<u32>>>,
+        empty: Vec<Vec<u8>>,
+    }
+
+    // bytes go through `serialize_bytes` and `deserialize_byte_buf`.
+    mod serde_bytes_compat {
+        use serde::{de::Visitor, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(bytes)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<u8>, D::Error> {
+            struct BytesVisitor;
+            impl Visitor<'_> for BytesVisitor {
+                type Value = Vec<u8>;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    formatter.write_str("bytes")
+                }
+
+                fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+                    Ok(bytes.to_vec())
+                }
+
+                fn visit_byte_buf<E: serde::de::Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+                    Ok(bytes)
+                }
+            }
+            deserializer.deserialize_byte_buf(BytesVisitor)
+        }
+    }
+
+    #[test]
+    fn data_model() {
+        let value = DataModel {
+            bool: true,
+            i8: -3,
+            i16: 3,
+            i32: -300,
+            i64: 3,
+            i128: -(1 << 100),
+            u8: 3,
+            u16: 11,
+            u32: 3,
+            u64: u64::MAX,
+            u128: 3 << 90,
+            f32: 1.5,
+            f64: -0.25,
+            char: 'ß',
+            string: "data model".to_string(),
+            bytes: vec![3, 0, 255, 2],
+            option: Some(2),
+            unit: (),
+            unit_struct: UnitStruct,
+            variants: vec![
+                Variants::Unit,
+                Variants::Newtype(3),
+                Variants::Tuple(3, false),
+                Variants::Struct {
+                    a: Some(2),
+                    b: vec![3, 11, 19],
+                },
+            ],
+            newtype_struct: NewtypeStruct(3),
+            seq: vec![vec![3], vec![], vec![11, 3]],
+            tuple: (3, 2, "tuple".to_string()),
+            tuple_struct: TupleStruct(3, "tuple struct".to_string(), -1),
+            map: [(1, (3, Some(2))), (2, (11, None))].into(),
+            nested_options: vec![Some(Some(2)), Some(None), None, Some(Some(3))],
+            unit_options: vec![Some(()), None, Some(())],
+            unit_struct_options: vec![Some(UnitStruct), None],
+            seq_options: vec![Some(vec![3]), None, Some(vec![])],
+            empty: vec![vec![], vec![]],
+        };
+
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                adaptive_ints: true,
+                run_length_encoding: true,
+                pack_u8_seqs: true,
+                offset_tables: true,
+                sorted_int_keys: true,
+                byte_aligned_bools: true,
+                option_bitmaps: true,
+                ..Config::default()
+            },
+            Config {
+                self_describing: true,
+                adaptive_ints: true,
+                run_length_encoding: true,
+                pack_u8_seqs: true,
+                offset_tables: true,
+                sorted_int_keys: true,
+                byte_aligned_bools: true,
+                struct_keys: crate::config::StructKeys::Omit,
+                option_bitmaps: false,
+                msb_first: true,
+                aligned_fields: false,
+                length_prefixed_keys: true,
+                string_framing: crate::config::StringFraming::Escaped,
+                max_map_entries: Some(64),
+                max_depth: Some(64),
+                on_unknown_variant: crate::config::UnknownVariant::SkipToDefault,
+                raw_str_keys: false,
+            },
+            Config {
+                msb_first: true,
+                ..Config::default()
+            },
+            Config::patchable(),
+            Config {
+                adaptive_ints: true,
+                option_bitmaps: true,
+                msb_first: true,
+                ..Config::patchable()
+            },
+            Config {
+                string_framing: crate::config::StringFraming::LengthPrefixed,
+                ..self_describing()
+            },
+        ];
+        for config in configs {
+            let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<DataModel>(&bytes, &config)
+                .unwrap_or_else(|e| panic!("{config:?}: {e}"));
+            assert_eq!(value, decoded, "{config:?}");
+        }
+    }
+
+    #[test]
+    fn format_tokens() {
+        use crate::protocol::format::{BitWidth, Token, TypeTag};
+        use bitvec::{field::BitField, prelude as bv};
+
+        for delimiter in Delimiter::ALL {
+            let token = delimiter.token();
+            let bits = token.bits().collect::<bv::BitVec<u8, bv::Lsb0>>();
+            assert_eq!(bits.len(), delimiter.width());
+            assert_eq!(bits.load_le::<u8>(), delimiter as u8);
+            assert!(token.is_prefix_of(&bits));
+        }
+        // the bit patterns of the 1 byte delimiters, least significant bit first.
+        let string = Delimiter::String.token().bits().collect::<Vec<_>>();
+        assert_eq!(
+            string,
+            [false, true, true, false, false, false, false, true]
+        );
+        let byte = Delimiter::Byte.token().bits().collect::<Vec<_>>();
+        assert_eq!(byte, [true, true, true, false, false, false, false, true]);
+
+        // no type tag starts with the end of a sequence.
+        let seq = Delimiter::Seq.token();
+        for tag in TypeTag::ALL {
+            let bits = tag.token().bits().collect::<bv::BitVec<u8, bv::Lsb0>>();
+            assert!(!seq.is_prefix_of(&bits), "{tag}");
+        }
+        let short = Token::new(0b1, BitWidth::new(1));
+        assert!(!Delimiter::Packed
+            .token()
+            .is_prefix_of(&short.bits().collect::<bv::BitVec<u8, bv::Lsb0>>()));
+    }
+
+    #[test]
+    fn durations_and_times() {
+        use std::time::{Duration, SystemTime};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Compact {
+            #[serde(with = "crate::with::duration")]
+            took: Duration,
+            #[serde(with = "crate::with::system_time")]
+            at: SystemTime,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Plain {
+            took: Duration,
+            at: SystemTime,
+        }
+
+        let times = [
+            SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789),
+            SystemTime::UNIX_EPOCH - Duration::new(86_400, 1),
+            SystemTime::UNIX_EPOCH,
+        ];
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                adaptive_ints: true,
+                ..Config::default()
+            },
+        ];
+        for config in configs {
+            for at in times {
+                let compact = Compact {
+                    took: Duration::new(3, 999_999_999),
+                    at,
+                };
+                let bytes = serializer::to_bytes_with_config(&compact, &config).unwrap();
+                let decoded =
+                    deserializer::from_bytes_with_config::<Compact>(&bytes, &config).unwrap();
+                assert_eq!(compact, decoded);
+            }
+        }
+
+        // smaller than the structs serde writes by default (after the epoch).
+        let config = Config {
+            adaptive_ints: true,
+            ..Config::default()
+        };
+        let (took, at) = (Duration::from_millis(1500), times[0]);
+        let compact = serializer::to_bytes_with_config(&Compact { took, at }, &config).unwrap();
+        let plain = serializer::to_bytes_with_config(&Plain { took, at }, &config).unwrap();
+        assert!(compact.len() * 2 < plain.len());
+
+        // nanoseconds of a second or more are rejected.
+        #[derive(Debug, Deserialize)]
+        struct Took(#[serde(with = "crate::with::duration")] Duration);
+        let bytes = serializer::to_bytes(&(1u64, 1_000_000_000u32)).unwrap();
+        assert!(deserializer::from_bytes::<Took>(&bytes)
+            .map(|took| took.0)
+            .is_err());
+    }
+
+    #[test]
+    fn omitted_struct_keys() {
+        use crate::config::StructKeys;
+
+        let value = CompundTypes {
+            a: vec![1, 2, 3],
+            b: [("a".to_string(), 1)].into(),
+            c: Some(1),
+            d: None,
+            e: Primitives {
+                a: 1,
+                b: 2,
+                c: 3,
+                d: 4,
+                e: -1,
+                f: -2,
+                g: -3,
+                h: -4,
+                i: 1.0,
+                j: 2.0,
+                k: true,
+                l: 'a',
+                m: "hello".to_string(),
+            },
+            f: vec![AnEnum::A(1), AnEnum::B { a: 1, b: 2 }, AnEnum::C],
+        };
+        let omit = |config: Config| Config {
+            struct_keys: StructKeys::Omit,
+            ..config
+        };
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                run_length_encoding: true,
+                pack_u8_seqs: true,
+                offset_tables: true,
+                sorted_int_keys: true,
+                ..Config::default()
+            },
+        ];
+        for config in configs {
+            let names = serializer::to_bytes_with_config(&value, &config).unwrap();
+            let config = omit(config);
+            let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+            let decoded =
+                deserializer::from_bytes_with_config::<CompundTypes>(&bytes, &config).unwrap();
+            assert_eq!(value, decoded);
+            assert!(bytes.len() < names.len());
+        }
+
+        // the fields alone, in order.
+        let config = omit(Config::default());
+        let human = Human {
+            name: "Ayush".to_string(),
+            age: 19,
+        };
+        let bytes = serializer::to_bytes_with_config(&human, &config).unwrap();
+        // header + the length of "Ayush" + padding (none) + "Ayush" + 19
+        let [low, high] = config.header().to_le_bytes();
+        assert_eq!(
+            bytes,
+            [&[low, high, 5, 0, 0, 0], &b"Ayush\x13"[..]].concat()
+        );
+
+        // skipped fields can't be told apart from the next one.
+        #[derive(Serialize)]
+        struct Sparse {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            a: Option<u8>,
+            b: u8,
+        }
+        let sparse = Sparse { a: None, b: 1 };
+        assert!(serializer::to_bytes_with_config(&sparse, &config).is_err());
+        assert!(sparse.a.is_none() && sparse.b == 1);
+    }
+
+    #[test]
+    fn config_header() {
+        let config = Config {
+            adaptive_ints: true,
+            ..self_describing()
+        };
+        let value = (7u64, "header".to_string());
+        let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+
+        // from_bytes adopts the config of the header.
+        let decoded = deserializer::from_bytes::<(u64, String)>(&bytes).unwrap();
+        assert_eq!(value, decoded);
+        assert_eq!(Config::from_header(config.header()).unwrap(), config);
+
+        // another config is a mismatch instead of wrong values.
+        let error =
+            deserializer::from_bytes_with_config::<(u64, String)>(&bytes, &Config::default())
+                .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::ConfigMismatch { ref expected, ref found }
+                if *expected == Config::default() && *found == config
+        ));
+        let error = deserializer::LazyMap::new(&bytes, &self_describing()).unwrap_err();
+        assert!(matches!(error, Error::ConfigMismatch { .. }));
+
+        // bits of options this version doesn't know.
+        assert!(matches!(
+            deserializer::from_bytes::<u8>(&[0, 0x80, 1]),
+            Err(Error::InvalidHeader(0x8000))
+        ));
+    }
+
+    #[test]
+    fn writer() {
+        use std::io::{self, Write};
+
+        // a writer that takes a number of bytes before it is full.
+        struct Full {
+            written: Vec<u8>,
+            capacity: usize,
+        }
+        impl Write for Full {
+            fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+                if self.written.len() + bytes.len() > self.capacity {
+                    return Err(io::Error::new(io::ErrorKind::StorageFull, "disk full"));
+                }
+                self.written.extend(bytes);
+                Ok(bytes.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let value = (0..2000u32).map(|i| i.to_string()).collect::<Vec<_>>();
+        let config = self_describing();
+        let mut written = Vec::new();
+        serializer::to_writer_with_config(&mut written, &value, &config).unwrap();
+        assert_eq!(
+            written,
+            serializer::to_bytes_with_config(&value, &config).unwrap()
+        );
+
+        // the error of the writer comes back with the position of the failed write.
+        let mut full = Full {
+            written: Vec::new(),
+            capacity: 5000,
+        };
+        let error = serializer::to_writer(&mut full, &value).unwrap_err();
+        match error {
+            Error::Io { source, position } => {
+                assert_eq!(source.kind(), io::ErrorKind::StorageFull);
+                assert_eq!(position, full.written.len());
+                assert!(position > 0);
+            }
+            error => panic!("expected an io error but got {error}"),
+        }
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    struct StrictHuman {
+        name: String,
+        age: u8,
+    }
+
+    #[test]
+    fn deny_unknown_fields() {
+        let human = HumanV2 {
+            name: "Ayush".to_string(),
+            age: 19,
+            email: "ayush@example.com".to_string(),
+        };
+        let offset_tables = Config {
+            offset_tables: true,
+            ..Config::default()
+        };
+        for config in [Config::default(), self_describing(), offset_tables] {
+            let bytes = serializer::to_bytes_with_config(&human, &config).unwrap();
+            let error =
+                deserializer::from_bytes_with_config::<StrictHuman>(&bytes, &config).unwrap_err();
+            assert!(matches!(&error, Error::UnknownField(field) if field == "email"));
+            assert_eq!(
+                error.to_string(),
+                "unknown field `email` is denied or can not be skipped"
+            );
+
+            // without the attribute the field is skipped where the data allows it.
+            let lenient = deserializer::from_bytes_with_config::<Human>(&bytes, &config);
+            match config.self_describing || config.offset_tables {
+                true => assert_eq!(lenient.unwrap().name, "Ayush"),
+                false => assert!(matches!(lenient, Err(Error::UnknownField(_)))),
+            }
+        }
+    }
+
+    /// A unit variant with an arbitrary index, standing in for an enum with many variants.
+    #[derive(Debug, PartialEq)]
+    struct Variant(u32);
+    impl Serialize for Variant {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_unit_variant("Variant", self.0, "V")
+        }
+    }
+    impl<'de> Deserialize<'de> for Variant {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct Visitor;
+            impl<'de> serde::de::Visitor<'de> for Visitor {
+                type Value = Variant;
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("a variant")
+                }
+                fn visit_enum<A>(self, data: A) -> Result<Variant, A::Error>
+                where
+                    A: serde::de::EnumAccess<'de>,
+                {
+                    use serde::de::VariantAccess;
+                    let (index, variant) = data.variant::<u32>()?;
+                    variant.unit_variant()?;
+                    Ok(Variant(index))
+                }
+            }
+            // the test goes up to the index 70_000.
+            deserializer.deserialize_enum("Variant", &["V"; 70_001], Visitor)
+        }
+    }
+
+    #[test]
+    fn variant_index_width() {
+        // a byte for the common enum, the escape byte and a u32 beyond.
+        for (index, size) in [(0, 1), (254, 1), (255, 5), (70_000, 5)] {
+            for config in [Config::default(), self_describing()] {
+                let bytes = serializer::to_bytes_with_config(&Variant(index), &config).unwrap();
+                if !config.self_describing {
+                    assert_eq!(bytes.len(), 2 + size);
+                }
+                let decoded =
+                    deserializer::from_bytes_with_config::<Variant>(&bytes, &config).unwrap();
+                assert_eq!(decoded, Variant(index));
+            }
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+    struct Sparse {
+        id: u32,
+        a: Option<u8>,
+        b: Option<String>,
+        c: Option<Option<u8>>,
+        d: Option<Human>,
+        e: Vec<Option<u8>>,
+        f: Wrapped,
+        g: Option<u64>,
+        h: Option<bool>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+    struct Wrapped(Option<u16>);
+
+    #[test]
+    fn option_bitmaps() {
+        use crate::config::StructKeys;
+
+        let values = [
+            Sparse::default(),
+            Sparse {
+                id: 7,
+                b: Some("b".to_string()),
+                c: Some(None),
+                e: vec![None, Some(1)],
+                h: Some(false),
+                ..Sparse::default()
+            },
+            Sparse {
+                id: 8,
+                a: Some(1),
+                c: Some(Some(2)),
+                d: Some(Human {
+                    name: "Ayush".to_string(),
+                    age: 19,
+                }),
+                f: Wrapped(Some(3)),
+                g: Some(4),
+                ..Sparse::default()
+            },
+        ];
+        let bitmaps = |config: Config| Config {
+            option_bitmaps: true,
+            ..config
+        };
+        let omit = Config {
+            struct_keys: StructKeys::Omit,
+            ..Config::default()
+        };
+        let configs = [
+            Config::default(),
+            self_describing(),
+            omit.clone(),
+            Config {
+                adaptive_ints: true,
+                run_length_encoding: true,
+                pack_u8_seqs: true,
+                offset_tables: true,
+                sorted_int_keys: true,
+                ..Config::default()
+            },
+        ];
+        for config in configs {
+            for value in &values {
+                let config = bitmaps(config.clone());
+                let bytes = serializer::to_bytes_with_config(value, &config).unwrap();
+                let decoded = deserializer::from_bytes_with_config::<Sparse>(&bytes, &config);
+                assert_eq!(&decoded.unwrap(), value, "{config:?}");
+            }
+        }
+
+        // absent fields take no space at all; the bitmap takes a byte for every 8 fields.
+        let empty = serializer::to_bytes_with_config(&Sparse::default(), &bitmaps(omit.clone()));
+        assert_eq!(empty.unwrap().len(), 2 + 2 + 4 + 1);
+        #[derive(Serialize)]
+        struct Present {
+            id: u32,
+            e: Vec<Option<u8>>,
+            f: Wrapped,
+        }
+        let present = Present {
+            id: 0,
+            e: Vec::new(),
+            f: Wrapped(None),
+        };
+        let names =
+            serializer::to_bytes_with_config(&Sparse::default(), &bitmaps(Config::default()));
+        let present = serializer::to_bytes_with_config(&present, &bitmaps(Config::default()));
+        assert_eq!(names.unwrap(), present.unwrap());
+
+        // a tuple or an array starting with an option isn't an option itself; the option
+        // inside keeps its presence bit.
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Leading {
+            t: (Option<u8>, u8),
+            a: [Option<u16>; 2],
+            b: u8,
+        }
+        let leading = Leading {
+            t: (Some(7), 9),
+            a: [None, Some(3)],
+            b: 1,
+        };
+        for config in [Config::default(), self_describing(), omit.clone()] {
+            let config = bitmaps(config);
+            let bytes = serializer::to_bytes_with_config(&leading, &config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<Leading>(&bytes, &config);
+            assert_eq!(decoded.unwrap(), leading, "{config:?}");
+        }
+
+        // fields that are None aren't there for a lazy reader.
+        let config = bitmaps(Config {
+            offset_tables: true,
+            ..Config::default()
+        });
+        let bytes = serializer::to_bytes_with_config(&values[1], &config).unwrap();
+        let lazy = deserializer::LazyStruct::new(&bytes, &config).unwrap();
+        assert_eq!(lazy.get::<Option<u8>>("a").unwrap(), None);
+        assert_eq!(lazy.get::<Option<&str>>("b").unwrap(), Some(Some("b")));
+
+        // the bitmaps don't describe themselves.
+        let config = bitmaps(Config {
+            self_describing: true,
+            ..omit
+        });
+        assert!(serializer::to_bytes_with_config(&values[0], &config).is_err());
+        assert!(matches!(
+            Config::from_header(config.header()),
+            Err(Error::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn adversarial_framing() {
+        use std::collections::BTreeMap;
+
+        // keys and values whose bits look like delimiters, e.g. 139 = MAP_DELIMITER and
+        // 3 = 0b011 = SEQ_DELIMITER, right where a map or sequence could end.
+        let bytes = (0..=255u8).collect::<Vec<_>>();
+        let map = bytes.iter().map(|&b| (b, b)).collect::<BTreeMap<_, _>>();
+        let nested = bytes
+            .chunks(16)
+            .map(|chunk| (chunk[0], vec![chunk.to_vec()]))
+            .collect::<BTreeMap<_, _>>();
+        let words = [(0x8b8b_u16, 0x038b_u16), (0x0b03, 0x8b03)]
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                adaptive_ints: true,
+                run_length_encoding: true,
+                pack_u8_seqs: true,
+                offset_tables: true,
+                ..Config::default()
+            },
+        ];
+        for config in configs {
+            let encoded = serializer::to_bytes_with_config(&map, &config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<BTreeMap<_, _>>(&encoded, &config);
+            assert_eq!(decoded.unwrap(), map, "{config:?}");
+            let encoded = serializer::to_bytes_with_config(&nested, &config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<BTreeMap<_, _>>(&encoded, &config);
+            assert_eq!(decoded.unwrap(), nested, "{config:?}");
+            let encoded = serializer::to_bytes_with_config(&words, &config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<BTreeMap<_, _>>(&encoded, &config);
+            assert_eq!(decoded.unwrap(), words, "{config:?}");
+        }
+    }
+
+    #[test]
+    fn bit_patterns() {
+        use std::collections::BTreeMap;
+
+        #[derive(Serialize)]
+        struct Unit;
+        #[derive(Serialize)]
+        struct Newtype(u8);
+        #[derive(Serialize)]
+        struct Field {
+            a: u8,
+        }
+        #[derive(Serialize)]
+        enum Variants {
+            Unit,
+            Newtype(u8),
+            Tuple(u8, u8),
+            Struct { a: u8 },
+        }
+
+        // the bits are written least significant first so the first token is in the lowest
+        // bits of the first byte, e.g. SEQ_DELIMITER (0b0000_0011) + SEQ_VALUE_DELIMITER
+        // (0b0000_0100) + the first two bits of a u8 1 read 0b0110_0011.
+        macro_rules! assert_bits {
+            ($value:expr, $bytes:expr) => {
+                let bytes = serializer::to_bytes(&$value).unwrap();
+                let header = Config::default().header().to_le_bytes();
+                assert_eq!(bytes[..2], header, "header of {}", stringify!($value));
+                assert_eq!(bytes[2..], $bytes, "{}", stringify!($value));
+            };
+        }
+
+        // primitives: a bool is a single bit, numbers are little endian.
+        assert_bits!(true, [0b0000_0001]);
+        assert_bits!(false, [0b0000_0000]);
+        assert_bits!(1u8, [0b0000_0001]);
+        assert_bits!(-2i8, [0b1111_1110]);
+        assert_bits!(0x0102u16, [0b0000_0010, 0b0000_0001]);
+        assert_bits!(-1i16, [0b1111_1111, 0b1111_1111]);
+        assert_bits!(1u32, [1, 0, 0, 0]);
+        assert_bits!(1u64, [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_bits!(-1i64, [0xff; 8]);
+        assert_bits!(1u128, [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_bits!(-1i128, [0xff; 16]);
+        assert_bits!(1.5f32, [0, 0, 0b1100_0000, 0b0011_1111]);
+        assert_bits!(1.5f64, [0, 0, 0, 0, 0, 0, 0b1111_1000, 0b0011_1111]);
+        assert_bits!('a', [b'a', 0, 0, 0]);
+
+        // strings & bytes: the length (u32) + padding + the bytes.
+        assert_bits!("ab", [2, 0, 0, 0, b'a', b'b']);
+        assert_bits!(ByteBuf(vec![1, 2]), [2, 0, 0, 0, 0b0000_0001, 0b0000_0010]);
+
+        // unit: UNIT_DELIMITER (0b0000_0010); option: a presence bit.
+        assert_bits!((), [0b0000_0010]);
+        assert_bits!(Unit, [0b0000_0010]);
+        assert_bits!(None::<u8>, [0b0000_0000]);
+        assert_bits!(Some(1u8), [0b0000_0011, 0b0000_0000]);
+        assert_bits!(Newtype(1), [0b0000_0001]);
+
+        // seq: SEQ + (SEQ_VALUE + value)* + SEQ.
+        assert_bits!(Vec::<u8>::new(), [0b0001_1011]);
+        assert_bits!(vec![1u8], [0b0110_0011, 0b1100_0000, 0b0000_0000]);
+
+        // tuple: the values one after the other.
+        assert_bits!((1u8, 2u8), [0b0000_0001, 0b0000_0010]);
+
+        // map: (MAP_KEY + key + MAP_VALUE + value)* + MAP (0b1000_1011).
+        assert_bits!(
+            BTreeMap::from([(1u8, 2u8)]),
+            [0b0000_1110, 0b1011_1000, 0b1100_0000, 0b0010_0010]
+        );
+        assert_bits!(
+            Field { a: 1 },
+            [
+                0b0000_1110,
+                0,
+                0,
+                0,
+                0,
+                b'a',
+                0b0000_1111,
+                0b0101_1000,
+                0b0000_0100
+            ]
+        );
+
+        // enums: the variant index (a byte) + the content.
+        assert_bits!(Variants::Unit, [0]);
+        assert_bits!(Variants::Newtype(1), [1, 1]);
+        assert_bits!(Variants::Tuple(1, 2), [2, 1, 2]);
+        assert_bits!(
+            Variants::Struct { a: 1 },
+            [
+                3,
+                0b0000_1110,
+                0,
+                0,
+                0,
+                0,
+                b'a',
+                0b0000_1111,
+                0b0101_1000,
+                0b0000_0100
+            ]
+        );
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Shifted {
+        a: Option<bool>,
+        b: Option<bool>,
+        c: Option<bool>,
+        d: Option<bool>,
+        e: Option<bool>,
+        f: Option<bool>,
+        g: Option<bool>,
+        number: u32,
+        text: String,
+        signed: i64,
+        letter: char,
+    }
+
+    #[test]
+    fn unaligned_bytes() {
+        use crate::config::StructKeys;
+
+        // without the field names `None` takes 1 bit and `Some(true)` 2 bits so the values
+        // after the options start at every bit of a byte.
+        for shift in 0..8 {
+            let flag = |i: usize| (i < shift).then_some(true);
+            let value = Shifted {
+                a: flag(0),
+                b: flag(1),
+                c: flag(2),
+                d: flag(3),
+                e: flag(4),
+                f: flag(5),
+                g: flag(6),
+                number: 0x0102_0304,
+                text: "bytes".to_string(),
+                signed: -5,
+                letter: 'z',
+            };
+            let config = Config {
+                struct_keys: StructKeys::Omit,
+                ..Config::default()
+            };
+            let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+            let decoded = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+            assert_eq!(value, decoded);
+        }
+    }
+
+    #[test]
+    fn stream() {
+        use crate::container::{StreamReader, StreamWriter};
+
+        let config = Config::default();
+        let humans = (0..20)
+            .map(|age| Human {
+                name: format!("human {age}"),
+                age,
+            })
+            .collect::<Vec<_>>();
+        let mut writer = StreamWriter::new(Vec::new(), &config).unwrap();
+        for human in &humans {
+            writer.push(human).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = StreamReader::new(bytes.as_slice(), &config).unwrap();
+        for human in &humans {
+            assert_eq!(reader.read::<Human>().unwrap().as_ref(), Some(human));
+        }
+        assert!(!reader.is_finished());
+        assert_eq!(reader.read::<Human>().unwrap(), None);
+        assert!(reader.is_finished());
+        assert_eq!(reader.read::<Human>().unwrap(), None);
+
+        // a stream cut off anywhere, even right between two records, is an error.
+        for cut in [4, 5, bytes.len() / 2, bytes.len() - 4, bytes.len() - 1] {
+            let mut reader = StreamReader::new(&bytes[..cut], &config).unwrap();
+            let error = loop {
+                match reader.read::<Human>() {
+                    Ok(human) => assert!(human.is_some()),
+                    Err(error) => break error,
+                }
+            };
+            assert!(matches!(error, Error::UnexpectedEOF), "{cut}");
+        }
+
+        // so is a writer that is dropped before it is finished.
+        let mut bytes = Vec::new();
+        {
+            let mut writer = StreamWriter::new(&mut bytes, &config).unwrap();
+            writer.push(&humans[0]).unwrap();
+        }
+        let mut reader = StreamReader::new(bytes.as_slice(), &config).unwrap();
+        assert_eq!(reader.read::<Human>().unwrap().as_ref(), Some(&humans[0]));
+        assert!(matches!(reader.read::<Human>(), Err(Error::UnexpectedEOF)));
+
+        let error = StreamReader::new(&b"data"[..], &config).unwrap_err();
+        assert!(matches!(error, Error::InvalidStreamStart(start) if &start == b"data"));
+    }
+
+    #[test]
+    fn stream_max_record_size() {
+        use crate::container::{StreamReader, StreamWriter};
+
+        let config = Config::default();
+        let mut writer = StreamWriter::new(Vec::new(), &config).unwrap();
+        writer.push(&"small").unwrap();
+        writer.push(&"large".repeat(1000)).unwrap();
+        writer.push(&"small again").unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = StreamReader::with_max_record_size(bytes.as_slice(), &config, 64).unwrap();
+        assert_eq!(reader.read::<String>().unwrap().unwrap(), "small");
+        match reader.read::<String>() {
+            Err(Error::RecordTooLarge { length, max: 64 }) => assert!(length > 5000),
+            other => panic!("{other:?}"),
+        }
+        // the reader goes on after the rejected record.
+        assert_eq!(reader.read::<String>().unwrap().unwrap(), "small again");
+        assert_eq!(reader.read::<String>().unwrap(), None);
+
+        // a header claiming a huge record is rejected before anything is allocated.
+        let mut bytes = crate::protocol::format::STREAM_START.to_vec();
+        bytes.extend((u32::MAX - 1).to_le_bytes());
+        let mut reader =
+            StreamReader::with_max_record_size(bytes.as_slice(), &config, 1 << 20).unwrap();
+        assert!(matches!(
+            reader.read::<String>(),
+            Err(Error::RecordTooLarge { .. })
+        ));
+        assert!(matches!(reader.read::<String>(), Err(Error::UnexpectedEOF)));
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Hex(String);
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Block {
+        sealed: bool,
+        hash: Hex,
+        parents: Vec<Hex>,
+    }
+
+    #[test]
+    fn encoders() {
+        use crate::encoders::{Encoders, Primitive};
+
+        let mut encoders = Encoders::new();
+        encoders.register(
+            "Hex",
+            |value| match value {
+                Primitive::Str(hex) => (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                    .collect::<Result<_, _>>()
+                    .map_err(|error| Error::SerializationError(error.to_string())),
+                _ => Err(Error::SerializationError("not a string".to_string())),
+            },
+            |bytes| {
+                Ok(Primitive::Str(
+                    bytes.iter().map(|b| format!("{b:02x}")).collect(),
+                ))
+            },
+        );
+        assert!(encoders.contains("Hex"));
+
+        let block = Block {
+            sealed: true,
+            hash: Hex("00ff10ab".repeat(4)),
+            parents: vec![Hex("cafe".to_string()), Hex(String::new())],
+        };
+        let config = Config::default();
+        let bytes = serializer::to_bytes_with_encoders(&block, &config, &encoders).unwrap();
+        let decoded = deserializer::from_bytes_with_encoders(&bytes, &config, &encoders).unwrap();
+        assert_eq!(block, decoded);
+        assert!(bytes.len() < serializer::to_bytes(&block).unwrap().len());
+
+        // the self-describing mode doesn't use them.
+        let config = Config {
+            self_describing: true,
+            ..Config::default()
+        };
+        let bytes = serializer::to_bytes_with_encoders(&block, &config, &encoders).unwrap();
+        assert_eq!(
+            bytes,
+            serializer::to_bytes_with_config(&block, &config).unwrap()
+        );
+
+        // an error of the encoder is returned as is.
+        let config = Config::default();
+        assert!(matches!(
+            serializer::to_bytes_with_encoders(&Hex("xyz!".to_string()), &config, &encoders),
+            Err(Error::SerializationError(_))
+        ));
+
+        // an encoder only takes primitives.
+        {
+            #[derive(Serialize)]
+            struct Hex(Vec<u8>);
+            assert!(matches!(
+                serializer::to_bytes_with_encoders(&Hex(vec![1]), &config, &encoders),
+                Err(Error::SerializationError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn normalizer() {
+        use std::{borrow::Cow, collections::BTreeMap};
+
+        fn nfc(s: &str) -> Cow<'_, str> {
+            match s.contains("A\u{30a}") {
+                true => s.replace("A\u{30a}", "\u{c5}").into(),
+                false => s.into(),
+            }
+        }
+
+        let config = Config::default();
+        let decomposed = BTreeMap::from([("A\u{30a}ngstr\u{f6}m", vec!["A\u{30a}".to_string()])]);
+        let composed = BTreeMap::from([("\u{c5}ngstr\u{f6}m", vec!["\u{c5}".to_string()])]);
+        let bytes = serializer::to_bytes_with_normalizer(&decomposed, &config, &nfc).unwrap();
+        assert_eq!(
+            bytes,
+            serializer::to_bytes_with_normalizer(&composed, &config, &nfc).unwrap()
+        );
+        assert_ne!(bytes, serializer::to_bytes(&decomposed).unwrap());
+        let decoded: BTreeMap<String, Vec<String>> = deserializer::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded["\u{c5}ngstr\u{f6}m"], ["\u{c5}"]);
+    }
+
+    #[cfg(feature = "nfc")]
+    #[test]
+    fn nfc() {
+        use std::{borrow::Cow, collections::BTreeMap};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Place {
+            name: String,
+            tags: BTreeMap<String, char>,
+        }
+
+        // the Angstrom sign, A with a combining ring, and Hangul jamo are composed.
+        let place = |name: &str, key: &str| Place {
+            name: name.to_string(),
+            tags: BTreeMap::from([(key.to_string(), '\u{212b}')]),
+        };
+        let decomposed = place("\u{212b}ngstr\u{f6}m", "\u{1112}\u{1161}\u{11ab}");
+        let composed = place("\u{c5}ngstr\u{f6}m", "\u{d55c}");
+        for config in [Config::default(), self_describing()] {
+            let bytes = serializer::to_bytes_nfc(&decomposed, &config).unwrap();
+            assert_eq!(bytes, serializer::to_bytes_nfc(&composed, &config).unwrap());
+            assert_eq!(
+                bytes,
+                serializer::to_bytes_with_config(&composed, &config).unwrap()
+            );
+            // characters are written as they are.
+            let decoded: Place = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+            assert_eq!(decoded.tags["\u{d55c}"], '\u{212b}');
+        }
+        assert!(matches!(serializer::nfc("plain"), Cow::Borrowed(_)));
+        assert_eq!(serializer::nfc("e\u{301}"), "\u{e9}");
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum Reading {
+        Empty,
+        Level(i16),
+        Span { from: u32, to: u32 },
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct RadioFrame {
+        id: u64,
+        label: Option<String>,
+        readings: Vec<Reading>,
+        counts: HashMap<String, u8>,
+        payload: Vec<u8>,
+        scale: f32,
+        big: i128,
+        letter: char,
+        pair: (bool, Option<u16>),
+    }
+
+    #[test]
+    fn malformed_input_never_panics() {
+        use crate::config::StructKeys;
+        use serde::de::IgnoredAny;
+        use std::panic::catch_unwind;
+
+        let frame = RadioFrame {
+            id: 0x8b03,
+            label: Some("node".to_string()),
+            readings: vec![
+                Reading::Empty,
+                Reading::Level(-3),
+                Reading::Span { from: 1, to: 139 },
+            ],
+            counts: HashMap::from([("ok".to_string(), 3)]),
+            payload: vec![0x86, 0x87, 0x8b, 0x03],
+            scale: 0.5,
+            big: -1,
+            letter: 'λ',
+            pair: (true, None),
+        };
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                struct_keys: StructKeys::Omit,
+                adaptive_ints: true,
+                pack_u8_seqs: true,
+                byte_aligned_bools: true,
+                ..Config::default()
+            },
+            Config {
+                self_describing: true,
+                offset_tables: true,
+                sorted_int_keys: true,
+                option_bitmaps: true,
+                ..Config::default()
+            },
+        ];
+        for config in configs {
+            let bytes = serializer::to_bytes_with_config(&frame, &config).unwrap();
+            let mut inputs = (0..bytes.len())
+                .map(|length| bytes[..length].to_vec())
+                .collect::<Vec<_>>();
+            for bit in 16..bytes.len() * 8 {
+                let mut flipped = bytes.clone();
+                flipped[bit / 8] ^= 1 << (bit % 8);
+                inputs.push(flipped);
+            }
+            for input in inputs {
+                let decoded = catch_unwind(|| {
+                    let _ = deserializer::from_bytes_with_config::<RadioFrame>(&input, &config);
+                    let _ = deserializer::from_bytes::<IgnoredAny>(&input);
+                    let _ = deserializer::LazyStruct::new(&input, &config);
+                });
+                assert!(decoded.is_ok(), "{config:?} {input:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn token_reader() {
+        use crate::tokens::{Event, TokenReader};
+        use std::collections::BTreeMap;
+
+        #[derive(Serialize)]
+        enum Shape {
+            Dot,
+            Circle(u8),
+            Line(u8, u8),
+            Box { side: u8 },
+        }
+
+        let value = (
+            vec![
+                Shape::Dot,
+                Shape::Circle(1),
+                Shape::Line(2, 3),
+                Shape::Box { side: 4 },
+            ],
+            BTreeMap::from([(10u16, Some(vec![7u8; 3])), (20, None)]),
+        );
+        let variant = |index, name: &'static str| Event::VariantStart {
+            index,
+            name: name.into(),
+        };
+        let expected = [
+            Event::SeqStart,
+            Event::SeqStart,
+            Event::UnitVariant {
+                index: 0,
+                name: "Dot".into(),
+            },
+            variant(1, "Circle"),
+            Event::U8(1),
+            Event::VariantEnd,
+            variant(2, "Line"),
+            Event::SeqStart,
+            Event::U8(2),
+            Event::U8(3),
+            Event::SeqEnd,
+            Event::VariantEnd,
+            variant(3, "Box"),
+            Event::MapStart,
+            Event::Str("side".into()),
+            Event::U8(4),
+            Event::MapEnd,
+            Event::VariantEnd,
+            Event::SeqEnd,
+            Event::MapStart,
+            Event::U16(10),
+            Event::Some,
+            Event::SeqStart,
+            Event::U8(7),
+            Event::U8(7),
+            Event::U8(7),
+            Event::SeqEnd,
+            Event::U16(20),
+            Event::None,
+            Event::MapEnd,
+            Event::SeqEnd,
+        ];
+        // the events don't depend on how sequences and maps are laid out.
+        let configs = [
+            self_describing(),
+            Config {
+                run_length_encoding: true,
+                offset_tables: true,
+                ..self_describing()
+            },
+            Config {
+                pack_u8_seqs: true,
+                sorted_int_keys: true,
+                adaptive_ints: true,
+                ..self_describing()
+            },
+        ];
+        for config in configs {
+            let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+            let mut reader = TokenReader::new(&bytes, &config).unwrap();
+            let events = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(events, expected, "{config:?}");
+            assert_eq!(reader.depth(), 0);
+
+            // the reader stops at the first error.
+            let mut reader = TokenReader::new(&bytes[..bytes.len() - 2], &config).unwrap();
+            assert!(reader.by_ref().any(|event| event.is_err()));
+            assert!(reader.next().is_none());
+        }
+
+        assert!(matches!(
+            TokenReader::new(&serializer::to_bytes(&value).unwrap(), &Config::default()),
+            Err(Error::SelfDescribingRequired(_))
+        ));
+    }
+
+    #[test]
+    fn token_writer() {
+        use crate::tokens::{Event, TokenReader, TokenWriter};
+        use std::collections::BTreeMap;
+
+        let value = (
+            vec!["run".to_string(); 3],
+            BTreeMap::from([(1u32, vec![(true, 'x')]), (2, Vec::new())]),
+            Shifted {
+                a: Some(true),
+                b: None,
+                c: None,
+                d: None,
+                e: None,
+                f: None,
+                g: None,
+                number: 139,
+                text: "text".to_string(),
+                signed: -1,
+                letter: 'λ',
+            },
+        );
+        let configs = [
+            self_describing(),
+            Config {
+                run_length_encoding: true,
+                pack_u8_seqs: true,
+                sorted_int_keys: true,
+                offset_tables: true,
+                ..self_describing()
+            },
+        ];
+        for config in configs {
+            // events read from the data are written back as the same bytes.
+            let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+            let mut writer = TokenWriter::new(&config).unwrap();
+            for event in TokenReader::new(&bytes, &config).unwrap() {
+                writer.push(event.unwrap()).unwrap();
+            }
+            assert_eq!(writer.finish().unwrap(), bytes, "{config:?}");
+        }
+
+        // the events must make up a single balanced value.
+        let config = self_describing();
+        let mut writer = TokenWriter::new(&config).unwrap();
+        writer.push(Event::SeqStart).unwrap();
+        assert!(writer.push(Event::MapEnd).is_err());
+        writer
+            .push(Event::VariantStart {
+                index: 0,
+                name: "A".into(),
+            })
+            .unwrap();
+        writer.push(Event::U8(1)).unwrap();
+        assert!(writer.push(Event::U8(2)).is_err());
+        writer.push(Event::VariantEnd).unwrap();
+        writer.push(Event::MapStart).unwrap();
+        writer.push(Event::Str("key".into())).unwrap();
+        assert!(writer.push(Event::MapEnd).is_err());
+        writer
+            .push(Event::Bytes(b"value".as_slice().into()))
+            .unwrap();
+        writer.push(Event::MapEnd).unwrap();
+        writer.push(Event::Some).unwrap();
+        assert!(writer.push(Event::SeqEnd).is_err());
+        writer.push(Event::Unit).unwrap();
+        writer.push(Event::SeqEnd).unwrap();
+        assert!(writer.push(Event::None).is_err());
+        let bytes = writer.finish().unwrap();
+        let events = TokenReader::new(&bytes, &config).unwrap().count();
+        assert_eq!(events, 11);
+
+        let mut writer = TokenWriter::new(&config).unwrap();
+        assert!(writer.push(Event::SeqStart).is_ok());
+        assert!(writer.finish().is_err());
+        assert!(TokenWriter::new(&Config::default()).is_err());
+    }
+
+    #[test]
+    fn length_prefixes_out_of_bounds() {
+        use std::collections::BTreeMap;
+
+        // a length prefix patched to u32::MAX runs past the end of the data.
+        let patch = |bytes: &mut Vec<u8>, length: u32| {
+            use bitvec::{field::BitField, view::BitView};
+
+            let bits = bytes.view_bits_mut::<bitvec::order::Lsb0>();
+            let at = (16..bits.len() - 32)
+                .find(|&at| bits[at..at + 32].load_le::<u32>() == length)
+                .unwrap();
+            bits[at..at + 32].store_le(u32::MAX);
+        };
+        let out_of_bounds = |error| {
+            matches!(
+                error,
+                Error::LengthOutOfBounds { length, .. } if length >= u32::MAX as u64
+            )
+        };
+
+        let config = Config {
+            pack_u8_seqs: true,
+            ..Config::default()
+        };
+        let mut bytes = serializer::to_bytes_with_config(&vec![7u8; 3], &config).unwrap();
+        patch(&mut bytes, 3);
+        let error = deserializer::from_bytes_with_config::<Vec<u8>>(&bytes, &config);
+        assert!(out_of_bounds(error.unwrap_err()));
+
+        let map = BTreeMap::from([(1u64, 'a'), (2, 'b'), (3, 'c'), (4, 'd'), (5, 'e')]);
+        for config in [
+            Config {
+                sorted_int_keys: true,
+                ..Config::default()
+            },
+            Config {
+                offset_tables: true,
+                ..Config::default()
+            },
+        ] {
+            let mut bytes = serializer::to_bytes_with_config(&map, &config).unwrap();
+            patch(&mut bytes, 5);
+            let error =
+                deserializer::from_bytes_with_config::<BTreeMap<u64, char>>(&bytes, &config);
+            assert!(out_of_bounds(error.unwrap_err()), "{config:?}");
+        }
+    }
+
+    #[test]
+    fn msb_first() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Sample<'a> {
+            name: &'a str,
+            calibrated: bool,
+            // starts in the middle of a byte.
+            reading: u32,
+            flags: Vec<bool>,
+        }
+
+        let config = Config {
+            msb_first: true,
+            ..Config::default()
+        };
+        let header = |bytes: &[u8]| u16::from_le_bytes([bytes[0], bytes[1]]);
+
+        // the bits of a byte are filled from the most significant one down.
+        let lsb = serializer::to_bytes(&true).unwrap();
+        let msb = serializer::to_bytes_with_config(&true, &config).unwrap();
+        assert_eq!(lsb[2..], [0b0000_0001]);
+        assert_eq!(msb[2..], [0b1000_0000]);
+        assert_eq!(header(&msb), header(&lsb) | 1 << 9);
+        // and so are the bits of a token (UNIT_DELIMITER is 0b010).
+        let msb = serializer::to_bytes_with_config(&(), &config).unwrap();
+        assert_eq!(msb[2..], [0b0100_0000]);
+        // whole bytes keep their values and integers stay little endian.
+        let msb = serializer::to_bytes_with_config(&0x0102_0304u32, &config).unwrap();
+        assert_eq!(msb[2..], [4, 3, 2, 1]);
+
+        let sample = Sample {
+            name: "probe",
+            calibrated: true,
+            reading: 0xdead_beef,
+            flags: vec![true, false, true],
+        };
+        let bytes = serializer::to_bytes_with_config(&sample, &config).unwrap();
+        assert_ne!(bytes, serializer::to_bytes(&sample).unwrap());
+        assert_eq!(
+            deserializer::from_bytes_with_config::<Sample>(&bytes, &config).unwrap(),
+            sample
+        );
+        // the header tells the order.
+        assert_eq!(deserializer::from_bytes::<Sample>(&bytes).unwrap(), sample);
+        assert!(matches!(
+            deserializer::from_bytes_with_config::<Sample>(&bytes, &Config::default()),
+            Err(Error::ConfigMismatch { .. })
+        ));
+
+        assert!(matches!(
+            serializer::to_bitvec_with_config(&sample, &config),
+            Err(Error::UnsupportedCall(_))
+        ));
+        assert!(matches!(
+            deserializer::LazyStruct::new(&bytes, &config),
+            Err(Error::UnsupportedCall(_))
+        ));
+    }
+
+    #[test]
+    fn document() {
+        use crate::document::Document;
+        use std::time::{Duration, SystemTime};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Invoice<'a> {
+            number: u64,
+            customer: &'a str,
+        }
+
+        let document = Document {
+            created_at: SystemTime::UNIX_EPOCH + Duration::from_nanos(1_700_000_000_000_000_123),
+            content_type: "application/invoice".to_string(),
+            schema_id: 2,
+            payload: Invoice {
+                number: 42,
+                customer: "ayush",
+            },
+        };
+        for config in [
+            Config::default(),
+            Config {
+                struct_keys: crate::config::StructKeys::Omit,
+                adaptive_ints: true,
+                ..Config::default()
+            },
+        ] {
+            let bytes = document.seal(&config).unwrap();
+            assert_eq!(Document::open(&bytes, &config).unwrap(), document);
+        }
+
+        let now = Document::new("text/plain", 1, "hi");
+        assert!(now.created_at <= SystemTime::now());
+        let bytes = now.seal(&Config::default()).unwrap();
+        let opened = Document::<String>::open(&bytes, &Config::default()).unwrap();
+        assert_eq!(
+            (opened.content_type.as_str(), opened.payload.as_str()),
+            ("text/plain", "hi")
+        );
+        assert!(Document::<u32>::open(&bytes, &Config::default()).is_err());
+    }
+
+    #[test]
+    fn serde_attributes() {
+        use crate::config::StructKeys;
+        use std::time::Duration;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+        #[serde(rename_all = "camelCase")]
+        struct Account {
+            #[serde(rename = "id")]
+            account_id: u64,
+            display_name: String,
+            #[serde(skip_serializing_if = "Option::is_none", default)]
+            nickname: Option<String>,
+            #[serde(skip_serializing_if = "Vec::is_empty", default)]
+            tags: Vec<String>,
+            #[serde(with = "crate::with::duration")]
+            session: Duration,
+            #[serde(skip)]
+            cache: u8,
+            kind: Kind,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+        #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+        enum Kind {
+            #[default]
+            Personal,
+            #[serde(rename = "company")]
+            Business {
+                vat_id: String,
+            },
+            Shared(u8),
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+        #[serde(default)]
+        struct Settings {
+            theme: String,
+            volume: u8,
+            #[serde(rename = "v2")]
+            version: u16,
+        }
+
+        // an older version of `Settings` without the fields added since.
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "Settings")]
+        struct SettingsV1 {
+            theme: String,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(transparent)]
+        struct Meters(f64);
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(tag = "t", content = "c")]
+        enum Command {
+            Move(i32, i32),
+            Say(String),
+            Stop,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(tag = "type", rename_all = "lowercase")]
+        enum Message {
+            Text { body: String },
+            Ping,
+            Settings(Settings),
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(untagged)]
+        enum Id {
+            Number(u64),
+            Name(String),
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Envelope {
+            id: Id,
+            #[serde(flatten)]
+            settings: Settings,
+        }
+
+        fn roundtrip<T>(value: &T, config: &Config) -> Result<T, Error>
+        where
+            T: Serialize + for<'de> Deserialize<'de>,
+        {
+            let bytes = serializer::to_bytes_with_config(value, config)?;
+            deserializer::from_bytes_with_config(&bytes, config)
+        }
+
+        let account = |cache| Account {
+            account_id: 7,
+            display_name: "Ayush".to_string(),
+            nickname: Some("ay".to_string()),
+            tags: vec!["admin".to_string()],
+            session: Duration::from_millis(1500),
+            cache,
+            kind: Kind::Business {
+                vat_id: "DE123".to_string(),
+            },
+        };
+        let sparse = Account {
+            account_id: 8,
+            display_name: "Ada".to_string(),
+            kind: Kind::Shared(3),
+            ..Account::default()
+        };
+
+        for config in [
+            Config::default(),
+            self_describing(),
+            Config {
+                offset_tables: true,
+                option_bitmaps: true,
+                adaptive_ints: true,
+                ..Config::default()
+            },
+            Config {
+                struct_keys: StructKeys::Omit,
+                ..Config::default()
+            },
+            Config {
+                struct_keys: StructKeys::Omit,
+                self_describing: true,
+                ..Config::default()
+            },
+        ] {
+            let names = config.struct_keys == StructKeys::Names;
+            let config = &config;
+
+            // rename, rename_all, with, skip and transparent work everywhere.
+            assert_eq!(roundtrip(&account(9), config).unwrap(), account(0));
+            assert_eq!(roundtrip(&Meters(1.5), config).unwrap(), Meters(1.5));
+
+            // adjacently tagged enums need the field names.
+            for command in [
+                Command::Move(-1, 2),
+                Command::Say("hi".to_string()),
+                Command::Stop,
+            ] {
+                let decoded = roundtrip(&command, config);
+                match names {
+                    true => assert_eq!(decoded.unwrap(), command, "{config:?}"),
+                    false => assert!(decoded.is_err(), "{config:?}"),
+                }
+            }
+
+            // skipped fields need the field names and so do fields missing from older data,
+            // except at the end of a struct in the self-describing mode.
+            let decoded = roundtrip(&sparse, config);
+            match names {
+                true => assert_eq!(decoded.unwrap(), sparse, "{config:?}"),
+                false => assert!(matches!(decoded, Err(Error::SerializationError(_)))),
+            }
+            let old = SettingsV1 {
+                theme: "dark".to_string(),
+            };
+            let bytes = serializer::to_bytes_with_config(&old, config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<Settings>(&bytes, config);
+            let expected = Settings {
+                theme: "dark".to_string(),
+                ..Settings::default()
+            };
+            match names || config.self_describing {
+                true => assert_eq!(decoded.unwrap(), expected, "{config:?}"),
+                false => assert!(decoded.is_err(), "{config:?}"),
+            }
+
+            // internally tagged and untagged enums and flatten are read through
+            // `deserialize_any`, so they need the self-describing mode.
+            let message = Message::Settings(expected);
+            let decoded = roundtrip(&message, config);
+            match config.self_describing {
+                true => assert_eq!(decoded.unwrap(), message, "{config:?}"),
+                false => assert!(matches!(decoded, Err(Error::SelfDescribingRequired(_)))),
+            }
+            for id in [Id::Number(3), Id::Name("three".to_string())] {
+                let envelope = Envelope {
+                    id,
+                    settings: Settings::default(),
+                };
+                let decoded = roundtrip(&envelope, config);
+                match config.self_describing {
+                    true => assert_eq!(decoded.unwrap(), envelope, "{config:?}"),
+                    false => assert!(decoded.is_err(), "{config:?}"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn validator() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Address {
+            city: String,
+            zip: u32,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Signup {
+            name: String,
+            age: u8,
+            nickname: Option<String>,
+            tags: Vec<String>,
+            address: Address,
+        }
+
+        let mut validator = Validator::new();
+        validator
+            .max_len("Signup", "name", 8)
+            .max_len("Signup", "nickname", 4)
+            .range("Signup", "age", 13..=120)
+            .range("Address", "zip", 10_000..=99_999)
+            .check("Signup", "tags", |value| match value {
+                Primitive::Str(tag) if tag.starts_with('#') => Ok(()),
+                _ => Err("tags start with #".to_string()),
+            });
+        let signup = || Signup {
+            name: "Ayush".to_string(),
+            age: 19,
+            nickname: Some("ayu".to_string()),
+            tags: vec!["#rust".to_string()],
+            address: Address {
+                city: "a city with a very long name".to_string(),
+                zip: 12_345,
+            },
+        };
+
+        for config in [Config::default(), self_describing()] {
+            let bytes =
+                serializer::to_bytes_with_validator(&signup(), &config, &validator).unwrap();
+            assert_eq!(
+                bytes,
+                serializer::to_bytes_with_config(&signup(), &config).unwrap()
+            );
+            let value: Signup = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+            assert_eq!(value, signup());
+
+            let invalid = [
+                Signup {
+                    name: "a long name".to_string(),
+                    ..signup()
+                },
+                Signup { age: 7, ..signup() },
+                Signup {
+                    nickname: Some("ayush".to_string()),
+                    ..signup()
+                },
+                Signup {
+                    tags: vec!["#rust".to_string(), "serde".to_string()],
+                    ..signup()
+                },
+                Signup {
+                    address: Address {
+                        city: "x".to_string(),
+                        zip: 123,
+                    },
+                    ..signup()
+                },
+            ];
+            let expected = [
+                ("Signup", "name"),
+                ("Signup", "age"),
+                ("Signup", "nickname"),
+                ("Signup", "tags"),
+                ("Address", "zip"),
+            ];
+            for (value, expected) in invalid.iter().zip(expected) {
+                match serializer::to_bytes_with_validator(value, &config, &validator) {
+                    Err(Error::Invalid { name, field, .. }) => assert_eq!((name, field), expected),
+                    other => panic!("expected {expected:?} to be invalid, got {other:?}"),
+                }
+            }
+        }
+
+        let error = serializer::to_bytes_with_validator(
+            &Signup {
+                age: 200,
+                ..signup()
+            },
+            &Config::default(),
+            &validator,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the field age of Signup is invalid: U64(200) is out of the range 13..=120"
+        );
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn chunking() {
+        use crate::chunking::{ChunkRef, Chunker, Manifest};
+        use digest::Output;
+        use sha2::Sha256;
+        use std::collections::HashSet;
+
+        let chunker = Chunker::new(256, 1024, 4096);
+        let mut snapshot: Vec<String> = (0..10_000).map(|i| format!("entry {i}")).collect();
+        let before = serializer::to_bytes(&snapshot).unwrap();
+        let chunks: Vec<_> = chunker.chunks(&before).collect();
+        assert!(chunks.len() > 10);
+        assert_eq!(chunks.concat(), before);
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(rest.iter().all(|chunk| (256..=4096).contains(&chunk.len())));
+        assert!(last.len() <= 4096);
+
+        // an insertion in the middle only changes the chunks around it.
+        snapshot.insert(5_000, "a new entry".to_string());
+        let after = serializer::to_bytes(&snapshot).unwrap();
+        let (old, old_chunks) = chunker.split::<Sha256>(&before);
+        let (new, new_chunks) = chunker.split::<Sha256>(&after);
+        let known: HashSet<_> = old.chunks.iter().map(|chunk| chunk.hash).collect();
+        let changed = new
+            .chunks
+            .iter()
+            .filter(|chunk| !known.contains(&chunk.hash))
+            .count();
+        assert!((1..=3).contains(&changed), "{changed} chunks changed");
+
+        let store: HashMap<Output<Sha256>, Vec<u8>> = old_chunks
+            .iter()
+            .chain(&new_chunks)
+            .map(|chunk| (chunk.hash, chunk.data.to_vec()))
+            .collect();
+        let fetch = |hash: &Output<Sha256>| store.get(hash).map(Vec::as_slice);
+        assert_eq!(old.reassemble::<Sha256, _>(fetch).unwrap(), before);
+        assert_eq!(new.reassemble::<Sha256, _>(fetch).unwrap(), after);
+        assert_eq!(new.len, after.len() as u64);
+
+        // the manifest is stored with rust-fr itself.
+        let bytes = serializer::to_bytes(&new).unwrap();
+        let manifest: Manifest<Output<Sha256>> = deserializer::from_bytes(&bytes).unwrap();
+        assert_eq!(manifest, new);
+        // hashes kept as plain bytes work too.
+        let manifest: Manifest<Vec<u8>> = Manifest {
+            len: new.len,
+            chunks: new
+                .chunks
+                .iter()
+                .map(|chunk| ChunkRef {
+                    hash: chunk.hash.to_vec(),
+                    len: chunk.len,
+                })
+                .collect(),
+        };
+        assert_eq!(
+            manifest
+                .reassemble::<Sha256, _>(|hash| fetch(&Output::<Sha256>::clone_from_slice(hash)))
+                .unwrap(),
+            after
+        );
+
+        let missing = new.chunks[1].hash;
+        assert!(matches!(
+            new.reassemble::<Sha256, _>(|hash| (*hash != missing).then(|| fetch(hash)).flatten()),
+            Err(Error::MissingChunk(1))
+        ));
+        assert!(matches!(
+            new.reassemble::<Sha256, _>(|_| Some(&after[..16])),
+            Err(Error::CorruptChunk(0))
+        ));
+        assert_eq!(chunker.chunks(&[]).count(), 0);
+    }
+
+    #[test]
+    fn hash_value() {
+        use crate::protocol;
+        use std::{collections::hash_map::DefaultHasher, hash::Hasher};
+
+        // records every write so the test can tell the bytes were fed in pieces.
+        #[derive(Default)]
+        struct Recorder(Vec<Vec<u8>>);
+
+        impl Hasher for Recorder {
+            fn finish(&self) -> u64 {
+                0
+            }
+            fn write(&mut self, bytes: &[u8]) {
+                self.0.push(bytes.to_vec());
+            }
+        }
+
+        let small = Human {
+            name: "Ayush".to_string(),
+            age: 19,
+        };
+        let large: Vec<(String, bool, Option<u16>)> = (0..20_000)
+            .map(|i| {
+                (
+                    format!("entry {i}"),
+                    i % 3 == 0,
+                    (i % 5 == 0).then_some(i as u16),
+                )
+            })
+            .collect();
+
+        let mut recorder = Recorder::default();
+        protocol::hash_value(&large, &mut recorder).unwrap();
+        assert!(recorder.0.len() > 1);
+        assert_eq!(recorder.0.concat(), serializer::to_bytes(&large).unwrap());
+
+        let hash = |bytes: &[u8]| {
+            let mut hasher = DefaultHasher::new();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        let hash_value = |value: &dyn Fn(&mut DefaultHasher)| {
+            let mut hasher = DefaultHasher::new();
+            value(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(
+            hash_value(&|hasher| protocol::hash_value(&small, hasher).unwrap()),
+            hash(&serializer::to_bytes(&small).unwrap())
+        );
+        assert_eq!(
+            hash_value(&|hasher| protocol::hash_value(&large, hasher).unwrap()),
+            hash(&serializer::to_bytes(&large).unwrap())
+        );
+        assert_ne!(
+            hash_value(&|hasher| protocol::hash_value(&small, hasher).unwrap()),
+            hash_value(&|hasher| protocol::hash_value(&large, hasher).unwrap())
+        );
+    }
+
+    #[test]
+    fn differential() {
+        use std::collections::BTreeMap;
+
+        // the values of the fuzz target in fuzz/fuzz_targets/differential.rs.
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        enum Value {
+            Unit,
+            Bool(bool),
+            I8(i8),
+            I64(i64),
+            U16(u16),
+            U64(u64),
+            I128(i128),
+            F32(f32),
+            F64(f64),
+            Char(char),
+            Str(String),
+            Bytes(Vec<u8>),
+            Option(Option<Box<Value>>),
+            Seq(Vec<Value>),
+            Map(BTreeMap<String, Value>),
+            IntMap(BTreeMap<u32, Value>),
+            Tuple(u8, String),
+            Struct {
+                a: Option<u16>,
+                b: String,
+                c: Vec<bool>,
+            },
+        }
+
+        impl Value {
+            // Whether the value has a string with the byte of the String delimiter or bytes
+            // with the byte of the Byte delimiter, which end them early.
+            fn collides(&self) -> bool {
+                let str = |s: &String| s.bytes().any(|b| b == Delimiter::String as u8);
+                match self {
+                    Value::Str(s) | Value::Tuple(_, s) | Value::Struct { b: s, .. } => str(s),
+                    Value::Bytes(bytes) => bytes.contains(&(Delimiter::Byte as u8)),
+                    Value::Option(value) => value.as_ref().is_some_and(|v| v.collides()),
+                    Value::Seq(values) => values.iter().any(Value::collides),
+                    Value::Map(map) => map.iter().any(|(k, v)| str(k) || v.collides()),
+                    Value::IntMap(map) => map.values().any(Value::collides),
+                    _ => false,
+                }
+            }
+        }
+
+        // xorshift64, so the values are the same on every run.
+        struct Rng(u64);
+
+        impl Rng {
+            fn next(&mut self) -> u64 {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                self.0
+            }
+            fn below(&mut self, n: u64) -> u64 {
+                self.next() % n
+            }
+            // strings with the bytes of delimiters, control characters and multi-byte chars.
+            fn string(&mut self) -> String {
+                let len = self.below(12);
+                (0..len)
+                    .map(|_| match self.below(4) {
+                        0 => char::from(self.below(256) as u8),
+                        1 => ['\0', '\u{1}', '\u{7f}', 'é', '€', '🦀'][self.below(6) as usize],
+                        _ => char::from(b'a' + self.below(26) as u8),
+                    })
+                    .collect()
+            }
+            fn value(&mut self, depth: u32) -> Value {
+                let kinds = if depth == 0 { 12 } else { 18 };
+                match self.below(kinds) {
+                    0 => Value::Unit,
+                    1 => Value::Bool(self.below(2) == 0),
+                    2 => Value::I8(self.next() as i8),
+                    3 => Value::I64(self.next() as i64 >> self.below(64)),
+                    4 => Value::U16(self.next() as u16),
+                    5 => Value::U64(self.next() >> self.below(64)),
+                    6 => Value::I128((self.next() as i128) << 64 | self.next() as i128),
+                    7 => Value::F32(f32::from_bits(self.next() as u32)),
+                    8 => Value::F64(f64::from_bits(self.next())),
+                    9 => Value::Char(char::from_u32(self.below(0x11_0000) as u32).unwrap_or('?')),
+                    10 => Value::Str(self.string()),
+                    11 => {
+                        let len = self.below(300);
+                        Value::Bytes((0..len).map(|_| self.next() as u8).collect())
+                    }
+                    12 => {
+                        Value::Option((self.below(2) == 0).then(|| Box::new(self.value(depth - 1))))
+                    }
+                    13 => Value::Seq((0..self.below(5)).map(|_| self.value(depth - 1)).collect()),
+                    14 => Value::Map(
+                        (0..self.below(5))
+                            .map(|_| (self.string(), self.value(depth - 1)))
+                            .collect(),
+                    ),
+                    15 => Value::IntMap(
+                        (0..self.below(5))
+                            .map(|_| (self.next() as u32 >> self.below(32), self.value(depth - 1)))
+                            .collect(),
+                    ),
+                    16 => Value::Tuple(self.next() as u8, self.string()),
+                    _ => Value::Struct {
+                        a: (self.below(2) == 0).then(|| self.next() as u16),
+                        b: self.string(),
+                        c: (0..self.below(20)).map(|_| self.below(2) == 0).collect(),
+                    },
+                }
+            }
+        }
+
+        let configs: Vec<Config> = (0..1 << Config::OPTIONS.len())
+            .filter_map(|header| Config::from_header(header).ok())
+            .collect();
+        let mut rng = Rng(0x5275_7374_2d66_7221);
+        let mut mangled = 0;
+        for i in 0..1000 {
+            let value = rng.value(3);
+            // NaN isn't equal to itself; such values are only checked to decode.
+            let comparable = value == value;
+
+            let mut bytes = Vec::new();
+            ciborium::into_writer(&value, &mut bytes).unwrap();
+            let reference: Value = ciborium::from_reader(bytes.as_slice()).unwrap();
+            assert!(!comparable || reference == value);
+
+            // every value with the default config and a few others, every config with a few
+            // values.
+            let sample = [0, i % configs.len(), i * 7 % configs.len()];
+            for config in sample.map(|i| &configs[i]) {
+                let bytes = serializer::to_bytes_with_config(&value, config)
+                    .unwrap_or_else(|e| panic!("{value:?} with {config:?}: {e}"));
+                let decoded = deserializer::from_bytes_with_config::<Value>(&bytes, config);
+                match decoded {
+                    Ok(decoded) if !comparable || decoded == value => continue,
+                    // the delimiters aren't escaped yet; this is the one known class of
+                    // values that is mangled.
+                    _ if value.collides() => mangled += 1,
+                    Ok(decoded) => panic!("{value:?} with {config:?} decoded as {decoded:?}"),
+                    Err(e) => panic!("{value:?} with {config:?}: {e}"),
+                }
+            }
+        }
+        assert!(mangled > 0);
+    }
+
+    #[test]
+    fn conversion_attributes() {
+        use crate::config::StructKeys;
+
+        // a validated newtype.
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        #[serde(try_from = "u8", into = "u8")]
+        struct Percent(u8);
+
+        impl TryFrom<u8> for Percent {
+            type Error = String;
+
+            fn try_from(value: u8) -> Result<Self, Self::Error> {
+                match value <= 100 {
+                    true => Ok(Percent(value)),
+                    false => Err(format!("{value} is more than 100 percent")),
+                }
+            }
+        }
+        impl From<Percent> for u8 {
+            fn from(percent: Percent) -> u8 {
+                percent.0
+            }
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        #[serde(from = "String", into = "String")]
+        struct Tag(String);
+
+        impl From<String> for Tag {
+            fn from(tag: String) -> Self {
+                Tag(tag.to_lowercase())
+            }
+        }
+        impl From<Tag> for String {
+            fn from(tag: Tag) -> String {
+                tag.0
+            }
+        }
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct Progress {
+            task: String,
+            done: Percent,
+            tags: Vec<Tag>,
+        }
+
+        // the same shape without the checks.
+        #[derive(Serialize)]
+        struct Unchecked {
+            task: &'static str,
+            done: u8,
+            tags: Vec<&'static str>,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Project {
+            name: String,
+            progress: Progress,
+        }
+
+        #[derive(Serialize)]
+        struct UncheckedProject {
+            name: &'static str,
+            progress: Unchecked,
+        }
+
+        let progress = Progress {
+            task: "write docs".to_string(),
+            done: Percent(40),
+            tags: vec![Tag("docs".to_string())],
+        };
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                struct_keys: StructKeys::Omit,
+                ..Config::default()
+            },
+            Config {
+                struct_keys: StructKeys::Omit,
+                ..self_describing()
+            },
+            Config {
+                struct_keys: StructKeys::Omit,
+                option_bitmaps: true,
+                ..Config::default()
+            },
+        ];
+        for config in configs {
+            let bytes = serializer::to_bytes_with_config(&progress, &config).unwrap();
+            let value: Progress = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+            assert_eq!(value, progress);
+
+            // `from` converts.
+            let unchecked = Unchecked {
+                task: "write docs",
+                done: 40,
+                tags: vec!["DOCS"],
+            };
+            let bytes = serializer::to_bytes_with_config(&unchecked, &config).unwrap();
+            let value: Progress = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+            assert_eq!(value, progress);
+
+            // the error of `try_from` comes with the field (and the fields around it).
+            let project = UncheckedProject {
+                name: "rust-fr",
+                progress: Unchecked {
+                    done: 150,
+                    ..unchecked
+                },
+            };
+            let bytes = serializer::to_bytes_with_config(&project, &config).unwrap();
+            match deserializer::from_bytes_with_config::<Project>(&bytes, &config) {
+                Err(Error::DeserializationError(message)) => assert_eq!(
+                    message,
+                    "the field progress of struct Project: \
+                     the field done of struct Progress: 150 is more than 100 percent",
+                    "{config:?}"
+                ),
+                other => panic!("{config:?}: {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn overhead() {
+        use crate::overhead::{self, *};
+
+        // the bits of a value without the header.
+        fn bits<T: Serialize>(value: &T) -> usize {
+            serializer::to_bitvec(value).unwrap().len() - HEADER_BITS
+        }
+
+        assert_eq!(bits(&()), UNIT_BITS);
+        assert_eq!(bits(&None::<u8>), OPTION_BITS);
+        assert_eq!(bits(&Some(1u8)), OPTION_BITS + 8);
+        assert_eq!(bits(&"ab"), 16 + STRING_BITS);
+        assert_eq!(bits(&ByteBuf(vec![1, 2])), 16 + BYTES_BITS);
+        assert_eq!(bits(&vec![1u8, 2]), SEQ_BITS + 2 * (SEQ_ELEMENT_BITS + 8));
+        assert_eq!(bits(&(1u8, 2u16)), 24);
+        let map: HashMap<u8, u8> = [(1, 2)].into();
+        assert_eq!(bits(&map), MAP_BITS + MAP_ENTRY_BITS + 16);
+        assert_eq!(bits(&Variants::Newtype(1)), VARIANT_INDEX_BITS + 8);
+        // 1 bit in front of the string: 7 bits of padding.
+        assert_eq!(bits(&(true, "ab")), 1 + MAX_PADDING_BITS + 16 + STRING_BITS);
+
+        let human = Human {
+            name: "Ayush".to_string(),
+            age: 19,
+        };
+        let estimate = overhead::estimate_size(&human).unwrap();
+        // the two keys and the name start on a byte boundary.
+        assert_eq!(estimate.max_bits - estimate.min_bits, 3 * MAX_PADDING_BITS);
+        let len = serializer::to_bytes(&human).unwrap().len();
+        assert!((estimate.min_bytes()..=estimate.max_bytes()).contains(&len));
+        // wherever the value starts inside a larger one.
+        for offset in 0..8 {
+            let flags = vec![true; offset];
+            let before = bits(&(flags.clone(),));
+            let after = bits(&(flags, &human));
+            assert!((estimate.min_bits..=estimate.max_bits).contains(&(after - before)));
+        }
+    }
+
+    #[test]
+    fn partial_decoder() {
+        use crate::container::{PartialDecoder, StreamWriter};
+        use std::task::Poll;
+
+        let config = Config::default();
+        let mut writer = StreamWriter::new(Vec::new(), &config).unwrap();
+        let humans: Vec<Human> = (0..50)
+            .map(|age| Human {
+                name: "a".repeat(age as usize),
+                age,
+            })
+            .collect();
+        for human in &humans {
+            writer.push(human).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+
+        // the bytes arrive in reads of 1 to 37 bytes.
+        let mut decoder = PartialDecoder::<Human>::new(&config);
+        let (mut decoded, mut start, mut size) = (Vec::new(), 0, 1);
+        while start < bytes.len() {
+            let end = bytes.len().min(start + size);
+            let mut poll = decoder.feed(&bytes[start..end]);
+            while let Poll::Ready(human) = poll {
+                decoded.push(human.unwrap());
+                poll = decoder.poll();
+            }
+            (start, size) = (end, size % 37 + 1);
+        }
+        assert_eq!(decoded, humans);
+        assert!(decoder.is_finished());
+        assert_eq!(decoder.buffered(), 0);
+
+        // a rejected record is dropped as it's fed and the next one is decoded.
+        let mut writer = StreamWriter::new(Vec::new(), &config).unwrap();
+        writer.push(&"large".repeat(100)).unwrap();
+        writer.push(&"small").unwrap();
+        let bytes = writer.finish().unwrap();
+        let mut decoder = PartialDecoder::<String>::with_max_record_size(&config, 64);
+        assert!(matches!(
+            decoder.feed(&bytes[..100]),
+            Poll::Ready(Err(Error::RecordTooLarge { max: 64, .. }))
+        ));
+        assert!(decoder.poll().is_pending());
+        assert_eq!(decoder.buffered(), 0);
+        assert!(decoder.feed(&bytes[100..400]).is_pending());
+        assert_eq!(decoder.buffered(), 0);
+        match decoder.feed(&bytes[400..]) {
+            Poll::Ready(Ok(value)) => assert_eq!(value, "small"),
+            other => panic!("{other:?}"),
+        }
+        assert!(decoder.poll().is_pending());
+        assert!(decoder.is_finished());
+
+        let mut decoder = PartialDecoder::<String>::new(&config);
+        assert!(decoder.feed(b"rf").is_pending());
+        assert!(matches!(
+            decoder.feed(b"x!"),
+            Poll::Ready(Err(Error::InvalidStreamStart(start))) if &start == b"rfx!"
+        ));
+    }
+
+    #[test]
+    fn patchable() {
+        use crate::patch::{self, Layout};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Record {
+            seen: Option<bool>,
+            version: u16,
+            flags: (bool, bool),
+            score: f32,
+            tags: Vec<String>,
+            // after a variable-size field.
+            checksum: u32,
+        }
+
+        let config = Config::patchable();
+        let record = |tags: &[&str]| Record {
+            seen: None,
+            version: 1,
+            flags: (false, true),
+            score: 0.5,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            checksum: 0xdead_beef,
+        };
+
+        // the fixed-width fields in front are at the same offsets in every record.
+        let short = Layout::of(&record(&[]), &config).unwrap();
+        let long = Layout::of(&record(&["a", "bcd", "efgh"]), &config).unwrap();
+        assert_eq!(short.fields()[..4], long.fields()[..4]);
+        assert_ne!(short.get("checksum"), long.get("checksum"));
+        let offsets: Vec<_> = short.fields().iter().map(|field| field.offset).collect();
+        assert_eq!(offsets[..5], [2, 3, 5, 7, 11]);
+        assert!(short.get("missing").is_none());
+
+        let mut bytes = serializer::to_bytes_with_config(&record(&["x"]), &config).unwrap();
+        let layout = Layout::of(&record(&["x"]), &config).unwrap();
+        let field = |name| layout.get(name).unwrap();
+        patch::patch(&mut bytes, field("version"), &7u16, &config).unwrap();
+        patch::patch(&mut bytes, field("flags"), &(true, false), &config).unwrap();
+        patch::patch(&mut bytes, field("score"), &2.5f32, &config).unwrap();
+        patch::patch(&mut bytes, field("checksum"), &42u32, &config).unwrap();
+        // `None` is a single bit and `Some(bool)` is 9.
+        assert!(matches!(
+            patch::patch(&mut bytes, field("seen"), &Some(true), &config),
+            Err(Error::PatchSizeMismatch {
+                field: "seen",
+                expected: 1,
+                found: 9
+            })
+        ));
+        patch::patch(&mut bytes, field("seen"), &None::<bool>, &config).unwrap();
+        let decoded = deserializer::from_bytes_with_config::<Record>(&bytes, &config).unwrap();
+        assert_eq!(
+            decoded,
+            Record {
+                version: 7,
+                flags: (true, false),
+                score: 2.5,
+                checksum: 42,
+                ..record(&["x"])
+            }
+        );
+
+        // the fields of a struct that ends in the middle of a byte are patched bit by bit.
+        let config = Config {
+            byte_aligned_bools: false,
+            ..Config::patchable()
+        };
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Pair {
+            left: Option<u8>,
+            right: bool,
+        }
+        // the option after the struct shares the byte of `right`.
+        let pairs = (
+            Pair {
+                left: Some(3),
+                right: true,
+            },
+            Some(false),
+        );
+        let mut bytes = serializer::to_bytes_with_config(&pairs, &config).unwrap();
+        let layout = Layout::of(&pairs, &config).unwrap();
+        assert_eq!(layout.fields().len(), 2);
+        let right = layout.get("right").unwrap();
+        assert_eq!(right.bits, 1);
+        patch::patch(&mut bytes, right, &false, &config).unwrap();
+        let decoded: (Pair, Option<bool>) =
+            deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+        assert_eq!(
+            decoded,
+            (
+                Pair {
+                    left: Some(3),
+                    right: false
+                },
+                Some(false)
+            )
+        );
+
+        // the profile needs the values alone.
+        let invalid = Config {
+            struct_keys: crate::config::StructKeys::Names,
+            ..Config::patchable()
+        };
+        assert!(serializer::to_bytes_with_config(&record(&[]), &invalid).is_err());
+        assert!(Config::from_header(invalid.header()).is_err());
+        assert!(matches!(
+            Layout::of(&record(&[]), &Config::default()),
+            Err(Error::UnsupportedCall(_))
+        ));
+    }
+
+    #[test]
+    fn with_text_adapters() {
+        use std::{
+            net::{IpAddr, Ipv4Addr},
+            time::{Duration, SystemTime},
+        };
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Entry {
+            #[serde(with = "crate::with::hex")]
+            hash: [u8; 8],
+            #[serde(with = "crate::with::hex")]
+            salt: Vec<u8>,
+            #[serde(with = "crate::with::base64")]
+            payload: Vec<u8>,
+            #[serde(with = "crate::with::rfc3339")]
+            at: SystemTime,
+            #[serde(with = "crate::with::bitset")]
+            flags: Vec<bool>,
+        }
+
+        let entry = |len: usize, nanos: i64| Entry {
+            hash: *b"\x00\x01\xab\xcd\xef\x10\x7f\xff",
+            salt: (0..len as u8).collect(),
+            payload: (0..len as u8).map(|i| i.wrapping_mul(37)).collect(),
+            at: match nanos < 0 {
+                true => SystemTime::UNIX_EPOCH - Duration::from_nanos(nanos.unsigned_abs()),
+                false => SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos as u64),
+            },
+            flags: (0..len * 3).map(|i| i % 3 == 0).collect(),
+        };
+        // every length of the last base64 group and times before and after the epoch.
+        for len in 0..8 {
+            for nanos in [0, 1, -1, 1_700_000_000_500_000_000, -86_400_000_000_001] {
+                let entry = entry(len, nanos);
+                for config in [Config::default(), self_describing()] {
+                    let bytes = serializer::to_bytes_with_config(&entry, &config).unwrap();
+                    let decoded = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+                    assert_eq!(entry, decoded);
+                }
+                let json = serde_json::to_string(&entry).unwrap();
+                assert_eq!(
+                    serde_json::from_str::<Entry>(&json).unwrap(),
+                    entry,
+                    "{json}"
+                );
+            }
+        }
+
+        let json = serde_json::to_value(entry(3, -1)).unwrap();
+        assert_eq!(json["hash"], "0001abcdef107fff");
+        assert_eq!(json["salt"], "000102");
+        assert_eq!(json["payload"], "ACVK");
+        assert_eq!(json["at"], "1969-12-31T23:59:59.999999999Z");
+        assert_eq!(json["flags"][3], true);
+        // the hash is raw bytes in rust-fr.
+        let bytes = serializer::to_bytes(&entry(3, 0)).unwrap();
+        assert!(bytes.windows(8).any(|window| window == entry(3, 0).hash));
+        assert!(bytes.len() < serde_json::to_vec(&entry(3, 0)).unwrap().len());
+
+        // RFC 3339 timestamps with an offset, in lowercase or with a long fraction.
+        let at = |text: &str| {
+            let json = format!(
+                r#"{{"hash":"0001ABCDEF107FFF","salt":"","payload":"","at":"{text}","flags":[]}}"#
+            );
+            serde_json::from_str::<Entry>(&json).map(|entry| entry.at)
+        };
+        let expected = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        assert_eq!(at("2023-11-14T22:13:20.5Z").unwrap(), expected);
+        assert_eq!(at("2023-11-15T00:13:20.500+02:00").unwrap(), expected);
+        assert_eq!(at("2023-11-14t22:13:20.5000000000z").unwrap(), expected);
+        for invalid in [
+            "2023-11-14T22:13:20",
+            "2023-02-29T00:00:00Z",
+            "2023-11-14T24:00:00Z",
+            "2023-11-14T22:13:20.Z",
+            "2023-11-14 22:13:20+0200",
+        ] {
+            assert!(at(invalid).is_err(), "{invalid}");
+        }
+
+        let invalid = |field: &str, value: &str| {
+            let mut json = serde_json::to_value(entry(1, 0)).unwrap();
+            json[field] = value.into();
+            serde_json::from_value::<Entry>(json).is_err()
+        };
+        assert!(invalid("hash", "0001"));
+        assert!(invalid("salt", "0"));
+        assert!(invalid("salt", "zz"));
+        assert!(invalid("payload", "AA=A"));
+        assert!(invalid("payload", "A"));
+
+        // the bits are packed; bits after the last one are rejected.
+        #[derive(Debug, Deserialize)]
+        struct Flags(#[serde(with = "crate::with::bitset")] Vec<bool>);
+        let bytes = serializer::to_bytes(&(3u64, ByteBuf(vec![0b101]))).unwrap();
+        let flags = deserializer::from_bytes::<Flags>(&bytes).unwrap();
+        assert_eq!(flags.0, [true, false, true]);
+        let bytes = serializer::to_bytes(&(3u64, ByteBuf(vec![0b1101]))).unwrap();
+        assert!(deserializer::from_bytes::<Flags>(&bytes).is_err());
+
+        // rust-fr isn't human readable, so addresses are their octets.
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1));
+        let bytes = serializer::to_bytes(&ip).unwrap();
+        assert_eq!(bytes[2..], [0, 192, 168, 0, 1]);
+        assert_eq!(deserializer::from_bytes::<IpAddr>(&bytes).unwrap(), ip);
+    }
+
+    #[test]
+    fn raw_payload() {
+        use crate::{
+            raw::RawRustFr,
+            tokens::{Event, TokenReader, TokenWriter},
+        };
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Envelope {
+            route: String,
+            body: RawRustFr,
+            trailer: u8,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Route {
+            route: String,
+            trailer: u8,
+        }
+
+        let inner = Config {
+            adaptive_ints: true,
+            ..self_describing()
+        };
+        // the body has the bytes of delimiters in it and is embedded as it is.
+        let body = ("ok".to_string(), 0x8786_8786u32, 1u64 << 40);
+        let payload = serializer::to_bytes_with_config(&body, &inner).unwrap();
+        assert!(payload.contains(&0x87));
+        for config in [
+            Config::default(),
+            self_describing(),
+            Config {
+                msb_first: true,
+                ..Config::default()
+            },
+        ] {
+            let envelope = Envelope {
+                route: "a".to_string(),
+                body: RawRustFr::from_value(&body, &inner).unwrap(),
+                trailer: 9,
+            };
+            let bytes = serializer::to_bytes_with_config(&envelope, &config).unwrap();
+            let decoded: Envelope = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+            assert_eq!(decoded, envelope, "{config:?}");
+            assert_eq!(decoded.body.as_bytes(), payload);
+            assert_eq!(decoded.body.config(), inner);
+            assert_eq!(decoded.body.decode::<(String, u32, u64)>().unwrap(), body);
+        }
+
+        // the body is skipped like any other unknown field.
+        let envelope = Envelope {
+            route: "b".to_string(),
+            body: RawRustFr::from_bytes(payload.clone()).unwrap(),
+            trailer: 2,
+        };
+        let bytes = serializer::to_bytes_with_config(&envelope, &self_describing()).unwrap();
+        let route: Route =
+            deserializer::from_bytes_with_config(&bytes, &self_describing()).unwrap();
+        assert_eq!((route.route.as_str(), route.trailer), ("b", 2));
+
+        // and is a single event.
+        let events = TokenReader::new(&bytes, &self_describing())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(events.contains(&Event::Raw(payload.as_slice().into())));
+        let mut writer = TokenWriter::new(&self_describing()).unwrap();
+        for event in events {
+            writer.push(event).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), bytes);
+
+        // other formats see bytes.
+        let json = serde_json::to_string(&envelope).unwrap();
+        assert_eq!(serde_json::from_str::<Envelope>(&json).unwrap(), envelope);
+
+        assert!(RawRustFr::from_bytes(vec![0xff, 0xff]).is_err());
+        assert!(RawRustFr::from_bytes(vec![0]).is_err());
+        // a truncated body fails instead of reading past the data.
+        let bytes = serializer::to_bytes(&envelope).unwrap();
+        let truncated = &bytes[..bytes.len() - 4];
+        assert!(deserializer::from_bytes::<Envelope>(truncated).is_err());
+    }
+
+    #[test]
+    fn smart_pointers() {
+        use std::{borrow::Cow, rc::Rc, sync::Arc};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Pointers<'a> {
+            boxed: Box<Human>,
+            #[serde(borrow)]
+            cow: Cow<'a, str>,
+            owned_cow: Cow<'a, str>,
+            rc: Rc<Vec<u8>>,
+            arc: Arc<str>,
+            slice: Box<[u16]>,
+            nested: Option<Box<Option<Arc<u32>>>>,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Plain {
+            boxed: Human,
+            cow: String,
+            owned_cow: String,
+            rc: Vec<u8>,
+            arc: String,
+            slice: Vec<u16>,
+            nested: Option<Option<u32>>,
+        }
+
+        let human = || Human {
+            name: "Ayush".to_string(),
+            age: 19,
+        };
+        let shared = Rc::new(vec![1u8, 2, 3]);
+        let pointers = Pointers {
+            boxed: Box::new(human()),
+            cow: Cow::Owned("owned".to_string()),
+            owned_cow: Cow::Borrowed("borrowed"),
+            rc: shared.clone(),
+            arc: Arc::from("arc"),
+            slice: vec![1, 2].into_boxed_slice(),
+            nested: Some(Box::new(Some(Arc::new(7)))),
+        };
+        let plain = Plain {
+            boxed: human(),
+            cow: "owned".to_string(),
+            owned_cow: "borrowed".to_string(),
+            rc: vec![1, 2, 3],
+            arc: "arc".to_string(),
+            slice: vec![1, 2],
+            nested: Some(Some(7)),
+        };
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                adaptive_ints: true,
+                pack_u8_seqs: true,
+                struct_keys: crate::config::StructKeys::Omit,
+                ..Config::default()
+            },
+        ];
+        for config in configs {
+            // the pointers add nothing to the data.
+            let bytes = serializer::to_bytes_with_config(&pointers, &config).unwrap();
+            assert_eq!(
+                bytes,
+                serializer::to_bytes_with_config(&plain, &config).unwrap()
+            );
+            let decoded: Pointers = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+            assert_eq!(decoded, pointers);
+            // a borrowed cow borrows from the data, whatever it was serialized from.
+            assert!(matches!(decoded.cow, Cow::Borrowed("owned")));
+            assert!(matches!(decoded.owned_cow, Cow::Owned(_)));
+            assert_eq!(
+                deserializer::from_bytes_with_config::<Plain>(&bytes, &config).unwrap(),
+                plain
+            );
+        }
+
+        // a value pointed to twice is written twice.
+        let twice = (shared.clone(), shared);
+        let bytes = serializer::to_bytes(&twice).unwrap();
+        assert_eq!(
+            bytes,
+            serializer::to_bytes(&(vec![1u8, 2, 3], vec![1u8, 2, 3])).unwrap()
+        );
+        let (left, right): (Rc<Vec<u8>>, Rc<Vec<u8>>) = deserializer::from_bytes(&bytes).unwrap();
+        assert!(!Rc::ptr_eq(&left, &right));
+    }
+
+    #[test]
+    fn insertion_ordered_maps() {
+        use indexmap::IndexMap;
+
+        let settings = IndexMap::from([
+            ("zeta".to_string(), 1u32),
+            ("alpha".to_string(), 2),
+            ("mid".to_string(), 3),
+        ]);
+        let ids = IndexMap::from([
+            (30u32, "c".to_string()),
+            (10, "a".to_string()),
+            (20, "b".to_string()),
+        ]);
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                offset_tables: true,
+                sorted_int_keys: true,
+                adaptive_ints: true,
+                ..Config::default()
+            },
+            Config {
+                sorted_int_keys: true,
+                ..self_describing()
+            },
+        ];
+        for config in configs {
+            let bytes = serializer::to_bytes_with_config(&(&settings, &ids), &config).unwrap();
+            let decoded: (IndexMap<String, u32>, IndexMap<u32, String>) =
+                deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+            // `IndexMap`'s equality ignores the order, so the entries are compared.
+            assert!(decoded.0.iter().eq(&settings), "{config:?}");
+            assert!(decoded.1.iter().eq(&ids), "{config:?}");
+        }
+    }
+
+    #[test]
+    fn small_vectors() {
+        use arrayvec::ArrayVec;
+        use smallvec::SmallVec;
+        use tinyvec::TinyVec;
+
+        // inline vectors are sequences: `ArrayVec`s fail to deserialize with more elements than
+        // fit, the others move to the heap.
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Packet {
+            bytes: ArrayVec<u8, 8>,
+            words: SmallVec<[u16; 4]>,
+            rows: TinyVec<[tinyvec::ArrayVec<[bool; 3]>; 2]>,
+        }
+
+        let packet = Packet {
+            bytes: ArrayVec::from_iter([0, 0, 0, 0, 7, 7]),
+            words: SmallVec::from_slice(&[1, 1, 1]),
+            rows: TinyVec::from_iter([
+                tinyvec::array_vec!([bool; 3] => true, false),
+                tinyvec::ArrayVec::new(),
+            ]),
+        };
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                run_length_encoding: true,
+                pack_u8_seqs: true,
+                adaptive_ints: true,
+                struct_keys: crate::config::StructKeys::Omit,
+                ..Config::default()
+            },
+            Config {
+                run_length_encoding: true,
+                pack_u8_seqs: true,
+                ..self_describing()
+            },
+        ];
+        for config in configs {
+            let bytes = serializer::to_bytes_with_config(&packet, &config).unwrap();
+            let decoded: Packet = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+            assert_eq!(decoded, packet, "{config:?}");
+            assert!(!decoded.words.spilled() && decoded.rows.is_inline());
+
+            // vectors that don't fit inline move to the heap.
+            let spilled = Packet {
+                words: SmallVec::from_slice(&[2; 5]),
+                rows: TinyVec::from_iter(vec![tinyvec::ArrayVec::new(); 3]),
+                ..decoded
+            };
+            let bytes = serializer::to_bytes_with_config(&spilled, &config).unwrap();
+            let decoded: Packet = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+            assert_eq!(decoded, spilled, "{config:?}");
+            assert!(decoded.words.spilled() && decoded.rows.is_heap());
+
+            // more elements than an `ArrayVec` fits (a run of them too) are rejected.
+            #[derive(Serialize)]
+            struct Plain {
+                bytes: Vec<u8>,
+                words: Vec<u16>,
+                rows: Vec<Vec<bool>>,
+            }
+            let overflow = Plain {
+                bytes: vec![5; 9],
+                words: vec![],
+                rows: vec![],
+            };
+            let bytes = serializer::to_bytes_with_config(&overflow, &config).unwrap();
+            let error =
+                deserializer::from_bytes_with_config::<Packet>(&bytes, &config).unwrap_err();
+            assert!(
+                error.to_string().contains("no more than 8 items"),
+                "{error}"
+            );
+        }
+    }
+
+    #[test]
+    fn map_entry_limit() {
+        use crate::error::PathSegment;
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Request {
+            id: u32,
+            batches: Vec<BTreeMap<u32, BTreeMap<String, u8>>>,
+        }
+
+        let tags = |count: usize| {
+            (0..count)
+                .map(|i| (format!("k{i}"), i as u8))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let request = |count: usize| Request {
+            id: 7,
+            batches: vec![
+                BTreeMap::from([(1, tags(2))]),
+                BTreeMap::from([(1, tags(3)), (2, tags(count))]),
+            ],
+        };
+
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                offset_tables: true,
+                ..Config::default()
+            },
+            Config {
+                sorted_int_keys: true,
+                struct_keys: crate::config::StructKeys::Omit,
+                ..Config::default()
+            },
+        ];
+        for config in configs {
+            let limited = Config {
+                max_map_entries: Some(4),
+                ..config.clone()
+            };
+            // maps within the limit decode; the limit doesn't have to match the writer.
+            let bytes = serializer::to_bytes_with_config(&request(4), &config).unwrap();
+            let decoded: Request = deserializer::from_bytes_with_config(&bytes, &limited).unwrap();
+            assert_eq!(decoded, request(4));
+
+            // a larger map fails with where it is.
+            let bytes = serializer::to_bytes_with_config(&request(1000), &config).unwrap();
+            let error =
+                deserializer::from_bytes_with_config::<Request>(&bytes, &limited).unwrap_err();
+            match &error {
+                Error::TooManyMapEntries { max: 4, path } => assert_eq!(
+                    path,
+                    &[
+                        PathSegment::Field("batches"),
+                        PathSegment::Element(1),
+                        PathSegment::Entry(1),
+                    ],
+                    "{config:?}"
+                ),
+                error => panic!("{config:?}: {error}"),
+            }
+            assert_eq!(
+                error.to_string(),
+                "the map at $.batches[1]{1} has more than 4 entries"
+            );
+        }
+
+        // maps read through `deserialize_any` are bounded too.
+        let bytes = serializer::to_bytes_with_config(&tags(5), &self_describing()).unwrap();
+        let limited = Config {
+            max_map_entries: Some(4),
+            ..self_describing()
+        };
+        assert!(matches!(
+            deserializer::from_bytes_with_config::<serde_json::Value>(&bytes, &limited),
+            Err(Error::TooManyMapEntries { .. })
+        ));
+    }
+
+    #[test]
+    fn collect_str() {
+        use crate::config::StringFraming;
+        use std::fmt;
+
+        // a big number that is written through its `Display` implementation.
+        struct Big(u128, u8);
+
+        impl fmt::Display for Big {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                for _ in 0..self.1 {
+                    write!(f, "{}", self.0)?;
+                }
+                Ok(())
+            }
+        }
+
+        impl Serialize for Big {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        // a value whose formatting fails.
+        struct Broken;
+
+        impl Serialize for Broken {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(&format_args!("{}", Broken))
+            }
+        }
+
+        impl fmt::Display for Broken {
+            fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+                Err(fmt::Error)
+            }
+        }
+
+        let values = (true, [Big(u128::MAX, 3), Big(u128::MAX, 3), Big(7, 1)]);
+        let strings = (true, values.1.each_ref().map(ToString::to_string));
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                run_length_encoding: true,
+                msb_first: true,
+                ..Config::default()
+            },
+            Config {
+                string_framing: StringFraming::Delimited,
+                ..Config::default()
+            },
+            Config {
+                string_framing: StringFraming::Escaped,
+                ..Config::default()
+            },
+        ];
+        for config in configs {
+            let bytes = serializer::to_bytes_with_config(&values, &config).unwrap();
+            assert_eq!(
+                bytes,
+                serializer::to_bytes_with_config(&strings, &config).unwrap()
+            );
+            let decoded: (bool, [String; 3]) =
+                deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+            assert_eq!(decoded, strings);
+            assert!(matches!(
+                serializer::to_bytes_with_config(&Broken, &config),
+                Err(Error::SerializationError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn max_encoded_len() {
+        use crate::overhead::{self, MaxEncodedLen};
+
+        #[derive(Serialize)]
+        struct Position {
+            x: i32,
+            y: i32,
+            fix: Option<(bool, u8)>,
+        }
+
+        impl MaxEncodedLen for Position {
+            const MAX_BITS: usize = overhead::struct_max_bits(&[
+                ("x", i32::MAX_BITS),
+                ("y", i32::MAX_BITS),
+                ("fix", Option::<(bool, u8)>::MAX_BITS),
+            ]);
+        }
+
+        #[derive(Serialize)]
+        enum Message {
+            Ping,
+            Moved(Position),
+            Samples([u16; 4], char),
+        }
+
+        impl MaxEncodedLen for Message {
+            const MAX_BITS: usize =
+                overhead::enum_max_bits(&[0, Position::MAX_BITS, <([u16; 4], char)>::MAX_BITS]);
+        }
+
+        // the bound is known at compile time.
+        const CAPACITY: usize = overhead::max_encoded_len::<Message>();
+        let buffer = [0u8; CAPACITY];
+
+        let largest = Message::Moved(Position {
+            x: -1,
+            y: i32::MAX,
+            fix: Some((true, 9)),
+        });
+        let values = [
+            Message::Ping,
+            Message::Moved(Position {
+                x: 0,
+                y: 0,
+                fix: None,
+            }),
+            Message::Samples([1, 2, 3, 4], 'x'),
+            largest,
+        ];
+        for value in &values {
+            let bytes = serializer::to_bytes(value).unwrap();
+            let estimate = overhead::estimate_size(value).unwrap();
+            assert!(estimate.max_bits <= Message::MAX_BITS);
+            assert!(bytes.len() <= buffer.len());
+        }
+        // the largest value reaches the bound.
+        let estimate = overhead::estimate_size(&values[3]).unwrap();
+        assert_eq!(estimate.max_bits, Message::MAX_BITS);
+        assert_eq!(estimate.max_bytes(), CAPACITY);
+        assert_eq!(<[Option<u64>; 3]>::MAX_BITS, 3 * 65);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "miri can't read files")]
+    fn replay() {
+        use crate::replay::{Fixtures, Recorder};
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Event {
+            Start,
+            Moved { x: i32, y: i32 },
+            Tagged(String, Option<u64>),
+        }
+
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/fixtures");
+        let human = Human {
+            name: "Ayush".to_string(),
+            age: 19,
+        };
+        let events = vec![
+            Event::Start,
+            Event::Moved { x: -3, y: 7 },
+            Event::Tagged("rust-fr".to_string(), Some(u64::MAX)),
+        ];
+        let scores = BTreeMap::from([(1u32, vec![0.5f32, 1.5]), (9, vec![])]);
+        let tuned = Config {
+            adaptive_ints: true,
+            run_length_encoding: true,
+            ..self_describing()
+        };
+
+        // `RUST_FR_RECORD_FIXTURES=1 cargo test replay` writes the file of the current version.
+        let mut recorder = Recorder::new();
+        recorder
+            .record("human", &human, &Config::default())
+            .unwrap();
+        recorder
+            .record("events", &events, &Config::default())
+            .unwrap();
+        recorder.record("events_tuned", &events, &tuned).unwrap();
+        recorder
+            .record("scores", &scores, &Config::default())
+            .unwrap();
+        recorder
+            .record("patchable", &(true, 9u16), &Config::patchable())
+            .unwrap();
+        if std::env::var_os("RUST_FR_RECORD_FIXTURES").is_some() {
+            recorder.write(&dir).unwrap();
+        }
+
+        let all = Fixtures::load_dir(&dir).unwrap();
+        assert_eq!(
+            all.last().map(|fixtures| fixtures.version),
+            Some(crate::protocol::format::FORMAT_VERSION),
+            "the fixtures of the current version are recorded"
+        );
+        for fixtures in &all {
+            assert!(fixtures.replay("human", &human).unwrap());
+            assert!(fixtures.replay("events", &events).unwrap());
+            assert!(fixtures.replay("events_tuned", &events).unwrap());
+            assert!(fixtures.replay("scores", &scores).unwrap());
+            assert!(fixtures.replay("patchable", &(true, 9u16)).unwrap());
+            assert!(!fixtures.replay("missing", &0u8).unwrap());
+        }
+
+        // a fixture file round trips and breaks are flagged.
+        let fixtures = Fixtures::parse(&recorder.to_text()).unwrap();
+        assert_eq!(fixtures.fixtures, recorder.fixtures());
+        let older = Human { age: 20, ..human };
+        assert!(matches!(
+            fixtures.replay("human", &older),
+            Err(Error::FixtureMismatch { name, .. }) if name == "human"
+        ));
+        assert!(matches!(
+            fixtures.replay("patchable", &(true, 9u32)),
+            Err(Error::FixtureMismatch { .. })
+        ));
+        let mut changed = fixtures.clone();
+        changed.fixtures[4].bytes.push(0);
+        assert!(matches!(
+            changed.replay("patchable", &(true, 9u16)),
+            Err(Error::FixtureMismatch { reason, .. })
+                if reason.contains("without a new format version")
+        ));
+        assert!(matches!(
+            recorder.record("human", &0u8, &Config::default()),
+            Err(Error::InvalidFixture(_))
+        ));
+        assert!(matches!(
+            Fixtures::parse("rust-fr fixtures v5\nhuman\tu8\t0g"),
+            Err(Error::InvalidFixture(_))
+        ));
+    }
+
+    #[test]
+    fn columnar() {
+        #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+        struct Reading {
+            sensor: u16,
+            value: f32,
+            ok: bool,
+            label: Option<String>,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Batch {
+            id: u8,
+            #[serde(with = "crate::columnar")]
+            readings: Vec<Reading>,
+            after: String,
+        }
+
+        #[derive(Serialize)]
+        struct Rows {
+            id: u8,
+            readings: Vec<Reading>,
+            after: String,
+        }
+
+        let readings = (0..50)
+            .map(|i| Reading {
+                sensor: i % 3,
+                value: i as f32 / 2.0,
+                ok: i % 7 != 0,
+                label: (i % 10 == 0).then(|| format!("r{i}")),
+            })
+            .collect::<Vec<_>>();
+        let batch = Batch {
+            id: 1,
+            readings: readings.clone(),
+            after: "end".to_string(),
+        };
+        let configs = [
+            Config::default(),
+            Config {
+                adaptive_ints: true,
+                msb_first: true,
+                ..Config::default()
+            },
+            Config {
+                byte_aligned_bools: true,
+                struct_keys: crate::config::StructKeys::Omit,
+                ..Config::default()
+            },
+        ];
+        for config in configs {
+            let bytes = serializer::to_bytes_with_config(&batch, &config).unwrap();
+            let decoded: Batch = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+            assert_eq!(decoded, batch, "{config:?}");
+        }
+
+        // the values of a field are next to each other: a column of the sensors holds them all.
+        let bytes = serializer::to_bytes(&batch).unwrap();
+        let sensors = (0..50).map(|i| (i % 3) as u16).collect::<Vec<_>>();
+        let column = sensors
+            .iter()
+            .flat_map(|sensor| sensor.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert!(bytes.windows(column.len()).any(|window| window == column));
+        // the names of the fields are written once instead of in every row.
+        let rows = serializer::to_bytes(&Rows {
+            id: 1,
+            readings: readings.clone(),
+            after: "end".to_string(),
+        })
+        .unwrap();
+        assert!(bytes.len() < rows.len() / 2);
+
+        // the self-describing mode and other formats see a plain sequence.
+        let plain = serializer::to_bytes_with_config(
+            &Rows {
+                id: 1,
+                readings: readings.clone(),
+                after: "end".to_string(),
+            },
+            &self_describing(),
+        )
+        .unwrap();
+        assert_eq!(
+            serializer::to_bytes_with_config(&batch, &self_describing()).unwrap(),
+            plain
+        );
+        let json = serde_json::to_string(&batch).unwrap();
+        assert_eq!(serde_json::from_str::<Batch>(&json).unwrap(), batch);
+
+        // empty batches and rows that aren't structs.
+        let empty = Batch {
+            readings: vec![],
+            ..batch
+        };
+        let bytes = serializer::to_bytes(&empty).unwrap();
+        assert_eq!(deserializer::from_bytes::<Batch>(&bytes).unwrap(), empty);
+        #[derive(Serialize)]
+        struct Numbers(#[serde(with = "crate::columnar")] Vec<u8>);
+        assert!(matches!(
+            serializer::to_bytes(&Numbers(vec![1])),
+            Err(Error::UnsupportedCall(_))
+        ));
+
+        // rows that are maps with string keys are written like the structs of their keys.
+        #[derive(Serialize)]
+        struct Maps(
+            #[serde(with = "crate::columnar")] Vec<std::collections::BTreeMap<String, u16>>,
+        );
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Pair {
+            a: u16,
+            b: u16,
+        }
+        #[derive(Deserialize)]
+        struct Pairs(#[serde(with = "crate::columnar")] Vec<Pair>);
+        let maps = (0..3)
+            .map(|i| [("a".to_string(), i), ("b".to_string(), i * 2)].into())
+            .collect();
+        let bytes = serializer::to_bytes(&Maps(maps)).unwrap();
+        let pairs = deserializer::from_bytes::<Pairs>(&bytes).unwrap().0;
+        assert_eq!(pairs[2], Pair { a: 2, b: 4 });
+        #[derive(Serialize)]
+        struct NumberKeys(
+            #[serde(with = "crate::columnar")] Vec<std::collections::BTreeMap<u8, u8>>,
+        );
+        assert!(matches!(
+            serializer::to_bytes(&NumberKeys(vec![[(1, 1)].into()])),
+            Err(Error::UnsupportedCall(_))
+        ));
+
+        // a count of rows that the columns can't hold is rejected before the rows are read,
+        // so rows that write nothing can't be written either.
+        let table = |rows: u32, fields: &[u8]| {
+            let mut bytes = Config::default().header().to_le_bytes().to_vec();
+            bytes.extend(rows.to_le_bytes());
+            bytes.extend(fields);
+            bytes
+        };
+        let no_fields = table(u32::MAX, &0u32.to_le_bytes());
+        assert!(matches!(
+            deserializer::from_bytes::<Pairs>(&no_fields),
+            Err(Error::DeserializationError(_))
+        ));
+        let mut one_field = 1u32.to_le_bytes().to_vec();
+        one_field.extend(1u32.to_le_bytes());
+        one_field.push(b'a');
+        one_field.extend(16u32.to_le_bytes());
+        one_field.extend(7u16.to_le_bytes());
+        assert!(matches!(
+            deserializer::from_bytes::<Pairs>(&table(1_000_000, &one_field)),
+            Err(Error::DeserializationError(_))
+        ));
+        let limited = Config {
+            max_map_entries: Some(10),
+            ..Config::default()
+        };
+        let bytes = serializer::to_bytes(&Batch {
+            id: 1,
+            readings,
+            after: String::new(),
+        })
+        .unwrap();
+        assert!(matches!(
+            deserializer::from_bytes_with_config::<Batch>(&bytes, &limited),
+            Err(Error::TooManyMapEntries { max: 10, .. })
+        ));
+        #[derive(Serialize)]
+        struct Nothing {}
+        #[derive(Serialize)]
+        struct Nothings(#[serde(with = "crate::columnar")] Vec<Nothing>);
+        assert!(matches!(
+            serializer::to_bytes(&Nothings(vec![Nothing {}, Nothing {}])),
+            Err(Error::SerializationError(_))
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "miri can't run processes")]
+    fn javascript_decoder() {
+        use crate::{
+            codegen,
+            schema::{Schema, Variant},
+        };
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Shape {
+            Point,
+            Circle(f32),
+            Line(i16, i16),
+            Rect { w: u16, h: u16 },
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Pair<T> {
+            left: T,
+            right: T,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Scene {
+            name: String,
+            id: u64,
+            offset: i32,
+            visible: bool,
+            initial: char,
+            scale: f64,
+            shapes: Vec<Shape>,
+            tags: BTreeMap<String, Option<u8>>,
+            #[serde(with = "crate::with::hex")]
+            thumbnail: Vec<u8>,
+            bounds: Pair<i8>,
+            labels: Pair<String>,
+            nothing: (),
+            big: i128,
+        }
+
+        let schema = Schema::of::<Scene>().unwrap();
+        let Schema::Struct { name, fields } = &schema else {
+            panic!("{schema:?}");
+        };
+        assert_eq!(*name, "Scene");
+        assert_eq!(fields[0], ("name", Schema::Str));
+        assert_eq!(
+            fields[6].1,
+            Schema::Seq(Box::new(Schema::Enum {
+                name: "Shape",
+                variants: vec![
+                    ("Point", Variant::Unit),
+                    ("Circle", Variant::Newtype(Schema::F32)),
+                    ("Line", Variant::Tuple(vec![Schema::I16, Schema::I16])),
+                    (
+                        "Rect",
+                        Variant::Struct(vec![("w", Schema::U16), ("h", Schema::U16)])
+                    ),
+                ],
+            }))
+        );
+        assert_eq!(fields[8].1, Schema::Bytes);
+        assert!(matches!(
+            Schema::of::<serde_json::Value>(),
+            Err(Error::UnsupportedCall(_))
+        ));
+
+        let module = codegen::javascript(&schema);
+        // the generic struct gets a function per schema.
+        assert!(
+            module.contains("function readPair(r)") && module.contains("function readPair1(r)")
+        );
+
+        // run the decoder if node is around; nothing is written to the disk otherwise.
+        let node = || std::process::Command::new("node");
+        if node().arg("--version").output().is_err() {
+            return;
+        }
+        let scene = Scene {
+            name: "demo".to_string(),
+            id: u64::MAX,
+            offset: -42,
+            visible: true,
+            initial: 'é',
+            scale: 0.25,
+            shapes: vec![
+                Shape::Point,
+                Shape::Circle(1.5),
+                Shape::Line(-1, 2),
+                Shape::Rect { w: 3, h: 4 },
+            ],
+            tags: BTreeMap::from([("a".to_string(), Some(1)), ("b".to_string(), None)]),
+            thumbnail: vec![0, 255, 7],
+            bounds: Pair { left: -8, right: 8 },
+            labels: Pair {
+                left: "l".to_string(),
+                right: "r".to_string(),
+            },
+            nothing: (),
+            big: -(1 << 100),
+        };
+        let bytes = serializer::to_bytes(&scene).unwrap();
+        let dir = std::env::temp_dir().join(format!("rust-fr-codegen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("decoder.mjs"), &module).unwrap();
+        std::fs::write(
+            dir.join("main.mjs"),
+            format!(
+                "import {{ decode }} from './decoder.mjs';\n\
+                 const value = decode(new Uint8Array({bytes:?}));\n\
+                 console.log(JSON.stringify(value, (_, v) => typeof v === 'bigint' ? v.toString() \
+                 : v instanceof Map ? Object.fromEntries(v) \
+                 : v instanceof Uint8Array ? [...v] : v));\n"
+            ),
+        )
+        .unwrap();
+        let output = node().arg(dir.join("main.mjs")).output();
+        let _ = std::fs::remove_dir_all(&dir);
+        let output = output.unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let decoded: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(
+            decoded,
+            serde_json::json!({
+                "name": "demo",
+                "id": u64::MAX.to_string(),
+                "offset": -42,
+                "visible": true,
+                "initial": "é",
+                "scale": 0.25,
+                "shapes": ["Point", {"Circle": 1.5}, {"Line": [-1, 2]}, {"Rect": {"w": 3, "h": 4}}],
+                "tags": {"a": 1, "b": null},
+                "thumbnail": [0, 255, 7],
+                "bounds": {"left": -8, "right": 8},
+                "labels": {"left": "l", "right": "r"},
+                "nothing": null,
+                "big": (-(1i128 << 100)).to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_variants() {
+        use crate::config::UnknownVariant;
+
+        // a newer producer added `Resize` (with content) and `Close`.
+        #[derive(Debug, Serialize)]
+        enum Newer {
+            Open(String),
+            Resize { width: u32, height: u32 },
+            Close,
+        }
+        #[derive(Debug, Deserialize, PartialEq)]
+        enum Older {
+            Open(String),
+            #[serde(other)]
+            Unknown,
+        }
+        #[derive(Debug, Deserialize, PartialEq)]
+        enum Strict {
+            Open(String),
+        }
+
+        let events = vec![
+            Newer::Open("a".to_string()),
+            Newer::Resize {
+                width: 640,
+                height: 480,
+            },
+            Newer::Close,
+            Newer::Open("b".to_string()),
+        ];
+        let config = Config {
+            self_describing: true,
+            ..Config::default()
+        };
+        let bytes = serializer::to_bytes_with_config(&events, &config).unwrap();
+
+        // by default the unknown indices fail, even with a fallback variant.
+        let error = deserializer::from_bytes::<Vec<Older>>(&bytes).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::UnknownVariant {
+                name: "Older",
+                index: 1,
+                variants: 2
+            }
+        ));
+        let lenient = Config {
+            on_unknown_variant: UnknownVariant::SkipToDefault,
+            ..config.clone()
+        };
+        let decoded = deserializer::from_bytes_with_config::<Vec<Older>>(&bytes, &lenient).unwrap();
+        assert_eq!(
+            decoded,
+            vec![
+                Older::Open("a".to_string()),
+                Older::Unknown,
+                Older::Unknown,
+                Older::Open("b".to_string()),
+            ]
+        );
+        // enums without a fallback variant still fail.
+        let error = deserializer::from_bytes_with_config::<Vec<Strict>>(&bytes, &lenient);
+        assert!(matches!(error, Err(Error::UnknownVariant { index: 1, .. })));
+
+        // without the self-describing mode the content of the unknown variant can't be skipped.
+        let lenient = Config {
+            on_unknown_variant: UnknownVariant::SkipToDefault,
+            ..Config::default()
+        };
+        let bytes = serializer::to_bytes(&Newer::Close).unwrap();
+        let error = deserializer::from_bytes_with_config::<Older>(&bytes, &lenient);
+        assert!(matches!(error, Err(Error::SelfDescribingRequired(_))));
+    }
+
+    #[test]
+    fn recursive_types() {
+        use std::collections::BTreeMap;
+
+        /// A JSON-like tree.
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Tree {
+            Null,
+            Number(f64),
+            Text(String),
+            List(Vec<Tree>),
+            Object(BTreeMap<String, Tree>),
+        }
+        /// An expression AST.
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Expr {
+            Literal(i64),
+            Variable(String),
+            Negate(Box<Expr>),
+            Binary {
+                op: char,
+                left: Box<Expr>,
+                right: Box<Expr>,
+            },
+        }
+        /// A singly linked list.
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Node {
+            value: u32,
+            next: Option<Box<Node>>,
+        }
+
+        fn tree(depth: usize) -> Tree {
+            match depth {
+                0 => Tree::Number(depth as f64),
+                _ => Tree::Object(BTreeMap::from([
+                    ("text".to_string(), Tree::Text(format!("level {depth}"))),
+                    ("null".to_string(), Tree::Null),
+                    ("items".to_string(), Tree::List(vec![tree(depth - 1)])),
+                ])),
+            }
+        }
+        fn expr(depth: usize) -> Expr {
+            match depth {
+                0 => Expr::Variable("x".to_string()),
+                _ if depth.is_multiple_of(3) => Expr::Negate(Box::new(expr(depth - 1))),
+                _ => Expr::Binary {
+                    op: '+',
+                    left: Box::new(Expr::Literal(depth as i64)),
+                    right: Box::new(expr(depth - 1)),
+                },
+            }
+        }
+        fn list(length: u32) -> Node {
+            (1..length).fold(
+                Node {
+                    value: 0,
+                    next: None,
+                },
+                |next, value| Node {
+                    value,
+                    next: Some(Box::new(next)),
+                },
+            )
+        }
+
+        // debug builds take several KB of stack a level.
+        let deep = std::thread::Builder::new().stack_size(256 << 20);
+        deep.spawn(|| {
+            for config in [Config::default(), self_describing()] {
+                let limited = Config {
+                    max_depth: Some(256),
+                    ..config.clone()
+                };
+                // a level of the tree is two enums, a map and a list; 60 levels fit the limit.
+                let value = tree(60);
+                let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+                let decoded = deserializer::from_bytes_with_config(&bytes, &limited).unwrap();
+                assert_eq!(value, decoded);
+                let value = tree(100);
+                let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+                let error = deserializer::from_bytes_with_config::<Tree>(&bytes, &limited);
+                assert!(matches!(error, Err(Error::TooDeep { max: 256 })));
+                // without a limit it reads as deep as the stack allows.
+                let decoded = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+                assert_eq!(value, decoded);
+
+                let value = expr(1000);
+                let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+                let decoded = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+                assert_eq!(value, decoded);
+
+                let value = list(2000);
+                let bytes = serializer::to_bytes_with_config(&value, &config).unwrap();
+                let decoded = deserializer::from_bytes_with_config(&bytes, &config).unwrap();
+                assert_eq!(value, decoded);
+                // unknown fields are skipped with the limit too.
+                if config.self_describing {
+                    #[derive(Debug, Deserialize)]
+                    #[allow(dead_code)]
+                    struct Head {
+                        value: u32,
+                    }
+                    let error = deserializer::from_bytes_with_config::<Head>(&bytes, &limited);
+                    assert!(matches!(error, Err(Error::TooDeep { .. })));
+                }
+            }
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+
+        // untrusted data that nests 100_000 nodes fails instead of overflowing the stack: a
+        // `Node` without the field names is its value and the presence bit of the next one.
+        let config = Config {
+            struct_keys: crate::config::StructKeys::Omit,
+            max_depth: Some(100),
+            ..Config::default()
+        };
+        let mut bytes = serializer::to_bytes_with_config(&list(1), &config).unwrap();
+        bytes.truncate(2);
+        bytes.extend(std::iter::repeat_n(0xff, 500_000));
+        let error = deserializer::from_bytes_with_config::<Node>(&bytes, &config);
+        assert!(matches!(error, Err(Error::TooDeep { max: 100 })));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "miri can't open sockets")]
+    fn rpc_transports() {
+        use crate::rpc::{
+            AsyncTransport, MemoryTransport, StreamTransport, TcpTransport, Transport,
+        };
+        use std::{
+            future::Future,
+            sync::Arc,
+            task::{Context, Poll, Wake},
+            thread::Thread,
+        };
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Request {
+            Greet(String),
+            Add(u32, u32),
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Response {
+            Greeting(String),
+            Sum(u32),
+        }
+
+        // answers requests until the client closes the link.
+        fn serve(mut transport: impl Transport, config: &Config) {
+            while let Some(request) = transport.recv::<Request>(config).unwrap() {
+                let response = match request {
+                    Request::Greet(name) => Response::Greeting(format!("hello {name}")),
+                    Request::Add(a, b) => Response::Sum(a + b),
+                };
+                transport.send(&response, config).unwrap();
+            }
+        }
+        fn call(transport: &mut impl Transport, config: &Config) {
+            transport
+                .send(&Request::Greet("Ayush".to_string()), config)
+                .unwrap();
+            assert_eq!(
+                transport.recv(config).unwrap(),
+                Some(Response::Greeting("hello Ayush".to_string()))
+            );
+            transport.send(&Request::Add(2, 3), config).unwrap();
+            assert_eq!(transport.recv(config).unwrap(), Some(Response::Sum(5)));
+        }
+
+        for config in [Config::default(), self_describing()] {
+            let (mut client, server) = MemoryTransport::pair();
+            let server_config = config.clone();
+            let handle = std::thread::spawn(move || serve(server, &server_config));
+            call(&mut client, &config);
+            drop(client);
+            handle.join().unwrap();
+
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            let server_config = config.clone();
+            let handle = std::thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                serve(TcpTransport::new(stream), &server_config);
+            });
+            let mut client = TcpTransport::new(std::net::TcpStream::connect(address).unwrap());
+            call(&mut client, &config);
+            drop(client);
+            handle.join().unwrap();
+
+            #[cfg(unix)]
+            {
+                let (a, b) = std::os::unix::net::UnixStream::pair().unwrap();
+                let server_config = config.clone();
+                let handle = std::thread::spawn(move || {
+                    serve(crate::rpc::UnixTransport::new(b), &server_config)
+                });
+                let mut client = crate::rpc::UnixTransport::new(a);
+                call(&mut client, &config);
+                drop(client);
+                handle.join().unwrap();
+            }
+        }
+
+        // frames over the limit are rejected by their length.
+        let mut stream = StreamTransport::new(std::io::Cursor::new(Vec::new()));
+        stream.send_frame(&[0; 100]).unwrap();
+        let mut stream = stream.into_inner();
+        stream.set_position(0);
+        let error = StreamTransport::with_max_frame_size(stream, 64).recv_frame();
+        assert!(matches!(
+            error,
+            Err(Error::RecordTooLarge {
+                length: 100,
+                max: 64
+            })
+        ));
+
+        // a length from a corrupt stream isn't allocated past the default limit.
+        let mut stream = StreamTransport::new(std::io::Cursor::new(u32::MAX.to_le_bytes()));
+        assert!(matches!(
+            stream.recv_frame(),
+            Err(Error::RecordTooLarge {
+                max: crate::rpc::DEFAULT_MAX_FRAME_SIZE,
+                ..
+            })
+        ));
+
+        // a read interrupted by a signal before a frame is retried.
+        struct Interrupted(bool, std::io::Cursor<Vec<u8>>);
+        impl std::io::Read for Interrupted {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                match std::mem::replace(&mut self.0, false) {
+                    true => Err(std::io::ErrorKind::Interrupted.into()),
+                    false => self.1.read(buf),
+                }
+            }
+        }
+        impl std::io::Write for Interrupted {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.1.write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut stream = StreamTransport::new(Interrupted(false, Default::default()));
+        stream.send(&"hello", &Config::default()).unwrap();
+        let Interrupted(_, mut bytes) = stream.into_inner();
+        bytes.set_position(0);
+        let mut stream = StreamTransport::new(Interrupted(true, bytes));
+        assert_eq!(
+            stream
+                .recv::<String>(&Config::default())
+                .unwrap()
+                .as_deref(),
+            Some("hello")
+        );
+
+        // the async side, polled by the thread until it is woken.
+        struct Unpark(Thread);
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        fn block_on<F: Future>(future: F) -> F::Output {
+            let waker = Arc::new(Unpark(std::thread::current())).into();
+            let mut context = Context::from_waker(&waker);
+            let mut future = std::pin::pin!(future);
+            loop {
+                match future.as_mut().poll(&mut context) {
+                    Poll::Ready(output) => return output,
+                    Poll::Pending => std::thread::park(),
+                }
+            }
+        }
+        let config = Config::default();
+        let (mut client, server) = MemoryTransport::pair();
+        let handle = std::thread::spawn(move || serve(server, &Config::default()));
+        block_on(async {
+            AsyncTransport::send(&mut client, &Request::Add(40, 2), &config)
+                .await
+                .unwrap();
+            let response = AsyncTransport::recv(&mut client, &config).await.unwrap();
+            assert_eq!(response, Some(Response::Sum(42)));
+        });
+        drop(client);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn delimiter_collisions() {
+        use crate::{
+            analyze::{self, Risk, RiskKind},
+            config::StringFraming,
+        };
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Attachment {
+            None,
+            Link(String),
+            File {
+                #[serde(with = "crate::with::hex")]
+                data: Vec<u8>,
+            },
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Message {
+            id: u64,
+            flags: [u8; 4],
+            tags: BTreeMap<String, (bool, String)>,
+            attachment: Option<Attachment>,
+        }
+
+        let risk = |path: &str, kind| Risk {
+            path: path.to_string(),
+            kind,
+        };
+        let report = analyze::analyze::<Message>().unwrap();
+        assert!(!report.is_safe());
+        assert_eq!(
+            report.risks,
+            [
+                risk("$.tags{key}", RiskKind::Str),
+                risk("$.tags{value}[1]", RiskKind::Str),
+                risk("$.attachment.Link", RiskKind::Str),
+                risk("$.attachment.File.data", RiskKind::Bytes),
+            ]
+        );
+        // names are strings in the data too.
+        #[derive(Debug, Deserialize)]
+        enum Named {
+            #[serde(rename = "Ćwiek")]
+            _Named,
+        }
+        assert_eq!(
+            analyze::analyze::<Named>().unwrap().to_string(),
+            "$.Ćwiek: the name \"Ćwiek\" contains the byte 0x86\n"
+        );
+        // fixed-width values never collide, whatever their bytes.
+        assert!(analyze::analyze::<(u8, [u8; 8], Option<char>)>()
+            .unwrap()
+            .is_safe());
+
+        let message = |tag: &str, data: Vec<u8>| Message {
+            id: 0x8786,
+            flags: [0x86, 0x87, 0x8b, 0xff],
+            tags: BTreeMap::from([("lang".to_string(), (true, tag.to_string()))]),
+            attachment: Some(Attachment::File { data }),
+        };
+        let samples = [
+            message("en", vec![1, 2, 3]),
+            // 'Ć' is 0xc4 0x86 in UTF-8.
+            message("Ćwiek", vec![]),
+            message("en", vec![0x86, 0x87]),
+            message("日本語", vec![0x88]),
+        ];
+        let delimited = |config| Config {
+            string_framing: StringFraming::Delimited,
+            ..config
+        };
+        for config in [delimited(Config::default()), delimited(self_describing())] {
+            assert_eq!(analyze::collisions(&samples, &config).unwrap(), [1, 2]);
+        }
+        // escaped or length-prefixed (the default) strings and bytes never collide.
+        assert!(analyze::collisions(&samples, &Config::default())
+            .unwrap()
+            .is_empty());
+        for string_framing in [StringFraming::Escaped, StringFraming::LengthPrefixed] {
+            let config = Config {
+                string_framing,
+                ..self_describing()
+            };
+            assert!(analyze::collisions(&samples, &config).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn string_framing() {
+        use crate::config::StringFraming;
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Kind {
+            #[serde(rename = "Ćwiek")]
+            Named,
+            Other(u8),
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Record {
+            flag: bool,
+            name: String,
+            #[serde(with = "crate::with::hex")]
+            data: Vec<u8>,
+            kind: Kind,
+            #[serde(rename = "ĆĆ")]
+            tags: BTreeMap<String, u8>,
+        }
+
+        // 'Ć' is 0xc4 0x86 in UTF-8, and 0x86, 0x87 and 0xff are the bytes of the string
+        // delimiter, the byte delimiter and the escape.
+        let record = Record {
+            flag: true,
+            name: "Ćwiczenie".to_string(),
+            data: vec![0x86, 0x87, 0xff, 0xff, 0x87, 1],
+            kind: Kind::Named,
+            tags: BTreeMap::from([("Ć".to_string(), 1), (String::new(), 2)]),
+        };
+        for string_framing in [StringFraming::Escaped, StringFraming::LengthPrefixed] {
+            let framed = Config {
+                string_framing,
+                ..Config::default()
+            };
+            let configs = [
+                framed.clone(),
+                Config {
+                    msb_first: true,
+                    length_prefixed_keys: true,
+                    ..framed.clone()
+                },
+                Config {
+                    adaptive_ints: true,
+                    offset_tables: true,
+                    ..Config {
+                        self_describing: true,
+                        ..framed
+                    }
+                },
+            ];
+            for config in configs {
+                let bytes = serializer::to_bytes_with_config(&record, &config).unwrap();
+                let decoded = deserializer::from_bytes_with_config::<Record>(&bytes, &config)
+                    .unwrap_or_else(|e| panic!("{config:?}: {e}"));
+                assert_eq!(decoded, record, "{config:?}");
+                // the framing is in the header.
+                assert_eq!(deserializer::from_bytes::<Record>(&bytes).unwrap(), record);
+            }
+        }
+
+        // the layouts, after the header.
+        let escaped = Config {
+            string_framing: StringFraming::Escaped,
+            ..Config::default()
+        };
+        let bytes = serializer::to_bytes_with_config(&"Ć", &escaped).unwrap();
+        assert_eq!(bytes[2..], [0xc4, 0xff, 0x86, 0x86]);
+        let prefixed = Config {
+            string_framing: StringFraming::LengthPrefixed,
+            ..Config::default()
+        };
+        let bytes = serializer::to_bytes_with_config(&"Ć", &prefixed).unwrap();
+        assert_eq!(bytes[2..], [2, 0, 0, 0, 0xc4, 0x86]);
+        // strings are prefixed with their length by default, so every string decodes back; data
+        // written with the delimited framing still decodes through its header.
+        assert_eq!(bytes, serializer::to_bytes(&"Ć").unwrap());
+        let text = "Ćwiczenie".to_string();
+        let bytes = serializer::to_bytes(&text).unwrap();
+        assert_eq!(deserializer::from_bytes::<String>(&bytes).unwrap(), text);
+        let delimited = Config {
+            string_framing: StringFraming::Delimited,
+            ..Config::default()
+        };
+        let bytes = serializer::to_bytes_with_config(&"rust-fr", &delimited).unwrap();
+        assert_eq!(deserializer::from_bytes::<&str>(&bytes).unwrap(), "rust-fr");
+        // strings are borrowed unless they had to be unescaped.
+        for (config, value) in [(&escaped, "plain"), (&prefixed, "Ć")] {
+            let bytes = serializer::to_bytes_with_config(&value, config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<&str>(&bytes, config).unwrap();
+            assert_eq!(decoded, value);
+        }
+        let bytes = serializer::to_bytes_with_config(&"Ć", &escaped).unwrap();
+        assert!(deserializer::from_bytes_with_config::<&str>(&bytes, &escaped).is_err());
+        assert_eq!(
+            deserializer::from_bytes_with_config::<String>(&bytes, &escaped).unwrap(),
+            "Ć"
+        );
+
+        // a header can't ask for both.
+        assert!(matches!(
+            deserializer::from_bytes::<u8>(&[0, 0x30, 1]),
+            Err(Error::InvalidHeader(0x3000))
+        ));
+    }
+
+    #[test]
+    #[ignore = "playground test; use cargo test -- --nocapture --ignored"]
+    fn string_framing_benchmark() {
+        use crate::config::StringFraming;
+        use std::time::Instant;
+
+        let ascii = (0..10_000)
+            .map(|i| format!("user-{i}@example.com"))
+            .collect::<Vec<_>>();
+        let multibyte = (0..10_000)
+            .map(|i| format!("Ćwiczenie {i}: żółć, 日本語, Ελληνικά"))
+            .collect::<Vec<_>>();
+        #[derive(Serialize, Deserialize)]
+        struct Blob(#[serde(with = "crate::with::hex")] Vec<u8>);
+        let binary = (0..1_000)
+            .map(|i| Blob((0..100).map(|j| ((i * 31 + j * 7) % 256) as u8).collect()))
+            .collect::<Vec<_>>();
+
+        fn measure<T: Serialize>(name: &str, value: &T, decode: impl Fn(&[u8], &Config)) {
+            println!("---- {name} ----");
+            for string_framing in [
+                StringFraming::Delimited,
+                StringFraming::Escaped,
+                StringFraming::LengthPrefixed,
+            ] {
+                let config = Config {
+                    string_framing,
+                    ..Config::default()
+                };
+                let start = Instant::now();
+                let bytes = serializer::to_bytes_with_config(value, &config).unwrap();
+                let encoded = start.elapsed();
+                let start = Instant::now();
+                decode(&bytes, &config);
+                let decoded = start.elapsed();
+                println!(
+                    "{string_framing:?}:\t{} bytes, encoded in {encoded:?}, decoded in {decoded:?}",
+                    bytes.len()
+                );
+            }
+        }
+
+        // the delimited strings that collide don't decode, so they are only measured.
+        measure("ASCII text", &ascii, |bytes, config| {
+            deserializer::from_bytes_with_config::<Vec<&str>>(bytes, config).unwrap();
+        });
+        measure("Multibyte text", &multibyte, |bytes, config| {
+            let _ = deserializer::from_bytes_with_config::<Vec<String>>(bytes, config);
+        });
+        measure("Binary", &binary, |bytes, config| {
+            let _ = deserializer::from_bytes_with_config::<Vec<Blob>>(bytes, config);
+        });
+    }
+
+    #[test]
+    fn encoded_payload() {
+        let human = Human {
+            name: "Ayush".to_string(),
+            age: 19,
+        };
+        for config in [Config::default(), self_describing()] {
+            let payload = serializer::to_payload_with_config(&human, &config).unwrap();
+            let bytes = serializer::to_bytes_with_config(&human, &config).unwrap();
+            assert_eq!(&*payload, &bytes[..]);
+            assert_eq!(payload.bit_len(), payload.stats().bits);
+            let text = payload.to_string();
+            assert!(
+                text.starts_with(&format!("{} bytes", bytes.len())),
+                "{text}"
+            );
+            assert!(text.contains("delimiters: Map 1, MapKey 2, MapValue 2"));
+            assert!(text.contains("Ayush"));
+            assert_eq!(payload.into_bytes(), bytes);
+        }
+
+        // long payloads are cut off after the limit.
+        let payload = serializer::to_payload(&vec![0xabu8; 1000]).unwrap();
+        let text = payload.to_string();
+        let dumped = serializer::EncodedPayload::DUMP_LIMIT / 16;
+        assert_eq!(
+            text.lines().filter(|line| line.contains("  |")).count(),
+            dumped
+        );
+        assert!(text.ends_with(&format!(
+            "... {} more bytes\n",
+            payload.len() - serializer::EncodedPayload::DUMP_LIMIT
+        )));
+        let debug = format!("{payload:?}");
+        assert!(debug.starts_with(&format!("EncodedPayload({} bytes", payload.len())));
+        assert!(debug.ends_with("..)"));
+    }
+
+    #[test]
+    fn format_versions() {
+        use crate::protocol::format::{
+            fingerprint, is_compatible, FINGERPRINTS, FORMAT_VERSION, MIN_COMPATIBLE_VERSION,
+        };
+
+        assert!(is_compatible(FORMAT_VERSION));
+        assert!(is_compatible(MIN_COMPATIBLE_VERSION));
+        assert!(!is_compatible(MIN_COMPATIBLE_VERSION - 1));
+        assert!(!is_compatible(FORMAT_VERSION + 1));
+        // every recorded version has its own fingerprint, and the current one is checked at
+        // compile time.
+        assert!(FINGERPRINTS
+            .windows(2)
+            .all(|pair| pair[0].0 < pair[1].0 && pair[0].1 != pair[1].1));
+        assert_eq!(FINGERPRINTS.last(), Some(&(FORMAT_VERSION, fingerprint())));
+        // the fixtures of the replay harness are named after the versions (miri can't look for
+        // files).
+        if !cfg!(miri) {
+            let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join(format!("testdata/fixtures/v{FORMAT_VERSION}.txt"));
+            assert!(fixtures.exists());
+        }
+    }
+
+    #[test]
+    fn length_prefixed_keys() {
+        use crate::tokens::{TokenReader, TokenWriter};
+        use bitvec::{field::BitField, prelude as bv};
+        use std::collections::{BTreeMap, HashMap};
+
+        // a map with keys that may repeat, as another implementation could write it.
+        struct Pairs<K, V>(Vec<(K, V)>);
+
+        impl<K: Serialize, V: Serialize> Serialize for Pairs<K, V> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_map(self.0.iter().map(|(key, value)| (key, value)))
+            }
+        }
+
+        let prefixed = |config: Config| Config {
+            length_prefixed_keys: true,
+            ..config
+        };
+        let human = Human {
+            name: "Ayush".to_string(),
+            age: 19,
+        };
+        let tuples = HashMap::from([((true, 'ß'), vec![1u8, 2]), ((false, 'a'), vec![])]);
+        let ids = BTreeMap::from([(3u32, "three".to_string()), (1, "one".to_string())]);
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                offset_tables: true,
+                sorted_int_keys: true,
+                ..self_describing()
+            },
+            Config {
+                msb_first: true,
+                adaptive_ints: true,
+                ..Config::default()
+            },
+        ];
+        for config in configs.map(prefixed) {
+            let bytes = serializer::to_bytes_with_config(&human, &config).unwrap();
+            assert_eq!(u16::from_le_bytes([bytes[0], bytes[1]]) >> 11 & 1, 1);
+            let decoded = deserializer::from_bytes::<Human>(&bytes).unwrap();
+            assert_eq!(decoded, human, "{config:?}");
+            let bytes = serializer::to_bytes_with_config(&tuples, &config).unwrap();
+            let decoded = deserializer::from_bytes::<HashMap<(bool, char), Vec<u8>>>(&bytes);
+            assert_eq!(decoded.unwrap(), tuples, "{config:?}");
+            let bytes = serializer::to_bytes_with_config(&ids, &config).unwrap();
+            let decoded = deserializer::from_bytes::<BTreeMap<u32, String>>(&bytes);
+            assert_eq!(decoded.unwrap(), ids, "{config:?}");
+        }
+
+        // the events of a token reader don't change and a token writer writes the same bytes.
+        let config = prefixed(self_describing());
+        let bytes = serializer::to_bytes_with_config(&tuples, &config).unwrap();
+        let mut writer = TokenWriter::new(&config).unwrap();
+        for event in TokenReader::new(&bytes, &config).unwrap() {
+            writer.push(event.unwrap()).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), bytes);
+
+        // equal keys have equal bits wherever they are.
+        let pairs = Pairs(vec![
+            ((true, "a"), 1u8),
+            ((false, "a"), 2),
+            ((true, "a"), 3),
+            ((true, "b"), 4),
+            ((false, "a"), 5),
+        ]);
+        for config in [config.clone(), prefixed(Config::default())] {
+            let config = Config {
+                offset_tables: !config.self_describing,
+                ..config
+            };
+            let bytes = serializer::to_bytes_with_config(&pairs, &config).unwrap();
+            assert_eq!(
+                deserializer::duplicate_keys(&bytes, &config).unwrap(),
+                [2, 4]
+            );
+        }
+        let config = Config {
+            sorted_int_keys: true,
+            ..Config::default()
+        };
+        let block = Pairs(vec![(7u16, ()), (7, ()), (1, ())]);
+        let bytes = serializer::to_bytes_with_config(&block, &config).unwrap();
+        assert_eq!(deserializer::duplicate_keys(&bytes, &config).unwrap(), [1]);
+        let config = self_describing();
+        let bytes = serializer::to_bytes_with_config(&pairs, &config).unwrap();
+        assert!(matches!(
+            deserializer::duplicate_keys(&bytes, &config),
+            Err(Error::UnsupportedCall(_))
+        ));
+        let config = prefixed(Config::default());
+        let bytes = serializer::to_bytes_with_config(&pairs, &config).unwrap();
+        assert!(matches!(
+            deserializer::duplicate_keys(&bytes, &config),
+            Err(Error::SelfDescribingRequired(_))
+        ));
+
+        // a key that doesn't take up its length is rejected: the length of the first key
+        // follows the header and the MAP_KEY_DELIMITER.
+        let bytes = serializer::to_bytes_with_config(&human, &config).unwrap();
+        let mut bits = bv::BitVec::<u8, bv::Lsb0>::from_vec(bytes);
+        let length = bits[19..51].load_le::<u32>();
+        bits[19..51].store_le(length + 8);
+        assert!(matches!(
+            deserializer::from_bytes::<Human>(bits.as_raw_slice()),
+            Err(Error::KeyLengthMismatch { index: 0, expected, found })
+                if expected == found + 8
+        ));
+    }
+
+    #[test]
+    fn unit_and_results() {
+        use std::{collections::BTreeMap, fmt::Debug};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Response {
+            id: u32,
+            result: Result<Vec<()>, String>,
+            ack: Result<(), ()>,
+        }
+
+        fn round_trip<T>(value: &T, config: &Config)
+        where
+            T: Serialize + serde::de::DeserializeOwned + PartialEq + Debug,
+        {
+            let bytes = serializer::to_bytes_with_config(value, config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<T>(&bytes, config)
+                .unwrap_or_else(|e| panic!("{value:?} with {config:?}: {e}"));
+            assert_eq!(*value, decoded, "{config:?}");
+        }
+
+        let responses = vec![
+            Response {
+                id: 1,
+                result: Ok(vec![(); 3]),
+                ack: Ok(()),
+            },
+            Response {
+                id: 2,
+                result: Err("not found".to_string()),
+                ack: Err(()),
+            },
+        ];
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                run_length_encoding: true,
+                pack_u8_seqs: true,
+                adaptive_ints: true,
+                ..self_describing()
+            },
+            Config {
+                struct_keys: crate::config::StructKeys::Omit,
+                option_bitmaps: true,
+                msb_first: true,
+                ..Config::default()
+            },
+            Config {
+                offset_tables: true,
+                sorted_int_keys: true,
+                length_prefixed_keys: true,
+                ..Config::default()
+            },
+        ];
+        for config in &configs {
+            round_trip(&(), config);
+            round_trip(&((), ()), config);
+            round_trip(&vec![(); 5], config);
+            round_trip(&Some(()), config);
+            round_trip(&Ok::<(), String>(()), config);
+            round_trip(&Err::<(), String>("boom".to_string()), config);
+            round_trip(&Ok::<u64, ()>(u64::MAX), config);
+            round_trip(&Err::<u64, ()>(()), config);
+            round_trip(&Ok::<Result<(), ()>, ()>(Err(())), config);
+            round_trip(&vec![Ok(()), Err(7u8), Ok(()), Ok(()), Err(0)], config);
+            round_trip(
+                &BTreeMap::from([(1u8, Ok(())), (2, Err(Some(()))), (3, Err(None))]),
+                config,
+            );
+            round_trip(&BTreeMap::from([((), Ok::<(), ()>(()))]), config);
+            round_trip(&responses, config);
+            round_trip(
+                &(Some(Err::<(), ()>(())), (), None::<Result<(), ()>>),
+                config,
+            );
+        }
+
+        // `()` is a single delimiter after the header and a `Result` is an enum of two variants.
+        let [low, high] = Config::default().header().to_le_bytes();
+        assert_eq!(serializer::to_bytes(&()).unwrap(), [low, high, 0b010]);
+        assert_eq!(
+            serializer::to_bytes(&Err::<(), ()>(())).unwrap(),
+            [low, high, 1, 0b010]
+        );
+        let bytes = serializer::to_bytes(&Ok::<u8, ()>(3)).unwrap();
+        assert!(matches!(
+            deserializer::from_bytes::<Result<u8, ()>>(&[&bytes[..2], &[2], &bytes[3..]].concat()),
+            Err(Error::UnknownVariant {
+                name: "Result",
+                index: 2,
+                variants: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn option_presence() {
+        use std::{
+            collections::{BTreeMap, HashMap},
+            fmt::Debug,
+        };
+
+        #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+        struct Record {
+            list: Option<Vec<u8>>,
+            map: Option<HashMap<String, u8>>,
+            nested: Option<Option<Vec<()>>>,
+            unit: Option<()>,
+            text: Option<String>,
+            last: Option<BTreeMap<u8, Option<Vec<u8>>>>,
+        }
+
+        fn round_trip<T>(value: &T, config: &Config)
+        where
+            T: Serialize + serde::de::DeserializeOwned + PartialEq + Debug,
+        {
+            let bytes = serializer::to_bytes_with_config(value, config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<T>(&bytes, config)
+                .unwrap_or_else(|e| panic!("{value:?} with {config:?}: {e}"));
+            assert_eq!(*value, decoded, "{config:?}");
+        }
+
+        let empty = Record {
+            list: Some(vec![]),
+            map: Some(HashMap::new()),
+            nested: Some(Some(vec![])),
+            unit: Some(()),
+            text: Some(String::new()),
+            last: Some(BTreeMap::from([(0, Some(vec![])), (1, None)])),
+        };
+        let none = Record {
+            list: None,
+            map: None,
+            nested: Some(None),
+            unit: None,
+            text: None,
+            last: None,
+        };
+        let full = Record {
+            list: Some(vec![0; 4]),
+            map: Some(HashMap::from([(String::new(), 0)])),
+            nested: None,
+            unit: Some(()),
+            text: Some("x".to_string()),
+            last: Some(BTreeMap::new()),
+        };
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Batch {
+            #[serde(with = "crate::columnar")]
+            records: Vec<Record>,
+        }
+
+        let records = vec![empty.clone(), none.clone(), none.clone(), full, empty];
+        let lists = vec![
+            Some(vec![]),
+            None,
+            Some(vec![0u8]),
+            None,
+            None,
+            Some(vec![]),
+        ];
+        let maps = vec![None, Some(HashMap::<u8, u8>::new()), None];
+        let omit = crate::config::StructKeys::Omit;
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                option_bitmaps: true,
+                ..Config::default()
+            },
+            Config {
+                option_bitmaps: true,
+                struct_keys: omit,
+                ..Config::default()
+            },
+            Config {
+                option_bitmaps: true,
+                run_length_encoding: true,
+                pack_u8_seqs: true,
+                ..self_describing()
+            },
+            Config {
+                struct_keys: omit,
+                run_length_encoding: true,
+                pack_u8_seqs: true,
+                offset_tables: true,
+                sorted_int_keys: true,
+                ..self_describing()
+            },
+            Config {
+                option_bitmaps: true,
+                struct_keys: omit,
+                byte_aligned_bools: true,
+                msb_first: true,
+                ..Config::default()
+            },
+            Config {
+                option_bitmaps: true,
+                length_prefixed_keys: true,
+                offset_tables: true,
+                ..Config::default()
+            },
+        ];
+        let batch = Batch {
+            records: records.clone(),
+        };
+        for config in &configs {
+            round_trip(&records, config);
+            round_trip(&batch, config);
+            round_trip(&lists, config);
+            round_trip(&maps, config);
+            round_trip(&Some(Vec::<u8>::new()), config);
+            round_trip(&None::<Vec<u8>>, config);
+            round_trip(&Some(Some(Vec::<u8>::new())), config);
+            round_trip(&Some(None::<Vec<u8>>), config);
+            round_trip(&(Some(HashMap::<u8, u8>::new()), None::<Vec<u8>>), config);
+        }
+    }
+
+    #[test]
+    fn map_streaming() {
+        use crate::{deserializer::MapReader, serializer::MapWriter};
+        use std::{collections::BTreeMap, io::Read};
+
+        // hands out a byte per read.
+        struct Trickle<'a>(&'a [u8]);
+
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                match (self.0.split_first(), buf.first_mut()) {
+                    (Some((byte, rest)), Some(slot)) => {
+                        *slot = *byte;
+                        self.0 = rest;
+                        Ok(1)
+                    }
+                    _ => Ok(0),
+                }
+            }
+        }
+
+        let map = (0..20_000u32)
+            .map(|i| {
+                (
+                    format!("key {i}"),
+                    (i % 7 == 0).then_some(vec![i as u8; i as usize % 5]),
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                adaptive_ints: true,
+                run_length_encoding: true,
+                pack_u8_seqs: true,
+                length_prefixed_keys: true,
+                ..self_describing()
+            },
+            Config {
+                byte_aligned_bools: true,
+                sorted_int_keys: true,
+                ..Config::default()
+            },
+        ];
+        for config in &configs {
+            let mut writer = MapWriter::new(Vec::new(), config).unwrap();
+            for (key, value) in &map {
+                writer.insert(key, value).unwrap();
+            }
+            let bytes = writer.finish().unwrap();
+            // the same data as the map serialized at once.
+            assert_eq!(
+                bytes,
+                serializer::to_bytes_with_config(&map, config).unwrap()
+            );
+            let decoded = MapReader::new(&bytes[..], config)
+                .collect::<Result<BTreeMap<_, _>, _>>()
+                .unwrap();
+            assert_eq!(decoded, map, "{config:?}");
+        }
+
+        // integer keys are written in front of their values instead of in a block.
+        let config = &configs[3];
+        let mut writer = MapWriter::new(Vec::new(), config).unwrap();
+        writer.insert(&3u32, "three").unwrap();
+        writer.insert(&1u32, "one").unwrap();
+        let bytes = writer.finish().unwrap();
+        let decoded = deserializer::from_bytes::<BTreeMap<u32, String>>(&bytes).unwrap();
+        assert_eq!(decoded[&3], "three");
+        let entries = MapReader::<_, u32, String>::new(Trickle(&bytes), config)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(entries, [(3, "three".to_string()), (1, "one".to_string())]);
+        let block = serializer::to_bytes_with_config(&decoded, config).unwrap();
+        assert!(matches!(
+            MapReader::<_, u32, String>::new(&block[..], config).next(),
+            Some(Err(Error::UnsupportedCall(_)))
+        ));
+
+        // a map that was cut off fails after its last complete entry, as does a long one.
+        let small = map.iter().take(3).collect::<BTreeMap<_, _>>();
+        let bytes = serializer::to_bytes(&small).unwrap();
+        let mut reader = MapReader::<_, String, Option<Vec<u8>>>::new(
+            Trickle(&bytes[..bytes.len() - 4]),
+            &Config::default(),
+        );
+        assert!(matches!(reader.next(), Some(Ok((key, Some(_)))) if key == "key 0"));
+        assert!(matches!(reader.next(), Some(Ok((key, None))) if key == "key 1"));
+
+        assert!(matches!(reader.next(), Some(Err(Error::UnexpectedEOF))));
+        assert!(reader.next().is_none());
+        let limited = Config {
+            max_map_entries: Some(2),
+            ..Config::default()
+        };
+        let mut reader = MapReader::<_, String, Option<Vec<u8>>>::new(&bytes[..], &limited);
+        assert_eq!(reader.by_ref().take_while(Result::is_ok).count(), 2);
+        assert!(matches!(
+            MapReader::<_, String, Option<Vec<u8>>>::new(&bytes[..], &limited).nth(2),
+            Some(Err(Error::TooManyMapEntries { max: 2, .. }))
+        ));
+
+        let tables = Config {
+            offset_tables: true,
+            ..Config::default()
+        };
+        assert!(matches!(
+            MapWriter::new(Vec::new(), &tables),
+            Err(Error::UnsupportedCall(_))
+        ));
+        // errors of the writer carry the position of the failed write.
+        let mut buffer = [0u8; 8];
+        let mut full = MapWriter::new(&mut buffer[..], &Config::default()).unwrap();
+        let error = (0..2000u32).find_map(|i| full.insert(&i, &i).err());
+        assert!(matches!(error, Some(Error::Io { position: 0, .. })));
+    }
+
+    #[test]
+    fn raw_str_keys() {
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Prices {
+            currency: String,
+            items: BTreeMap<String, u32>,
+        }
+
+        let map = BTreeMap::from([("cafe".to_string(), 3u32), ("tea".to_string(), 2)]);
+        let prices = Prices {
+            currency: "EUR".to_string(),
+            items: map.clone(),
+        };
+        for config in [Config::default(), self_describing()] {
+            let raw = Config {
+                raw_str_keys: true,
+                ..config.clone()
+            };
+            // valid keys and the field names of structs read as usual.
+            let bytes = serializer::to_bytes_with_config(&prices, &config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<Prices>(&bytes, &raw).unwrap();
+            assert_eq!(decoded, prices);
+
+            // "caf\xe9" is Latin-1 from a foreign producer.
+            let mut bytes = serializer::to_bytes_with_config(&map, &config).unwrap();
+            let at = bytes
+                .windows(4)
+                .position(|window| window == b"cafe")
+                .unwrap();
+            bytes[at + 3] = 0xe9;
+            assert!(matches!(
+                deserializer::from_bytes_with_config::<BTreeMap<String, u32>>(&bytes, &config),
+                Err(Error::ConversionError)
+            ));
+            let decoded =
+                deserializer::from_bytes_with_config::<BTreeMap<&[u8], u32>>(&bytes, &raw).unwrap();
+            assert_eq!(
+                decoded,
+                BTreeMap::from([(&b"caf\xe9"[..], 3), (&b"tea"[..], 2)])
+            );
+            // keys that are strings still check them.
+            assert!(
+                deserializer::from_bytes_with_config::<BTreeMap<String, u32>>(&bytes, &raw)
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn seekable_container() {
+        use crate::container::{FrameCodec, SeekableReader, SeekableWriter};
+        use std::{cell::Cell, rc::Rc};
+
+        // a run-length codec of (count, byte) pairs that counts the frames it decompresses.
+        #[derive(Default)]
+        struct RunLength {
+            decompressed: Rc<Cell<usize>>,
+            fail: bool,
+        }
+
+        impl FrameCodec for RunLength {
+            fn compress(&self, frame: &[u8]) -> std::io::Result<Vec<u8>> {
+                let mut compressed = Vec::new();
+                for run in frame.chunk_by(|a, b| a == b) {
+                    for part in run.chunks(255) {
+                        compressed.extend([part.len() as u8, part[0]]);
+                    }
+                }
+                Ok(compressed)
+            }
+            fn decompress(&self, frame: &[u8], size: usize) -> std::io::Result<Vec<u8>> {
+                if self.fail {
+                    return Err(std::io::Error::other("corrupt frame"));
+                }
+                self.decompressed.set(self.decompressed.get() + 1);
+                let mut decompressed = Vec::with_capacity(size);
+                for pair in frame.chunks(2) {
+                    decompressed.extend(std::iter::repeat_n(pair[1], pair[0] as usize));
+                }
+                Ok(decompressed)
+            }
+        }
+
+        let config = Config::default();
+        let humans = (0..250)
+            .map(|age| Human {
+                name: "a".repeat(age as usize % 40),
+                age,
+            })
+            .collect::<Vec<_>>();
+        let mut writer = SeekableWriter::new(RunLength::default(), &config, 256);
+        let mut container = ContainerWriter::new(&config);
+        let mut offsets = Vec::new();
+        for human in &humans {
+            offsets.push(writer.push(human).unwrap());
+            container.push(human).unwrap();
+        }
+        let bytes = writer.into_bytes().unwrap();
+        let container = container.into_bytes();
+        assert!(bytes.len() < container.len());
+        // the seek table is a skippable zstd frame ending with the seekable magic number.
+        assert!(bytes.ends_with(&[0, 0xb1, 0xea, 0x92, 0x8f]));
+
+        let reader = SeekableReader::new(&bytes, RunLength::default(), &config).unwrap();
+        assert_eq!(reader.decompressed_len(), container.len());
+        let frames = (0..reader.frames())
+            .map(|index| reader.frame(index).unwrap())
+            .collect::<Vec<_>>();
+        assert!(frames.len() > 10);
+        // the frames are the container, cut between records.
+        assert_eq!(frames.concat(), container);
+        for frame in &frames {
+            assert!(ContainerReader::new(frame, &config).all(|record| record.is_ok()));
+        }
+        assert_eq!(reader.frame_at(0), Some(0));
+        assert_eq!(reader.frame_at(container.len() - 1), Some(frames.len() - 1));
+        assert_eq!(reader.frame_at(container.len()), None);
+        assert!(matches!(
+            reader.frame(frames.len()),
+            Err(Error::UnexpectedEOF)
+        ));
+
+        // a record at random decompresses its frame alone.
+        let codec = RunLength::default();
+        let decompressed = codec.decompressed.clone();
+        let reader = SeekableReader::new(&bytes, codec, &config).unwrap();
+        for index in [0, 201, 249, 42] {
+            let human = reader.decode::<Human>(offsets[index]).unwrap();
+            assert_eq!(human, humans[index]);
+        }
+        assert_eq!(decompressed.get(), 4);
+
+        // seek tables with checksums (from other writers) read the same.
+        let count = reader.frames();
+        let table = bytes.len() - 9 - count * 8 - 8;
+        let mut checksummed = bytes[..table].to_vec();
+        checksummed.extend(0x184D_2A5Eu32.to_le_bytes());
+        checksummed.extend(((count * 12 + 9) as u32).to_le_bytes());
+        for entry in bytes[table + 8..bytes.len() - 9].chunks(8) {
+            checksummed.extend(entry);
+            checksummed.extend([0xaa; 4]);
+        }
+        checksummed.extend((count as u32).to_le_bytes());
+        checksummed.push(0x80);
+        checksummed.extend(0x8F92_EAB1u32.to_le_bytes());
+        let reader = SeekableReader::new(&checksummed, RunLength::default(), &config).unwrap();
+        assert_eq!(reader.decode::<Human>(offsets[7]).unwrap(), humans[7]);
+
+        // an empty container has an empty seek table.
+        let empty = SeekableWriter::new(RunLength::default(), &config, 256)
+            .into_bytes()
+            .unwrap();
+        assert_eq!(empty.len(), 17);
+        let reader = SeekableReader::new(&empty, RunLength::default(), &config).unwrap();
+        assert_eq!((reader.frames(), reader.frame_at(0)), (0, None));
+
+        // a cut off container loses its seek table; a frame that fails to decompress is
+        // reported with its position.
+        for length in [0, 8, bytes.len() - 1] {
+            assert!(SeekableReader::new(&bytes[..length], RunLength::default(), &config).is_err());
+        }
+        let mut shifted = bytes.clone();
+        shifted.insert(0, 0);
+        assert!(matches!(
+            SeekableReader::new(&shifted, RunLength::default(), &config),
+            Err(Error::DeserializationError(_))
+        ));
+        let failing = RunLength {
+            fail: true,
+            ..RunLength::default()
+        };
+        let reader = SeekableReader::new(&bytes, failing, &config).unwrap();
+        assert!(matches!(
+            reader.decode::<Human>(offsets[249]),
+            Err(Error::Io { position, .. }) if position > 0
+        ));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_seekable_container() {
+        use crate::container::{SeekableReader, SeekableWriter, Zstd};
+        use zstd::zstd_safe;
+
+        let config = Config::default();
+        let humans = (0..2000u32)
+            .map(|i| Human {
+                name: format!("human {}", i % 97),
+                age: i as u8,
+            })
+            .collect::<Vec<_>>();
+        let mut writer = SeekableWriter::new(Zstd::default(), &config, 4096);
+        let mut container = ContainerWriter::new(&config);
+        let mut offsets = Vec::new();
+        for human in &humans {
+            offsets.push(writer.push(human).unwrap());
+            container.push(human).unwrap();
+        }
+        let bytes = writer.into_bytes().unwrap();
+        let container = container.into_bytes();
+        assert!(bytes.len() < container.len() / 2);
+
+        // the seek table as the seekable format lays it out: a skippable frame of the sizes of
+        // the frames, the number of frames, the descriptor and the magic number.
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let footer = bytes.len() - 9;
+        let count = u32_at(footer) as usize;
+        assert!(count > 5);
+        assert_eq!(bytes[footer + 4], 0);
+        assert_eq!(u32_at(footer + 5), 0x8F92_EAB1);
+        let table = footer - count * 8 - 8;
+        assert_eq!(u32_at(table), 0x184D_2A5E);
+        assert_eq!(u32_at(table + 4) as usize, count * 8 + 9);
+        let mut start = 0;
+        for index in 0..count {
+            let (compressed, decompressed) = (
+                u32_at(table + 8 + index * 8),
+                u32_at(table + 12 + index * 8),
+            );
+            // every entry is the size of a whole zstd frame and of its content.
+            let frame = &bytes[start..table];
+            assert_eq!(
+                zstd_safe::find_frame_compressed_size(frame),
+                Ok(compressed as usize)
+            );
+            assert_eq!(
+                zstd_safe::get_frame_content_size(frame).ok(),
+                Some(Some(decompressed as u64))
+            );
+            start += compressed as usize;
+        }
+        assert_eq!(start, table);
+
+        // zstd decompresses the whole file to the container, skipping the seek table.
+        assert_eq!(
+            zstd::stream::decode_all(bytes.as_slice()).unwrap(),
+            container
+        );
+
+        let reader = SeekableReader::new(&bytes, Zstd::default(), &config).unwrap();
+        assert_eq!(reader.frames(), count);
+        for index in [0, 1999, 1234] {
+            assert_eq!(
+                reader.decode::<Human>(offsets[index]).unwrap(),
+                humans[index]
+            );
+        }
+    }
+
+    #[test]
+    fn grammar() {
+        use crate::{
+            config::StringFraming,
+            protocol::format::{Production, Symbol, GRAMMAR},
+        };
+        use bitvec::prelude::{BitVec, Lsb0};
+        use std::collections::BTreeMap;
+
+        type Bits = BitVec<u8, Lsb0>;
+
+        // what fills the symbols of a rule that aren't tokens or padding.
+        enum Fill<'a> {
+            Value(&'a dyn Fn(&mut Bits)),
+            Byte(u8),
+            Length(u32),
+        }
+
+        fn push(bits: &mut Bits, value: u64, width: usize) {
+            bits.extend((0..width).map(|i| value & (1 << i) != 0));
+        }
+
+        // write the symbols of the rule of `production` from the grammar alone, with its item
+        // repeated `items` times.
+        fn generate<'a>(
+            bits: &mut Bits,
+            production: Production,
+            items: usize,
+            fills: &mut impl Iterator<Item = Fill<'a>>,
+        ) {
+            fn emit<'a>(
+                bits: &mut Bits,
+                symbol: &Symbol,
+                items: usize,
+                fills: &mut dyn Iterator<Item = Fill<'a>>,
+            ) {
+                match symbol {
+                    Symbol::Token(delimiter) => bits.extend(delimiter.token().bits()),
+                    Symbol::Padding => bits.resize(bits.len().div_ceil(8) * 8, false),
+                    Symbol::Repeat(item) => {
+                        for _ in 0..items {
+                            for symbol in *item {
+                                emit(bits, symbol, items, fills);
+                            }
+                        }
+                    }
+                    symbol => match (symbol, fills.next()) {
+                        (Symbol::Byte, Some(Fill::Byte(byte))) => push(bits, byte.into(), 8),
+                        (Symbol::Length, Some(Fill::Length(length))) => {
+                            push(bits, length.into(), 32)
+                        }
+                        (Symbol::Value, Some(Fill::Value(value))) => value(bits),
+                        _ => panic!("nothing fills {symbol}"),
+                    },
+                }
+            }
+            for symbol in production.rule().symbols {
+                emit(bits, symbol, items, fills);
+            }
+        }
+
+        let with_header = |config: &Config, body: &dyn Fn(&mut Bits)| {
+            let mut bits = Bits::new();
+            push(&mut bits, config.header().into(), 16);
+            body(&mut bits);
+            bits.into_vec()
+        };
+        let u8_value = |value: u8| move |bits: &mut Bits| push(bits, value.into(), 8);
+        let string = |text: &'static str| {
+            move |bits: &mut Bits| {
+                let mut fills = text.bytes().map(Fill::Byte);
+                generate(bits, Production::Str, text.len(), &mut fills)
+            }
+        };
+
+        // the bytes generated from the grammar are the ones the serializer writes and the
+        // deserializer reads; its strings are the delimited ones.
+        let config = Config {
+            string_framing: StringFraming::Delimited,
+            ..Config::default()
+        };
+        let (one, two) = (u8_value(1), u8_value(2));
+        let bytes = with_header(&config, &|bits| {
+            let mut fills = [Fill::Value(&one), Fill::Value(&two)].into_iter();
+            generate(bits, Production::Seq, 2, &mut fills)
+        });
+        assert_eq!(
+            bytes,
+            serializer::to_bytes_with_config(&vec![1u8, 2], &config).unwrap()
+        );
+        assert_eq!(deserializer::from_bytes::<Vec<u8>>(&bytes).unwrap(), [1, 2]);
+
+        let (key, unit) = (string("key"), |bits: &mut Bits| {
+            generate(bits, Production::Unit, 0, &mut std::iter::empty())
+        });
+        let bytes = with_header(&config, &|bits| {
+            let mut fills = [Fill::Value(&key), Fill::Value(&unit)].into_iter();
+            generate(bits, Production::Map, 1, &mut fills)
+        });
+        let map = BTreeMap::from([("key".to_string(), ())]);
+        assert_eq!(
+            bytes,
+            serializer::to_bytes_with_config(&map, &config).unwrap()
+        );
+        assert_eq!(
+            deserializer::from_bytes::<BTreeMap<String, ()>>(&bytes).unwrap(),
+            map
+        );
+
+        let config = Config {
+            run_length_encoding: true,
+            ..Config::default()
+        };
+        let seven = u8_value(7);
+        let run = |bits: &mut Bits| {
+            let mut fills = [Fill::Length(5), Fill::Value(&seven)].into_iter();
+            generate(bits, Production::Run, 0, &mut fills)
+        };
+        let bytes = with_header(&config, &|bits| {
+            // the run takes the place of the item of the sequence.
+            bits.extend(Production::Seq.first().token().bits());
+            run(bits);
+            bits.extend(Production::Seq.last().token().bits());
+        });
+        assert_eq!(
+            bytes,
+            serializer::to_bytes_with_config(&vec![7u8; 5], &config).unwrap()
+        );
+        assert_eq!(deserializer::from_bytes::<Vec<u8>>(&bytes).unwrap(), [7; 5]);
+
+        let config = Config {
+            pack_u8_seqs: true,
+            ..Config::default()
+        };
+        let bytes = with_header(&config, &|bits| {
+            let mut fills =
+                [Fill::Length(3), Fill::Byte(4), Fill::Byte(5), Fill::Byte(6)].into_iter();
+            generate(bits, Production::Packed, 3, &mut fills)
+        });
+        assert_eq!(
+            bytes,
+            serializer::to_bytes_with_config(&vec![4u8, 5, 6], &config).unwrap()
+        );
+        assert_eq!(
+            deserializer::from_bytes::<Vec<u8>>(&bytes).unwrap(),
+            [4, 5, 6]
+        );
+
+        // every delimiter frames something and the rules are checked by the spec.
+        let tokens = GRAMMAR
+            .iter()
+            .flat_map(|rule| rule.symbols)
+            .flat_map(|symbol| match symbol {
+                Symbol::Repeat(item) => item.to_vec(),
+                symbol => vec![*symbol],
+            })
+            .filter_map(|symbol| match symbol {
+                Symbol::Token(delimiter) => Some(delimiter),
+                _ => None,
+            })
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(tokens.len(), Delimiter::ALL.len());
+        assert_eq!(Production::Map.item(1), Delimiter::MapValue);
+        assert!(spec::generate()
+            .contains("seq: SEQ_DELIMITER + (SEQ_VALUE_DELIMITER + value)* + SEQ_DELIMITER"));
+    }
+
+    #[test]
+    fn tuple_variant_elements() {
+        // the elements of tuple variants are framed by the state of the serializer, not by
+        // looking back at the bits written before them: elements of any width (e.g. bools
+        // whose bits read like SEQ_DELIMITER) keep their tokens.
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum Event {
+            Flags(bool, bool, bool, bool),
+            Mixed(bool, u64, Vec<bool>, i64),
+            Nested(Vec<Event>, (bool, bool, bool)),
+        }
+
+        let events = vec![
+            // 1, 1, 0 is 0b011 least significant bit first.
+            Event::Flags(true, true, false, true),
+            Event::Mixed(true, 3, vec![true, true, false], -2),
+            Event::Nested(
+                vec![
+                    Event::Flags(false, true, true, false),
+                    Event::Mixed(false, u64::MAX, vec![], 1),
+                ],
+                (true, true, false),
+            ),
+        ];
+        let configs = [
+            Config::default(),
+            self_describing(),
+            Config {
+                adaptive_ints: true,
+                run_length_encoding: true,
+                ..self_describing()
+            },
+            Config {
+                adaptive_ints: true,
+                msb_first: true,
+                ..self_describing()
+            },
+        ];
+        for config in &configs {
+            let bytes = serializer::to_bytes_with_config(&events, config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<Vec<Event>>(&bytes, config)
+                .unwrap_or_else(|e| panic!("{config:?}: {e}"));
+            assert_eq!(decoded, events, "{config:?}");
+        }
+    }
+
+    #[test]
+    fn nested_containers_in_every_mode() {
+        use crate::config::StructKeys;
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Row {
+            bytes: Vec<u8>,
+            note: Option<String>,
+            cells: BTreeMap<u16, Vec<Vec<u8>>>,
+        }
+
+        // bytes that read like delimiters, repeated so they form runs.
+        let delimiters = vec![0b000, 0b001, 0b010, 0b011, 0b100, 0b101, 0b110, 0b111];
+        let rows = (0..4u16)
+            .map(|i| Row {
+                bytes: delimiters.repeat(usize::from(i)),
+                note: (i % 2 == 0).then(|| "SEQ".repeat(usize::from(i))),
+                cells: (0..i)
+                    .map(|key| (key, vec![delimiters.clone(), vec![], vec![0; 3]]))
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+        let all = Config {
+            run_length_encoding: true,
+            pack_u8_seqs: true,
+            offset_tables: true,
+            sorted_int_keys: true,
+            length_prefixed_keys: true,
+            ..Config::default()
+        };
+        let configs = [
+            Config {
+                struct_keys: StructKeys::Omit,
+                option_bitmaps: true,
+                ..all.clone()
+            },
+            Config {
+                msb_first: true,
+                ..all.clone()
+            },
+            all,
+        ];
+        for config in &configs {
+            let bytes = serializer::to_bytes_with_config(&rows, config).unwrap();
+            let mut written = Vec::new();
+            serializer::to_writer_with_config(&mut written, &rows, config).unwrap();
+            assert_eq!(written, bytes, "{config:?}");
+            let decoded = deserializer::from_bytes_with_config::<Vec<Row>>(&bytes, config)
+                .unwrap_or_else(|e| panic!("{config:?}: {e}"));
+            assert_eq!(decoded, rows, "{config:?}");
+        }
+    }
+
+    #[test]
+    fn size_hints() {
+        use std::{collections::BTreeMap, marker::PhantomData};
+
+        // The size hints a visitor was given, ignoring them while collecting the elements (or
+        // entries) one by one.
+        #[derive(Debug, Default)]
+        struct Hinted<T> {
+            hint: Option<usize>,
+            len: usize,
+            _values: PhantomData<T>,
+        }
+
+        impl<'de, T: Deserialize<'de>> Deserialize<'de> for Hinted<T> {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct HintVisitor<T>(PhantomData<T>);
+
+                impl<'de, T: Deserialize<'de>> serde::de::Visitor<'de> for HintVisitor<T> {
+                    type Value = Hinted<T>;
+
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        write!(f, "a seq or a map")
+                    }
+                    fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                        self,
+                        mut seq: A,
+                    ) -> Result<Self::Value, A::Error> {
+                        let hint = seq.size_hint();
+                        let mut len = 0;
+                        while seq.next_element::<T>()?.is_some() {
+                            len += 1;
+                        }
+                        Ok(Hinted {
+                            hint,
+                            len,
+                            _values: PhantomData,
+                        })
+                    }
+                    fn visit_map<A: serde::de::MapAccess<'de>>(
+                        self,
+                        mut map: A,
+                    ) -> Result<Self::Value, A::Error> {
+                        let hint = map.size_hint();
+                        let mut len = 0;
+                        while map.next_entry::<T, T>()?.is_some() {
+                            len += 1;
+                        }
+                        Ok(Hinted {
+                            hint,
+                            len,
+                            _values: PhantomData,
+                        })
+                    }
+                }
+
+                deserializer.deserialize_any(HintVisitor(PhantomData))
+            }
+        }
+
+        let hint = |bytes: &[u8], config: &Config| {
+            let hinted = deserializer::from_bytes_with_config::<Hinted<u32>>(bytes, config)
+                .unwrap_or_else(|e| panic!("{config:?}: {e}"));
+            (hinted.hint, hinted.len)
+        };
+        let map = (0..100u32).map(|i| (i, i * 2)).collect::<BTreeMap<_, _>>();
+        let seq = (0..100u32).collect::<Vec<_>>();
+        let bytes = (0..100u8).collect::<Vec<_>>();
+
+        // maps with a block of keys or an offset table tell how many entries they have.
+        let counted = [
+            Config {
+                sorted_int_keys: true,
+                ..self_describing()
+            },
+            Config {
+                offset_tables: true,
+                ..self_describing()
+            },
+        ];
+        for config in &counted {
+            let data = serializer::to_bytes_with_config(&map, config).unwrap();
+            assert_eq!(hint(&data, config), (Some(100), 100), "{config:?}");
+        }
+        // so do packed sequences of u8.
+        let packed = Config {
+            pack_u8_seqs: true,
+            ..self_describing()
+        };
+        let data = serializer::to_bytes_with_config(&bytes, &packed).unwrap();
+        assert_eq!(hint(&data, &packed), (Some(100), 100));
+        // other maps and sequences are read up to their end.
+        let data = serializer::to_bytes_with_config(&map, &self_describing()).unwrap();
+        assert_eq!(hint(&data, &self_describing()), (None, 100));
+        let data = serializer::to_bytes_with_config(&seq, &self_describing()).unwrap();
+        assert_eq!(hint(&data, &self_describing()), (None, 100));
+
+        // the collections of serde allocate with the hint.
+        let config = Config {
+            sorted_int_keys: true,
+            ..Config::default()
+        };
+        let data = serializer::to_bytes_with_config(&map, &config).unwrap();
+        let decoded =
+            deserializer::from_bytes_with_config::<HashMap<u32, u32>>(&data, &config).unwrap();
+        assert!(decoded.capacity() >= 100 && decoded.len() == 100);
+    }
+
+    #[test]
+    #[ignore = "playground test; use cargo test -- --nocapture --ignored"]
+    fn size_hint_benchmark() {
+        use std::time::{Duration, Instant};
+
+        // the times only mean something with `--release`.
+
+        // a collection that grows one element (or entry) at a time, ignoring the hint.
+        #[derive(Debug)]
+        struct Grown<C>(C);
+
+        impl<'de> Deserialize<'de> for Grown<Vec<u8>> {
+            fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                struct Elements;
+                impl<'de> serde::de::Visitor<'de> for Elements {
+                    type Value = Vec<u8>;
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        write!(f, "a seq")
+                    }
+                    fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                        self,
+                        mut seq: A,
+                    ) -> Result<Self::Value, A::Error> {
+                        let mut values = Vec::new();
+                        while let Some(value) = seq.next_element()? {
+                            values.push(value);
+                        }
+                        Ok(values)
+                    }
+                }
+                d.deserialize_seq(Elements).map(Grown)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for Grown<HashMap<u32, u32>> {
+            fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                struct Entries;
+                impl<'de> serde::de::Visitor<'de> for Entries {
+                    type Value = HashMap<u32, u32>;
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        write!(f, "a map")
+                    }
+                    fn visit_map<A: serde::de::MapAccess<'de>>(
+                        self,
+                        mut map: A,
+                    ) -> Result<Self::Value, A::Error> {
+                        let mut entries = HashMap::new();
+                        while let Some((key, value)) = map.next_entry()? {
+                            entries.insert(key, value);
+                        }
+                        Ok(entries)
+                    }
+                }
+                d.deserialize_map(Entries).map(Grown)
+            }
+        }
+
+        fn time<T: serde::de::DeserializeOwned>(bytes: &[u8], config: &Config) -> Duration {
+            let start = Instant::now();
+            deserializer::from_bytes_with_config::<T>(bytes, config).unwrap();
+            start.elapsed()
+        }
+
+        let bytes = (0..1_000_000).map(|i| i as u8).collect::<Vec<u8>>();
+        let packed = Config {
+            pack_u8_seqs: true,
+            ..Config::default()
+        };
+        let data = serializer::to_bytes_with_config(&bytes, &packed).unwrap();
+        println!("---- 1,000,000 packed u8 ----");
+        println!("with the hint:\t{:?}", time::<Vec<u8>>(&data, &packed));
+        println!("without it:\t{:?}", time::<Grown<Vec<u8>>>(&data, &packed));
+
+        let map = (0..1_000_000u32)
+            .map(|i| (i, !i))
+            .collect::<HashMap<_, _>>();
+        let configs = [
+            (
+                "sorted integer keys",
+                Config {
+                    sorted_int_keys: true,
+                    ..Config::default()
+                },
+            ),
+            (
+                "offset tables",
+                Config {
+                    offset_tables: true,
+                    ..Config::default()
+                },
+            ),
+        ];
+        for (name, config) in configs {
+            let data = serializer::to_bytes_with_config(&map, &config).unwrap();
+            println!("---- 1,000,000 entries with {name} ----");
+            println!(
+                "with the hint:\t{:?}",
+                time::<HashMap<u32, u32>>(&data, &config)
+            );
+            println!(
+                "without it:\t{:?}",
+                time::<Grown<HashMap<u32, u32>>>(&data, &config)
+            );
+        }
+    }
+
+    #[test]
+    fn error_categories() {
+        use crate::error::ErrorCategory;
+        use std::io;
+
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Pet {
+            name: String,
+            legs: u8,
+        }
+
+        let human = Human {
+            name: "Ayush".to_string(),
+            age: 19,
+        };
+        let bytes = serializer::to_bytes(&human).unwrap();
+        let category = |error: Error| (error.category(), error.is_recoverable());
+
+        // truncated data.
+        let error = deserializer::from_bytes::<Human>(&bytes[..bytes.len() - 3]).unwrap_err();
+        assert_eq!(category(error), (ErrorCategory::Corruption, true));
+        // data of another type.
+        let error = deserializer::from_bytes::<Pet>(&bytes).unwrap_err();
+        assert_eq!(category(error), (ErrorCategory::SchemaMismatch, true));
+        // data of another config.
+        let error =
+            deserializer::from_bytes_with_config::<Human>(&bytes, &self_describing()).unwrap_err();
+        assert_eq!(category(error), (ErrorCategory::SchemaMismatch, true));
+        // data nested deeper than the limit.
+        let config = Config {
+            max_depth: Some(1),
+            ..Config::default()
+        };
+        let nested = serializer::to_bytes_with_config(&vec![vec![1u8]], &config).unwrap();
+        let error =
+            deserializer::from_bytes_with_config::<Vec<Vec<u8>>>(&nested, &config).unwrap_err();
+        assert_eq!(category(error), (ErrorCategory::ResourceLimit, true));
+        assert_eq!(
+            category(Error::Cancelled),
+            (ErrorCategory::ResourceLimit, false)
+        );
+        // a writer that fails.
+        struct Broken;
+        impl io::Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let error = serializer::to_writer(Broken, &human).unwrap_err();
+        assert_eq!(category(error), (ErrorCategory::Io, false));
+    }
+
+    #[test]
+    fn text_adapters() {
+        use std::{collections::BTreeMap, ffi::OsString, path::PathBuf};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Entry {
+            #[serde(with = "crate::with::utf16")]
+            wide: Vec<u16>,
+            #[serde(with = "crate::with::latin1")]
+            legacy: Vec<u8>,
+            #[serde(with = "crate::with::os_str")]
+            path: PathBuf,
+            #[serde(with = "crate::with::os_str")]
+            name: OsString,
+        }
+
+        // a wide string as a Windows API hands it out, with a surrogate pair for the emoji.
+        let wide = "C:\\Users\\Łukasz\\日本\\🦀.txt"
+            .encode_utf16()
+            .collect::<Vec<_>>();
+        let entry = Entry {
+            wide,
+            legacy: b"caf\xe9 \xa9 1999".to_vec(),
+            path: PathBuf::from("sync/Łukasz/notes.md"),
+            name: OsString::from("日本"),
+        };
+        for config in [Config::default(), self_describing()] {
+            let bytes = serializer::to_bytes_with_config(&entry, &config).unwrap();
+            let decoded = deserializer::from_bytes_with_config::<Entry>(&bytes, &config).unwrap();
+            assert_eq!(decoded, entry);
+        }
+        // the fields are strings, the same as their UTF-8 would be.
+        let bytes = serializer::to_bytes(&entry).unwrap();
+        let fields = deserializer::from_bytes::<BTreeMap<String, String>>(&bytes).unwrap();
+        assert_eq!(fields["wide"], "C:\\Users\\Łukasz\\日本\\🦀.txt");
+        assert_eq!(fields["legacy"], "café © 1999");
+        assert_eq!(fields["name"], "日本");
+        let json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["wide"], "C:\\Users\\Łukasz\\日本\\🦀.txt");
+        assert_eq!(json["legacy"], "café © 1999");
+        assert_eq!(json["path"], "sync/Łukasz/notes.md");
+        assert_eq!(deserializer::from_bytes::<Entry>(&bytes).unwrap(), entry);
+
+        // an unpaired surrogate or a char outside Latin-1 has no encoding.
+        let lone = Entry {
+            wide: vec![0x61, 0xd800, 0x62],
+            ..entry
+        };
+        assert!(matches!(
+            serializer::to_bytes(&lone),
+            Err(Error::SerializationError(message)) if message.contains("unpaired surrogate")
+        ));
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Legacy(#[serde(with = "crate::with::latin1")] Vec<u8>);
+        let bytes = serializer::to_bytes(&"café").unwrap();
+        let legacy = deserializer::from_bytes::<Legacy>(&bytes).unwrap();
+        assert_eq!(legacy, Legacy(b"caf\xe9".to_vec()));
+        let bytes = serializer::to_bytes(&"Łukasz").unwrap();
+        assert!(deserializer::from_bytes::<Legacy>(&bytes).is_err());
+
+        // a path that isn't UTF-8 fails instead of losing its invalid bytes.
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+
+            #[derive(Debug, Serialize)]
+            struct Synced<'a>(#[serde(with = "crate::with::os_str")] &'a std::path::Path);
+            let path = std::path::Path::new(std::ffi::OsStr::from_bytes(b"sync/caf\xe9.md"));
+            assert!(matches!(
+                serializer::to_bytes(&Synced(path)),
+                Err(Error::SerializationError(message)) if message.contains("valid Unicode")
+            ));
+        }
+    }
+
+    #[test]
+    fn lossless_paths() {
+        use crate::with::os_bytes::{decode_wtf8, encode_wtf8};
+        use std::{ffi::OsString, path::PathBuf};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Archived {
+            #[serde(with = "crate::with::os_bytes")]
+            path: PathBuf,
+            #[serde(with = "crate::with::os_bytes")]
+            name: OsString,
+            size: u64,
+        }
+
+        // paths that are valid Unicode are their UTF-8 on every platform.
+        let archived = Archived {
+            path: PathBuf::from("sync/Łukasz/日本/🦀.txt"),
+            name: OsString::from("Ćwiek"),
+            size: 4096,
+        };
+        let bytes = serializer::to_bytes(&archived).unwrap();
+        assert_eq!(
+            deserializer::from_bytes::<Archived>(&bytes).unwrap(),
+            archived
+        );
+
+        // wide strings of Windows, unpaired surrogates included, round-trip through WTF-8.
+        let wide = [0x61, 0xd800, 0x62, 0xdc00, 0xd83e, 0xdd80, 0xdbff];
+        let wtf8 = encode_wtf8(&wide);
+        assert_eq!(
+            wtf8,
+            b"a\xed\xa0\x80b\xed\xb0\x80\xf0\x9f\xa6\x80\xed\xaf\xbf"
+        );
+        assert_eq!(decode_wtf8(&wtf8).unwrap(), wide);
+        let text = "C:\\Users\\Łukasz\\🦀";
+        let units = text.encode_utf16().collect::<Vec<_>>();
+        assert_eq!(encode_wtf8(&units), text.as_bytes());
+        // a pair of surrogates encoded one by one, overlong encodings and stray bytes aren't
+        // WTF-8.
+        assert_eq!(decode_wtf8(b"\xed\xa0\xbe\xed\xb6\x80"), None);
+        assert_eq!(decode_wtf8(b"\xc0\xaf"), None);
+        assert_eq!(decode_wtf8(b"a\xff"), None);
+        assert_eq!(decode_wtf8(b"\xe6\x97"), None);
+
+        // the raw bytes of a path that isn't UTF-8 round-trip through an archive.
+        #[cfg(unix)]
+        {
+            use crate::{
+                config::StringFraming,
+                container::{ContainerReader, ContainerWriter},
+            };
+            use std::os::unix::ffi::OsStringExt;
+
+            let files =
+                [b"caf\xe9.md".to_vec(), b"\xff\xfe/\x86\x87".to_vec()].map(|raw| Archived {
+                    path: PathBuf::from(OsString::from_vec(raw.clone())),
+                    name: OsString::from_vec(raw),
+                    size: 1,
+                });
+            // the bytes of the byte delimiter need one of the framings that don't collide.
+            let configs = [
+                Config {
+                    string_framing: StringFraming::Escaped,
+                    ..Config::default()
+                },
+                Config {
+                    string_framing: StringFraming::LengthPrefixed,
+                    ..self_describing()
+                },
+            ];
+            for config in configs {
+                let mut writer = ContainerWriter::new(&config);
+                for file in &files {
+                    writer.push(file).unwrap();
+                }
+                let bytes = writer.into_bytes();
+                let read = ContainerReader::new(&bytes, &config)
+                    .map(|record| record.unwrap().decode::<Archived>(&config).unwrap())
+                    .collect::<Vec<_>>();
+                assert_eq!(read, files);
+            }
+        }
+    }
+
+    #[test]
+    fn precomputed_keys() {
+        use crate::{config::StringFraming, precompute, precompute::EncodedStr};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Event {
+            id: u64,
+            name: String,
+            #[serde(rename = "Ć")]
+            weird: Option<bool>,
+            tags: Vec<Tag>,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Tag {
+            key: String,
+        }
+
+        const ID: EncodedStr = precompute!("id");
+        const KEYS: [EncodedStr; 4] = [
+            ID,
+            precompute!("name"),
+            precompute!("Ć"),
+            precompute!("key"),
+        ];
+        assert_eq!(ID.text(), "id");
+        assert_eq!(ID.bytes(), b"id\x86");
+        assert_eq!(ID.prefixed(), b"\x02\0\0\0id");
+        // "Ć" is 0xc4 0x86 in UTF-8, so the byte of the String delimiter is escaped.
+        assert_eq!(precompute::encoded_len("Ć"), 4);
+        assert_eq!(KEYS[2].bytes(), [0xc4, 0xff, 0x86, 0x86]);
+        assert_eq!(precompute!("").bytes(), [0x86]);
+
+        let event = Event {
+            id: 7,
+            name: "login".to_string(),
+            weird: Some(true),
+            tags: vec![
+                Tag {
+                    key: "a".to_string(),
+                },
+                Tag {
+                    key: "b".to_string(),
+                },
+            ],
+        };
+        // the keys copied from their encodings give the same bytes in every config.
+        let configs = [
+            Config::default(),
+            Config {
+                string_framing: StringFraming::Escaped,
+                ..Config::default()
+            },
+            Config {
+                string_framing: StringFraming::Delimited,
+                ..Config::default()
+            },
+            Config {
+                msb_first: true,
+                ..Config::default()
+            },
+            Config {
+                length_prefixed_keys: true,
+                ..Config::default()
+            },
+            self_describing(),
+        ];
+        for config in &configs {
+            let bytes = serializer::to_bytes_with_keys(&event, config, &KEYS).unwrap();
+            assert_eq!(
+                bytes,
+                serializer::to_bytes_with_config(&event, config).unwrap()
+            );
+            if config.string_framing != StringFraming::Delimited {
+                let decoded = deserializer::from_bytes_with_config::<Event>(&bytes, config);
+                assert_eq!(decoded.unwrap(), event);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "playground test; use cargo test -- --nocapture --ignored"]
+    fn precomputed_keys_benchmark() {
+        use crate::{config::StringFraming, precompute, precompute::EncodedStr};
+        use std::time::Instant;
+
+        #[derive(Serialize)]
+        struct Record {
+            timestamp: u64,
+            level: u8,
+            service_name: &'static str,
+            request_id: u64,
+        }
+
+        const KEYS: [EncodedStr; 4] = [
+            precompute!("timestamp"),
+            precompute!("level"),
+            precompute!("service_name"),
+            precompute!("request_id"),
+        ];
+        let records = (0..100_000)
+            .map(|i| Record {
+                timestamp: 1_700_000_000 + i,
+                level: (i % 5) as u8,
+                service_name: "api",
+                request_id: i * 7,
+            })
+            .collect::<Vec<_>>();
+        // the escaped framing copies the keys instead of looking for bytes to escape in them
+        // and the default one copies their lengths along with them.
+        let configs = [
+            (
+                "escaped",
+                Config {
+                    string_framing: StringFraming::Escaped,
+                    ..Config::default()
+                },
+            ),
+            ("length prefixed", Config::default()),
+        ];
+        for (name, config) in configs {
+            let start = Instant::now();
+            let encoded = serializer::to_bytes_with_config(&records, &config).unwrap();
+            println!(
+                "{name}, encoded keys:\t{} bytes in {:?}",
+                encoded.len(),
+                start.elapsed()
+            );
+            let start = Instant::now();
+            let copied = serializer::to_bytes_with_keys(&records, &config, &KEYS).unwrap();
+            println!(
+                "{name}, copied keys:\t{} bytes in {:?}",
+                copied.len(),
+                start.elapsed()
+            );
+            assert_eq!(encoded, copied);
+        }
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn big_integers() {
+        use num_bigint::{BigInt, BigUint};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Balance {
+            #[serde(with = "crate::with::bigint")]
+            signed: BigInt,
+            #[serde(with = "crate::with::biguint")]
+            unsigned: BigUint,
+        }
+
+        let values = [
+            "0",
+            "1",
+            "-1",
+            "255",
+            "-256",
+            "18446744073709551616",
+            "-340282366920938463463374607431768211457",
+            "123456789012345678901234567890123456789012345678901234567890",
+        ];
+        for text in values {
+            let signed = text.parse::<BigInt>().unwrap();
+            let balance = Balance {
+                unsigned: signed.magnitude().clone(),
+                signed,
+            };
+            for config in [Config::default(), self_describing()] {
+                let bytes = serializer::to_bytes_with_config(&balance, &config).unwrap();
+                let decoded = deserializer::from_bytes_with_config::<Balance>(&bytes, &config);
+                assert_eq!(decoded.unwrap(), balance);
+            }
+            let json = serde_json::to_string(&balance).unwrap();
+            assert!(json.contains(&format!("\"signed\":\"{text}\"")));
+            assert_eq!(serde_json::from_str::<Balance>(&json).unwrap(), balance);
+        }
+
+        // the sign and the length and bytes of the magnitude without trailing zeros.
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Signed(#[serde(with = "crate::with::bigint")] BigInt);
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Unsigned(#[serde(with = "crate::with::biguint")] BigUint);
+        let header = serializer::to_bytes(&()).unwrap().len() - 1;
+        let bytes = serializer::to_bytes(&Signed(BigInt::from(-256))).unwrap();
+        assert_eq!(bytes[header..], [0xff, 2, 0, 0, 0, 0x00, 0x01]);
+        let bytes = serializer::to_bytes(&Signed(BigInt::from(0))).unwrap();
+        assert_eq!(bytes[header..], [0x00, 0, 0, 0, 0]);
+        // a BigUint reads the non-negative values of a BigInt but no negative one.
+        let bytes = serializer::to_bytes(&Signed(BigInt::from(300))).unwrap();
+        let decoded = deserializer::from_bytes::<Unsigned>(&bytes).unwrap();
+        assert_eq!(decoded.0, BigUint::from(300u32));
+        let bytes = serializer::to_bytes(&Signed(BigInt::from(-300))).unwrap();
+        assert!(deserializer::from_bytes::<Unsigned>(&bytes).is_err());
+
+        // every number has one encoding.
+        #[derive(Serialize)]
+        struct Raw(i8, #[serde(with = "crate::with::hex")] Vec<u8>);
+        let bytes = serializer::to_bytes(&Raw(-1, vec![1])).unwrap();
+        let decoded = deserializer::from_bytes::<Signed>(&bytes).unwrap();
+        assert_eq!(decoded.0, BigInt::from(-1));
+        for raw in [
+            Raw(2, vec![1]),
+            Raw(1, vec![1, 0]),
+            Raw(0, vec![1]),
+            Raw(1, vec![]),
+        ] {
+            let bytes = serializer::to_bytes(&raw).unwrap();
+            assert!(deserializer::from_bytes::<Signed>(&bytes).is_err());
+        }
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn decimals() {
+        use rust_decimal::Decimal;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Price {
+            #[serde(with = "crate::with::decimal")]
+            amount: Decimal,
+        }
+
+        let values = [
+            "0",
+            "1.50",
+            "-0.000001",
+            "19.99",
+            "79228162514264337593543950335",
+            "-7.9228162514264337593543950335",
+            "0.0000000000000000000000000001",
+        ];
+        for text in values {
+            let price = Price {
+                amount: text.parse().unwrap(),
+            };
+            for config in [Config::default(), self_describing()] {
+                let bytes = serializer::to_bytes_with_config(&price, &config).unwrap();
+                let decoded = deserializer::from_bytes_with_config::<Price>(&bytes, &config);
+                // the same value with the same scale.
+                let decoded = decoded.unwrap();
+                assert_eq!(decoded.amount.to_string(), text);
+            }
+            let json = serde_json::to_string(&price).unwrap();
+            assert_eq!(json, format!("{{\"amount\":\"{text}\"}}"));
+            assert_eq!(serde_json::from_str::<Price>(&json).unwrap(), price);
+        }
+
+        // 19.99 is the mantissa 1999 (0x07cf) and the scale 2, in 8 bytes.
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Amount(#[serde(with = "crate::with::decimal")] Decimal);
+        let bytes = serializer::to_bytes(&Amount("19.99".parse().unwrap())).unwrap();
+        let header = serializer::to_bytes(&()).unwrap().len() - 1;
+        assert_eq!(bytes[header..], [0x01, 2, 0, 0, 0, 0xcf, 0x07, 0x02]);
+
+        // a scale above 28 and a mantissa above 96 bits aren't decimals.
+        #[derive(Serialize)]
+        struct Raw(i8, #[serde(with = "crate::with::hex")] Vec<u8>, u8);
+        let bytes = serializer::to_bytes(&Raw(1, vec![0xcf, 0x07], 2)).unwrap();
+        let decoded = deserializer::from_bytes::<Amount>(&bytes).unwrap();
+        assert_eq!(decoded.0.to_string(), "19.99");
+        for raw in [Raw(1, vec![1], 29), Raw(1, vec![1; 13], 0)] {
+            let bytes = serializer::to_bytes(&raw).unwrap();
+            assert!(deserializer::from_bytes::<Amount>(&bytes).is_err());
+        }
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn arrow_batches() {
+        use crate::arrow;
+        use arrow_array::{
+            ArrayRef, BinaryArray, BooleanArray, Float32Array, Int64Array, LargeStringArray,
+            RecordBatch, StringArray, UInt8Array,
+        };
+        use arrow_schema::{DataType, Field, Schema};
+        use std::sync::Arc;
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("flag", DataType::Boolean, true),
+            Field::new("level", DataType::UInt8, false),
+            Field::new("score", DataType::Float32, true),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("note", DataType::LargeUtf8, true),
+            Field::new("blob", DataType::Binary, true),
+        ]));
+        let rows = 20;
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Int64Array::from_iter_values((0..rows).map(|i| i * -1000))),
+            Arc::new(BooleanArray::from_iter(
+                (0..rows).map(|i| (i % 3 != 0).then_some(i % 2 == 0)),
+            )),
+            Arc::new(UInt8Array::from_iter_values((0..rows).map(|i| i as u8))),
+            Arc::new(Float32Array::from_iter(
+                (0..rows).map(|i| (i % 4 != 0).then_some(i as f32 / 4.0)),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                (0..rows).map(|i| format!("n{i}")),
+            )),
+            Arc::new(LargeStringArray::from_iter(
+                (0..rows).map(|i| (i % 5 == 0).then(|| "é".repeat(i as usize))),
+            )),
+            Arc::new(BinaryArray::from_iter(
+                (0..rows).map(|i| (i % 2 == 0).then(|| vec![i as u8; 3])),
+            )),
+        ];
+        let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+        let configs = [
+            Config::default(),
+            Config {
+                adaptive_ints: true,
+                msb_first: true,
+                ..Config::default()
+            },
+            Config {
+                string_framing: crate::config::StringFraming::Delimited,
+                ..Config::default()
+            },
+        ];
+        for config in configs {
+            let bytes = arrow::to_bytes(&batch, &config).unwrap();
+            assert_eq!(
+                arrow::from_bytes(&bytes, schema.clone()).unwrap(),
+                batch,
+                "{config:?}"
+            );
+        }
+        let empty = batch.slice(0, 0);
+        let bytes = arrow::to_bytes(&empty, &Config::default()).unwrap();
+        assert_eq!(arrow::from_bytes(&bytes, schema.clone()).unwrap(), empty);
+
+        // a peer without Arrow reads the batch as a sequence of structs.
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Blob(#[serde(with = "crate::with::hex")] Vec<u8>);
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Row {
+            id: i64,
+            flag: Option<bool>,
+            level: u8,
+            score: Option<f32>,
+            name: String,
+            note: Option<String>,
+            blob: Option<Blob>,
+        }
+        #[derive(Deserialize)]
+        struct Rows(#[serde(with = "crate::columnar")] Vec<Row>);
+        let bytes = arrow::to_bytes(&batch, &Config::default()).unwrap();
+        let decoded = deserializer::from_bytes::<Rows>(&bytes).unwrap().0;
+        assert_eq!(decoded.len(), 20);
+        assert_eq!(
+            decoded[10],
+            Row {
+                id: -10_000,
+                flag: Some(true),
+                level: 10,
+                score: Some(2.5),
+                name: "n10".to_string(),
+                note: Some("é".repeat(10)),
+                blob: Some(Blob(vec![10; 3])),
+            }
+        );
+
+        // a schema with a column the data lacks, without a column of the data, or with another
+        // type of a column.
+        let other = |fields: Vec<Field>| Arc::new(Schema::new(fields));
+        let mut fields = schema
+            .fields()
+            .iter()
+            .map(|f| f.as_ref().clone())
+            .collect::<Vec<_>>();
+        fields.push(Field::new("extra", DataType::Int64, false));
+        assert!(arrow::from_bytes(&bytes, other(fields.clone())).is_err());
+        fields.truncate(6);
+        assert!(arrow::from_bytes(&bytes, other(fields.clone())).is_err());
+        fields[2] = Field::new("level", DataType::Utf8, false);
+        assert!(arrow::from_bytes(&bytes, other(fields)).is_err());
+
+        // other Arrow types are rejected on both sides.
+        let dates = Arc::new(Schema::new(vec![Field::new(
+            "day",
+            DataType::Date32,
+            false,
+        )]));
+        let batch = RecordBatch::try_new(
+            dates.clone(),
+            vec![Arc::new(arrow_array::Date32Array::from(vec![1]))],
+        )
+        .unwrap();
+        assert!(matches!(
+            arrow::to_bytes(&batch, &Config::default()),
+            Err(Error::SerializationError(_))
+        ));
+        assert!(arrow::from_bytes(&bytes, dates).is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn dictionary_container() {
+        use crate::container::{DictionaryReader, DictionaryWriter};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Event {
+            service: String,
+            level: String,
+            message: String,
+            id: u32,
+        }
+
+        let events = (0..3000)
+            .map(|id| Event {
+                service: format!("service-{}", id % 11),
+                level: ["debug", "info", "warning"][id as usize % 3].to_string(),
+                message: format!("request {} handled in {} ms", id * 7919 % 1000, id % 97),
+                id,
+            })
+            .collect::<Vec<_>>();
+        let write = |config: &Config, dictionary_size| {
+            let mut writer = DictionaryWriter::new(config, 3, dictionary_size);
+            for event in &events {
+                writer.push(event).unwrap();
+            }
+            writer.into_bytes().unwrap()
+        };
+        for config in [Config::default(), self_describing()] {
+            let trained = write(&config, 8 * 1024);
+            let untrained = write(&config, 0);
+            // the records share a lot that one record alone doesn't show.
+            assert!(trained.len() * 2 < untrained.len(), "{config:?}");
+            for bytes in [&trained, &untrained] {
+                let mut reader = DictionaryReader::new(bytes, &config).unwrap();
+                for event in &events {
+                    assert_eq!(reader.read::<Event>().unwrap().as_ref(), Some(event));
+                }
+                assert!(reader.read::<Event>().unwrap().is_none());
+            }
+            let reader = DictionaryReader::new(&untrained, &config).unwrap();
+            assert!(reader.dictionary().is_empty());
+            // every record is a zstd frame by itself.
+            let reader = DictionaryReader::new(&trained, &config).unwrap();
+            let records = reader.collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(
+                records[42],
+                serializer::to_bytes_with_config(&events[42], &config).unwrap()
+            );
+        }
+
+        // too few records to train on are compressed without a dictionary.
+        let config = Config::default();
+        let mut writer = DictionaryWriter::new(&config, 3, 1024);
+        writer.push(&events[0]).unwrap();
+        let bytes = writer.into_bytes().unwrap();
+        let mut reader = DictionaryReader::new(&bytes, &config).unwrap();
+        assert!(reader.dictionary().is_empty());
+        assert_eq!(reader.read::<Event>().unwrap().as_ref(), Some(&events[0]));
+        let empty = DictionaryWriter::new(&config, 3, 1024)
+            .into_bytes()
+            .unwrap();
+        assert_eq!(empty, [0, 0, 0, 0]);
+        assert_eq!(DictionaryReader::new(&empty, &config).unwrap().count(), 0);
+
+        // a cut off dictionary, a record that isn't a zstd frame and a corrupt frame.
+        let bytes = write(&config, 1024);
+        for length in [0, 3, 100] {
+            assert!(matches!(
+                DictionaryReader::new(&bytes[..length], &config),
+                Err(Error::UnexpectedEOF)
+            ));
+        }
+        let start = 4 + DictionaryReader::new(&bytes, &config)
+            .unwrap()
+            .dictionary()
+            .len();
+        let mut garbage = bytes[..start].to_vec();
+        garbage.extend([4, 0, 0, 0, 1, 2, 3, 4]);
+        let mut reader = DictionaryReader::new(&garbage, &config).unwrap();
+        assert!(matches!(
+            reader.read::<Event>(),
+            Err(Error::DeserializationError(_))
+        ));
+        let mut corrupt = bytes.clone();
+        let end = corrupt.len();
+        corrupt[end - 4..].fill(0xff);
+        let reader = DictionaryReader::new(&corrupt, &config).unwrap();
+        assert!(matches!(
+            reader.last(),
+            Some(Err(Error::Io { position, .. })) if position > start
+        ));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn pipeline() {
+        use crate::pipeline::{Cipher, Compression, Pipeline};
+        use sha2::{Digest, Sha256};
+
+        // a keystream and a tag derived from a key with sha256, which stands in for an AEAD.
+        struct Keyed(u8, [u8; 32]);
+
+        impl Keyed {
+            fn apply(&self, data: &[u8]) -> Vec<u8> {
+                let keystream = (0u64..).flat_map(|block| {
+                    Sha256::new()
+                        .chain_update(self.1)
+                        .chain_update(block.to_le_bytes())
+                        .finalize()
+                });
+                data.iter().zip(keystream).map(|(a, b)| a ^ b).collect()
+            }
+
+            fn tag(&self, header: &[u8], data: &[u8]) -> Vec<u8> {
+                let tag = Sha256::new()
+                    .chain_update(self.1)
+                    .chain_update(header)
+                    .chain_update(data)
+                    .finalize();
+                tag.to_vec()
+            }
+        }
+
+        impl Cipher for Keyed {
+            fn id(&self) -> u8 {
+                self.0
+            }
+            fn seal(&self, header: &[u8], plaintext: &[u8]) -> std::io::Result<Vec<u8>> {
+                let mut sealed = self.apply(plaintext);
+                sealed.extend(self.tag(header, &sealed));
+                Ok(sealed)
+            }
+            fn open(&self, header: &[u8], ciphertext: &[u8]) -> std::io::Result<Vec<u8>> {
+                let (data, tag) = ciphertext
+                    .split_at_checked(ciphertext.len().saturating_sub(32))
+                    .filter(|(_, tag)| tag.len() == 32)
+                    .ok_or(std::io::ErrorKind::UnexpectedEof)?;
+                if self.tag(header, data) != tag {
+                    return Err(std::io::Error::other("the tag doesn't match"));
+                }
+                Ok(self.apply(data))
+            }
+        }
+
+        let records = (0..500u32)
+            .map(|i| Human {
+                name: format!("human number {}", i % 13),
+                age: i as u8,
+            })
+            .collect::<Vec<_>>();
+        let zstd = Compression::Zstd { level: 3 };
+        for config in [Config::default(), self_describing()] {
+            let plain = Pipeline::new(Compression::None, Keyed(1, [7; 32]), &config);
+            let compressed = Pipeline::new(zstd, Keyed(1, [7; 32]), &config);
+            let small = plain.seal(&records).unwrap();
+            let sealed = compressed.seal(&records).unwrap();
+            assert_eq!(sealed[..2], [1, 1]);
+            assert!(sealed.len() * 4 < small.len(), "{config:?}");
+            // either pipeline opens what the other sealed.
+            for bytes in [&small, &sealed] {
+                assert_eq!(plain.open::<Vec<Human>>(bytes).unwrap(), records);
+                assert_eq!(compressed.open::<Vec<Human>>(bytes).unwrap(), records);
+            }
+            // the serialized records aren't visible in the sealed ones.
+            let serialized = serializer::to_bytes_with_config(&records, &config).unwrap();
+            assert_eq!(small.len(), 2 + serialized.len() + 32);
+            assert_ne!(small[2..2 + serialized.len()], serialized[..]);
+        }
+
+        // a changed byte of the value or of the header, a wrong key or cipher and a cut off
+        // value fail before anything is decompressed.
+        let config = Config::default();
+        let pipeline = Pipeline::new(zstd, Keyed(1, [7; 32]), &config);
+        let sealed = pipeline.seal(&records).unwrap();
+        for index in [0, 5, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[index] ^= 1;
+            assert!(matches!(
+                pipeline.open::<Vec<Human>>(&tampered),
+                Err(Error::Io { position: 2, .. })
+            ));
+        }
+        let other_key = Pipeline::new(zstd, Keyed(1, [8; 32]), &config);
+        assert!(matches!(
+            other_key.open::<Vec<Human>>(&sealed),
+            Err(Error::Io { position: 2, .. })
+        ));
+        let other_cipher = Pipeline::new(zstd, Keyed(2, [7; 32]), &config);
+        assert!(matches!(
+            other_cipher.open::<Vec<Human>>(&sealed),
+            Err(Error::DeserializationError(_))
+        ));
+        for length in [0, 1, 20] {
+            assert!(pipeline.open::<Vec<Human>>(&sealed[..length]).is_err());
+        }
+        // a value sealed with a compression the reader doesn't know.
+        let mut unknown = vec![9, 1];
+        unknown.extend(Keyed(1, [7; 32]).seal(&[9, 1], &[1, 2, 3]).unwrap());
+        assert!(matches!(
+            pipeline.open::<Vec<Human>>(&unknown),
+            Err(Error::DeserializationError(_))
+        ));
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn rust_fr_stream() {
+        use crate::rpc::{RustFrStream, StreamTransport, Transport};
+        use futures_core::Stream;
+        use std::{
+            io::ErrorKind,
+            pin::Pin,
+            task::{Context, Poll, Waker},
+        };
+
+        // a peer that has sent `available` bytes so far, handed out at most `chunk` at a time.
+        struct Network {
+            data: Vec<u8>,
+            position: usize,
+            available: usize,
+            chunk: usize,
+            // the errors of the next reads.
+            errors: Vec<ErrorKind>,
+        }
+
+        impl futures_io::AsyncRead for Network {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                _: &mut Context,
+                buf: &mut [u8],
+            ) -> Poll<std::io::Result<usize>> {
+                if let Some(kind) = self.errors.pop() {
+                    return Poll::Ready(Err(kind.into()));
+                }
+                let end = self.available.min(self.data.len());
+                if self.position == end && end < self.data.len() {
+                    return Poll::Pending;
+                }
+                let read = buf.len().min(self.chunk).min(end - self.position);
+                buf[..read].copy_from_slice(&self.data[self.position..self.position + read]);
+                self.position += read;
+                Poll::Ready(Ok(read))
+            }
+        }
+
+        let frames = |messages: &[Human]| {
+            let mut transport = StreamTransport::new(std::io::Cursor::new(Vec::new()));
+            for message in messages {
+                transport.send(message, &Config::default()).unwrap();
+            }
+            transport.into_inner().into_inner()
+        };
+        let network = |data: Vec<u8>, chunk| Network {
+            available: data.len(),
+            data,
+            position: 0,
+            chunk,
+            errors: Vec::new(),
+        };
+        let humans = (0..500)
+            .map(|age| Human {
+                name: "a".repeat(age as usize % 30),
+                age: age as u8,
+            })
+            .collect::<Vec<_>>();
+        let config = Config::default();
+        let mut context = Context::from_waker(Waker::noop());
+
+        // every message in order, whatever the size of the reads.
+        for chunk in [1, 7, 4096] {
+            let stream = RustFrStream::<_, Human>::new(network(frames(&humans), chunk), &config);
+            let mut stream = std::pin::pin!(stream);
+            for human in &humans {
+                let next = stream.as_mut().poll_next(&mut context);
+                assert!(matches!(next, Poll::Ready(Some(Ok(ref h))) if h == human));
+            }
+            assert!(matches!(
+                stream.as_mut().poll_next(&mut context),
+                Poll::Ready(None)
+            ));
+        }
+
+        // the stream reads ahead up to the high watermark, then only once the consumer has
+        // taken the buffer down to the low one.
+        let (low, high) = (200, 1000);
+        let mut stream = RustFrStream::<_, Human>::with_watermarks(
+            network(frames(&humans), 4096),
+            &config,
+            low,
+            high,
+        );
+        let (mut reads, mut received) = (0, 0);
+        loop {
+            let (buffered, position) = (stream.buffered(), stream.get_ref().position);
+            let next = Pin::new(&mut stream).poll_next(&mut context);
+            assert!(stream.buffered() <= high);
+            if stream.get_ref().position > position {
+                reads += 1;
+                // a frame is at most 4 + 8 + 29 + 1 bytes.
+                assert!(
+                    buffered <= low || buffered < 42,
+                    "read with {buffered} bytes"
+                );
+            }
+            match next {
+                Poll::Ready(Some(human)) => {
+                    assert_eq!(human.unwrap(), humans[received]);
+                    received += 1;
+                }
+                Poll::Ready(None) => break,
+                Poll::Pending => unreachable!(),
+            }
+        }
+        assert_eq!(received, humans.len());
+        let total = frames(&humans).len();
+        assert!(reads <= total / (high - low) + 2, "{reads} reads");
+
+        // a peer that hasn't sent everything yet leaves the stream pending.
+        let data = frames(&humans[..2]);
+        let mut peer = network(data.clone(), 4096);
+        peer.available = data.len() - 3;
+        let mut stream = RustFrStream::<_, Human>::new(peer, &config);
+        let mut next = |stream: &mut RustFrStream<_, _>| Pin::new(stream).poll_next(&mut context);
+        assert!(matches!(next(&mut stream), Poll::Ready(Some(Ok(_)))));
+        assert!(next(&mut stream).is_pending());
+        stream.get_mut().available = data.len();
+        assert!(matches!(next(&mut stream), Poll::Ready(Some(Ok(ref h))) if *h == humans[1]));
+        assert!(matches!(next(&mut stream), Poll::Ready(None)));
+
+        // a message larger than the high watermark, an interrupted read, and a message that
+        // doesn't decode, which the stream goes on after.
+        let large = Human {
+            name: "b".repeat(5000),
+            age: 1,
+        };
+        let mut data = frames(std::slice::from_ref(&large));
+        data.extend([1, 0, 0, 0, 0xff]);
+        data.extend(frames(&humans[..1]));
+        let mut peer = network(data, 100);
+        peer.errors.push(ErrorKind::Interrupted);
+        let mut stream = RustFrStream::<_, Human>::with_watermarks(peer, &config, 10, 100);
+        assert!(matches!(next(&mut stream), Poll::Ready(Some(Ok(ref h))) if *h == large));
+        assert!(matches!(next(&mut stream), Poll::Ready(Some(Err(_)))));
+        assert!(matches!(next(&mut stream), Poll::Ready(Some(Ok(ref h))) if *h == humans[0]));
+        assert!(matches!(next(&mut stream), Poll::Ready(None)));
+
+        // a frame over the limit, a cut off frame and a failing reader end the stream.
+        let data = frames(&humans[..3]);
+        let peer = network(data.clone(), 4096);
+        // the second message has a longer name than the first.
+        let max = serializer::to_bytes(&humans[0]).unwrap().len();
+        let mut stream = RustFrStream::<_, Human>::new(peer, &config).with_max_frame_size(max);
+        assert!(matches!(next(&mut stream), Poll::Ready(Some(Ok(_)))));
+        assert!(matches!(
+            next(&mut stream),
+            Poll::Ready(Some(Err(Error::RecordTooLarge { length, .. }))) if length == max + 1
+        ));
+        assert!(matches!(next(&mut stream), Poll::Ready(None)));
+        let peer = network(data[..data.len() - 1].to_vec(), 4096);
+        let mut stream = RustFrStream::<_, Human>::new(peer, &config);
+        assert!(matches!(next(&mut stream), Poll::Ready(Some(Ok(_)))));
+        assert!(matches!(next(&mut stream), Poll::Ready(Some(Ok(_)))));
+        assert!(matches!(
+            next(&mut stream),
+            Poll::Ready(Some(Err(Error::UnexpectedEOF)))
+        ));
+        assert!(matches!(next(&mut stream), Poll::Ready(None)));
+        let mut peer = network(data, 4096);
+        peer.errors.push(ErrorKind::ConnectionReset);
+        let mut stream = RustFrStream::<_, Human>::new(peer, &config);
+        assert!(matches!(
+            next(&mut stream),
+            Poll::Ready(Some(Err(Error::Io { .. })))
+        ));
+        assert!(matches!(next(&mut stream), Poll::Ready(None)));
+    }
 }
@@ -0,0 +1,76 @@
+//! ### Metrics
+//! A module that publishes what the serializer and the deserializer do through the
+//! [`metrics`](https://docs.rs/metrics) facade, so whatever recorder is installed (e.g. a
+//! Prometheus exporter) sees how much rust-fr encodes and decodes without every call site being
+//! wrapped. It is only available with the `metrics` feature; without a recorder the calls do
+//! nothing.
+//!
+//! Every serialization (or deserialization) of a value records:
+//! - [`ENCODED_BYTES`] (or [`DECODED_BYTES`]): a counter of the bytes produced (or read),
+//! - [`ENCODE_SECONDS`] (or [`DECODE_SECONDS`]): a histogram of how long it took,
+//! - [`ENCODE_ERRORS`] (or [`DECODE_ERRORS`]): a counter of the failures, labeled with the
+//!   [`ERROR_KIND`] (the name of the [`Error`] variant, e.g. `UnexpectedEOF`).
+
+use std::time::Instant;
+
+use crate::error::Error;
+
+/// The counter of the bytes produced by serializing values.
+pub const ENCODED_BYTES: &str = "rust_fr_encoded_bytes";
+/// The counter of the bytes of the data values are deserialized from.
+pub const DECODED_BYTES: &str = "rust_fr_decoded_bytes";
+/// The histogram of the time (in seconds) it takes to serialize a value.
+pub const ENCODE_SECONDS: &str = "rust_fr_encode_seconds";
+/// The histogram of the time (in seconds) it takes to deserialize a value.
+pub const DECODE_SECONDS: &str = "rust_fr_decode_seconds";
+/// The counter of the values that failed to serialize.
+pub const ENCODE_ERRORS: &str = "rust_fr_encode_errors";
+/// The counter of the values that failed to deserialize.
+pub const DECODE_ERRORS: &str = "rust_fr_decode_errors";
+/// The label of the error counters with the kind of error.
+pub const ERROR_KIND: &str = "kind";
+
+// The names of the metrics recorded for one direction.
+struct Names {
+    bytes: &'static str,
+    seconds: &'static str,
+    errors: &'static str,
+}
+
+const ENCODE: Names = Names {
+    bytes: ENCODED_BYTES,
+    seconds: ENCODE_SECONDS,
+    errors: ENCODE_ERRORS,
+};
+
+const DECODE: Names = Names {
+    bytes: DECODED_BYTES,
+    seconds: DECODE_SECONDS,
+    errors: DECODE_ERRORS,
+};
+
+/// Record the serialization of a value that started at `started` and produced `bytes`.
+pub(crate) fn encoded<T>(result: &Result<T, Error>, bytes: usize, started: Instant) {
+    record(&ENCODE, result, bytes, started);
+}
+
+/// Record the deserialization of a value that started at `started` from `bytes`.
+pub(crate) fn decoded<T>(result: &Result<T, Error>, bytes: usize, started: Instant) {
+    record(&DECODE, result, bytes, started);
+}
+
+fn record<T>(names: &Names, result: &Result<T, Error>, bytes: usize, started: Instant) {
+    ::metrics::counter!(names.bytes).increment(bytes as u64);
+    ::metrics::histogram!(names.seconds).record(started.elapsed().as_secs_f64());
+    if let Err(error) = result {
+        ::metrics::counter!(names.errors, ERROR_KIND => kind(error)).increment(1);
+    }
+}
+
+// The name of the variant of the error.
+fn kind(error: &Error) -> String {
+    format!("{error:?}")
+        .chars()
+        .take_while(char::is_ascii_alphanumeric)
+        .collect()
+}
@@ -0,0 +1,236 @@
+//! ### Overhead
+//! A module with the number of bits the format adds to the values of each construct with the
+//! default [`Config`], e.g. to plan how much fits through a link of a fixed bandwidth. The
+//! numbers come from the widths in [`format`](crate::protocol::format).
+//!
+//! - bool: 1 bit; integers, floats and chars: their size (no overhead)
+//! - string: the bytes + [`STRING_BITS`] + up to [`MAX_PADDING_BITS`] in front
+//! - bytes: the bytes + [`BYTES_BITS`] + up to [`MAX_PADDING_BITS`] in front
+//! - embedded payload ([`RawRustFr`](crate::raw::RawRustFr)): the bytes + [`RAW_BITS`] + up to
+//!   [`MAX_PADDING_BITS`] in front
+//! - option: [`OPTION_BITS`] + the value if it's `Some`
+//! - unit (and unit structs): [`UNIT_BITS`]
+//! - seq: [`SEQ_BITS`] + [`SEQ_ELEMENT_BITS`] per element
+//! - tuple (and tuple structs): no overhead
+//! - map: [`MAP_BITS`] + [`MAP_ENTRY_BITS`] per entry
+//! - struct: a map with the names of the fields as string keys
+//! - enum: [`VARIANT_INDEX_BITS`] (up to [`MAX_VARIANT_INDEX_BITS`]) + the content
+//! - the whole data: [`HEADER_BITS`] + padding up to the next byte
+//!
+//! [`estimate_size`] adds these up for a value. Types with a bounded size (no strings, bytes,
+//! seqs or maps) tell the most bits any of their values takes up at compile time through
+//! [`MaxEncodedLen`], e.g. to size static buffers on firmware; see [`max_encoded_len`].
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::overhead::{self, SEQ_BITS, SEQ_ELEMENT_BITS};
+//!
+//! // 100 coordinates: the seq + 100 elements of 2 u16 each.
+//! let coordinates = vec![(1u16, 2u16); 100];
+//! let bits = SEQ_BITS + 100 * (SEQ_ELEMENT_BITS + 32);
+//! let estimate = overhead::estimate_size(&coordinates).unwrap();
+//! assert_eq!((estimate.min_bits, estimate.max_bits), (bits, bits));
+//! assert_eq!(estimate.max_bytes(), 2 + bits.div_ceil(8));
+//! ```
+//!
+//! ### Compile-time bounds
+//! ```rust
+//! use rust_fr::overhead::{self, MaxEncodedLen};
+//!
+//! #[derive(serde::Serialize)]
+//! struct Reading {
+//!     sensor: u8,
+//!     value: Option<f32>,
+//! }
+//!
+//! impl MaxEncodedLen for Reading {
+//!     const MAX_BITS: usize = overhead::struct_max_bits(&[
+//!         ("sensor", u8::MAX_BITS),
+//!         ("value", Option::<f32>::MAX_BITS),
+//!     ]);
+//! }
+//!
+//! const CAPACITY: usize = overhead::max_encoded_len::<Reading>();
+//!
+//! let mut buffer = [0u8; CAPACITY];
+//! let reading = Reading { sensor: 3, value: Some(21.5) };
+//! let bytes = rust_fr::serializer::to_bytes(&reading).unwrap();
+//! buffer[..bytes.len()].copy_from_slice(&bytes);
+//! ```
+
+use serde::Serialize;
+
+use super::{
+    config::Config,
+    error::Error,
+    protocol::format::{Production, HEADER_WIDTH},
+    serializer,
+};
+
+/// The header in front of the data.
+pub const HEADER_BITS: usize = HEADER_WIDTH.bits();
+
+/// The length (u32) in front of the bytes of a string.
+pub const STRING_BITS: usize = u32::BITS as usize;
+
+/// The length (u32) in front of bytes.
+pub const BYTES_BITS: usize = u32::BITS as usize;
+
+/// The length (u32) in front of an embedded payload.
+pub const RAW_BITS: usize = 32;
+
+/// The most padding in front of a string or bytes, which start on a byte boundary.
+pub const MAX_PADDING_BITS: usize = 7;
+
+/// The presence bit of an option.
+pub const OPTION_BITS: usize = 1;
+
+/// A unit (or unit struct).
+pub const UNIT_BITS: usize = Production::Unit.first().bit_width().bits();
+
+/// The delimiters at the start and at the end of a sequence.
+pub const SEQ_BITS: usize =
+    Production::Seq.first().bit_width().bits() + Production::Seq.last().bit_width().bits();
+
+/// The delimiter in front of an element of a sequence.
+pub const SEQ_ELEMENT_BITS: usize = Production::Seq.item(0).bit_width().bits();
+
+/// The delimiter at the end of a map (or struct).
+pub const MAP_BITS: usize = Production::Map.last().bit_width().bits();
+
+/// The delimiters in front of the key and the value of an entry of a map (or struct).
+pub const MAP_ENTRY_BITS: usize =
+    Production::Map.item(0).bit_width().bits() + Production::Map.item(1).bit_width().bits();
+
+/// The index of an enum variant below the escape (i.e. of the first 255 variants).
+pub const VARIANT_INDEX_BITS: usize = u8::BITS as usize;
+
+/// The index of an enum variant after the escape.
+pub const MAX_VARIANT_INDEX_BITS: usize = VARIANT_INDEX_BITS + u32::BITS as usize;
+
+/// The size of a value with the default [`Config`]; see [`estimate_size`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeEstimate {
+    /// The fewest bits the value takes up inside a larger value, with no padding in front of
+    /// its strings and bytes.
+    pub min_bits: usize,
+    /// The most bits the value takes up inside a larger value, with the most padding in front
+    /// of its strings and bytes.
+    pub max_bits: usize,
+}
+
+impl SizeEstimate {
+    /// The fewest bytes of the data of the value on its own (with the header).
+    pub fn min_bytes(&self) -> usize {
+        (HEADER_BITS + self.min_bits).div_ceil(8)
+    }
+
+    /// The most bytes of the data of the value on its own (with the header).
+    pub fn max_bytes(&self) -> usize {
+        (HEADER_BITS + self.max_bits).div_ceil(8)
+    }
+}
+
+/// The fewest and the most bits `value` takes up, which depend on where it starts because of
+/// the padding in front of strings and bytes. The data of the value on its own is exactly
+/// [`to_bytes`](crate::serializer::to_bytes)`(value)?.len()` bytes, which lies between
+/// [`SizeEstimate::min_bytes`] and [`SizeEstimate::max_bytes`].
+pub fn estimate_size<T: Serialize>(value: &T) -> Result<SizeEstimate, Error> {
+    let (_, stats) = serializer::to_bytes_and_stats(value, &Config::default())?;
+    let min_bits = stats.bits - HEADER_BITS - stats.padding;
+    Ok(SizeEstimate {
+        min_bits,
+        max_bits: min_bits + stats.aligned * MAX_PADDING_BITS,
+    })
+}
+
+/// A type whose values take up at most [`MaxEncodedLen::MAX_BITS`] bits inside a larger value
+/// with the default [`Config`], whatever the values are. It is implemented for the types with a
+/// bounded size: bools, integers, floats, chars, the unit, options, arrays and tuples of them.
+/// Structs and enums made of them implement it with [`struct_max_bits`] and [`enum_max_bits`];
+/// a newtype struct takes up as many bits as the value it wraps.
+pub trait MaxEncodedLen {
+    /// The most bits a value takes up, with the most padding in front of the string keys of
+    /// structs.
+    const MAX_BITS: usize;
+}
+
+/// The most bytes of the data of a value of `T` on its own (with the header). It is a
+/// `const fn`, so it can size arrays, e.g. `[u8; max_encoded_len::<T>()]`.
+pub const fn max_encoded_len<T: MaxEncodedLen>() -> usize {
+    (HEADER_BITS + T::MAX_BITS).div_ceil(8)
+}
+
+/// The most bits of a struct whose fields have the given names and the given most bits (e.g.
+/// their [`MaxEncodedLen::MAX_BITS`]), in the order of the struct: a map with the names of the
+/// fields as string keys.
+pub const fn struct_max_bits(fields: &[(&str, usize)]) -> usize {
+    let mut bits = MAP_BITS;
+    let mut i = 0;
+    while i < fields.len() {
+        let (name, value) = fields[i];
+        bits += MAP_ENTRY_BITS + MAX_PADDING_BITS + 8 * name.len() + STRING_BITS + value;
+        i += 1;
+    }
+    bits
+}
+
+/// The most bits of an enum whose variants have the given most bits of their content (0 for
+/// unit variants, the fields as in [`struct_max_bits`] for struct variants), in the order of the
+/// enum: the index of the variant and the largest content.
+pub const fn enum_max_bits(variants: &[usize]) -> usize {
+    let mut content = 0;
+    let mut i = 0;
+    while i < variants.len() {
+        if variants[i] > content {
+            content = variants[i];
+        }
+        i += 1;
+    }
+    match variants.len() > u8::MAX as usize {
+        true => MAX_VARIANT_INDEX_BITS + content,
+        false => VARIANT_INDEX_BITS + content,
+    }
+}
+
+macro_rules! impl_max_encoded_len {
+    ($($ty:ty => $bits:expr),* $(,)?) => {
+        $(impl MaxEncodedLen for $ty {
+            const MAX_BITS: usize = $bits;
+        })*
+    };
+}
+
+impl_max_encoded_len! {
+    bool => 1,
+    u8 => 8, u16 => 16, u32 => 32, u64 => 64, u128 => 128,
+    i8 => 8, i16 => 16, i32 => 32, i64 => 64, i128 => 128,
+    f32 => 32, f64 => 64,
+    char => 32,
+    () => UNIT_BITS,
+}
+
+impl<T: MaxEncodedLen> MaxEncodedLen for Option<T> {
+    const MAX_BITS: usize = OPTION_BITS + T::MAX_BITS;
+}
+
+impl<T: MaxEncodedLen, const N: usize> MaxEncodedLen for [T; N] {
+    const MAX_BITS: usize = N * T::MAX_BITS;
+}
+
+macro_rules! impl_max_encoded_len_tuple {
+    ($($name:ident)+) => {
+        impl<$($name: MaxEncodedLen),+> MaxEncodedLen for ($($name,)+) {
+            const MAX_BITS: usize = 0 $(+ $name::MAX_BITS)+;
+        }
+    };
+}
+
+impl_max_encoded_len_tuple!(A);
+impl_max_encoded_len_tuple!(A B);
+impl_max_encoded_len_tuple!(A B C);
+impl_max_encoded_len_tuple!(A B C D);
+impl_max_encoded_len_tuple!(A B C D E);
+impl_max_encoded_len_tuple!(A B C D E F);
+impl_max_encoded_len_tuple!(A B C D E F G);
+impl_max_encoded_len_tuple!(A B C D E F G H);
@@ -0,0 +1,142 @@
+//! ### Patch
+//! A module for updating fields of serialized records in place, without serializing the
+//! whole record again, e.g. a counter or a status flag of records in a memory mapped file.
+//! The records must be serialized with [`Config::aligned_fields`], most simply with the
+//! [`Config::patchable`] profile, which starts every struct field on a byte boundary.
+//!
+//! A [`Layout`] has the offsets of the fields of a record. With the profile a fixed-width
+//! field (a bool, an integer, a float or a char) that only has fixed-width fields in front of
+//! it is at the same offset in every record of the type, so the layout of one record holds for
+//! all of them. A field can be replaced by a value of the same number of bits; anything else
+//! fails with [`Error::PatchSizeMismatch`].
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{
+//!     config::Config,
+//!     deserializer,
+//!     patch::{self, Layout},
+//!     serializer,
+//! };
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! struct Account {
+//!     id: u64,
+//!     frozen: bool,
+//!     balance: i64,
+//!     owner: String,
+//! }
+//!
+//! let config = Config::patchable();
+//! let account = Account { id: 7, frozen: false, balance: 100, owner: "ayush".to_string() };
+//! let mut bytes = serializer::to_bytes_with_config(&account, &config).unwrap();
+//!
+//! let layout = Layout::of(&account, &config).unwrap();
+//! let balance = layout.get("balance").unwrap();
+//! // the header (2 bytes), the id (8 bytes) and the bool (1 byte) come first.
+//! assert_eq!(balance.offset, 11);
+//!
+//! patch::patch(&mut bytes, balance, &250i64, &config).unwrap();
+//! let frozen = layout.get("frozen").unwrap();
+//! patch::patch(&mut bytes, frozen, &true, &config).unwrap();
+//!
+//! let account = deserializer::from_bytes_with_config::<Account>(&bytes, &config).unwrap();
+//! assert_eq!((account.frozen, account.balance), (true, 250));
+//! ```
+
+use bitvec::prelude as bv;
+use serde::Serialize;
+
+use super::{config::Config, error::Error, overhead::HEADER_BITS, serializer};
+
+/// A field of a serialized struct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    /// The name of the field.
+    pub name: &'static str,
+    /// The offset of the value in bytes from the start of the data (including the header).
+    pub offset: usize,
+    /// The number of bits of the value, which may end in the middle of a byte.
+    pub bits: usize,
+}
+
+/// The fields of the outermost struct of a record, in order. If a record has several
+/// outermost structs (e.g. it's a tuple of structs), only the first one is in the layout.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Layout {
+    fields: Vec<Field>,
+}
+
+impl Layout {
+    /// The layout of `value` serialized with the given config. The config must have
+    /// [`Config::aligned_fields`], otherwise it fails with [`Error::UnsupportedCall`].
+    pub fn of<T: Serialize>(value: &T, config: &Config) -> Result<Layout, Error> {
+        if !config.aligned_fields {
+            return Err(Error::UnsupportedCall(
+                "Layout::of without `Config::aligned_fields`".to_string(),
+            ));
+        }
+        let (_, ranges) = serializer::to_bytes_and_field_ranges(value, config)?;
+        let fields = ranges
+            .fields
+            .into_iter()
+            .map(|(name, range)| Field {
+                name,
+                offset: range.start / 8,
+                bits: range.len(),
+            })
+            .collect();
+        Ok(Layout { fields })
+    }
+
+    /// The field with the given name, if the struct has it.
+    pub fn get(&self, name: &str) -> Option<Field> {
+        self.fields.iter().find(|field| field.name == name).copied()
+    }
+
+    /// The fields in order.
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+}
+
+/// Replace the value of `field` in the serialized record `bytes` with `value`, serialized with
+/// the same config as the record. Only the bits of the field are written; the new value must
+/// have as many bits as the old one.
+pub fn patch<T: ?Sized + Serialize>(
+    bytes: &mut [u8],
+    field: Field,
+    value: &T,
+    config: &Config,
+) -> Result<(), Error> {
+    if !config.aligned_fields {
+        return Err(Error::UnsupportedCall(
+            "patch without `Config::aligned_fields`".to_string(),
+        ));
+    }
+    let (new, bits) = serializer::to_bytes_and_bit_len_with_config(&value, config)?;
+    let header = HEADER_BITS;
+    if bits - header != field.bits {
+        return Err(Error::PatchSizeMismatch {
+            field: field.name,
+            expected: field.bits,
+            found: bits - header,
+        });
+    }
+    if bytes.len() * 8 < field.offset * 8 + field.bits {
+        return Err(Error::UnexpectedEOF);
+    }
+    match config.msb_first {
+        true => copy_bits::<bv::Msb0>(bytes, field, &new),
+        false => copy_bits::<bv::Lsb0>(bytes, field, &new),
+    }
+    Ok(())
+}
+
+// Copy the bits of a value serialized on its own (after the header) over the field.
+fn copy_bits<O: bv::BitOrder>(bytes: &mut [u8], field: Field, new: &[u8]) {
+    let start = field.offset * 8;
+    let header = HEADER_BITS;
+    bv::BitSlice::<u8, O>::from_slice_mut(bytes)[start..start + field.bits]
+        .copy_from_bitslice(&bv::BitSlice::<u8, O>::from_slice(new)[header..header + field.bits]);
+}
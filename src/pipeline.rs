@@ -0,0 +1,198 @@
+//! ### Pipeline
+//! A module for values that are both compressed and encrypted, e.g. before they are stored or
+//! sent over an untrusted link. A [`Pipeline`] always compresses before it encrypts, as
+//! encrypted data doesn't compress, and it checks a sealed value before it decompresses it, so a
+//! tampered message never reaches the decompressor. The order can't be configured the other
+//! way round.
+//!
+//! The compression is one of the [`Compression`] codecs (zstd with the `zstd` feature) and the
+//! encryption is any authenticated cipher behind the [`Cipher`] trait, e.g. a wrapper of an
+//! AEAD crate. The ids of both are written in front of the message and authenticated along
+//! with it, so a reader knows how to open it and a changed id fails like changed data.
+//!
+//! - sealed: compression id (u8) + cipher id (u8) + the encrypted (compressed) value
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{
+//!     config::Config,
+//!     pipeline::{Cipher, Compression, Pipeline},
+//! };
+//!
+//! // a stand-in for an AEAD: it doesn't hide anything but checks a (weak) tag.
+//! struct Tagged;
+//!
+//! impl Tagged {
+//!     fn tag(header: &[u8], data: &[u8]) -> u8 {
+//!         header.iter().chain(data).fold(0, |tag: u8, byte| tag.rotate_left(1) ^ byte)
+//!     }
+//! }
+//!
+//! impl Cipher for Tagged {
+//!     fn id(&self) -> u8 {
+//!         1
+//!     }
+//!     fn seal(&self, header: &[u8], plaintext: &[u8]) -> std::io::Result<Vec<u8>> {
+//!         Ok([plaintext, &[Self::tag(header, plaintext)]].concat())
+//!     }
+//!     fn open(&self, header: &[u8], ciphertext: &[u8]) -> std::io::Result<Vec<u8>> {
+//!         match ciphertext.split_last() {
+//!             Some((tag, data)) if *tag == Self::tag(header, data) => Ok(data.to_vec()),
+//!             _ => Err(std::io::Error::other("the tag doesn't match")),
+//!         }
+//!     }
+//! }
+//!
+//! let pipeline = Pipeline::new(Compression::None, Tagged, &Config::default());
+//! let sealed = pipeline.seal(&vec!["hello"; 3]).unwrap();
+//! assert_eq!(pipeline.open::<Vec<String>>(&sealed).unwrap(), vec!["hello"; 3]);
+//!
+//! let mut tampered = sealed.clone();
+//! tampered[4] ^= 1;
+//! assert!(pipeline.open::<Vec<String>>(&tampered).is_err());
+//! ```
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{config::Config, deserializer, error::Error, serializer};
+
+/// Encrypts and authenticates the (compressed) values of a [`Pipeline`], e.g. a wrapper of an
+/// AEAD cipher with its key.
+pub trait Cipher {
+    /// The id of the cipher written in front of sealed values, e.g. to tell algorithms or keys
+    /// apart.
+    fn id(&self) -> u8;
+
+    /// Encrypt `plaintext` and authenticate it along with `header`, e.g. as the associated
+    /// data of an AEAD.
+    fn seal(&self, header: &[u8], plaintext: &[u8]) -> std::io::Result<Vec<u8>>;
+
+    /// Check `ciphertext` and `header` and decrypt the ciphertext. It must fail if either of
+    /// them isn't what [`Cipher::seal`] was given.
+    fn open(&self, header: &[u8], ciphertext: &[u8]) -> std::io::Result<Vec<u8>>;
+}
+
+/// How a [`Pipeline`] compresses values before it encrypts them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// The value is encrypted as it is serialized (id 0).
+    None,
+    /// The value is compressed into a zstd frame at the given level (id 1); see
+    /// `container::Zstd` for the levels.
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+}
+
+impl Compression {
+    /// The id of the compression written in front of sealed values.
+    pub fn id(&self) -> u8 {
+        match self {
+            Compression::None => 0,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => 1,
+        }
+    }
+
+    fn compress(&self, bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+        match self {
+            Compression::None => Ok(bytes),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level } => {
+                zstd::bulk::compress(&bytes, *level).map_err(|source| Error::Io {
+                    source,
+                    position: 0,
+                })
+            }
+        }
+    }
+}
+
+// Decompress the value of a sealed message compressed with the compression `id`.
+fn decompress(id: u8, bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+    match id {
+        0 => Ok(bytes),
+        #[cfg(feature = "zstd")]
+        1 => {
+            let size = match zstd::zstd_safe::get_frame_content_size(&bytes) {
+                Ok(Some(size)) if size <= u64::from(u32::MAX) => size as usize,
+                _ => {
+                    return Err(Error::DeserializationError(
+                        "the sealed value isn't a zstd frame with its size".to_string(),
+                    ))
+                }
+            };
+            zstd::bulk::decompress(&bytes, size).map_err(|source| Error::Io {
+                source,
+                position: HEADER_SIZE,
+            })
+        }
+        id => Err(Error::DeserializationError(format!(
+            "the value was compressed with the unknown compression {id}"
+        ))),
+    }
+}
+
+// The size of the ids in front of a sealed value.
+const HEADER_SIZE: usize = 2;
+
+/// Serializes, compresses and encrypts values, and the other way round (see the
+/// [module](self) docs).
+#[derive(Debug)]
+pub struct Pipeline<C: Cipher> {
+    compression: Compression,
+    cipher: C,
+    config: Config,
+}
+
+impl<C: Cipher> Pipeline<C> {
+    /// Seal values serialized with the given config, compressed with `compression` and
+    /// encrypted with `cipher`.
+    pub fn new(compression: Compression, cipher: C, config: &Config) -> Self {
+        Self {
+            compression,
+            cipher,
+            config: config.clone(),
+        }
+    }
+
+    /// Serialize `value`, compress it and encrypt it.
+    pub fn seal<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        let bytes = serializer::to_bytes_with_config(value, &self.config)?;
+        let compressed = self.compression.compress(bytes)?;
+        let header = [self.compression.id(), self.cipher.id()];
+        let encrypted = self
+            .cipher
+            .seal(&header, &compressed)
+            .map_err(|source| Error::Io {
+                source,
+                position: HEADER_SIZE,
+            })?;
+        Ok([&header[..], &encrypted].concat())
+    }
+
+    /// Check and decrypt a sealed value, then decompress and deserialize it. A value that
+    /// doesn't pass the checks of the cipher fails with the [`Error::Io`] of the cipher
+    /// before anything is decompressed. Values compressed with any known compression are
+    /// opened, whatever the compression of the pipeline is.
+    pub fn open<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        let Some((header, encrypted)) = bytes.split_first_chunk::<HEADER_SIZE>() else {
+            return Err(Error::UnexpectedEOF);
+        };
+        let [compression, cipher] = *header;
+        if cipher != self.cipher.id() {
+            return Err(Error::DeserializationError(format!(
+                "the value was sealed with the cipher {cipher} instead of {}",
+                self.cipher.id()
+            )));
+        }
+        let compressed = self
+            .cipher
+            .open(header, encrypted)
+            .map_err(|source| Error::Io {
+                source,
+                position: HEADER_SIZE,
+            })?;
+        let bytes = decompress(compression, compressed)?;
+        deserializer::from_bytes_with_config(&bytes, &self.config)
+    }
+}
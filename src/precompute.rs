@@ -0,0 +1,156 @@
+//! ### Precompute
+//! A module that encodes constant strings (e.g. the field names of a struct) at compile time,
+//! so a serializer writing the same keys for every record of a hot loop copies their bytes
+//! instead of encoding them again; see [`precompute!`](crate::precompute!) and
+//! [`to_bytes_with_keys`](crate::serializer::to_bytes_with_keys).
+//!
+//! An [`EncodedStr`] holds the encodings of a string for every
+//! [`Config::string_framing`](crate::config::Config::string_framing):
+//! - [`EncodedStr::bytes`], as the delimited and the escaped framing write it after its
+//!   padding: the text with the bytes of the String delimiter and of [`STRING_ESCAPE`]
+//!   escaped, and the String delimiter. Without those bytes in the text (e.g. every ASCII field
+//!   name) they are the same in both.
+//! - [`EncodedStr::prefixed`], as the default framing writes it: the length (u32, little
+//!   endian) and the text. The padding between them is only known when the string is written,
+//!   so the whole encoding is copied at once where the length starts on a byte boundary.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{config::Config, precompute, precompute::EncodedStr, serializer};
+//!
+//! #[derive(serde::Serialize)]
+//! struct Point {
+//!     x: i32,
+//!     y: i32,
+//! }
+//!
+//! const KEYS: [EncodedStr; 2] = [precompute!("x"), precompute!("y")];
+//! assert_eq!(KEYS[0].bytes(), b"x\x86");
+//!
+//! let config = Config::default();
+//! let point = Point { x: 1, y: -1 };
+//! let bytes = serializer::to_bytes_with_keys(&point, &config, &KEYS).unwrap();
+//! assert_eq!(bytes, serializer::to_bytes_with_config(&point, &config).unwrap());
+//! ```
+
+use crate::protocol::format::{Production, STRING_ESCAPE};
+
+/// A constant string along with its encoding; made with [`precompute!`](crate::precompute!).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodedStr {
+    text: &'static str,
+    bytes: &'static [u8],
+    prefixed: &'static [u8],
+}
+
+impl EncodedStr {
+    /// A string and its encodings, which must be [`encode`] and [`encode_prefixed`] of the
+    /// string.
+    pub const fn new(text: &'static str, bytes: &'static [u8], prefixed: &'static [u8]) -> Self {
+        Self {
+            text,
+            bytes,
+            prefixed,
+        }
+    }
+
+    /// The string.
+    pub const fn text(&self) -> &'static str {
+        self.text
+    }
+
+    /// The encoding of the string: its escaped bytes and the String delimiter.
+    pub const fn bytes(&self) -> &'static [u8] {
+        self.bytes
+    }
+
+    /// The encoding of the string with its length: the length (u32, little endian) and the
+    /// bytes of the string.
+    pub const fn prefixed(&self) -> &'static [u8] {
+        self.prefixed
+    }
+}
+
+/// The number of bytes of the encoding of `text`.
+pub const fn encoded_len(text: &str) -> usize {
+    let end = Production::Str.last().value();
+    let bytes = text.as_bytes();
+    let mut len = bytes.len() + 1;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == end || bytes[i] == STRING_ESCAPE {
+            len += 1;
+        }
+        i += 1;
+    }
+    len
+}
+
+/// The encoding of `text`: its bytes with the bytes of the String delimiter and of
+/// [`STRING_ESCAPE`] preceded by [`STRING_ESCAPE`], and the String delimiter. `N` must be
+/// [`encoded_len`] of the text; in a constant a wrong `N` fails to compile.
+pub const fn encode<const N: usize>(text: &str) -> [u8; N] {
+    assert!(
+        N == encoded_len(text),
+        "N isn't the encoded length of the text"
+    );
+    let end = Production::Str.last().value();
+    let bytes = text.as_bytes();
+    let mut encoded = [0; N];
+    let (mut i, mut j) = (0, 0);
+    while i < bytes.len() {
+        if bytes[i] == end || bytes[i] == STRING_ESCAPE {
+            encoded[j] = STRING_ESCAPE;
+            j += 1;
+        }
+        encoded[j] = bytes[i];
+        i += 1;
+        j += 1;
+    }
+    encoded[j] = end;
+    encoded
+}
+
+/// The encoding of `text` with its length: the length (u32, little endian) and the bytes of the
+/// text. `N` must be 4 more than the length of the text; in a constant a wrong `N` (or a text
+/// longer than `u32::MAX` bytes) fails to compile.
+pub const fn encode_prefixed<const N: usize>(text: &str) -> [u8; N] {
+    assert!(N == text.len() + 4, "N isn't the length of the text and 4");
+    assert!(
+        text.len() <= u32::MAX as usize,
+        "the text is longer than u32::MAX bytes"
+    );
+    let length = (text.len() as u32).to_le_bytes();
+    let bytes = text.as_bytes();
+    let mut encoded = [0; N];
+    let mut i = 0;
+    while i < 4 {
+        encoded[i] = length[i];
+        i += 1;
+    }
+    while i < N {
+        encoded[i] = bytes[i - 4];
+        i += 1;
+    }
+    encoded
+}
+
+/// Encode a constant string at compile time into an [`EncodedStr`].
+///
+/// ```rust
+/// use rust_fr::{precompute, precompute::EncodedStr};
+///
+/// const NAME: EncodedStr = precompute!("name");
+/// assert_eq!(NAME.text(), "name");
+/// assert_eq!(NAME.bytes(), b"name\x86");
+/// assert_eq!(NAME.prefixed(), b"\x04\0\0\0name");
+/// ```
+#[macro_export]
+macro_rules! precompute {
+    ($text:expr) => {{
+        const TEXT: &str = $text;
+        const BYTES: [u8; $crate::precompute::encoded_len(TEXT)] = $crate::precompute::encode(TEXT);
+        const PREFIXED: [u8; TEXT.len() + 4] = $crate::precompute::encode_prefixed(TEXT);
+        $crate::precompute::EncodedStr::new(TEXT, &BYTES, &PREFIXED)
+    }};
+}
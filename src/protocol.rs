@@ -0,0 +1,41 @@
+//! ### Protocol
+//! A module with the definitions of the wire protocol that don't belong to either the
+//! serializer or the deserializer; see [`format`].
+//!
+//! [`hash_value`] hashes a value as if it was serialized, without keeping the serialized data,
+//! e.g. to fingerprint values for the keys of a cache.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{protocol, serializer};
+//! use std::{collections::hash_map::DefaultHasher, hash::Hasher};
+//!
+//! let value = vec![("key", 42u32); 1000];
+//! let mut hasher = DefaultHasher::new();
+//! protocol::hash_value(&value, &mut hasher).unwrap();
+//!
+//! let mut expected = DefaultHasher::new();
+//! expected.write(&serializer::to_bytes(&value).unwrap());
+//! assert_eq!(hasher.finish(), expected.finish());
+//! ```
+
+use std::hash::Hasher;
+
+use serde::Serialize;
+
+use super::{config::Config, error::Error, serializer};
+
+pub mod format;
+
+/// Feed the bytes `value` serializes to (with [`to_bytes`](crate::serializer::to_bytes)) into
+/// `hasher` while it is serialized. Only a few KiB of the data are held at a time.
+///
+/// The bytes are written to the hasher in pieces, so the hash is the same as that of the
+/// serialized bytes for hashers that hash a stream of bytes regardless of how it's split
+/// (e.g. `DefaultHasher`) but not necessarily for others.
+pub fn hash_value<T: Serialize, H: Hasher>(value: &T, hasher: &mut H) -> Result<(), Error> {
+    serializer::to_sink(value, &Config::default(), |bytes| {
+        hasher.write(bytes);
+        Ok(())
+    })
+}
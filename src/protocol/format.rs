@@ -0,0 +1,640 @@
+//! ### Format
+//! The definitions of the wire format shared by the serializer and the deserializer: the
+//! version, the delimiters, the type tags and the widths of everything that isn't a whole
+//! number of bytes. Both sides write and read tokens through [`Token`] so the bit patterns
+//! are spelled out once, and take the tokens that frame strings, sequences and maps from the
+//! rules of the [`GRAMMAR`] so the layout is too.
+
+use bitvec::{field::BitField, prelude as bv};
+
+use crate::config::Config;
+
+/// The version of the format. It is bumped whenever data written by one version can no
+/// longer be read by another.
+pub const FORMAT_VERSION: u32 = 7;
+
+/// The oldest format version whose data this version still reads.
+pub const MIN_COMPATIBLE_VERSION: u32 = 5;
+
+/// Whether data written with the format `version` can be read by this version.
+pub const fn is_compatible(version: u32) -> bool {
+    MIN_COMPATIBLE_VERSION <= version && version <= FORMAT_VERSION
+}
+
+/// The [`fingerprint`] of every format version since they were recorded, oldest first; the
+/// last one is the fingerprint of [`FORMAT_VERSION`]. Changing a constant that shapes the data
+/// without bumping the version (and recording the new fingerprint) fails to compile.
+pub const FINGERPRINTS: [(u32, u64); 3] = [
+    (5, 0x8959_1da1_bb5b_420a),
+    (6, 0xe58e_3ee3_7202_83e9),
+    (7, 0x3da6_8ec8_0278_75f8),
+];
+
+/// A hash (64 bit FNV-1a) of the constants that shape the data: the widths, the delimiters,
+/// the type tags, the markers of streams, the escapes of variant indices and strings and the
+/// options of the header in the order of their bits.
+pub const fn fingerprint() -> u64 {
+    let mut hash = Fnv::new()
+        .usize(HEADER_WIDTH.bits())
+        .usize(DELIMITER_WIDTH.bits())
+        .usize(WIDE_DELIMITER_WIDTH.bits())
+        .usize(TYPE_TAG_WIDTH.bits())
+        .usize(ADAPTIVE_WIDTH.bits())
+        .bytes(&STREAM_START)
+        .bytes(&STREAM_END.to_le_bytes())
+        .bytes(&[VARIANT_INDEX_ESCAPE])
+        .bytes(&[STRING_ESCAPE]);
+    let mut i = 0;
+    while i < Delimiter::ALL.len() {
+        let delimiter = &Delimiter::ALL[i];
+        hash = hash
+            .bytes(&[delimiter.value()])
+            .usize(delimiter.bit_width().bits());
+        i += 1;
+    }
+    let mut i = 0;
+    while i < TypeTag::ALL.len() {
+        hash = hash.bytes(&[TypeTag::ALL[i].token().value()]);
+        i += 1;
+    }
+    let mut i = 0;
+    while i < Config::OPTIONS.len() {
+        hash = hash.bytes(Config::OPTIONS[i].as_bytes()).bytes(&[0]);
+        i += 1;
+    }
+    hash.0
+}
+
+const _: () = {
+    let (version, fingerprint) = FINGERPRINTS[FINGERPRINTS.len() - 1];
+    assert!(
+        version == FORMAT_VERSION,
+        "the last fingerprint isn't the one of FORMAT_VERSION"
+    );
+    assert!(
+        fingerprint == self::fingerprint(),
+        "a constant of the format changed: bump FORMAT_VERSION and record its fingerprint"
+    );
+};
+
+// The state of a 64 bit FNV-1a hash.
+struct Fnv(u64);
+
+impl Fnv {
+    const fn new() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    const fn bytes(mut self, bytes: &[u8]) -> Self {
+        let mut i = 0;
+        while i < bytes.len() {
+            self.0 = (self.0 ^ bytes[i] as u64).wrapping_mul(0x0100_0000_01b3);
+            i += 1;
+        }
+        self
+    }
+
+    const fn usize(self, value: usize) -> Self {
+        self.bytes(&(value as u64).to_le_bytes())
+    }
+}
+
+/// A number of bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BitWidth(usize);
+
+impl BitWidth {
+    /// A width of `bits` bits.
+    pub const fn new(bits: usize) -> Self {
+        Self(bits)
+    }
+
+    /// The number of bits.
+    pub const fn bits(self) -> usize {
+        self.0
+    }
+}
+
+/// The width of the header in front of the data: the options the data was serialized with
+/// (see [`Config::header`](crate::config::Config::header)).
+pub const HEADER_WIDTH: BitWidth = BitWidth(16);
+
+/// The width of the 3 bit delimiters.
+pub const DELIMITER_WIDTH: BitWidth = BitWidth(3);
+
+/// The width of the 1 byte delimiters (`String`, `Byte` & `Map`).
+pub const WIDE_DELIMITER_WIDTH: BitWidth = BitWidth(8);
+
+/// The width of a type tag in the self-describing mode.
+pub const TYPE_TAG_WIDTH: BitWidth = BitWidth(5);
+
+/// The width of the tag that tells how many bytes (1, 2, 4 or 8) an integer takes in the
+/// adaptive integer mode (see [`Config::adaptive_ints`](crate::config::Config::adaptive_ints)).
+pub const ADAPTIVE_WIDTH: BitWidth = BitWidth(2);
+
+/// [`ADAPTIVE_WIDTH`] in bits.
+pub const ADAPTIVE_WIDTH_BITS: usize = ADAPTIVE_WIDTH.bits();
+
+/// The bytes a stream of records starts with (see
+/// [`StreamWriter`](crate::container::StreamWriter)).
+pub const STREAM_START: [u8; 4] = *b"rfr\x01";
+
+/// The length (u32) that takes the place of the next record at the end of a stream. A
+/// record can't be that long.
+pub const STREAM_END: u32 = u32::MAX;
+
+/// An enum variant index below this byte is written as that single byte; any other index is
+/// this byte followed by the index as a u32.
+pub const VARIANT_INDEX_ESCAPE: u8 = 0xff;
+
+/// The byte put in front of a byte of a string (or bytes) that is the byte of the delimiter
+/// ending it or this byte itself when strings are escaped (see
+/// [`StringFraming::Escaped`](crate::config::StringFraming::Escaped)). It never occurs in
+/// UTF-8, so strings only escape the byte of their delimiter.
+pub const STRING_ESCAPE: u8 = 0xff;
+
+/// A value written in a fixed number of bits, least significant bit first (most significant
+/// first with [`Config::msb_first`](crate::config::Config::msb_first)): a delimiter or a type
+/// tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Token {
+    value: u8,
+    width: BitWidth,
+}
+
+impl Token {
+    /// A token of the lowest `width` bits of `value`.
+    pub const fn new(value: u8, width: BitWidth) -> Self {
+        Self { value, width }
+    }
+
+    /// The value of the token.
+    pub const fn value(self) -> u8 {
+        self.value
+    }
+
+    /// The width of the token.
+    pub const fn width(self) -> BitWidth {
+        self.width
+    }
+
+    /// The bits of the token, least significant first. This is the order they are written in
+    /// unless [`Config::msb_first`](crate::config::Config::msb_first) is set.
+    pub fn bits(self) -> impl DoubleEndedIterator<Item = bool> {
+        (0..self.width.bits()).map(move |i| self.value & (1 << i) != 0)
+    }
+
+    /// Whether the bits start with the token.
+    pub fn is_prefix_of(self, bits: &bv::BitSlice<u8, bv::Lsb0>) -> bool {
+        bits.len() >= self.width.bits()
+            && self.bits().zip(bits.iter().by_vals()).all(|(a, b)| a == b)
+    }
+}
+
+/// The order the bits of the data are packed into bytes in: `Lsb0` by default or `Msb0` with
+/// [`Config::msb_first`](crate::config::Config::msb_first). Fields that aren't whole bytes
+/// (delimiters, type tags, the width tags of adaptive integers) are written in the same order
+/// as the bits of a byte: least significant bit first with `Lsb0` and most significant bit
+/// first with `Msb0`. Whole bytes keep their values and integers stay little endian.
+pub(crate) trait WireOrder: bv::BitOrder + Sized {
+    /// Whether the bits are packed from the most significant bit of a byte down.
+    const MSB_FIRST: bool;
+
+    /// The value of a field of at most 64 bits: one of at most 8 bits (e.g. a token) or a
+    /// little endian integer of whole bytes.
+    fn load(bits: &bv::BitSlice<u8, Self>) -> u64;
+}
+
+impl WireOrder for bv::Lsb0 {
+    const MSB_FIRST: bool = false;
+
+    fn load(bits: &bv::BitSlice<u8, Self>) -> u64 {
+        bits.load_le()
+    }
+}
+
+impl WireOrder for bv::Msb0 {
+    const MSB_FIRST: bool = true;
+
+    fn load(bits: &bv::BitSlice<u8, Self>) -> u64 {
+        bits.chunks(8).rfold(0, |value, byte| {
+            value << 8 | u64::from(byte.load_be::<u8>())
+        })
+    }
+}
+
+/// The delimiter used in the format specification. The purpose
+/// of delimiters is to separate different types of data such
+/// that they don't mangle. There are 10 different delimiters
+/// in the format specification out of which 3 (`String`, `Byte` & `Map`)
+/// are 1 byte long and 7 (the rest...) are 3 bits long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Delimiter {
+    // 0b10000110
+    String = 134,
+    // 0b10000111
+    Byte = 135,
+    // 0b010
+    Unit = 2,
+    // 0b011
+    Seq = 3,
+    // 0b100
+    SeqValue = 4,
+    // 0b10001011
+    Map = 139,
+    // 0b110
+    MapKey = 6,
+    // 0b111
+    MapValue = 7,
+    // 0b101
+    Run = 5,
+    // 0b001
+    Packed = 1,
+}
+
+impl Delimiter {
+    /// All the delimiters.
+    pub const ALL: [Delimiter; 10] = [
+        Delimiter::String,
+        Delimiter::Byte,
+        Delimiter::Unit,
+        Delimiter::Seq,
+        Delimiter::SeqValue,
+        Delimiter::Map,
+        Delimiter::MapKey,
+        Delimiter::MapValue,
+        Delimiter::Run,
+        Delimiter::Packed,
+    ];
+
+    /// The width of the delimiter in bits.
+    pub fn width(&self) -> usize {
+        self.bit_width().bits()
+    }
+
+    /// The width of the delimiter.
+    pub const fn bit_width(&self) -> BitWidth {
+        match self {
+            Delimiter::String | Delimiter::Byte | Delimiter::Map => WIDE_DELIMITER_WIDTH,
+            _ => DELIMITER_WIDTH,
+        }
+    }
+
+    /// The value of the delimiter.
+    pub const fn value(&self) -> u8 {
+        match self {
+            Delimiter::String => Delimiter::String as u8,
+            Delimiter::Byte => Delimiter::Byte as u8,
+            Delimiter::Unit => Delimiter::Unit as u8,
+            Delimiter::Seq => Delimiter::Seq as u8,
+            Delimiter::SeqValue => Delimiter::SeqValue as u8,
+            Delimiter::Map => Delimiter::Map as u8,
+            Delimiter::MapKey => Delimiter::MapKey as u8,
+            Delimiter::MapValue => Delimiter::MapValue as u8,
+            Delimiter::Run => Delimiter::Run as u8,
+            Delimiter::Packed => Delimiter::Packed as u8,
+        }
+    }
+
+    /// The token written for the delimiter.
+    pub const fn token(&self) -> Token {
+        Token::new(self.value(), self.bit_width())
+    }
+}
+
+impl std::fmt::Display for Delimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Delimiter::String => write!(f, "String"),
+            Delimiter::Byte => write!(f, "Byte"),
+            Delimiter::Unit => write!(f, "Unit"),
+            Delimiter::Seq => write!(f, "Seq"),
+            Delimiter::SeqValue => write!(f, "SeqValue"),
+            Delimiter::Map => write!(f, "Map"),
+            Delimiter::MapKey => write!(f, "MapKey"),
+            Delimiter::MapValue => write!(f, "MapValue"),
+            Delimiter::Run => write!(f, "Run"),
+            Delimiter::Packed => write!(f, "Packed"),
+        }
+    }
+}
+
+/// The constructs of the format whose layout is a rule of the [`GRAMMAR`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Production {
+    /// A string.
+    Str,
+    /// Bytes.
+    Bytes,
+    /// A unit (or a unit struct).
+    Unit,
+    /// A sequence (and a tuple in the self-describing mode).
+    Seq,
+    /// A map (and a struct with its field names).
+    Map,
+    /// A run of equal elements in a sequence (see
+    /// [`Config::run_length_encoding`](crate::config::Config::run_length_encoding)).
+    Run,
+    /// A sequence of `u8` written like bytes (see
+    /// [`Config::pack_u8_seqs`](crate::config::Config::pack_u8_seqs)).
+    Packed,
+}
+
+/// A part of a rule of the [`GRAMMAR`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    /// The token of a delimiter.
+    Token(Delimiter),
+    /// Zero bits up to the next byte boundary.
+    Padding,
+    /// A byte of a string or of bytes.
+    Byte,
+    /// A length or a count (u32).
+    Length,
+    /// A value, e.g. an element of a sequence or the key of a map entry.
+    Value,
+    /// The symbols of an item that is repeated zero or more times.
+    Repeat(&'static [Symbol]),
+}
+
+/// The layout of a [`Production`]: its symbols in the order they are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rule {
+    /// The production the rule lays out.
+    pub production: Production,
+    /// The symbols of the production.
+    pub symbols: &'static [Symbol],
+}
+
+/// The rules of the format in the order of the [`Production`]s. The serializer writes and
+/// the deserializer expects the tokens of the rules (see [`Production::first`],
+/// [`Production::item`] and [`Production::last`]) rather than delimiters of their own. The
+/// rules of strings and bytes are their delimited framing (see
+/// [`StringFraming`](crate::config::StringFraming)); the other framings take the same tokens.
+pub const GRAMMAR: [Rule; 7] = {
+    use Delimiter as D;
+    use Symbol::*;
+    [
+        Rule {
+            production: Production::Str,
+            symbols: &[Padding, Repeat(&[Byte]), Token(D::String)],
+        },
+        Rule {
+            production: Production::Bytes,
+            symbols: &[Padding, Repeat(&[Byte]), Token(D::Byte)],
+        },
+        Rule {
+            production: Production::Unit,
+            symbols: &[Token(D::Unit)],
+        },
+        Rule {
+            production: Production::Seq,
+            symbols: &[
+                Token(D::Seq),
+                Repeat(&[Token(D::SeqValue), Value]),
+                Token(D::Seq),
+            ],
+        },
+        Rule {
+            production: Production::Map,
+            symbols: &[
+                Repeat(&[Token(D::MapKey), Value, Token(D::MapValue), Value]),
+                Token(D::Map),
+            ],
+        },
+        Rule {
+            production: Production::Run,
+            symbols: &[Token(D::Run), Length, Value],
+        },
+        Rule {
+            production: Production::Packed,
+            symbols: &[Token(D::Packed), Length, Padding, Repeat(&[Byte])],
+        },
+    ]
+};
+
+const _: () = {
+    let mut i = 0;
+    while i < GRAMMAR.len() {
+        assert!(
+            GRAMMAR[i].production as usize == i,
+            "the rules of the grammar aren't in the order of their productions"
+        );
+        i += 1;
+    }
+};
+
+impl Production {
+    /// The rule of the production.
+    pub const fn rule(self) -> &'static Rule {
+        &GRAMMAR[self as usize]
+    }
+
+    /// The token the production starts with, e.g. the opening token of a sequence.
+    ///
+    /// # Panics
+    /// If the production doesn't start with a token.
+    pub const fn first(self) -> Delimiter {
+        match self.rule().symbols.first() {
+            Some(Symbol::Token(delimiter)) => *delimiter,
+            _ => panic!("the production doesn't start with a token"),
+        }
+    }
+
+    /// The token the production ends with, e.g. the closing token of a sequence.
+    ///
+    /// # Panics
+    /// If the production doesn't end with a token.
+    pub const fn last(self) -> Delimiter {
+        match self.rule().symbols.last() {
+            Some(Symbol::Token(delimiter)) => *delimiter,
+            _ => panic!("the production doesn't end with a token"),
+        }
+    }
+
+    /// The `n`th token of the repeated item of the production, e.g. the token in front of the
+    /// value of a map entry for `Production::Map.item(1)`.
+    ///
+    /// # Panics
+    /// If the item doesn't have that many tokens.
+    pub const fn item(self, n: usize) -> Delimiter {
+        let symbols = self.rule().symbols;
+        let mut i = 0;
+        while i < symbols.len() {
+            if let Symbol::Repeat(item) = symbols[i] {
+                let (mut j, mut tokens) = (0, 0);
+                while j < item.len() {
+                    if let Symbol::Token(delimiter) = item[j] {
+                        if tokens == n {
+                            return delimiter;
+                        }
+                        tokens += 1;
+                    }
+                    j += 1;
+                }
+            }
+            i += 1;
+        }
+        panic!("the item of the production doesn't have that many tokens")
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    /// The symbol as it is written in the specification, e.g. `SEQ_VALUE_DELIMITER` or
+    /// `(MAP_KEY_DELIMITER + value)*`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Symbol::Token(delimiter) => {
+                let mut name = String::new();
+                for (i, c) in delimiter.to_string().char_indices() {
+                    if c.is_uppercase() && i > 0 {
+                        name.push('_');
+                    }
+                    name.push(c.to_ascii_uppercase());
+                }
+                write!(f, "{name}_DELIMITER")
+            }
+            Symbol::Padding => write!(f, "padding"),
+            Symbol::Byte => write!(f, "byte"),
+            Symbol::Length => write!(f, "length (u32)"),
+            Symbol::Value => write!(f, "value"),
+            Symbol::Repeat(item) => write!(f, "({})*", join_symbols(item)),
+        }
+    }
+}
+
+impl std::fmt::Display for Rule {
+    /// The rule as it is written in the specification, e.g.
+    /// `seq: SEQ_DELIMITER + (SEQ_VALUE_DELIMITER + value)* + SEQ_DELIMITER`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = format!("{:?}", self.production).to_lowercase();
+        write!(f, "{name}: {}", join_symbols(self.symbols))
+    }
+}
+
+// Join symbols like "A + B + C".
+fn join_symbols(symbols: &[Symbol]) -> String {
+    symbols
+        .iter()
+        .map(Symbol::to_string)
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+/// The type tag that prefixes every value in the self-describing mode
+/// (see [`Config::self_describing`](crate::config::Config::self_describing)). Tags are 5 bits
+/// long. Tags whose first 3 bits equal the `Seq` delimiter (0b011) are never used so a tag
+/// can't be mistaken for the end of a sequence (or, for that matter, of a map).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TypeTag {
+    Unit = 0,
+    Bool = 1,
+    I8 = 2,
+    I16 = 4,
+    I32 = 5,
+    I64 = 6,
+    U8 = 7,
+    U16 = 8,
+    U32 = 9,
+    U64 = 10,
+    F32 = 12,
+    F64 = 13,
+    Char = 14,
+    Str = 15,
+    Bytes = 16,
+    None = 17,
+    Some = 18,
+    Seq = 20,
+    Map = 21,
+    UnitVariant = 22,
+    NewtypeVariant = 23,
+    TupleVariant = 24,
+    StructVariant = 25,
+    I128 = 26,
+    U128 = 28,
+    /// A payload embedded with [`RawRustFr`](crate::raw::RawRustFr).
+    Raw = 29,
+}
+
+impl TypeTag {
+    /// The width of a type tag in bits.
+    pub const WIDTH: usize = TYPE_TAG_WIDTH.bits();
+
+    /// All the type tags.
+    pub const ALL: [TypeTag; 26] = [
+        TypeTag::Unit,
+        TypeTag::Bool,
+        TypeTag::I8,
+        TypeTag::I16,
+        TypeTag::I32,
+        TypeTag::I64,
+        TypeTag::U8,
+        TypeTag::U16,
+        TypeTag::U32,
+        TypeTag::U64,
+        TypeTag::F32,
+        TypeTag::F64,
+        TypeTag::Char,
+        TypeTag::Str,
+        TypeTag::Bytes,
+        TypeTag::None,
+        TypeTag::Some,
+        TypeTag::Seq,
+        TypeTag::Map,
+        TypeTag::UnitVariant,
+        TypeTag::NewtypeVariant,
+        TypeTag::TupleVariant,
+        TypeTag::StructVariant,
+        TypeTag::I128,
+        TypeTag::U128,
+        TypeTag::Raw,
+    ];
+
+    /// The name of the type tag.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TypeTag::Unit => "Unit",
+            TypeTag::Bool => "Bool",
+            TypeTag::I8 => "I8",
+            TypeTag::I16 => "I16",
+            TypeTag::I32 => "I32",
+            TypeTag::I64 => "I64",
+            TypeTag::U8 => "U8",
+            TypeTag::U16 => "U16",
+            TypeTag::U32 => "U32",
+            TypeTag::U64 => "U64",
+            TypeTag::F32 => "F32",
+            TypeTag::F64 => "F64",
+            TypeTag::Char => "Char",
+            TypeTag::Str => "Str",
+            TypeTag::Bytes => "Bytes",
+            TypeTag::None => "None",
+            TypeTag::Some => "Some",
+            TypeTag::Seq => "Seq",
+            TypeTag::Map => "Map",
+            TypeTag::UnitVariant => "UnitVariant",
+            TypeTag::NewtypeVariant => "NewtypeVariant",
+            TypeTag::TupleVariant => "TupleVariant",
+            TypeTag::StructVariant => "StructVariant",
+            TypeTag::I128 => "I128",
+            TypeTag::U128 => "U128",
+            TypeTag::Raw => "Raw",
+        }
+    }
+
+    /// The token written for the type tag.
+    pub const fn token(self) -> Token {
+        Token::new(self as u8, TYPE_TAG_WIDTH)
+    }
+
+    /// Get the type tag with the given value.
+    pub fn from_u8(value: u8) -> Option<TypeTag> {
+        TypeTag::ALL.into_iter().find(|tag| *tag as u8 == value)
+    }
+}
+
+impl std::fmt::Display for TypeTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
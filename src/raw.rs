@@ -0,0 +1,147 @@
+//! ### Raw
+//! A module for embedding a payload that is already serialized with rust-fr in another value,
+//! e.g. the body of an envelope that a gateway routes by its headers alone. A [`RawRustFr`]
+//! is written as the bytes of the payload, which are neither decoded nor encoded again, and is
+//! read back the same way; the payload is only decoded when [`RawRustFr::decode`] is called.
+//!
+//! The payload keeps its own header, so it's decoded with the config it was serialized with
+//! whatever the config of the outer value is.
+//!
+//! - raw: length (u32) + padding + bytes (the payload with its header)
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{config::Config, document::Document, raw::RawRustFr};
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! struct Order {
+//!     id: u64,
+//!     items: Vec<String>,
+//! }
+//!
+//! // the producer serializes the body with its own config.
+//! let inner = Config {
+//!     adaptive_ints: true,
+//!     ..Config::default()
+//! };
+//! let order = Order { id: 7, items: vec!["tea".to_string()] };
+//! let body = RawRustFr::from_value(&order, &inner).unwrap();
+//! let config = Config::default();
+//! let bytes = Document::new("shop/order", 1, body).seal(&config).unwrap();
+//!
+//! // the gateway routes it by its content type without decoding the body.
+//! let routed = Document::<RawRustFr>::open(&bytes, &config).unwrap();
+//! assert_eq!(routed.content_type, "shop/order");
+//! let forwarded = routed.payload.as_bytes().to_vec();
+//!
+//! // the consumer decodes the body.
+//! let body = RawRustFr::from_bytes(forwarded).unwrap();
+//! assert_eq!(body.decode::<Order>().unwrap(), order);
+//! ```
+
+use std::fmt;
+
+use serde::{
+    de::{Error as _, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::{config::Config, deserializer, error::Error, serializer};
+
+/// The name of the newtype struct a [`RawRustFr`] serializes as, which the serializer and the
+/// deserializer of rust-fr recognize. Other formats see a newtype struct around bytes.
+pub(crate) const NAME: &str = "$rust_fr::private::RawRustFr";
+
+/// A payload serialized with rust-fr, along with its header.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RawRustFr(Vec<u8>);
+
+impl RawRustFr {
+    /// Serialize `value` with the given config into a payload.
+    pub fn from_value<T: Serialize>(value: &T, config: &Config) -> Result<Self, Error> {
+        serializer::to_bytes_with_config(value, config).map(Self)
+    }
+
+    /// A payload from bytes serialized with rust-fr. Only the header is checked; the rest is
+    /// checked when the payload is decoded.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        header(&bytes)?;
+        Ok(Self(bytes))
+    }
+
+    /// Decode the payload according to the config in its header.
+    pub fn decode<'de, T: Deserialize<'de>>(&'de self) -> Result<T, Error> {
+        deserializer::from_bytes(&self.0)
+    }
+
+    /// The config the payload was serialized with.
+    pub fn config(&self) -> Config {
+        // the header was checked when the payload was created.
+        header(&self.0).unwrap_or_default()
+    }
+
+    /// The bytes of the payload.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The bytes of the payload.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+fn header(bytes: &[u8]) -> Result<Config, Error> {
+    match bytes {
+        [low, high, ..] => Config::from_header(u16::from_le_bytes([*low, *high])),
+        _ => Err(Error::UnexpectedEOF),
+    }
+}
+
+impl Serialize for RawRustFr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RawRef(&self.0).serialize(serializer)
+    }
+}
+
+// A borrowed payload that serializes like a `RawRustFr`.
+pub(crate) struct RawRef<'a>(pub(crate) &'a [u8]);
+
+impl Serialize for RawRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(NAME, &Payload(self.0))
+    }
+}
+
+// The bytes of the payload, the content of the newtype struct.
+struct Payload<'a>(&'a [u8]);
+
+impl Serialize for Payload<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for RawRustFr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(NAME, RawVisitor)
+    }
+}
+
+struct RawVisitor;
+
+impl<'de> Visitor<'de> for RawVisitor {
+    type Value = RawRustFr;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a rust-fr payload")
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<RawRustFr, D::Error> {
+        let bytes = crate::with::bytes::byte_buf(deserializer)?;
+        RawRustFr::from_bytes(bytes).map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,231 @@
+//! ### Replay
+//! A harness that guards the wire format against accidental changes across releases. A
+//! [`Recorder`] collects the payloads of values (their name, Rust type and bytes) and writes
+//! them as the fixture file of the current [`FORMAT_VERSION`], e.g. `testdata/fixtures/v5.txt`.
+//! The files of older versions are kept and [`Fixtures::replay`] checks their payloads against
+//! the current code:
+//! - the payload of every version must still decode to the recorded value;
+//! - the value must still encode to the payload of the current version, so a change of the
+//!   bytes without a new format version is flagged too.
+//!
+//! A fixture file is text: a `rust-fr fixtures v<version>` line and then a line per payload with
+//! its name, Rust type and hex bytes separated by tabs.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{config::Config, replay::{Fixtures, Recorder}};
+//!
+//! let mut recorder = Recorder::new();
+//! recorder.record("point", &(1u8, -2i32), &Config::default()).unwrap();
+//! let text = recorder.to_text();
+//!
+//! // later (with the file of an older release): the payload still decodes and encodes the same.
+//! let fixtures = Fixtures::parse(&text).unwrap();
+//! assert!(fixtures.replay("point", &(1u8, -2i32)).unwrap());
+//! ```
+
+use std::{
+    fmt::{Debug, Write},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    config::Config, deserializer, error::Error, protocol::format::FORMAT_VERSION, serializer,
+};
+
+/// A recorded payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixture {
+    /// The name of the payload; unique in its file.
+    pub name: String,
+    /// The Rust type of the value as given by `std::any::type_name`, which may change with the
+    /// compiler; it only guards against replaying a payload as another type.
+    pub rust_type: String,
+    /// The payload, with its header.
+    pub bytes: Vec<u8>,
+}
+
+/// Collects the payloads of values for the fixture file of the current format version.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    fixtures: Vec<Fixture>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serialize `value` with `config` and record it as `name`. Names must be unique and
+    /// neither names nor types may contain tabs or line breaks.
+    pub fn record<T: Serialize>(
+        &mut self,
+        name: &str,
+        value: &T,
+        config: &Config,
+    ) -> Result<(), Error> {
+        let rust_type = std::any::type_name::<T>();
+        if [name, rust_type]
+            .iter()
+            .any(|text| text.contains(['\t', '\n', '\r']))
+        {
+            return Err(Error::InvalidFixture(format!(
+                "the name or the type of {name} has a tab or a line break"
+            )));
+        }
+        if self.fixtures.iter().any(|fixture| fixture.name == name) {
+            return Err(Error::InvalidFixture(format!("{name} is recorded twice")));
+        }
+        self.fixtures.push(Fixture {
+            name: name.to_string(),
+            rust_type: rust_type.to_string(),
+            bytes: serializer::to_bytes_with_config(value, config)?,
+        });
+        Ok(())
+    }
+
+    /// The recorded payloads in the order they were recorded.
+    pub fn fixtures(&self) -> &[Fixture] {
+        &self.fixtures
+    }
+
+    /// The fixture file of the recorded payloads.
+    pub fn to_text(&self) -> String {
+        self.fixtures.iter().fold(
+            format!("rust-fr fixtures v{FORMAT_VERSION}\n"),
+            |mut text, fixture| {
+                let hex = fixture.bytes.iter().fold(String::new(), |mut hex, byte| {
+                    let _ = write!(hex, "{byte:02x}");
+                    hex
+                });
+                let _ = writeln!(text, "{}\t{}\t{hex}", fixture.name, fixture.rust_type);
+                text
+            },
+        )
+    }
+
+    /// Write the fixture file of the current format version to `dir` (as `v<version>.txt`),
+    /// replacing the one that is there. It returns the path of the file.
+    pub fn write(&self, dir: &Path) -> Result<PathBuf, Error> {
+        let path = dir.join(format!("v{FORMAT_VERSION}.txt"));
+        fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&path, self.to_text()))
+            .map_err(|source| Error::Io {
+                source,
+                position: 0,
+            })?;
+        Ok(path)
+    }
+}
+
+/// The payloads of a fixture file, recorded with the format version in its first line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixtures {
+    pub version: u32,
+    pub fixtures: Vec<Fixture>,
+}
+
+impl Fixtures {
+    /// Parse a fixture file (see [`Recorder::to_text`]).
+    pub fn parse(text: &str) -> Result<Fixtures, Error> {
+        let mut lines = text.lines();
+        let version = lines
+            .next()
+            .and_then(|line| line.strip_prefix("rust-fr fixtures v"))
+            .and_then(|version| version.parse().ok())
+            .ok_or_else(|| Error::InvalidFixture("the file has no version line".to_string()))?;
+        let fixtures = lines
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let invalid = || Error::InvalidFixture(format!("the line {line:?} is invalid"));
+                let [name, rust_type, hex] = line.split('\t').collect::<Vec<_>>()[..] else {
+                    return Err(invalid());
+                };
+                let bytes = (0..hex.len())
+                    .step_by(2)
+                    .map(|i| {
+                        hex.get(i..i + 2)
+                            .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid)?;
+                Ok(Fixture {
+                    name: name.to_string(),
+                    rust_type: rust_type.to_string(),
+                    bytes,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Fixtures { version, fixtures })
+    }
+
+    /// Load every fixture file (`v<version>.txt`) in `dir`, oldest version first.
+    pub fn load_dir(dir: &Path) -> Result<Vec<Fixtures>, Error> {
+        let io = |source| Error::Io {
+            source,
+            position: 0,
+        };
+        let mut all = Vec::new();
+        for entry in fs::read_dir(dir).map_err(io)? {
+            let path = entry.map_err(io)?.path();
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("");
+            if name.starts_with('v') && name.ends_with(".txt") {
+                all.push(Fixtures::parse(&fs::read_to_string(&path).map_err(io)?)?);
+            }
+        }
+        all.sort_by_key(|fixtures| fixtures.version);
+        Ok(all)
+    }
+
+    /// Check the payload recorded as `name` against `expected`: it must be of the same type and
+    /// decode (with the config in its header) to `expected`. A payload of the current format
+    /// version must also be what `expected` encodes to. It returns `false` if there is no such
+    /// payload, e.g. because the value was added after this version was recorded.
+    pub fn replay<T>(&self, name: &str, expected: &T) -> Result<bool, Error>
+    where
+        T: Serialize + DeserializeOwned + PartialEq + Debug,
+    {
+        let Some(fixture) = self.fixtures.iter().find(|fixture| fixture.name == name) else {
+            return Ok(false);
+        };
+        let mismatch = |reason: String| Error::FixtureMismatch {
+            name: name.to_string(),
+            version: self.version,
+            reason,
+        };
+        let rust_type = std::any::type_name::<T>();
+        if fixture.rust_type != rust_type {
+            return Err(mismatch(format!(
+                "it was recorded as {} but is replayed as {rust_type}",
+                fixture.rust_type
+            )));
+        }
+        let decoded = deserializer::from_bytes::<T>(&fixture.bytes)
+            .map_err(|error| mismatch(format!("it no longer decodes: {error}")))?;
+        if decoded != *expected {
+            return Err(mismatch(format!(
+                "it decodes to {decoded:?} instead of {expected:?}"
+            )));
+        }
+        if self.version == FORMAT_VERSION {
+            let config = match fixture.bytes[..] {
+                [low, high, ..] => Config::from_header(u16::from_le_bytes([low, high]))?,
+                _ => return Err(Error::UnexpectedEOF),
+            };
+            let bytes = serializer::to_bytes_with_config(expected, &config)?;
+            if bytes != fixture.bytes {
+                return Err(mismatch(format!(
+                    "it encodes to {bytes:?} instead of {:?} without a new format version",
+                    fixture.bytes
+                )));
+            }
+        }
+        Ok(true)
+    }
+}
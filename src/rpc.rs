@@ -0,0 +1,596 @@
+//! ### RPC
+//! A module for exchanging messages (e.g. requests and responses) over a link. A [`Transport`]
+//! moves whole frames, each a serialized message with its header, and its provided
+//! [`send`](Transport::send) and [`recv`](Transport::recv) serialize and deserialize the
+//! messages; a link is supported by implementing the two frame methods, e.g. over a QUIC
+//! stream or a serial line. [`AsyncTransport`] is the same for links driven by an async
+//! runtime.
+//!
+//! The provided transports:
+//! - [`StreamTransport`] frames messages on a byte stream with their length (u32, little
+//!   endian), e.g. a [`TcpTransport`] or (on Unix) a [`UnixTransport`];
+//!   - frame: length + message
+//! - [`MemoryTransport`] hands frames to its peer in the same process, both blocking and async.
+//!
+//! The async transports of sockets depend on the runtime that drives them, so only the
+//! in-memory one is provided; a runtime's sockets implement [`AsyncTransport`] with the frames
+//! of a [`StreamTransport`] to talk to it.
+//!
+//! With the `futures` feature a `RustFrStream` reads the frames of a [`StreamTransport`] from
+//! any `futures::io::AsyncRead` and hands out the decoded messages as a `futures::Stream`. It
+//! reads ahead of the consumer only up to a high watermark of buffered bytes and resumes below
+//! a low one, so a consumer that decodes slower than the network stops the reads (and the
+//! peer, once the socket buffers are full) instead of growing the buffer.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{config::Config, rpc::{MemoryTransport, Transport}};
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! enum Request {
+//!     Add(u32, u32),
+//! }
+//!
+//! let config = Config::default();
+//! let (mut client, mut server) = MemoryTransport::pair();
+//! let server_config = config.clone();
+//! let handle = std::thread::spawn(move || {
+//!     while let Some(Request::Add(a, b)) = server.recv(&server_config).unwrap() {
+//!         server.send(&(a + b), &server_config).unwrap();
+//!     }
+//! });
+//! client.send(&Request::Add(1, 2), &config).unwrap();
+//! assert_eq!(client.recv::<u32>(&config).unwrap(), Some(3));
+//! drop(client);
+//! handle.join().unwrap();
+//! ```
+
+use std::{
+    collections::VecDeque,
+    future::{poll_fn, Future},
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    task::{Poll, Waker},
+};
+#[cfg(feature = "futures")]
+use std::{pin::Pin, task::Context};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{config::Config, deserializer, error::Error, serializer};
+
+/// A link that carries frames (serialized messages) both ways.
+pub trait Transport {
+    /// Send a frame to the peer.
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), Error>;
+
+    /// Wait for the next frame from the peer or `None` once the peer has closed the link.
+    fn recv_frame(&mut self) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Serialize `message` with `config` and send it.
+    fn send<T: Serialize>(&mut self, message: &T, config: &Config) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        self.send_frame(&serializer::to_bytes_with_config(message, config)?)
+    }
+
+    /// Wait for the next message and deserialize it with `config`, or `None` once the peer
+    /// has closed the link.
+    fn recv<T: DeserializeOwned>(&mut self, config: &Config) -> Result<Option<T>, Error>
+    where
+        Self: Sized,
+    {
+        match self.recv_frame()? {
+            Some(frame) => deserializer::from_bytes_with_config(&frame, config).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A [`Transport`] for links driven by an async runtime. The futures aren't required to be
+/// `Send`; a runtime that needs them to be can only use transports whose futures are.
+pub trait AsyncTransport {
+    /// Send a frame to the peer.
+    fn send_frame(&mut self, frame: &[u8]) -> impl Future<Output = Result<(), Error>>;
+
+    /// Wait for the next frame from the peer or `None` once the peer has closed the link.
+    fn recv_frame(&mut self) -> impl Future<Output = Result<Option<Vec<u8>>, Error>>;
+
+    /// Serialize `message` with `config` and send it.
+    fn send<T: Serialize>(
+        &mut self,
+        message: &T,
+        config: &Config,
+    ) -> impl Future<Output = Result<(), Error>>
+    where
+        Self: Sized,
+    {
+        let frame = serializer::to_bytes_with_config(message, config);
+        async move { self.send_frame(&frame?).await }
+    }
+
+    /// Wait for the next message and deserialize it with `config`, or `None` once the peer
+    /// has closed the link.
+    fn recv<T: DeserializeOwned>(
+        &mut self,
+        config: &Config,
+    ) -> impl Future<Output = Result<Option<T>, Error>>
+    where
+        Self: Sized,
+    {
+        let config = config.clone();
+        async move {
+            match self.recv_frame().await? {
+                Some(frame) => deserializer::from_bytes_with_config(&frame, &config).map(Some),
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+/// The size in bytes of the largest frame that a [`StreamTransport`] made with
+/// [`StreamTransport::new`] or a `RustFrStream` reads, so a corrupt or hostile length can't
+/// make them allocate gigabytes.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+/// Frames messages on a byte stream (see the [module](self) docs).
+#[derive(Debug)]
+pub struct StreamTransport<S: Read + Write> {
+    stream: S,
+    max_frame_size: usize,
+}
+
+/// A [`StreamTransport`] over a TCP connection.
+pub type TcpTransport = StreamTransport<TcpStream>;
+
+/// A [`StreamTransport`] over a Unix domain socket.
+#[cfg(unix)]
+pub type UnixTransport = StreamTransport<std::os::unix::net::UnixStream>;
+
+impl<S: Read + Write> StreamTransport<S> {
+    /// Frame messages on `stream`; frames larger than [`DEFAULT_MAX_FRAME_SIZE`] are rejected.
+    pub fn new(stream: S) -> Self {
+        Self::with_max_frame_size(stream, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// The same as [`StreamTransport::new`] but frames larger than `max` bytes fail with
+    /// [`Error::RecordTooLarge`] before they are read. The stream is then out of step with
+    /// the peer and should be closed.
+    pub fn with_max_frame_size(stream: S, max: usize) -> Self {
+        Self {
+            stream,
+            max_frame_size: max,
+        }
+    }
+
+    /// The stream, e.g. to set its timeouts.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Read + Write> Transport for StreamTransport<S> {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), Error> {
+        let length = u32::try_from(frame.len()).map_err(|_| {
+            Error::SerializationError("the frame is too large for a stream".to_string())
+        })?;
+        self.stream
+            .write_all(&length.to_le_bytes())
+            .and_then(|_| self.stream.write_all(frame))
+            .and_then(|_| self.stream.flush())
+            .map_err(io)
+    }
+
+    /// - If the stream ends before a frame; exit. A read interrupted by a signal is retried.
+    /// - Read the length; reject it if it's over the limit.
+    /// - Read as many bytes as the frame.
+    fn recv_frame(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let mut length = [0u8; 4];
+        loop {
+            match self.stream.read(&mut length[..1]) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(source) if source.kind() == ErrorKind::Interrupted => continue,
+                Err(source) => return Err(io(source)),
+            }
+        }
+        self.stream.read_exact(&mut length[1..]).map_err(io)?;
+        let length = u32::from_le_bytes(length) as usize;
+        if length > self.max_frame_size {
+            return Err(Error::RecordTooLarge {
+                length,
+                max: self.max_frame_size,
+            });
+        }
+        let mut frame = vec![0; length];
+        self.stream.read_exact(&mut frame).map_err(io)?;
+        Ok(Some(frame))
+    }
+}
+
+fn io(source: std::io::Error) -> Error {
+    match source.kind() {
+        ErrorKind::UnexpectedEof => Error::UnexpectedEOF,
+        _ => Error::Io {
+            source,
+            position: 0,
+        },
+    }
+}
+
+/// One end of a link within the process (see [`MemoryTransport::pair`]). It is both a
+/// blocking [`Transport`] and an [`AsyncTransport`]; dropping it closes the link.
+#[derive(Debug)]
+pub struct MemoryTransport {
+    incoming: Arc<Channel>,
+    outgoing: Arc<Channel>,
+}
+
+// The frames going one way and whether the sender is gone.
+#[derive(Debug, Default)]
+struct Channel {
+    queue: Mutex<Queue>,
+    ready: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    frames: VecDeque<Vec<u8>>,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl Channel {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn push(&self, frame: Option<Vec<u8>>) {
+        let mut queue = self.lock();
+        match frame {
+            Some(frame) => queue.frames.push_back(frame),
+            None => queue.closed = true,
+        }
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+        self.ready.notify_all();
+    }
+}
+
+impl MemoryTransport {
+    /// The two ends of a new link.
+    pub fn pair() -> (MemoryTransport, MemoryTransport) {
+        let (a, b) = (Arc::<Channel>::default(), Arc::<Channel>::default());
+        (
+            MemoryTransport {
+                incoming: a.clone(),
+                outgoing: b.clone(),
+            },
+            MemoryTransport {
+                incoming: b,
+                outgoing: a,
+            },
+        )
+    }
+
+    fn push(&self, frame: &[u8]) -> Result<(), Error> {
+        // a dropped peer closes the frames coming from it.
+        if self.incoming.lock().closed {
+            return Err(io(ErrorKind::BrokenPipe.into()));
+        }
+        self.outgoing.push(Some(frame.to_vec()));
+        Ok(())
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), Error> {
+        self.push(frame)
+    }
+
+    fn recv_frame(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let mut queue = self.incoming.lock();
+        loop {
+            if let Some(frame) = queue.frames.pop_front() {
+                return Ok(Some(frame));
+            }
+            if queue.closed {
+                return Ok(None);
+            }
+            queue = self
+                .incoming
+                .ready
+                .wait(queue)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+}
+
+impl AsyncTransport for MemoryTransport {
+    fn send_frame(&mut self, frame: &[u8]) -> impl Future<Output = Result<(), Error>> {
+        let sent = self.push(frame);
+        async move { sent }
+    }
+
+    fn recv_frame(&mut self) -> impl Future<Output = Result<Option<Vec<u8>>, Error>> {
+        poll_fn(|context| {
+            let mut queue = self.incoming.lock();
+            if let Some(frame) = queue.frames.pop_front() {
+                return Poll::Ready(Ok(Some(frame)));
+            }
+            if queue.closed {
+                return Poll::Ready(Ok(None));
+            }
+            queue.waker = Some(context.waker().clone());
+            Poll::Pending
+        })
+    }
+}
+
+impl Drop for MemoryTransport {
+    fn drop(&mut self) {
+        self.outgoing.push(None);
+    }
+}
+
+/// The number of buffered bytes at which a [`RustFrStream`] made with [`RustFrStream::new`]
+/// stops reading ahead.
+#[cfg(feature = "futures")]
+pub const DEFAULT_HIGH_WATERMARK: usize = 64 * 1024;
+
+/// The number of buffered bytes under which a [`RustFrStream`] made with [`RustFrStream::new`]
+/// reads ahead again.
+#[cfg(feature = "futures")]
+pub const DEFAULT_LOW_WATERMARK: usize = 16 * 1024;
+
+/// Decodes the messages framed by a [`StreamTransport`] (see the [module](self) docs) from an
+/// `AsyncRead`, as a `Stream` of the messages.
+///
+/// The bytes read but not decoded yet are buffered. The stream reads ahead of the consumer
+/// until the buffer holds `high` bytes and then reads only what the next message needs until
+/// the consumer has taken enough messages to bring it down to `low` bytes. A message that
+/// fails to decode is an error of its own and the stream goes on with the next one; an error
+/// of the reader, a frame over the limit or a cut off frame ends the stream.
+///
+/// ```rust
+/// use futures_core::Stream;
+/// use rust_fr::{
+///     config::Config,
+///     rpc::{RustFrStream, StreamTransport, Transport},
+/// };
+/// use std::{pin::pin, task::{Context, Poll, Waker}};
+///
+/// // the frames of a `StreamTransport`, e.g. from a socket.
+/// let config = Config::default();
+/// let mut transport = StreamTransport::new(std::io::Cursor::new(Vec::new()));
+/// for i in 0..3u32 {
+///     transport.send(&i, &config).unwrap();
+/// }
+/// let bytes = transport.into_inner().into_inner();
+///
+/// let mut stream = pin!(RustFrStream::<_, u32>::new(bytes.as_slice(), &config));
+/// let mut context = Context::from_waker(Waker::noop());
+/// for i in 0..3 {
+///     let next = stream.as_mut().poll_next(&mut context);
+///     assert!(matches!(next, Poll::Ready(Some(Ok(value))) if value == i));
+/// }
+/// assert!(matches!(stream.poll_next(&mut context), Poll::Ready(None)));
+/// ```
+#[cfg(feature = "futures")]
+#[derive(Debug)]
+pub struct RustFrStream<R, T> {
+    reader: R,
+    config: Config,
+    // The bytes read are `buffer[..filled]`; the rest of it is zeroed space for reads.
+    buffer: Vec<u8>,
+    filled: usize,
+    // The bytes at the start of the buffer that have been decoded already.
+    consumed: usize,
+    low: usize,
+    high: usize,
+    // Whether the buffer went over the high watermark and hasn't been drained to the low one.
+    paused: bool,
+    max_frame_size: usize,
+    // The number of bytes read so far.
+    position: usize,
+    // Whether the reader has ended or the stream has failed.
+    done: bool,
+    message: std::marker::PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "futures")]
+impl<R: futures_io::AsyncRead + Unpin, T: DeserializeOwned> RustFrStream<R, T> {
+    /// Decode the messages of `reader` with `config`, between the default watermarks
+    /// ([`DEFAULT_LOW_WATERMARK`] and [`DEFAULT_HIGH_WATERMARK`]).
+    pub fn new(reader: R, config: &Config) -> Self {
+        Self::with_watermarks(
+            reader,
+            config,
+            DEFAULT_LOW_WATERMARK,
+            DEFAULT_HIGH_WATERMARK,
+        )
+    }
+
+    /// The same as [`RustFrStream::new`] but reading ahead stops at `high` buffered bytes and
+    /// resumes under `low` ones.
+    ///
+    /// # Panics
+    /// If `low` is greater than `high`.
+    pub fn with_watermarks(reader: R, config: &Config, low: usize, high: usize) -> Self {
+        assert!(
+            low <= high,
+            "the low watermark must not be greater than the high one"
+        );
+        Self {
+            reader,
+            config: config.clone(),
+            buffer: Vec::new(),
+            filled: 0,
+            consumed: 0,
+            low,
+            high,
+            paused: false,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            position: 0,
+            done: false,
+            message: std::marker::PhantomData,
+        }
+    }
+
+    /// Reject frames larger than `max` bytes (instead of [`DEFAULT_MAX_FRAME_SIZE`]) with
+    /// [`Error::RecordTooLarge`] before they are read, which ends the stream.
+    pub fn with_max_frame_size(mut self, max: usize) -> Self {
+        self.max_frame_size = max;
+        self
+    }
+
+    /// The number of bytes read but not decoded yet.
+    pub fn buffered(&self) -> usize {
+        self.filled - self.consumed
+    }
+
+    /// The reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// The reader, e.g. to change its options. Reading from it takes bytes from the stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    // The length of the next frame, once it is buffered.
+    fn next_length(&self) -> Option<usize> {
+        let length = self.buffer[..self.filled].get(self.consumed..self.consumed + 4)?;
+        Some(u32::from_le_bytes(length.try_into().unwrap()) as usize)
+    }
+
+    // The number of bytes the next frame still needs, or zero if it is complete.
+    fn missing(&self) -> usize {
+        match self.next_length() {
+            // a frame over the limit is rejected without reading it.
+            Some(length) if length > self.max_frame_size => 0,
+            Some(length) => (4 + length).saturating_sub(self.buffered()),
+            None => 4 - self.buffered(),
+        }
+    }
+
+    // Whether to read: ahead of the consumer between the watermarks, or because the next
+    // frame isn't complete.
+    fn wants_bytes(&mut self) -> bool {
+        let buffered = self.buffered();
+        if buffered >= self.high {
+            self.paused = true;
+        }
+        if buffered <= self.low {
+            self.paused = false;
+        }
+        !self.done && (!self.paused || self.missing() > 0)
+    }
+
+    // Read once into the buffer: up to the high watermark or what the next frame needs. The
+    // end of the reader ends the stream.
+    fn poll_fill(&mut self, context: &mut Context) -> Poll<Result<(), Error>> {
+        if self.consumed > 0 && self.consumed >= self.filled / 2 {
+            self.buffer.copy_within(self.consumed..self.filled, 0);
+            self.filled -= self.consumed;
+            self.consumed = 0;
+        }
+        let wanted = self
+            .high
+            .saturating_sub(self.buffered())
+            .max(self.missing())
+            .max(1);
+        let end = self.filled + wanted;
+        if self.buffer.len() < end {
+            self.buffer.resize(end, 0);
+        }
+        let space = &mut self.buffer[self.filled..end];
+        let read = loop {
+            match Pin::new(&mut self.reader).poll_read(context, space) {
+                Poll::Ready(Err(source)) if source.kind() == ErrorKind::Interrupted => continue,
+                read => break read,
+            }
+        };
+        match read {
+            Poll::Ready(Ok(read)) => {
+                self.filled += read;
+                self.position += read;
+                self.done = read == 0;
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(source)) => Poll::Ready(Err(Error::Io {
+                source,
+                position: self.position,
+            })),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    // End the stream after an error it can't go on from.
+    fn fail(&mut self, error: Error) -> Poll<Option<Result<T, Error>>> {
+        self.done = true;
+        self.filled = 0;
+        self.consumed = 0;
+        Poll::Ready(Some(Err(error)))
+    }
+
+    // Decode the next frame if it is complete.
+    fn take(&mut self) -> Option<Result<T, Error>> {
+        let start = self.consumed + 4;
+        let frame = self.buffer[..self.filled].get(start..start + self.next_length()?)?;
+        let message = deserializer::from_bytes_with_config(frame, &self.config);
+        self.consumed = start + frame.len();
+        Some(message)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<R: futures_io::AsyncRead + Unpin, T: DeserializeOwned> futures_core::Stream
+    for RustFrStream<R, T>
+{
+    type Item = Result<T, Error>;
+
+    /// - Read while the stream wants bytes (see the watermarks).
+    /// - Reject the next frame if it's over the limit.
+    /// - Decode the next frame once all of it is buffered.
+    /// - At the end of the reader, a frame that isn't complete is cut off.
+    fn poll_next(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while this.wants_bytes() {
+            match this.poll_fill(context) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(error)) => return this.fail(error),
+                Poll::Pending => break,
+            }
+        }
+        match this.next_length() {
+            Some(length) if length > this.max_frame_size => {
+                let max = this.max_frame_size;
+                return this.fail(Error::RecordTooLarge { length, max });
+            }
+            _ => {}
+        }
+        if let Some(message) = this.take() {
+            return Poll::Ready(Some(message));
+        }
+        match (this.done, this.buffered()) {
+            (false, _) => Poll::Pending,
+            (true, 0) => Poll::Ready(None),
+            (true, _) => this.fail(Error::UnexpectedEOF),
+        }
+    }
+}
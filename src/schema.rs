@@ -0,0 +1,485 @@
+//! ### Schema
+//! A module for extracting the shape of a type from its `Deserialize` implementation, e.g. to
+//! generate decoders for other languages (see the [`codegen`](crate::codegen) module).
+//! [`Schema::of`] deserializes the type from a tracer that hands out placeholder values and
+//! records what was asked for; it runs again until every variant of every enum it met was
+//! seen.
+//!
+//! Types read through `deserialize_any` (e.g. untagged enums or `flatten`) and recursive types
+//! have no schema. A type whose `Deserialize` implementation rejects the placeholder values
+//! (zeros, empty strings and the first variants) fails with the error it returns.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::schema::{Schema, Variant};
+//!
+//! #[derive(serde::Deserialize)]
+//! enum Shape {
+//!     Point,
+//!     Circle(f32),
+//! }
+//!
+//! let schema = Schema::of::<Vec<Shape>>().unwrap();
+//! assert_eq!(
+//!     schema,
+//!     Schema::Seq(Box::new(Schema::Enum {
+//!         name: "Shape",
+//!         variants: vec![("Point", Variant::Unit), ("Circle", Variant::Newtype(Schema::F32))],
+//!     }))
+//! );
+//! ```
+
+use std::collections::HashMap;
+
+use serde::{
+    de::{
+        DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor,
+    },
+    Deserialize, Deserializer,
+};
+
+use super::error::Error;
+
+/// The shape of a type as it is serialized. Newtype structs are their content, unit structs
+/// are units and tuple structs are tuples.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    Bool,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    F32,
+    F64,
+    Char,
+    Str,
+    Bytes,
+    Unit,
+    Option(Box<Schema>),
+    Seq(Box<Schema>),
+    Tuple(Vec<Schema>),
+    Map(Box<Schema>, Box<Schema>),
+    Struct {
+        name: &'static str,
+        fields: Vec<(&'static str, Schema)>,
+    },
+    Enum {
+        name: &'static str,
+        variants: Vec<(&'static str, Variant)>,
+    },
+}
+
+/// The content of an enum variant.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Variant {
+    Unit,
+    Newtype(Schema),
+    Tuple(Vec<Schema>),
+    Struct(Vec<(&'static str, Schema)>),
+}
+
+/// The most nested values a schema may have; deeper ones are taken for a recursive type.
+const MAX_DEPTH: usize = 64;
+
+impl Schema {
+    /// The schema of `T`.
+    pub fn of<T: for<'de> Deserialize<'de>>() -> Result<Schema, Error> {
+        let mut state = State::default();
+        loop {
+            let explored = state.explored();
+            let mut schema = Schema::Unit;
+            T::deserialize(Tracer {
+                state: &mut state,
+                schema: &mut schema,
+                depth: 0,
+            })?;
+            if state.explored() == explored {
+                return state.fill(schema);
+            }
+        }
+    }
+}
+
+// What the runs of the tracer found out about the enums so far: the name and the content (if
+// it was seen) of every variant.
+#[derive(Default)]
+struct State {
+    enums: HashMap<&'static str, Vec<(&'static str, Option<Variant>)>>,
+}
+
+impl State {
+    /// The number of variants seen so far.
+    fn explored(&self) -> usize {
+        self.enums
+            .values()
+            .flatten()
+            .filter(|(_, content)| content.is_some())
+            .count()
+    }
+
+    /// Fill in the variants of the enums in `schema`.
+    fn fill(&self, schema: Schema) -> Result<Schema, Error> {
+        let fill_all = |schemas: Vec<Schema>| {
+            schemas
+                .into_iter()
+                .map(|schema| self.fill(schema))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let fill_fields = |fields: Vec<(&'static str, Schema)>| {
+            fields
+                .into_iter()
+                .map(|(name, schema)| Ok((name, self.fill(schema)?)))
+                .collect::<Result<Vec<_>, Error>>()
+        };
+        Ok(match schema {
+            Schema::Option(inner) => Schema::Option(Box::new(self.fill(*inner)?)),
+            Schema::Seq(inner) => Schema::Seq(Box::new(self.fill(*inner)?)),
+            Schema::Tuple(elements) => Schema::Tuple(fill_all(elements)?),
+            Schema::Map(key, value) => {
+                Schema::Map(Box::new(self.fill(*key)?), Box::new(self.fill(*value)?))
+            }
+            Schema::Struct { name, fields } => Schema::Struct {
+                name,
+                fields: fill_fields(fields)?,
+            },
+            Schema::Enum { name, .. } => {
+                let variants = self.enums.get(name).into_iter().flatten();
+                let variants = variants
+                    .map(|(variant, content)| {
+                        let content = content.clone().ok_or_else(|| {
+                            Error::UnsupportedCall(format!(
+                                "a schema of {name} whose variant {variant} can't be reached"
+                            ))
+                        })?;
+                        let content = match content {
+                            Variant::Unit => Variant::Unit,
+                            Variant::Newtype(schema) => Variant::Newtype(self.fill(schema)?),
+                            Variant::Tuple(elements) => Variant::Tuple(fill_all(elements)?),
+                            Variant::Struct(fields) => Variant::Struct(fill_fields(fields)?),
+                        };
+                        Ok((*variant, content))
+                    })
+                    .collect::<Result<_, Error>>()?;
+                Schema::Enum { name, variants }
+            }
+            schema => schema,
+        })
+    }
+}
+
+// A deserializer that hands out placeholder values and records the schema of the value it
+// was asked for in `schema`. Enums only get their name; `State::fill` adds their variants.
+struct Tracer<'t> {
+    state: &'t mut State,
+    schema: &'t mut Schema,
+    depth: usize,
+}
+
+impl Tracer<'_> {
+    /// A tracer of a nested value whose schema goes into `schema`.
+    fn nested<'n>(&'n mut self, schema: &'n mut Schema) -> Result<Tracer<'n>, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(Error::UnsupportedCall(
+                "a schema of a recursive type".to_string(),
+            ));
+        }
+        Ok(Tracer {
+            state: &mut *self.state,
+            schema,
+            depth: self.depth + 1,
+        })
+    }
+}
+
+macro_rules! trace_primitive {
+    ($($method:ident => $schema:ident, $visit:ident($($value:expr)?)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                *self.schema = Schema::$schema;
+                visitor.$visit($($value)?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Tracer<'_> {
+    type Error = Error;
+
+    trace_primitive! {
+        deserialize_bool => Bool, visit_bool(false),
+        deserialize_i8 => I8, visit_i8(0),
+        deserialize_i16 => I16, visit_i16(0),
+        deserialize_i32 => I32, visit_i32(0),
+        deserialize_i64 => I64, visit_i64(0),
+        deserialize_i128 => I128, visit_i128(0),
+        deserialize_u8 => U8, visit_u8(0),
+        deserialize_u16 => U16, visit_u16(0),
+        deserialize_u32 => U32, visit_u32(0),
+        deserialize_u64 => U64, visit_u64(0),
+        deserialize_u128 => U128, visit_u128(0),
+        deserialize_f32 => F32, visit_f32(0.0),
+        deserialize_f64 => F64, visit_f64(0.0),
+        deserialize_char => Char, visit_char('\0'),
+        deserialize_str => Str, visit_borrowed_str(""),
+        deserialize_string => Str, visit_borrowed_str(""),
+        deserialize_bytes => Bytes, visit_borrowed_bytes(&[]),
+        deserialize_byte_buf => Bytes, visit_borrowed_bytes(&[]),
+        deserialize_unit => Unit, visit_unit(),
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Error> {
+        Err(Error::UnsupportedCall(
+            "a schema of a type read through `deserialize_any`".to_string(),
+        ))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        let mut inner = Schema::Unit;
+        let value = visitor.visit_some(self.nested(&mut inner)?)?;
+        *self.schema = Schema::Option(Box::new(inner));
+        Ok(value)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        let mut elements = vec![Schema::Unit];
+        let value = visitor.visit_seq(Elements {
+            tracer: &mut self,
+            schemas: &mut elements,
+            index: 0,
+        })?;
+        *self.schema = Schema::Seq(Box::new(elements.remove(0)));
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        mut self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let mut elements = vec![Schema::Unit; len];
+        let value = visitor.visit_seq(Elements {
+            tracer: &mut self,
+            schemas: &mut elements,
+            index: 0,
+        })?;
+        *self.schema = Schema::Tuple(elements);
+        Ok(value)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        let mut entry = vec![Schema::Unit; 2];
+        let value = visitor.visit_map(Elements {
+            tracer: &mut self,
+            schemas: &mut entry,
+            index: 0,
+        })?;
+        let value_schema = entry.remove(1);
+        *self.schema = Schema::Map(Box::new(entry.remove(0)), Box::new(value_schema));
+        Ok(value)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        mut self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let mut schemas = vec![Schema::Unit; fields.len()];
+        let value = visitor.visit_seq(Elements {
+            tracer: &mut self,
+            schemas: &mut schemas,
+            index: 0,
+        })?;
+        *self.schema = Schema::Struct {
+            name,
+            fields: fields.iter().copied().zip(schemas).collect(),
+        };
+        Ok(value)
+    }
+
+    /// Pick the first variant that wasn't seen yet (or the first one) and trace its content.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let known = self
+            .state
+            .enums
+            .entry(name)
+            .or_insert_with(|| variants.iter().map(|variant| (*variant, None)).collect());
+        let index = known
+            .iter()
+            .position(|(_, content)| content.is_none())
+            .unwrap_or(0);
+        *self.schema = Schema::Enum {
+            name,
+            variants: Vec::new(),
+        };
+        visitor.visit_enum(Enum {
+            tracer: self,
+            name,
+            index,
+        })
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Error> {
+        Err(Error::UnsupportedCall(
+            "a schema of a type with identifiers outside of structs and enums".to_string(),
+        ))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+// Hands out one element of a sequence, the elements of a tuple or struct, or one entry of a
+// map, recording the schema of each in `schemas`.
+struct Elements<'a, 't> {
+    tracer: &'a mut Tracer<'t>,
+    schemas: &'a mut Vec<Schema>,
+    index: usize,
+}
+
+impl<'de> SeqAccess<'de> for Elements<'_, '_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        let Some(schema) = self.schemas.get_mut(self.index) else {
+            return Ok(None);
+        };
+        self.index += 1;
+        seed.deserialize(self.tracer.nested(schema)?).map(Some)
+    }
+}
+
+impl<'de> MapAccess<'de> for Elements<'_, '_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if self.index != 0 {
+            return Ok(None);
+        }
+        self.index += 1;
+        seed.deserialize(self.tracer.nested(&mut self.schemas[0])?)
+            .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(self.tracer.nested(&mut self.schemas[1])?)
+    }
+}
+
+// The variant picked for an enum; its content is recorded in the state.
+struct Enum<'t> {
+    tracer: Tracer<'t>,
+    name: &'static str,
+    index: usize,
+}
+
+impl Enum<'_> {
+    /// Record the content of the variant.
+    fn record(&mut self, content: Variant) {
+        if let Some(variants) = self.tracer.state.enums.get_mut(self.name) {
+            variants[self.index].1 = Some(content);
+        }
+    }
+}
+
+impl<'de, 't> EnumAccess<'de> for Enum<'t> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let index = u32::try_from(self.index).map_err(|_| Error::ConversionError)?;
+        let variant = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(index))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Enum<'_> {
+    type Error = Error;
+
+    fn unit_variant(mut self) -> Result<(), Error> {
+        self.record(Variant::Unit);
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(mut self, seed: T) -> Result<T::Value, Error> {
+        let mut schema = Schema::Unit;
+        let value = seed.deserialize(self.tracer.nested(&mut schema)?)?;
+        self.record(Variant::Newtype(schema));
+        Ok(value)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(mut self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        let mut schemas = vec![Schema::Unit; len];
+        let value = visitor.visit_seq(Elements {
+            tracer: &mut self.tracer,
+            schemas: &mut schemas,
+            index: 0,
+        })?;
+        self.record(Variant::Tuple(schemas));
+        Ok(value)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        mut self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let mut schemas = vec![Schema::Unit; fields.len()];
+        let value = visitor.visit_seq(Elements {
+            tracer: &mut self.tracer,
+            schemas: &mut schemas,
+            index: 0,
+        })?;
+        self.record(Variant::Struct(
+            fields.iter().copied().zip(schemas).collect(),
+        ));
+        Ok(value)
+    }
+}
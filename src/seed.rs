@@ -0,0 +1,166 @@
+//! ### Seed
+//! A module with `DeserializeSeed`s that deserialize sequences and maps into collections that
+//! already exist, so consumers decoding a stream of messages can reuse the allocations of their
+//! collections from one message to the next instead of building new ones every time. They are
+//! used with [`from_bytes_seed`](crate::deserializer::from_bytes_seed) (or any serde
+//! deserializer).
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{
+//!     deserializer,
+//!     seed::{MapSeed, VecSeed},
+//!     serializer,
+//! };
+//! use std::collections::HashMap;
+//!
+//! let mut readings = Vec::<f32>::new();
+//! let mut labels = HashMap::<String, u16>::new();
+//! for frame in 0..3u16 {
+//!     let bytes = serializer::to_bytes(&vec![frame as f32; 100]).unwrap();
+//!     deserializer::from_bytes_seed(&bytes, VecSeed::into(&mut readings)).unwrap();
+//!     assert_eq!(readings, vec![frame as f32; 100]);
+//!
+//!     let bytes = serializer::to_bytes(&HashMap::from([("frame", frame)])).unwrap();
+//!     deserializer::from_bytes_seed(&bytes, MapSeed::into(&mut labels)).unwrap();
+//!     assert_eq!(labels["frame"], frame);
+//! }
+//!
+//! // or a new vector with room for the elements that are expected.
+//! let bytes = serializer::to_bytes(&vec![1.0f32, 2.0, 3.0]).unwrap();
+//! let seed = VecSeed::<f32>::with_capacity(64);
+//! let decoded = deserializer::from_bytes_seed(&bytes, seed).unwrap();
+//! assert!(decoded.capacity() >= 64);
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+};
+
+use serde::{
+    de::{DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+/// Deserializes a sequence into a new vector with room for a given number of elements.
+#[derive(Debug, Clone, Copy)]
+pub struct VecSeed<T> {
+    capacity: usize,
+    element: PhantomData<T>,
+}
+
+impl<T> VecSeed<T> {
+    /// Deserialize into a vector with room for at least `capacity` elements.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            element: PhantomData,
+        }
+    }
+
+    /// Deserialize into `vec`, replacing its elements but keeping its allocation.
+    pub fn into(vec: &mut Vec<T>) -> IntoVec<'_, T> {
+        IntoVec { vec }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> DeserializeSeed<'de> for VecSeed<T> {
+    type Value = Vec<T>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let mut vec = Vec::with_capacity(self.capacity);
+        IntoVec { vec: &mut vec }.deserialize(deserializer)?;
+        Ok(vec)
+    }
+}
+
+/// Deserializes a sequence into an existing vector; see [`VecSeed::into`].
+#[derive(Debug)]
+pub struct IntoVec<'a, T> {
+    vec: &'a mut Vec<T>,
+}
+
+impl<'de, T: Deserialize<'de>> DeserializeSeed<'de> for IntoVec<'_, T> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for IntoVec<'_, T> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        self.vec.clear();
+        self.vec.reserve(cautious::<T>(seq.size_hint()));
+        while let Some(element) = seq.next_element()? {
+            self.vec.push(element);
+        }
+        Ok(())
+    }
+}
+
+/// Deserializes a map into an existing `HashMap`, replacing its entries but keeping its
+/// allocation.
+#[derive(Debug)]
+pub struct MapSeed<'a, K, V, S> {
+    map: &'a mut HashMap<K, V, S>,
+}
+
+impl<'a, K, V, S> MapSeed<'a, K, V, S> {
+    /// Deserialize into `map`.
+    pub fn into(map: &'a mut HashMap<K, V, S>) -> Self {
+        Self { map }
+    }
+}
+
+impl<'de, K, V, S> DeserializeSeed<'de> for MapSeed<'_, K, V, S>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    S: BuildHasher,
+{
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, K, V, S> Visitor<'de> for MapSeed<'_, K, V, S>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    S: BuildHasher,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        self.map.clear();
+        self.map.reserve(cautious::<(K, V)>(map.size_hint()));
+        while let Some((key, value)) = map.next_entry()? {
+            self.map.insert(key, value);
+        }
+        Ok(())
+    }
+}
+
+// The number of elements to make room for ahead of time: the size hint of a deserializer may be
+// a count read from the data, so at most 1 MiB is allocated for it, as serde does.
+fn cautious<T>(hint: Option<usize>) -> usize {
+    const MAX_PREALLOCATED_BYTES: usize = 1024 * 1024;
+    let size = std::mem::size_of::<T>().max(1);
+    hint.unwrap_or(0).min(MAX_PREALLOCATED_BYTES / size)
+}
@@ -3,153 +3,1782 @@
 //!
 //! To use the serializer, call the [`to_bytes`] function with a reference to the data to be
 //! serialized. The data must implement the `Serialize` trait from the `serde` library.
+//!
+//! ### Bytes
+//! Types that serialize through `Serializer::serialize_bytes` (like `serde_bytes::ByteBuf`
+//! or `serde_bytes::Bytes`) are written as a single run of bytes. A plain `Vec<u8>` is a
+//! sequence to serde and is written element by element unless
+//! [`Config::pack_u8_seqs`] is set.
+//!
+//! ```rust
+//! use rust_fr::{config::Config, deserializer, serializer};
+//!
+//! // what `serde_bytes::ByteBuf` does.
+//! struct ByteBuf(Vec<u8>);
+//!
+//! impl serde::Serialize for ByteBuf {
+//!     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+//!         serializer.serialize_bytes(&self.0)
+//!     }
+//! }
+//!
+//! let payload = vec![7u8; 64];
+//! let bytes = serializer::to_bytes(&ByteBuf(payload.clone())).unwrap();
+//! // `&[u8]` (or `serde_bytes::ByteBuf`) reads bytes back.
+//! assert_eq!(deserializer::from_bytes::<&[u8]>(&bytes).unwrap(), payload);
+//!
+//! let config = Config {
+//!     pack_u8_seqs: true,
+//!     ..Config::default()
+//! };
+//! let packed = serializer::to_bytes_with_config(&payload, &config).unwrap();
+//! let decoded = deserializer::from_bytes_with_config::<Vec<u8>>(&packed, &config).unwrap();
+//! assert_eq!(decoded, payload);
+//! assert!(packed.len() < serializer::to_bytes(&payload).unwrap().len());
+//! ```
 
-use bitvec::{prelude as bv, slice::BitSlice};
+use std::{borrow::Cow, collections::HashMap, io::Write, ops::Range};
+
+use bitvec::{prelude as bv, view::BitView};
 use serde::{
     ser::{
-        SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
-        SerializeTupleStruct, SerializeTupleVariant,
+        Impossible, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant,
+        SerializeTuple, SerializeTupleStruct, SerializeTupleVariant,
     },
     Serialize, Serializer,
 };
 
-use super::error::Error;
-
-/// The delimiter used in the format specification. The purpose
-/// of delimiters is to separate different types of data such
-/// that they don't mangle. There are 8 different delimiters
-/// in the format specification out of which 3 (`String`, `Byte` & `Map`)
-/// are 1 byte long and 5 (the rest...) are 3 bits long.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Delimiter {
-    // 0b10000110
-    String = 134,
-    // 0b10000111
-    Byte = 135,
-    // 0b010
-    Unit = 2,
-    // 0b011
-    Seq = 3,
-    // 0b100
-    SeqValue = 4,
-    // 0b10001011
-    Map = 139,
-    // 0b110
-    MapKey = 6,
-    // 0b111
-    MapValue = 7,
-}
-
-impl std::fmt::Display for Delimiter {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+pub use super::protocol::format::{Delimiter, TypeTag, ADAPTIVE_WIDTH_BITS};
+use super::{
+    columnar,
+    config::{Config, StringFraming, StructKeys},
+    encoders::{self, Encoders, Primitive},
+    error::Error,
+    precompute::EncodedStr,
+    protocol::format::{
+        Production, Token, WireOrder, ADAPTIVE_WIDTH, STRING_ESCAPE, VARIANT_INDEX_ESCAPE,
+    },
+    raw,
+    validator::Validator,
+};
+#[cfg(feature = "digest")]
+use digest::{Digest, Output};
+
+/// Statistics about serialized data; see [`to_bytes_and_stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of bits of the data.
+    pub bits: usize,
+    /// The number of times every delimiter was written.
+    pub delimiters: HashMap<Delimiter, usize>,
+    /// The number of values of every type, whether or not their type tags were written (see
+    /// [`Config::self_describing`]).
+    pub values: HashMap<TypeTag, usize>,
+    /// The number of values that start on a byte boundary (e.g. strings and bytes).
+    pub aligned: usize,
+    /// The number of bits of padding in front of the values that start on a byte boundary.
+    pub padding: usize,
+}
+
+impl Stats {
+    /// The number of times the delimiter was written.
+    pub fn delimiter(&self, delimiter: &Delimiter) -> usize {
+        self.delimiters.get(delimiter).copied().unwrap_or(0)
+    }
+
+    /// The number of values of the type.
+    pub fn values(&self, tag: TypeTag) -> usize {
+        self.values.get(&tag).copied().unwrap_or(0)
+    }
+
+    /// The number of bits taken up by delimiters.
+    pub fn delimiter_bits(&self) -> usize {
+        self.delimiters
+            .iter()
+            .map(|(delimiter, count)| delimiter.width() * count)
+            .sum()
+    }
+}
+
+// Receives the bytes of the data as soon as they can't change anymore.
+type Sink<'a> = &'a mut dyn FnMut(&[u8]) -> Result<(), Error>;
+
+/// A function that normalizes strings (e.g. to Unicode NFC) before they are serialized; see
+/// [`to_bytes_with_normalizer`].
+pub type Normalizer<'a> = &'a dyn Fn(&str) -> Cow<'_, str>;
+
+// The number of settled bytes that are handed to the sink at once.
+const FLUSH_SIZE: usize = 4096;
+
+// Internal struct that handles the serialization of the data in the bit order `O` (see
+// `Config::msb_first`).
+pub(crate) struct CustomSerializer<'a, O: WireOrder = bv::Lsb0> {
+    pub(crate) data: bv::BitVec<u8, O>,
+    config: Config,
+    sink: Option<Sink<'a>>,
+    // The number of bytes handed to the sink so far.
+    flushed: usize,
+    // Whether the bytes handed to the sink are dropped from the data when nothing before
+    // them can change anymore, so the data stays small.
+    drain: bool,
+    // The number of bytes dropped from the data so far.
+    drained: usize,
+    // The open containers along with the state they are written with, innermost last. What
+    // is written next only depends on them, never on the bits written before.
+    containers: Vec<Frame>,
+    // Whether the next `Some` is a struct field written without its presence bit.
+    implied_some: bool,
+    // The statistics about the data, if they are collected.
+    stats: Option<Stats>,
+    // The custom encodings of newtype structs, if any.
+    encoders: Option<&'a Encoders>,
+    // The function strings are normalized with before they are written, if any.
+    normalizer: Option<Normalizer<'a>>,
+    // The constraints on the fields of structs, if any.
+    validator: Option<&'a Validator>,
+    // The names of the open structs (or struct variants) when validating.
+    structs: Vec<&'static str>,
+    // The struct and the field the values being written are in if the field has constraints.
+    field: Option<(&'static str, &'static str)>,
+    // The field names encoded ahead of time, if any.
+    keys: &'a [EncodedStr],
+    // The encodings of the field names looked up so far, by the address and the length of the
+    // names, so a field is only looked for in `keys` the first time it is written.
+    found_keys: HashMap<(usize, usize), Option<EncodedStr>>,
+    // The byte ranges of the fields of the outermost struct, if they are recorded.
+    ranges: Option<FieldRanges>,
+}
+
+// The bit ranges of the fields of the first outermost struct, e.g. to find them again with
+// aligned fields (see `Config::aligned_fields`).
+#[derive(Debug, Default)]
+pub(crate) struct FieldRanges {
+    // The number of open structs.
+    depth: usize,
+    // Whether the first outermost struct has ended.
+    done: bool,
+    pub(crate) fields: Vec<(&'static str, Range<usize>)>,
+}
+
+// An open container on the stack of a serializer.
+#[derive(Debug)]
+enum Frame {
+    // A sequence (or a tuple in the self-describing mode).
+    Seq(SeqFrame),
+    // A map (or a struct with its field names).
+    Map(MapFrame),
+    // A struct without field names outside the self-describing mode, with the bitmap of its
+    // present fields when writing option bitmaps.
+    Values(Option<OptionBitmap>),
+}
+
+impl Frame {
+    // The positions in the data that the container may still rewrite.
+    fn held(&self) -> [Option<usize>; 3] {
         match self {
-            Delimiter::String => write!(f, "String"),
-            Delimiter::Byte => write!(f, "Byte"),
-            Delimiter::Unit => write!(f, "Unit"),
-            Delimiter::Seq => write!(f, "Seq"),
-            Delimiter::SeqValue => write!(f, "SeqValue"),
-            Delimiter::Map => write!(f, "Map"),
-            Delimiter::MapKey => write!(f, "MapKey"),
-            Delimiter::MapValue => write!(f, "MapValue"),
+            Frame::Seq(seq) => [
+                seq.group.as_ref().map(|group| group.start),
+                seq.packed
+                    .as_ref()
+                    .filter(|packed| packed.bytes.is_some())
+                    .map(|packed| packed.start),
+                None,
+            ],
+            Frame::Map(map) => [
+                map.table.as_ref().map(|table| table.start),
+                map.block
+                    .as_ref()
+                    .filter(|block| !block.decided || block.keys.is_some())
+                    .map(|block| block.start),
+                map.key,
+            ],
+            Frame::Values(bitmap) => [bitmap.as_ref().map(|bitmap| bitmap.start), None, None],
         }
     }
 }
 
-// Internal struct that handles the serialization of the data.
-// It has a few methods that lets us peeking bytes in the data.
+// The state of an open sequence.
+#[derive(Debug, Default)]
+struct SeqFrame {
+    // The last group in the run-length encoding mode.
+    group: Option<Group>,
+    // The elements held back when packing sequences of u8.
+    packed: Option<PackedSeq>,
+}
+
+// The state of an open map.
+#[derive(Debug, Default)]
+struct MapFrame {
+    // The offset table when writing offset tables.
+    table: Option<OffsetTable>,
+    // The block of keys when writing integer keys as a block.
+    block: Option<KeyBlock>,
+    // The position of the open key when prefixing keys with their length.
+    key: Option<usize>,
+}
+
+// The error of a container that ends while another kind of container is open.
+fn unbalanced(container: &str) -> Error {
+    Error::SerializationError(format!(
+        "{container} ended while another kind of container is open"
+    ))
+}
+
+// The entries of a map that get an offset table in front of them.
+#[derive(Debug)]
+struct OffsetTable {
+    // The position of the first entry (and of the table once it is written).
+    start: usize,
+    // The positions of the entries.
+    entries: Vec<usize>,
+}
+
+// The keys of a map that are written as a block in front of the values if they are unsigned
+// integers. Whether they are is decided by the first key.
+#[derive(Debug)]
+struct KeyBlock {
+    // The position of the bit telling the layout of the map.
+    start: usize,
+    // The keys so far or `None` if they are written in front of every value.
+    keys: Option<BlockKeys>,
+    // Whether a key has been seen.
+    decided: bool,
+}
+
+// The unsigned integer keys of a map.
+#[derive(Debug)]
+struct BlockKeys {
+    // The type of the keys.
+    kind: TypeTag,
+    // The number of keys.
+    count: u32,
+    // The keys one after the other (little endian).
+    bytes: Vec<u8>,
+    // The last key.
+    last: u64,
+    // Whether the keys are in ascending order.
+    sorted: bool,
+}
+
+// The bitmap telling which fields of a struct without field names are present.
+#[derive(Debug)]
+struct OptionBitmap {
+    // The position of the bitmap.
+    start: usize,
+    // The number of bits of the bitmap.
+    len: usize,
+    // The index of the next field.
+    field: usize,
+}
+
+// A sequence that is packed (like bytes) as long as all of its elements are u8.
 #[derive(Debug)]
-struct CustomSerializer {
-    data: bv::BitVec<u8, bv::Lsb0>,
+struct PackedSeq {
+    // The position of the SEQ_DELIMITER of the sequence.
+    start: usize,
+    // The elements so far or `None` if one of them wasn't a u8.
+    bytes: Option<Vec<u8>>,
+}
+
+// A group of equal elements of a sequence in the run-length encoding mode.
+#[derive(Debug)]
+struct Group {
+    // The position of the SEQ_VALUE_DELIMITER (or RUN_DELIMITER) of the group.
+    start: usize,
+    // The position of the element of the group.
+    element: usize,
+    // The number of times the element repeats.
+    count: u32,
+}
+
+// Formats text straight into the data of a serializer (see `collect_str`).
+struct TextWriter<'s, 'a, O: WireOrder>(&'s mut CustomSerializer<'a, O>);
+
+impl<O: WireOrder> std::fmt::Write for TextWriter<'_, '_, O> {
+    fn write_str(&mut self, text: &str) -> std::fmt::Result {
+        self.0.write_bytes(text.as_bytes());
+        Ok(())
+    }
 }
 
 /// The function to serialize data of a given type to a byte vector. The
 /// `value` must implement the `Serialize` trait from the `serde` library. It returns
 /// a Result with the serialized byte vector or an error.
 pub fn to_bytes<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
-    let mut serializer = CustomSerializer {
-        data: bv::BitVec::new(),
-    };
-    value.serialize(&mut serializer)?;
+    to_bytes_with_config(value, &Config::default())
+}
+
+/// The same as [`to_bytes`] but the data is encoded according to the given [`Config`].
+/// The bytes must be deserialized with the same config.
+pub fn to_bytes_with_config<T: Serialize>(value: &T, config: &Config) -> Result<Vec<u8>, Error> {
+    Ok(to_bytes_and_bit_len_with_config(value, config)?.0)
+}
+
+/// The same as [`to_bytes_with_config`] but newtype structs with a custom encoding in
+/// `encoders` are written with it. The bytes must be deserialized with the same encoders (see
+/// [`from_bytes_with_encoders`](crate::deserializer::from_bytes_with_encoders)).
+pub fn to_bytes_with_encoders<T: Serialize>(
+    value: &T,
+    config: &Config,
+    encoders: &Encoders,
+) -> Result<Vec<u8>, Error> {
+    let mut serializer = CustomSerializer::new(config.clone(), None);
+    serializer.encoders = Some(encoders);
+    serializer.serialize_root(value)?;
+    Ok(serializer.data.into_vec())
+}
+
+/// The same as [`to_bytes_with_config`] but the names of struct fields found in `keys` are
+/// copied from their encodings instead of being encoded again (see the
+/// [`precompute`](mod@crate::precompute) module). The bytes are the same.
+pub fn to_bytes_with_keys<T: Serialize>(
+    value: &T,
+    config: &Config,
+    keys: &[EncodedStr],
+) -> Result<Vec<u8>, Error> {
+    let mut serializer = CustomSerializer::new(config.clone(), None);
+    serializer.keys = keys;
+    serializer.serialize_root(value)?;
     Ok(serializer.data.into_vec())
 }
 
-impl CustomSerializer {
-    /// Get 'n' bits from end of the data.
-    fn _peek_n_bits(&self, size: usize) -> Result<&BitSlice<u8>, Error> {
+/// The same as [`to_bytes_with_config`] but every string (including map keys and field names)
+/// is passed through `normalize` before it is written. Producers that disagree on the Unicode
+/// normalization of their strings get the same bytes if they normalize them the same way, e.g.
+/// to NFC, which matters when the bytes are signed or hashed. Characters are written as they
+/// are. The bytes are deserialized as usual. The `nfc` feature adds `to_bytes_nfc`, which
+/// normalizes to NFC with `unicode-normalization`.
+///
+/// ```rust
+/// use rust_fr::{config::Config, serializer};
+/// use std::borrow::Cow;
+///
+/// // a normalizer of a single character.
+/// fn nfc(s: &str) -> Cow<'_, str> {
+///     match s.contains("e\u{301}") {
+///         true => s.replace("e\u{301}", "\u{e9}").into(),
+///         false => s.into(),
+///     }
+/// }
+///
+/// let config = Config::default();
+/// let decomposed = serializer::to_bytes_with_normalizer(&"cafe\u{301}", &config, &nfc).unwrap();
+/// let composed = serializer::to_bytes_with_normalizer(&"caf\u{e9}", &config, &nfc).unwrap();
+/// assert_eq!(decomposed, composed);
+/// ```
+pub fn to_bytes_with_normalizer<T: Serialize>(
+    value: &T,
+    config: &Config,
+    normalize: Normalizer,
+) -> Result<Vec<u8>, Error> {
+    let mut serializer = CustomSerializer::new(config.clone(), None);
+    serializer.normalizer = Some(normalize);
+    serializer.serialize_root(value)?;
+    Ok(serializer.data.into_vec())
+}
+
+/// The same as [`to_bytes_with_config`] but every string (including map keys and field names)
+/// is normalized to Unicode NFC (see [`nfc`]); [`to_bytes_with_normalizer`] with [`nfc`].
+///
+/// ```rust
+/// use rust_fr::{config::Config, serializer};
+///
+/// let config = Config::default();
+/// let decomposed = serializer::to_bytes_nfc(&"cafe\u{301}", &config).unwrap();
+/// let composed = serializer::to_bytes_nfc(&"caf\u{e9}", &config).unwrap();
+/// assert_eq!(decomposed, composed);
+/// ```
+#[cfg(feature = "nfc")]
+pub fn to_bytes_nfc<T: Serialize>(value: &T, config: &Config) -> Result<Vec<u8>, Error> {
+    to_bytes_with_normalizer(value, config, &nfc)
+}
+
+/// Normalize `s` to Unicode NFC with `unicode-normalization`. A string that is in NFC already
+/// (e.g. every ASCII string) is borrowed.
+#[cfg(feature = "nfc")]
+pub fn nfc(s: &str) -> Cow<'_, str> {
+    use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+    match is_nfc_quick(s.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(s),
+        IsNormalized::No | IsNormalized::Maybe => Cow::Owned(s.nfc().collect()),
+    }
+}
+
+/// The same as [`to_bytes_with_config`] but the fields of structs are checked against the
+/// constraints of `validator` while they are written; the first invalid one fails with
+/// [`Error::Invalid`]. See the [`validator`](crate::validator) module.
+pub fn to_bytes_with_validator<T: Serialize>(
+    value: &T,
+    config: &Config,
+    validator: &Validator,
+) -> Result<Vec<u8>, Error> {
+    let mut serializer = CustomSerializer::new(config.clone(), None);
+    serializer.validator = Some(validator);
+    serializer.serialize_root(value)?;
+    Ok(serializer.data.into_vec())
+}
+
+/// The same as [`to_bytes`] but the data is written into `buffer`, replacing its contents.
+/// The allocation of the buffer is reused, so serializing many values into the same buffer
+/// only allocates when a value outgrows it. To serialize into the memory of another allocator
+/// see `to_bytes_in` (with the `allocator-api2` feature).
+pub fn to_bytes_into<T: Serialize>(value: &T, buffer: &mut Vec<u8>) -> Result<(), Error> {
+    to_bytes_into_with_config(value, buffer, &Config::default())
+}
+
+/// The same as [`to_bytes_into`] but the data is encoded according to the given [`Config`].
+pub fn to_bytes_into_with_config<T: Serialize>(
+    value: &T,
+    buffer: &mut Vec<u8>,
+    config: &Config,
+) -> Result<(), Error> {
+    let mut storage = std::mem::take(buffer);
+    storage.clear();
+    let mut serializer = CustomSerializer::new(config.clone(), None);
+    serializer.data = bv::BitVec::from_vec(storage);
+    let result = serializer.serialize_root(value);
+    *buffer = serializer.data.into_vec();
+    if result.is_err() {
+        buffer.clear();
+    }
+    result
+}
+
+/// The same as [`to_bytes_with_config`] but the output is written into a vector of the
+/// allocator `alloc` (e.g. an arena of a plugin host) as it is serialized. Running out of
+/// memory in `alloc` fails with [`Error::SerializationError`] instead of aborting.
+///
+/// Only the output is allocated in `alloc`. The serializer keeps the bytes that may still
+/// change in a working buffer of its own in the global allocator: usually a few KiB, but the
+/// whole of a sequence or map that is written in the run-length encoding mode, as packed u8s,
+/// with an offset table, with its integer keys as a block or with option bitmaps. The
+/// deserializer has no counterpart; its temporary buffers are in the global allocator too.
+///
+/// ```rust
+/// use allocator_api2::alloc::Global;
+/// use rust_fr::{config::Config, serializer};
+///
+/// let config = Config::default();
+/// let bytes = serializer::to_bytes_in(&vec!["hello"; 1000], &config, Global).unwrap();
+/// assert_eq!(bytes[..], serializer::to_bytes(&vec!["hello"; 1000]).unwrap()[..]);
+/// ```
+#[cfg(feature = "allocator-api2")]
+pub fn to_bytes_in<T: Serialize, A: allocator_api2::alloc::Allocator>(
+    value: &T,
+    config: &Config,
+    alloc: A,
+) -> Result<allocator_api2::vec::Vec<u8, A>, Error> {
+    let mut bytes = allocator_api2::vec::Vec::new_in(alloc);
+    to_sink(value, config, |data| {
+        bytes
+            .try_reserve(data.len())
+            .map_err(|_| Error::SerializationError("the allocator is out of memory".to_string()))?;
+        bytes.extend_from_slice(data);
+        Ok(())
+    })?;
+    Ok(bytes)
+}
+
+/// The same as [`to_bytes_with_config`] but statistics about the data (the number of
+/// delimiters, of values of every type and of bits) are collected along the way, e.g. to see
+/// what the options of the [`Config`] save on a given data set.
+pub fn to_bytes_and_stats<T: Serialize>(
+    value: &T,
+    config: &Config,
+) -> Result<(Vec<u8>, Stats), Error> {
+    let mut serializer = CustomSerializer::new(config.clone(), None);
+    serializer.stats = Some(Stats::default());
+    serializer.serialize_root(value)?;
+    let mut stats = serializer.stats.unwrap_or_default();
+    stats.bits = serializer.data.len();
+    Ok((serializer.data.into_vec(), stats))
+}
+
+/// Serialized data along with its [`Stats`], for debugging; see [`to_payload`]. It derefs to
+/// the bytes. `Display` shows the size, the number of delimiters and of values of every type
+/// and a hexdump of the first [`EncodedPayload::DUMP_LIMIT`] bytes, while `Debug` is a single
+/// line.
+///
+/// ```rust
+/// use rust_fr::serializer;
+///
+/// let payload = serializer::to_payload(&("hi", vec![7u8])).unwrap();
+/// assert_eq!(payload.len(), 11);
+/// assert_eq!(
+///     payload.to_string(),
+///     "11 bytes (81 bits), header 0x2000
+/// delimiters: Seq 2, SeqValue 1
+/// values: U8 1, Str 1, Seq 2
+/// 0000  00 20 02 00 00 00 68 69 e3 c1 00                 |. ....hi...|
+/// "
+/// );
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncodedPayload {
+    bytes: Vec<u8>,
+    stats: Stats,
+}
+
+impl EncodedPayload {
+    /// The most bytes the hexdump of `Display` shows.
+    pub const DUMP_LIMIT: usize = 256;
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// The number of meaningful bits; the rest of the last byte is padding.
+    pub fn bit_len(&self) -> usize {
+        self.stats.bits
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl std::ops::Deref for EncodedPayload {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl AsRef<[u8]> for EncodedPayload {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl std::fmt::Display for EncodedPayload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let header = match self.bytes[..] {
+            [low, high, ..] => u16::from_le_bytes([low, high]),
+            _ => 0,
+        };
+        writeln!(
+            f,
+            "{} bytes ({} bits), header {header:#06x}",
+            self.bytes.len(),
+            self.stats.bits
+        )?;
+        // in the order of the specification, leaving out the ones that don't occur.
+        let delimiters = Delimiter::ALL
+            .iter()
+            .filter(|delimiter| self.stats.delimiter(delimiter) > 0)
+            .map(|delimiter| format!("{delimiter} {}", self.stats.delimiter(delimiter)))
+            .collect::<Vec<_>>();
+        writeln!(f, "delimiters: {}", delimiters.join(", "))?;
+        let values = TypeTag::ALL
+            .iter()
+            .filter(|tag| self.stats.values(**tag) > 0)
+            .map(|tag| format!("{tag} {}", self.stats.values(*tag)))
+            .collect::<Vec<_>>();
+        writeln!(f, "values: {}", values.join(", "))?;
+        let shown = &self.bytes[..self.bytes.len().min(Self::DUMP_LIMIT)];
+        for (line, chunk) in shown.chunks(16).enumerate() {
+            let hex = chunk
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let text = chunk
+                .iter()
+                .map(|&byte| match byte.is_ascii_graphic() || byte == b' ' {
+                    true => byte as char,
+                    false => '.',
+                })
+                .collect::<String>();
+            writeln!(f, "{:04x}  {hex:<47}  |{text}|", line * 16)?;
+        }
+        if shown.len() < self.bytes.len() {
+            writeln!(f, "... {} more bytes", self.bytes.len() - shown.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for EncodedPayload {
+    /// The size and the first bytes in hex.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hex = self
+            .bytes
+            .iter()
+            .take(16)
+            .fold(String::new(), |hex, byte| format!("{hex}{byte:02x}"));
+        let more = match self.bytes.len() > 16 {
+            true => "..",
+            false => "",
+        };
+        write!(
+            f,
+            "EncodedPayload({} bytes, {} bits: {hex}{more})",
+            self.bytes.len(),
+            self.stats.bits
+        )
+    }
+}
+
+/// The same as [`to_bytes`] but the bytes come as an [`EncodedPayload`] which shows what they
+/// are made of when printed.
+pub fn to_payload<T: Serialize>(value: &T) -> Result<EncodedPayload, Error> {
+    to_payload_with_config(value, &Config::default())
+}
+
+/// The same as [`to_payload`] but the data is encoded according to the given [`Config`].
+pub fn to_payload_with_config<T: Serialize>(
+    value: &T,
+    config: &Config,
+) -> Result<EncodedPayload, Error> {
+    let (bytes, stats) = to_bytes_and_stats(value, config)?;
+    Ok(EncodedPayload { bytes, stats })
+}
+
+/// The same as [`to_bytes_with_config`] but the bit ranges of the fields of the (first)
+/// outermost struct are returned along with the bytes; see the [`patch`](crate::patch) module.
+pub(crate) fn to_bytes_and_field_ranges<T: Serialize>(
+    value: &T,
+    config: &Config,
+) -> Result<(Vec<u8>, FieldRanges), Error> {
+    let mut serializer = CustomSerializer::new(config.clone(), None);
+    serializer.ranges = Some(FieldRanges::default());
+    serializer.serialize_root(value)?;
+    let ranges = serializer.ranges.unwrap_or_default();
+    Ok((serializer.data.into_vec(), ranges))
+}
+
+/// The same as [`to_bytes`] but the number of meaningful bits is returned along with the bytes.
+/// The last byte holds `8 * bytes.len() - bit_len` (less than 8) zero bits of padding, which
+/// matters when payloads are concatenated at the bit level or checked for trailing data.
+pub fn to_bytes_and_bit_len<T: Serialize>(value: &T) -> Result<(Vec<u8>, usize), Error> {
+    to_bytes_and_bit_len_with_config(value, &Config::default())
+}
+
+/// The same as [`to_bytes_and_bit_len`] but the data is encoded according to the given
+/// [`Config`].
+pub fn to_bytes_and_bit_len_with_config<T: Serialize>(
+    value: &T,
+    config: &Config,
+) -> Result<(Vec<u8>, usize), Error> {
+    let mut serializer = CustomSerializer::new(config.clone(), None);
+    serializer.serialize_root(value)?;
+    let bit_len = serializer.data.len();
+    Ok((serializer.data.into_vec(), bit_len))
+}
+
+/// The same as [`to_bytes`] but the bits are returned as they are instead of being rounded up
+/// to whole bytes, e.g. to append them to other bits. They can be deserialized with
+/// [`from_bitslice`](crate::deserializer::from_bitslice).
+pub fn to_bitvec<T: Serialize>(value: &T) -> Result<bv::BitVec<u8, bv::Lsb0>, Error> {
+    to_bitvec_with_config(value, &Config::default())
+}
+
+/// The same as [`to_bitvec`] but the data is encoded according to the given [`Config`]. The
+/// bits are in the default order so [`Config::msb_first`] isn't supported.
+pub fn to_bitvec_with_config<T: Serialize>(
+    value: &T,
+    config: &Config,
+) -> Result<bv::BitVec<u8, bv::Lsb0>, Error> {
+    if config.msb_first {
+        return Err(Error::UnsupportedCall(
+            "to_bitvec with `Config::msb_first`".to_string(),
+        ));
+    }
+    let mut serializer = CustomSerializer::new(config.clone(), None);
+    serializer.serialize_root(value)?;
+    Ok(serializer.data)
+}
+
+/// The same as [`to_bytes`] but the bytes are also fed into a [`Digest`] (e.g. `sha2::Sha256`)
+/// while they are produced. It returns the serialized bytes along with their digest.
+///
+/// ```rust
+/// use rust_fr::{digest::Digest, serializer};
+/// use sha2::Sha256;
+///
+/// let (bytes, hash) = serializer::to_bytes_hashed::<Sha256, _>(&vec!["hello"; 1000]).unwrap();
+/// assert_eq!(hash, Sha256::digest(&bytes));
+/// ```
+#[cfg(feature = "digest")]
+pub fn to_bytes_hashed<H: Digest, T: Serialize>(value: &T) -> Result<(Vec<u8>, Output<H>), Error> {
+    to_bytes_hashed_with_config::<H, T>(value, &Config::default())
+}
+
+/// The same as [`to_bytes_hashed`] but the data is encoded according to the given [`Config`].
+#[cfg(feature = "digest")]
+pub fn to_bytes_hashed_with_config<H: Digest, T: Serialize>(
+    value: &T,
+    config: &Config,
+) -> Result<(Vec<u8>, Output<H>), Error> {
+    let mut hasher = H::new();
+    let mut update = |bytes: &[u8]| {
+        hasher.update(bytes);
+        Ok(())
+    };
+    let mut serializer = CustomSerializer::new(config.clone(), Some(&mut update));
+    serializer.serialize_root(value)?;
+    let flushed = serializer.flushed;
+    let bytes = serializer.data.into_vec();
+    hasher.update(&bytes[flushed..]);
+    Ok((bytes, hasher.finalize()))
+}
+
+/// The same as [`to_bytes`] but the data is handed to `writer` in chunks while it is
+/// serialized. Errors of the writer are returned as [`Error::Io`] along with the position in
+/// the data of the write that failed, so they can be told apart from serialization errors.
+pub fn to_writer<W: Write, T: Serialize>(writer: W, value: &T) -> Result<(), Error> {
+    to_writer_with_config(writer, value, &Config::default())
+}
+
+/// The same as [`to_writer`] but the data is encoded according to the given [`Config`].
+pub fn to_writer_with_config<W: Write, T: Serialize>(
+    mut writer: W,
+    value: &T,
+    config: &Config,
+) -> Result<(), Error> {
+    let mut position = 0;
+    let mut write = |bytes: &[u8]| {
+        writer
+            .write_all(bytes)
+            .map_err(|source| Error::Io { source, position })?;
+        position += bytes.len();
+        Ok(())
+    };
+    let mut serializer = CustomSerializer::new(config.clone(), Some(&mut write));
+    serializer.serialize_root(value)?;
+    let flushed = serializer.flushed;
+    let bytes = serializer.data.into_vec();
+    write(&bytes[flushed..])?;
+    writer.flush().map_err(|source| Error::Io {
+        source,
+        position: bytes.len(),
+    })
+}
+
+// Serialize a value handing its bytes to `write` in pieces without keeping them all at once.
+pub(crate) fn to_sink<T: Serialize>(
+    value: &T,
+    config: &Config,
+    mut write: impl FnMut(&[u8]) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut serializer = CustomSerializer::new(config.clone(), Some(&mut write));
+    serializer.drain = true;
+    serializer.serialize_root(value)?;
+    let flushed = serializer.flushed;
+    let bytes = serializer.data.into_vec();
+    write(&bytes[flushed..])
+}
+
+/// Writes a map to an `io::Write` one entry at a time, e.g. a key-value dump too large to be
+/// held in memory. The data is the same as that of a map serialized at once (it is read back
+/// as any map, or entry by entry with a [`MapReader`](crate::deserializer::MapReader)) and the
+/// bytes are handed to the writer as they settle, so only the last few KiB are kept.
+///
+/// The map is only complete once [`MapWriter::finish`] has written its end; a writer that is
+/// dropped before (or after an error) leaves a map that readers see as cut off. The entries
+/// are written as they come, so maps with an offset table in front (see
+/// [`Config::offset_tables`]) can't be written this way and integer keys aren't put in a
+/// block (see [`Config::sorted_int_keys`]).
+///
+/// ```rust
+/// use rust_fr::{config::Config, deserializer, serializer::MapWriter};
+/// use std::collections::BTreeMap;
+///
+/// let config = Config::default();
+/// let mut map = MapWriter::new(Vec::new(), &config).unwrap();
+/// for i in 0..1000u32 {
+///     map.insert(&i, &format!("value {i}")).unwrap();
+/// }
+/// let bytes = map.finish().unwrap();
+///
+/// let decoded = deserializer::from_bytes::<BTreeMap<u32, String>>(&bytes).unwrap();
+/// assert_eq!(decoded.len(), 1000);
+/// assert_eq!(decoded[&42], "value 42");
+/// ```
+pub struct MapWriter<W: Write> {
+    writer: W,
+    serializer: CustomSerializer<'static>,
+    // The number of bytes handed to the writer so far.
+    position: usize,
+}
+
+impl<W: Write> MapWriter<W> {
+    /// Start a map in `writer` that is serialized with the given config.
+    pub fn new(writer: W, config: &Config) -> Result<Self, Error> {
+        for (set, option) in [
+            (config.offset_tables, "`Config::offset_tables`"),
+            (config.msb_first, "`Config::msb_first`"),
+        ] {
+            if set {
+                return Err(Error::UnsupportedCall(format!(
+                    "MapWriter::new with {option}"
+                )));
+            }
+        }
+        let mut serializer = CustomSerializer::new(config.clone(), None);
+        serializer.serialize_header()?;
+        Serializer::serialize_map(&mut serializer, None)?;
+        // the keys go in front of their values whatever they are.
+        if let Some(Frame::Map(MapFrame {
+            block: Some(block), ..
+        })) = serializer.containers.last_mut()
+        {
+            block.decided = true;
+        }
+        Ok(Self {
+            writer,
+            serializer,
+            position: 0,
+        })
+    }
+
+    /// Serialize the next entry of the map.
+    pub fn insert<K, V>(&mut self, key: &K, value: &V) -> Result<(), Error>
+    where
+        K: ?Sized + Serialize,
+        V: ?Sized + Serialize,
+    {
+        // the inherent `serialize_value` would write a header.
+        SerializeMap::serialize_key(&mut &mut self.serializer, key)?;
+        SerializeMap::serialize_value(&mut &mut self.serializer, value)?;
+        if self.serializer.data.len() / 8 >= FLUSH_SIZE {
+            self.write_settled()?;
+        }
+        Ok(())
+    }
+
+    /// Write the end of the map and flush the writer. Returns the writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.serializer.end_map()?;
+        let bytes = std::mem::take(&mut self.serializer.data).into_vec();
+        self.write(&bytes)?;
+        self.writer.flush().map_err(|source| Error::Io {
+            source,
+            position: self.position,
+        })?;
+        Ok(self.writer)
+    }
+
+    /// Hand the whole bytes written so far to the writer, keeping the bits of the last byte
+    /// that isn't complete yet.
+    fn write_settled(&mut self) -> Result<(), Error> {
+        let data = &mut self.serializer.data;
+        let settled = data.len() / 8;
+        self.writer
+            .write_all(&data.as_raw_slice()[..settled])
+            .map_err(|source| Error::Io {
+                source,
+                position: self.position,
+            })?;
+        self.position += settled;
+        let rest = data.len() - settled * 8;
+        if rest > 0 {
+            data.copy_within(settled * 8.., 0);
+        }
+        data.truncate(rest);
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.writer.write_all(bytes).map_err(|source| Error::Io {
+            source,
+            position: self.position,
+        })?;
+        self.position += bytes.len();
+        Ok(())
+    }
+}
+
+impl<'a> CustomSerializer<'a> {
+    pub(crate) fn new(config: Config, sink: Option<Sink<'a>>) -> Self {
+        Self {
+            data: bv::BitVec::new(),
+            config,
+            sink,
+            flushed: 0,
+            drain: false,
+            drained: 0,
+            containers: Vec::new(),
+            implied_some: false,
+            stats: None,
+            encoders: None,
+            normalizer: None,
+            validator: None,
+            structs: Vec::new(),
+            field: None,
+            keys: &[],
+            found_keys: HashMap::new(),
+            ranges: None,
+        }
+    }
+
+    /// Serialize the header (the options of the config as a u16) and the value in the bit
+    /// order of the config.
+    fn serialize_root<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+        let result = self.serialize_ordered(value);
+        #[cfg(feature = "metrics")]
+        crate::metrics::encoded(&result, self.drained + self.data.len().div_ceil(8), started);
+        result
+    }
+
+    /// Serialize the header and the value in the bit order of the config.
+    fn serialize_ordered<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        if !self.config.msb_first {
+            return self.serialize_value(value);
+        }
+        let placeholder = CustomSerializer::new(Config::default(), None);
+        let mut serializer = std::mem::replace(self, placeholder).reorder::<bv::Msb0>();
+        let result = serializer.serialize_value(value);
+        *self = serializer.reorder();
+        result
+    }
+}
+
+impl<'a, O: WireOrder> CustomSerializer<'a, O> {
+    /// The same serializer in the bit order `P`. The bits written so far are taken over as
+    /// bytes, so the data must be empty or complete.
+    fn reorder<P: WireOrder>(self) -> CustomSerializer<'a, P> {
         let len = self.data.len();
-        if size > len {
-            return Err(Error::NLargerThanLength(size, self.data.len()));
+        let mut data = bv::BitVec::from_vec(self.data.into_vec());
+        data.truncate(len);
+        CustomSerializer {
+            data,
+            config: self.config,
+            sink: self.sink,
+            flushed: self.flushed,
+            drain: self.drain,
+            drained: self.drained,
+            containers: self.containers,
+            implied_some: self.implied_some,
+            stats: self.stats,
+            encoders: self.encoders,
+            normalizer: self.normalizer,
+            validator: self.validator,
+            structs: self.structs,
+            field: self.field,
+            keys: self.keys,
+            found_keys: self.found_keys,
+            ranges: self.ranges,
+        }
+    }
+
+    /// Serialize the header and the value.
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.serialize_header()?;
+        value.serialize(&mut *self)
+    }
+
+    /// Serialize the header: the options of the config as a u16.
+    pub(crate) fn serialize_header(&mut self) -> Result<(), Error> {
+        if let Some(conflict) = self.config.conflict() {
+            return Err(Error::SerializationError(conflict.to_string()));
+        }
+        self.write_bytes(&self.config.header().to_le_bytes());
+        Ok(())
+    }
+
+    /// Hand the bytes that can't change anymore to the sink (if any). Bytes of an open
+    /// sequence (or map) may still be rewritten in the run-length encoding mode, when packing
+    /// sequences of u8, when writing offset tables, when writing integer keys as a block, when
+    /// writing option bitmaps and when prefixing keys with their length.
+    fn flush(&mut self) -> Result<(), Error> {
+        let Some(sink) = self.sink.as_mut() else {
+            return Ok(());
+        };
+        let held = self.containers.iter().flat_map(Frame::held).flatten();
+        let settled = held.clone().fold(self.data.len(), usize::min) / 8;
+        if settled >= self.flushed + FLUSH_SIZE {
+            sink(&self.data.as_raw_slice()[self.flushed..settled])?;
+            self.flushed = settled;
+            // the positions held by the open containers mustn't move.
+            if self.drain && held.count() == 0 {
+                let rest = self.data.len() - settled * 8;
+                if rest > 0 {
+                    self.data.copy_within(settled * 8.., 0);
+                }
+                self.data.truncate(rest);
+                self.drained += settled;
+                self.flushed = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Count a value of the given type in the statistics (if any).
+    fn count_value(&mut self, tag: TypeTag, count: usize) {
+        if let Some(stats) = self.stats.as_mut() {
+            *stats.values.entry(tag).or_default() += count;
+        }
+    }
+
+    /// Take a delimiter that was removed from the data off the statistics (if any).
+    fn uncount_token(&mut self, token: Delimiter) {
+        if let Some(count) = self
+            .stats
+            .as_mut()
+            .and_then(|s| s.delimiters.get_mut(&token))
+        {
+            *count -= 1;
+        }
+    }
+
+    /// Serialize a type tag to the data; only in the self-describing mode.
+    pub fn serialize_tag(&mut self, tag: TypeTag) {
+        self.count_value(tag, 1);
+        if self.config.self_describing {
+            self.write_token(tag.token());
         }
-        self.data.get(len - size..).ok_or(Error::NoByte)
     }
 
-    /// Construst a byte from the last 3 bits of the data.
-    pub fn peek_token(&self, token: Delimiter) -> Result<bool, Error> {
-        let bits = match token {
-            Delimiter::String => self._peek_n_bits(8)?,
-            Delimiter::Byte => self._peek_n_bits(8)?,
-            Delimiter::Map => self._peek_n_bits(8)?,
-            _ => self._peek_n_bits(3)?,
+    /// Check a primitive against the constraints of the field it is in (if any).
+    fn validate(&self, value: impl FnOnce() -> Primitive) -> Result<(), Error> {
+        match (self.validator, self.field) {
+            (Some(validator), Some((name, field))) => validator.validate(name, field, &value()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Write whole bytes in one bulk copy rather than one byte (or bit) at a time.
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_raw_slice(bytes);
+    }
+
+    /// An empty serializer with the same config, encodings and normalizer, for a column of a
+    /// columnar sequence (see [`columnar`]).
+    fn column(&self) -> CustomSerializer<'a, O> {
+        let mut column = CustomSerializer::new(self.config.clone(), None).reorder::<O>();
+        column.encoders = self.encoders;
+        column.normalizer = self.normalizer;
+        column.keys = self.keys;
+        column
+    }
+
+    /// Write a sequence of structs column by column (see [`columnar`]): the number of rows
+    /// (u32), the number of fields (u32), the names of the fields as strings and every column:
+    /// padding up to the next byte boundary, its length in bits (u32) and its bits.
+    fn serialize_columns(&mut self, columns: columnar::Columns<'_, O>) -> Result<(), Error> {
+        let too_long = |what: &str| {
+            Error::SerializationError(format!("a columnar sequence has more than u32::MAX {what}"))
         };
-        let mut byte = 0u8;
-        for (i, bit) in bits.iter().enumerate() {
-            if *bit {
-                byte |= 1 << i;
+        let rows = u32::try_from(columns.rows).map_err(|_| too_long("rows"))?;
+        let fields = u32::try_from(columns.names.len()).map_err(|_| too_long("fields"))?;
+        // so a reader can bound the number of rows by the bits of the columns.
+        let bits = columns.values.iter().map(|column| column.data.len());
+        if columns.rows > bits.sum() {
+            return Err(Error::SerializationError(
+                "the rows of a columnar sequence must write at least a bit each".to_string(),
+            ));
+        }
+        self.write_bytes(&rows.to_le_bytes());
+        self.write_bytes(&fields.to_le_bytes());
+        for name in columns.names {
+            self.write_framed(name.as_bytes(), Production::Str.last())?;
+        }
+        for column in columns.values {
+            self.align_to_byte();
+            let bits =
+                u32::try_from(column.data.len()).map_err(|_| too_long("bits in a column"))?;
+            self.write_bytes(&bits.to_le_bytes());
+            self.data.extend_from_bitslice(&column.data);
+        }
+        self.flush()
+    }
+
+    /// Write the bytes of a string (or bytes) ended by `end` the way [`Config::string_framing`]
+    /// says: padding + bytes + `end`, with the bytes escaped (see [`STRING_ESCAPE`]) or not,
+    /// or length (u32) + padding + bytes.
+    fn write_framed(&mut self, bytes: &[u8], end: Delimiter) -> Result<(), Error> {
+        match self.config.string_framing {
+            StringFraming::Delimited => {
+                self.align_to_byte();
+                self.write_bytes(bytes);
+                self.serialize_token(end);
+            }
+            StringFraming::Escaped => {
+                self.align_to_byte();
+                let mut start = 0;
+                for (i, byte) in bytes.iter().enumerate() {
+                    if *byte == end.value() || *byte == STRING_ESCAPE {
+                        self.write_bytes(&bytes[start..i]);
+                        self.write_bytes(&[STRING_ESCAPE]);
+                        start = i;
+                    }
+                }
+                self.write_bytes(&bytes[start..]);
+                self.serialize_token(end);
+            }
+            StringFraming::LengthPrefixed => {
+                let length = u32::try_from(bytes.len()).map_err(|_| {
+                    Error::SerializationError(
+                        "a string or bytes is longer than u32::MAX bytes".to_string(),
+                    )
+                })?;
+                self.write_bytes(&length.to_le_bytes());
+                self.align_to_byte();
+                self.write_bytes(bytes);
             }
         }
-        Ok(byte == token as u8)
+        Ok(())
+    }
+
+    /// Write the bytes of a custom encoding (see [`Encoders`]): their length (u32), padding up
+    /// to the next byte boundary and the bytes.
+    fn serialize_custom(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let length = u32::try_from(bytes.len()).map_err(|_| {
+            Error::SerializationError("a custom encoding is longer than u32::MAX bytes".to_string())
+        })?;
+        self.write_bytes(&length.to_le_bytes());
+        self.align_to_byte();
+        self.write_bytes(bytes);
+        Ok(())
+    }
+
+    /// Write a 64-bit integer in the adaptive integer mode (see [`Config::adaptive_ints`]):
+    /// a 2 bit width tag (1, 2, 4 or 8 bytes) followed by that many bytes of the value.
+    fn serialize_adaptive(&mut self, v: u64) {
+        let width = match v {
+            0..=0xff => 0,
+            0x100..=0xffff => 1,
+            0x1_0000..=0xffff_ffff => 2,
+            _ => 3,
+        };
+        self.write_token(Token::new(width, ADAPTIVE_WIDTH));
+        self.write_bytes(&v.to_le_bytes()[..1 << width]);
     }
 
-    /// Get token before 'n' bits.
-    pub fn peek_token_before_n_bits(&self, n: usize) -> Result<u8, Error> {
-        let bits = self._peek_n_bits(n + 3)?[0..3].as_ref();
-        let mut byte = 0u8;
-        for (i, bit) in bits.iter().enumerate() {
-            if *bit {
-                byte |= 1 << i;
+    /// Write the index of an enum variant: one byte, or [`VARIANT_INDEX_ESCAPE`] followed by
+    /// the index as a u32 for the rare enums with 255 variants or more.
+    pub(crate) fn serialize_variant_index(&mut self, index: u32) {
+        match u8::try_from(index) {
+            Ok(index) if index != VARIANT_INDEX_ESCAPE => self.write_bytes(&[index]),
+            _ => {
+                self.write_bytes(&[VARIANT_INDEX_ESCAPE]);
+                self.write_bytes(&index.to_le_bytes());
             }
         }
-        Ok(byte)
     }
 
-    /// Serialize a token to the data.
-    pub fn serialize_token(&mut self, token: Delimiter) {
-        match token {
-            Delimiter::String => {
-                self.data
-                    .extend(&[false, true, true, false, false, false, false, true]);
-                // 10000110
+    /// Write the name of an enum variant; only in the self-describing mode.
+    pub(crate) fn serialize_variant_name(&mut self, variant: &str) -> Result<(), Error> {
+        match self.config.self_describing {
+            true => self.write_framed(variant.as_bytes(), Production::Str.last()),
+            false => Ok(()),
+        }
+    }
+
+    /// Begin a map (or struct).
+    fn begin_map(&mut self) {
+        let mut map = MapFrame::default();
+        if self.config.offset_tables {
+            map.table = Some(OffsetTable {
+                start: self.data.len(),
+                entries: Vec::new(),
+            });
+        }
+        if self.config.sorted_int_keys {
+            map.block = Some(KeyBlock {
+                start: self.data.len(),
+                keys: None,
+                decided: false,
+            });
+            self.data.push(false);
+        }
+        self.containers.push(Frame::Map(map));
+    }
+
+    /// Begin an entry of a map right before its key.
+    fn begin_entry(&mut self) {
+        let position = self.data.len();
+        if let Some(Frame::Map(MapFrame {
+            table: Some(table), ..
+        })) = self.containers.last_mut()
+        {
+            table.entries.push(position);
+        }
+    }
+
+    /// Begin the key of a map entry (or the name of a struct field): MAP_KEY_DELIMITER. When
+    /// prefixing keys with their length (see [`Config::length_prefixed_keys`]) the key starts
+    /// on a byte boundary, so its bits are the same wherever it is.
+    fn begin_key(&mut self) {
+        self.serialize_token(Production::Map.item(0));
+        if self.config.length_prefixed_keys {
+            if let Some(Frame::Map(map)) = self.containers.last_mut() {
+                map.key = Some(self.data.len());
+            }
+            self.align_to_byte();
+        }
+    }
+
+    /// End the key of a map entry. When prefixing keys with their length the length of the
+    /// key in bits (u32) is put in front of its padding.
+    pub(crate) fn end_key(&mut self) -> Result<(), Error> {
+        let Some(Frame::Map(map)) = self.containers.last_mut() else {
+            return Ok(());
+        };
+        let Some(start) = map.key.take() else {
+            return Ok(());
+        };
+        let key = self.data.split_off(start);
+        let padding = (8 - start % 8) % 8;
+        let length = u32::try_from(key.len() - padding).map_err(|_| {
+            Error::SerializationError("the key is too large for its length".to_string())
+        })?;
+        self.write_bytes(&length.to_le_bytes());
+        self.data.extend_from_bitslice(&key);
+        Ok(())
+    }
+
+    /// Put the key of a map into the block of keys if the keys of the map are unsigned
+    /// integers. Returns false if the key must be written in front of its value.
+    fn block_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<bool, Error> {
+        let Some(Frame::Map(MapFrame {
+            block: Some(block), ..
+        })) = self.containers.last_mut()
+        else {
+            return Ok(false);
+        };
+        let unsigned = key.serialize(UnsignedProbe).unwrap_or(None);
+        if !block.decided {
+            block.decided = true;
+            if let Some(unsigned) = unsigned {
+                block.keys = Some(BlockKeys {
+                    kind: unsigned.tag,
+                    count: 0,
+                    bytes: Vec::new(),
+                    last: 0,
+                    sorted: true,
+                });
+            }
+        }
+        let Some(keys) = block.keys.as_mut() else {
+            return Ok(false);
+        };
+        let Some(Unsigned { tag, value }) = unsigned.filter(|key| key.tag == keys.kind) else {
+            return Err(Error::SerializationError(format!(
+                "the keys of a map must all be {} to be written as a block",
+                keys.kind
+            )));
+        };
+        keys.sorted &= keys.count == 0 || keys.last < value;
+        keys.last = value;
+        keys.count = keys.count.checked_add(1).ok_or_else(|| {
+            Error::SerializationError("the map has too many keys for a block".to_string())
+        })?;
+        keys.bytes
+            .extend_from_slice(&value.to_le_bytes()[..Unsigned::size(tag)]);
+        self.count_value(tag, 1);
+        Ok(true)
+    }
+
+    /// End a map. With offset tables the table is put in front of the entries:
+    /// count (u32) + offset of every entry (u32) + offset of the end (u32). The offsets are
+    /// in bits from the end of the table.
+    pub(crate) fn end_map(&mut self) -> Result<(), Error> {
+        let Some(Frame::Map(mut map)) = self.containers.pop() else {
+            return Err(unbalanced("a map"));
+        };
+        if let Some(block) = map.block {
+            self.write_key_block(block, map.table.as_mut());
+        }
+        if let Some(table) = map.table {
+            self.write_offset_table(table)?;
+        }
+        self.serialize_token(Production::Map.last());
+        Ok(())
+    }
+
+    /// Put the block of keys (if any) in front of the values of a map:
+    /// 1 + count (u32) + size of a key in bytes (u8) + sorted (u8) + key_1 + key_2 + ...
+    /// The block is a whole number of bytes so the values stay aligned. The entries of the
+    /// offset table (if any) move along with the values.
+    fn write_key_block(&mut self, block: KeyBlock, table: Option<&mut OffsetTable>) {
+        let Some(keys) = block.keys else {
+            return;
+        };
+        let values = self.data.split_off(block.start + 1);
+        self.data.set(block.start, true);
+        self.write_bytes(&keys.count.to_le_bytes());
+        // the keys are at most 8 bytes long.
+        let size = keys.bytes.len() / keys.count as usize;
+        self.write_bytes(&[size as u8, keys.sorted as u8]);
+        self.write_bytes(&keys.bytes);
+        let moved = self.data.len() - (block.start + 1);
+        self.data.extend_from_bitslice(&values);
+        if let Some(table) = table {
+            for entry in &mut table.entries {
+                *entry += moved;
+            }
+        }
+    }
+
+    fn write_offset_table(&mut self, table: OffsetTable) -> Result<(), Error> {
+        let offset = |position: usize| {
+            u32::try_from(position - table.start).map_err(|_| {
+                Error::SerializationError("the map is too large for an offset table".to_string())
+            })
+        };
+        let count = u32::try_from(table.entries.len()).map_err(|_| {
+            Error::SerializationError("the map is too large for an offset table".to_string())
+        })?;
+        let mut offsets = vec![count];
+        for position in table.entries.iter().chain([&self.data.len()]) {
+            offsets.push(offset(*position)?);
+        }
+        let entries = self.data.split_off(table.start);
+        for value in offsets {
+            self.write_bytes(&value.to_le_bytes());
+        }
+        self.data.extend_from_bitslice(&entries);
+        Ok(())
+    }
+
+    /// Begin a tuple (or tuple struct or tuple variant): a seq() in the self-describing mode
+    /// and otherwise the elements alone, as both ends know how many there are.
+    fn begin_tuple(&mut self, len: usize) -> Result<&mut Self, Error> {
+        match self.config.self_describing {
+            true => self.serialize_seq(Some(len)),
+            false => {
+                self.serialize_tag(TypeTag::Seq);
+                Ok(self)
+            }
+        }
+    }
+
+    /// Serialize an element of a tuple (or tuple struct or tuple variant).
+    fn serialize_tuple_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        match self.config.self_describing {
+            true => self.serialize_seq_element(value),
+            false => {
+                value.serialize(&mut *self)?;
+                self.flush()
+            }
+        }
+    }
+
+    /// End a tuple (or tuple struct or tuple variant).
+    fn end_tuple(&mut self) -> Result<(), Error> {
+        match self.config.self_describing {
+            true => self.end_seq(),
+            false => Ok(()),
+        }
+    }
+
+    /// Begin a struct (or struct variant): a map() or, without the field names (see
+    /// [`StructKeys::Omit`]), the values alone. The values are a tuple() in the
+    /// self-describing mode and follow a bitmap of the present fields when writing option
+    /// bitmaps (see [`Config::option_bitmaps`]).
+    fn begin_struct(&mut self, len: usize) -> Result<&mut Self, Error> {
+        if let Some(ranges) = self.ranges.as_mut() {
+            ranges.depth += 1;
+        }
+        match (self.config.struct_keys, self.config.self_describing) {
+            (StructKeys::Names, _) => self.serialize_map(Some(len)),
+            (StructKeys::Omit, true) => self.serialize_tuple(len),
+            (StructKeys::Omit, false) => {
+                let mut bitmap = None;
+                if self.config.option_bitmaps {
+                    let bits = len.div_ceil(8) * 8;
+                    bitmap = Some(OptionBitmap {
+                        start: self.data.len(),
+                        len: bits,
+                        field: 0,
+                    });
+                    self.data.resize(self.data.len() + bits, false);
+                }
+                self.containers.push(Frame::Values(bitmap));
+                Ok(self)
+            }
+        }
+    }
+
+    /// Whether the field of a struct is written at all when writing option bitmaps: `None`
+    /// isn't and `Some` is without its presence bit. Without the field names the field is
+    /// marked as present in the bitmap of the struct.
+    fn option_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<bool, Error> {
+        if !self.config.option_bitmaps {
+            return Ok(true);
+        }
+        let probe = value.serialize(OptionProbe).unwrap_or(Probe::Other);
+        if probe == Probe::None {
+            self.count_value(TypeTag::None, 1);
+        }
+        self.implied_some = probe == Probe::Some;
+        if let Some(Frame::Values(Some(bitmap))) = self.containers.last_mut() {
+            if bitmap.field == bitmap.len {
+                return Err(Error::SerializationError(
+                    "the struct has more fields than it announced".to_string(),
+                ));
             }
-            Delimiter::Byte => {
-                self.data
-                    .extend(&[true, true, true, false, false, false, false, true]);
-                // 10000111
+            if probe != Probe::None {
+                self.data.set(bitmap.start + bitmap.field, true);
             }
-            Delimiter::Unit => {
-                self.data.extend(&[false, true, false]); // 010
+            bitmap.field += 1;
+        }
+        Ok(probe != Probe::None)
+    }
+
+    /// Serialize a field of a struct: MAP_KEY_DELIMITER + key + MAP_VALUE_DELIMITER + value
+    /// or the value alone (after padding with aligned fields).
+    fn serialize_struct_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        if !self.option_field(value)? {
+            return self.flush();
+        }
+        let field = match (self.validator, self.structs.last()) {
+            (Some(validator), Some(&name)) => validator.contains(name, key).then_some((name, key)),
+            _ => None,
+        };
+        let outer = self.field.take();
+        match (self.config.struct_keys, self.config.self_describing) {
+            (StructKeys::Names, _) => {
+                self.begin_entry();
+                self.begin_key();
+                match self.encoded_key(key) {
+                    Some(encoded) => {
+                        self.serialize_tag(TypeTag::Str);
+                        self.write_encoded(encoded);
+                    }
+                    None => key.serialize(&mut *self)?,
+                }
+                self.end_key()?;
+                self.serialize_token(Production::Map.item(1));
+                self.field = field;
+                value.serialize(&mut *self)?;
             }
-            Delimiter::Seq => {
-                self.data.extend(&[true, true, false]); // 011
+            (StructKeys::Omit, true) => {
+                self.field = field;
+                self.serialize_seq_element(value)?;
             }
-            Delimiter::SeqValue => {
-                self.data.extend(&[false, false, true]); // 100
+            (StructKeys::Omit, false) => {
+                if self.config.aligned_fields {
+                    self.align_to_byte();
+                }
+                let start = self.data.len();
+                self.field = field;
+                value.serialize(&mut *self)?;
+                let end = self.data.len();
+                if let Some(ranges) = self.ranges.as_mut() {
+                    if ranges.depth == 1 && !ranges.done {
+                        ranges.fields.push((key, start..end));
+                    }
+                }
             }
-            Delimiter::Map => {
-                self.data
-                    .extend(&[true, true, false, true, false, false, false, true]);
-                // 10001011
+        }
+        self.field = outer;
+        self.flush()
+    }
+
+    /// The encodings of the name of a struct field if it was encoded ahead of time and can be
+    /// copied as it is: the name is written like any other string otherwise, e.g. when it is
+    /// normalized or counted in the statistics.
+    fn encoded_key(&mut self, key: &'static str) -> Option<EncodedStr> {
+        if self.keys.is_empty() || self.normalizer.is_some() || self.stats.is_some() {
+            return None;
+        }
+        let keys = self.keys;
+        let framing = self.config.string_framing;
+        *self
+            .found_keys
+            .entry((key.as_ptr() as usize, key.len()))
+            .or_insert_with(|| {
+                let encoded = *keys.iter().find(|encoded| encoded.text() == key)?;
+                match framing {
+                    StringFraming::Delimited => {
+                        (encoded.bytes().len() == key.len() + 1).then_some(encoded)
+                    }
+                    StringFraming::Escaped | StringFraming::LengthPrefixed => Some(encoded),
+                }
+            })
+    }
+
+    /// Write a string encoded ahead of time (see [`EncodedStr`]) the way
+    /// [`Config::string_framing`] says. With the length prefixed the length and the text are
+    /// copied at once if there's no padding between them.
+    fn write_encoded(&mut self, encoded: EncodedStr) {
+        match self.config.string_framing {
+            StringFraming::Delimited | StringFraming::Escaped => {
+                self.align_to_byte();
+                self.write_bytes(encoded.bytes());
             }
-            Delimiter::MapKey => {
-                self.data.extend(&[false, true, true]); // 110
+            StringFraming::LengthPrefixed if self.data.len().is_multiple_of(8) => {
+                self.write_bytes(encoded.prefixed());
             }
-            Delimiter::MapValue => {
-                self.data.extend(&[true, true, true]); // 111
+            StringFraming::LengthPrefixed => {
+                let (length, text) = encoded.prefixed().split_at(4);
+                self.write_bytes(length);
+                self.align_to_byte();
+                self.write_bytes(text);
             }
         }
     }
+
+    /// Fail on a skipped field of a struct whose field names are omitted.
+    fn skip_struct_field(&mut self, key: &'static str) -> Result<(), Error> {
+        match self.config.struct_keys {
+            StructKeys::Names => Ok(()),
+            StructKeys::Omit => Err(Error::SerializationError(format!(
+                "the field {key} was skipped but struct field names are omitted"
+            ))),
+        }
+    }
+
+    /// End a struct (or struct variant).
+    fn end_struct(&mut self) -> Result<(), Error> {
+        if self.validator.is_some() {
+            self.structs.pop();
+        }
+        if let Some(ranges) = self.ranges.as_mut() {
+            ranges.depth -= 1;
+            ranges.done |= ranges.depth == 0;
+        }
+        match (self.config.struct_keys, self.config.self_describing) {
+            (StructKeys::Names, _) => self.end_map(),
+            (StructKeys::Omit, true) => self.end_seq(),
+            (StructKeys::Omit, false) => match self.containers.pop() {
+                Some(Frame::Values(_)) => Ok(()),
+                _ => Err(unbalanced("a struct")),
+            },
+        }
+    }
+
+    /// Begin an element of a sequence that is written piece by piece instead of from a
+    /// value (see [`TokenWriter`](crate::tokens::TokenWriter)). It is neither packed nor
+    /// folded into a run.
+    pub(crate) fn begin_seq_element(&mut self) -> Result<(), Error> {
+        if self.config.pack_u8_seqs {
+            self.pack_seq_element(&())?;
+        }
+        if let Some(Frame::Seq(seq)) = self.containers.last_mut() {
+            seq.group = None;
+        }
+        self.serialize_token(Production::Seq.item(0));
+        Ok(())
+    }
+
+    /// Begin the key of a map entry that is written piece by piece; it can't be part of a
+    /// block of keys and ends with `end_key`.
+    pub(crate) fn begin_map_key(&mut self) -> Result<(), Error> {
+        self.begin_entry();
+        self.block_key(&())?;
+        self.begin_key();
+        Ok(())
+    }
+
+    /// Begin a sequence right after its SEQ_DELIMITER.
+    fn begin_seq(&mut self) {
+        let packed = self.config.pack_u8_seqs.then(|| PackedSeq {
+            start: self.data.len() - Production::Seq.first().width(),
+            bytes: Some(Vec::new()),
+        });
+        self.containers.push(Frame::Seq(SeqFrame {
+            group: None,
+            packed,
+        }));
+    }
+
+    /// Hold back the element if it is a u8 and so were all the elements before it. Otherwise
+    /// the held back elements are written as usual.
+    fn pack_seq_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<bool, Error> {
+        let Some(Frame::Seq(SeqFrame {
+            packed: Some(packed),
+            ..
+        })) = self.containers.last_mut()
+        else {
+            return Ok(false);
+        };
+        let Some(bytes) = packed.bytes.as_mut() else {
+            return Ok(false);
+        };
+        if let Ok(Some(Unsigned {
+            tag: TypeTag::U8,
+            value,
+        })) = value.serialize(UnsignedProbe)
+        {
+            bytes.push(value as u8);
+            return Ok(true);
+        }
+        let held_back = packed.bytes.take().unwrap_or_default();
+        for byte in held_back {
+            self.serialize_seq_element(&byte)?;
+        }
+        Ok(false)
+    }
+
+    /// End a sequence that was packed: PACKED_DELIMITER + length (u32) + padding + bytes.
+    /// Returns false if the sequence can't be packed.
+    fn end_packed_seq(&mut self, packed: Option<PackedSeq>) -> Result<bool, Error> {
+        match packed {
+            Some(PackedSeq {
+                start,
+                bytes: Some(bytes),
+            }) if !bytes.is_empty() => {
+                let length = u32::try_from(bytes.len()).map_err(|_| {
+                    Error::SerializationError(
+                        "a packed sequence is longer than u32::MAX bytes".to_string(),
+                    )
+                })?;
+                self.data.truncate(start);
+                self.uncount_token(Production::Seq.first());
+                self.count_value(TypeTag::U8, bytes.len());
+                self.serialize_token(Production::Packed.first());
+                self.write_bytes(&length.to_le_bytes());
+                self.align_to_byte();
+                self.write_bytes(&bytes);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Serialize an element of a sequence. In the run-length encoding mode an element equal
+    /// to the previous one is folded into its group instead of being written again.
+    pub(crate) fn serialize_seq_element<T: ?Sized + Serialize>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.write_seq_element(value)?;
+        self.flush()
+    }
+
+    fn write_seq_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        if self.config.pack_u8_seqs && self.pack_seq_element(value)? {
+            return Ok(());
+        }
+        if !self.config.run_length_encoding {
+            self.serialize_token(Production::Seq.item(0));
+            return value.serialize(self);
+        }
+        let Some(Frame::Seq(SeqFrame {
+            group: Some(group), ..
+        })) = self.containers.last()
+        else {
+            return self.serialize_group(value, None);
+        };
+        // the element is serialized at the same bit offset as the last one so the
+        // padding (if any) in front of strings and bytes comes out the same.
+        let offset = group.element % 8;
+        let element = self.serialize_detached(value, offset)?;
+        if group.count < u32::MAX && element == self.data[group.element..] {
+            self.repeat_group();
+            return Ok(());
+        }
+        // the element is serialized again if its values are to be counted.
+        match (self.data.len() + Production::Seq.item(0).width()) % 8 == offset
+            && self.stats.is_none()
+        {
+            true => self.serialize_group(value, Some(element)),
+            false => self.serialize_group(value, None),
+        }
+    }
+
+    /// Start a new group with the given element, written as is if it was already serialized
+    /// at the right offset.
+    fn serialize_group<T: ?Sized + Serialize>(
+        &mut self,
+        value: &T,
+        serialized: Option<bv::BitVec<u8, O>>,
+    ) -> Result<(), Error> {
+        let start = self.data.len();
+        self.serialize_token(Production::Seq.item(0));
+        let element = self.data.len();
+        match serialized {
+            Some(bits) => self.data.extend_from_bitslice(&bits),
+            None => value.serialize(&mut *self)?,
+        }
+        if let Some(Frame::Seq(seq)) = self.containers.last_mut() {
+            seq.group = Some(Group {
+                start,
+                element,
+                count: 1,
+            });
+        }
+        Ok(())
+    }
+
+    /// Count the element of the last group once more. A group of one element becomes
+    /// RUN_DELIMITER + count + element.
+    fn repeat_group(&mut self) {
+        let Some(Frame::Seq(SeqFrame {
+            group: Some(group), ..
+        })) = self.containers.last_mut()
+        else {
+            return;
+        };
+        group.count += 1;
+        let (start, count) = (group.start, group.count);
+        if count == 2 {
+            let element = self.data.split_off(group.element);
+            group.element += u32::BITS as usize;
+            self.data.truncate(start);
+            self.uncount_token(Production::Seq.item(0));
+            self.serialize_token(Production::Run.first());
+            self.write_bytes(&0u32.to_le_bytes());
+            self.data.extend_from_bitslice(&element);
+        }
+        let at = start + Production::Run.first().width();
+        self.data[at..at + u32::BITS as usize].copy_from_bitslice(count.to_le_bytes().view_bits());
+    }
+
+    /// Serialize a value on its own as if it was written at the given bit offset.
+    fn serialize_detached<T: ?Sized + Serialize>(
+        &self,
+        value: &T,
+        offset: usize,
+    ) -> Result<bv::BitVec<u8, O>, Error> {
+        let mut serializer = CustomSerializer::new(self.config.clone(), None).reorder::<O>();
+        serializer.data.resize(offset, false);
+        value.serialize(&mut serializer)?;
+        Ok(serializer.data.split_off(offset))
+    }
+
+    /// End a sequence.
+    pub(crate) fn end_seq(&mut self) -> Result<(), Error> {
+        let Some(Frame::Seq(seq)) = self.containers.pop() else {
+            return Err(unbalanced("a sequence"));
+        };
+        if !self.end_packed_seq(seq.packed)? {
+            self.serialize_token(Production::Seq.last());
+        }
+        Ok(())
+    }
+
+    /// Pad the data with zero bits up to the next byte boundary. Strings and bytes
+    /// start on a byte boundary so that they can be borrowed when deserializing.
+    pub fn align_to_byte(&mut self) {
+        let padding = (8 - self.data.len() % 8) % 8;
+        self.data.resize(self.data.len() + padding, false);
+        if let Some(stats) = self.stats.as_mut() {
+            stats.aligned += 1;
+            stats.padding += padding;
+        }
+    }
+
+    /// Serialize a token to the data.
+    pub fn serialize_token(&mut self, token: Delimiter) {
+        if let Some(stats) = self.stats.as_mut() {
+            *stats.delimiters.entry(token).or_default() += 1;
+        }
+        self.write_token(token.token());
+    }
+
+    /// Write the bits of a token in the bit order of the data.
+    fn write_token(&mut self, token: Token) {
+        match O::MSB_FIRST {
+            true => self.data.extend(token.bits().rev()),
+            false => self.data.extend(token.bits()),
+        }
+    }
 }
 
-impl<'a> Serializer for &'a mut CustomSerializer {
+impl<O: WireOrder> Serializer for &mut CustomSerializer<'_, O> {
     type Ok = ();
     type Error = Error;
 
@@ -163,91 +1792,208 @@ impl<'a> Serializer for &'a mut CustomSerializer {
     type SerializeTupleVariant = Self;
     type SerializeStructVariant = Self;
 
-    /// bool: 0 -> false, 1 -> true (1 bit)
+    /// The data is binary, so types with both a readable and a compact representation (e.g.
+    /// `IpAddr`) use the compact one.
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    /// bool: 0 -> false, 1 -> true (1 bit or, with byte-aligned bools, 1 byte)
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
-        self.data.push(v);
+        self.validate(|| Primitive::Bool(v))?;
+        self.serialize_tag(TypeTag::Bool);
+        match self.config.byte_aligned_bools {
+            true => self.write_bytes(&[v as u8]),
+            false => self.data.push(v),
+        }
         Ok(())
     }
 
     /// i8, i16, i32, i64: Little Endian (1, 2, 4, 8 bytes)
     fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
+        self.validate(|| Primitive::I64(v.into()))?;
+        self.serialize_tag(TypeTag::I8);
+        self.write_bytes(&v.to_le_bytes());
         Ok(())
     }
     fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
+        self.validate(|| Primitive::I64(v.into()))?;
+        self.serialize_tag(TypeTag::I16);
+        self.write_bytes(&v.to_le_bytes());
         Ok(())
     }
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
+        self.validate(|| Primitive::I64(v.into()))?;
+        self.serialize_tag(TypeTag::I32);
+        self.write_bytes(&v.to_le_bytes());
         Ok(())
     }
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
+        self.validate(|| Primitive::I64(v))?;
+        self.serialize_tag(TypeTag::I64);
+        match self.config.adaptive_ints {
+            // zigzag: 0, -1, 1, -2, 2... -> 0, 1, 2, 3, 4...
+            true => self.serialize_adaptive(((v << 1) ^ (v >> 63)) as u64),
+            false => self.write_bytes(&v.to_le_bytes()),
+        }
+        Ok(())
+    }
+    /// i128: Little Endian (16 bytes)
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        self.validate(|| Primitive::I128(v))?;
+        self.serialize_tag(TypeTag::I128);
+        self.write_bytes(&v.to_le_bytes());
         Ok(())
     }
 
     /// u8, u16, u32, u64: Little Endian (1, 2, 4, 8 bytes)
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
+        self.validate(|| Primitive::U64(v.into()))?;
+        self.serialize_tag(TypeTag::U8);
+        self.write_bytes(&v.to_le_bytes());
         Ok(())
     }
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
+        self.validate(|| Primitive::U64(v.into()))?;
+        self.serialize_tag(TypeTag::U16);
+        self.write_bytes(&v.to_le_bytes());
         Ok(())
     }
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
+        self.validate(|| Primitive::U64(v.into()))?;
+        self.serialize_tag(TypeTag::U32);
+        self.write_bytes(&v.to_le_bytes());
         Ok(())
     }
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
+        self.validate(|| Primitive::U64(v))?;
+        self.serialize_tag(TypeTag::U64);
+        match self.config.adaptive_ints {
+            true => self.serialize_adaptive(v),
+            false => self.write_bytes(&v.to_le_bytes()),
+        }
+        Ok(())
+    }
+    /// u128: Little Endian (16 bytes)
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        self.validate(|| Primitive::U128(v))?;
+        self.serialize_tag(TypeTag::U128);
+        self.write_bytes(&v.to_le_bytes());
         Ok(())
     }
 
     /// f32, f64: Little Endian (4, 8 bytes)
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
+        self.validate(|| Primitive::F64(v.into()))?;
+        self.serialize_tag(TypeTag::F32);
+        self.write_bytes(&v.to_le_bytes());
         Ok(())
     }
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(&v.to_le_bytes());
+        self.validate(|| Primitive::F64(v))?;
+        self.serialize_tag(TypeTag::F64);
+        self.write_bytes(&v.to_le_bytes());
         Ok(())
     }
 
     /// char: as u32 (4 bytes)
     fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
-        self.serialize_u32(u32::from(v))
+        self.validate(|| Primitive::Char(v))?;
+        self.serialize_tag(TypeTag::Char);
+        self.write_bytes(&u32::from(v).to_le_bytes());
+        Ok(())
     }
-    /// str: bytes STRING_DELIMITER
+    /// str: length (u32) + padding + bytes (see [`Config::string_framing`] for the others)
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(v.as_bytes());
-        self.serialize_token(Delimiter::String);
+        let v = match self.normalizer {
+            Some(normalize) => normalize(v),
+            None => Cow::Borrowed(v),
+        };
+        self.validate(|| Primitive::Str(v.to_string()))?;
+        self.serialize_tag(TypeTag::Str);
+        self.write_framed(v.as_bytes(), Production::Str.last())
+    }
+    /// The same as a str but the value is formatted straight into the data instead of into a
+    /// `String` first, e.g. for paths, addresses or big numbers; a length prefix is filled in
+    /// once the text is written. A normalizer or a validator needs the whole string, so with
+    /// one the value is formatted into a `String` after all; so is it when strings are escaped.
+    fn collect_str<T: ?Sized + std::fmt::Display>(
+        self,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        use std::fmt::Write as _;
+
+        let failed = |_: std::fmt::Error| {
+            Error::SerializationError("the `Display` implementation of a value failed".to_string())
+        };
+        if self.normalizer.is_some()
+            || self.validator.is_some()
+            || self.config.string_framing == StringFraming::Escaped
+        {
+            let mut text = String::new();
+            write!(text, "{value}").map_err(failed)?;
+            return self.serialize_str(&text);
+        }
+        self.serialize_tag(TypeTag::Str);
+        if self.config.string_framing == StringFraming::LengthPrefixed {
+            let prefix = self.data.len();
+            self.write_bytes(&0u32.to_le_bytes());
+            self.align_to_byte();
+            let start = self.data.len();
+            write!(TextWriter(self), "{value}").map_err(failed)?;
+            let length = u32::try_from((self.data.len() - start) / 8).map_err(|_| {
+                Error::SerializationError(
+                    "a string or bytes is longer than u32::MAX bytes".to_string(),
+                )
+            })?;
+            self.data[prefix..prefix + u32::BITS as usize]
+                .copy_from_bitslice(length.to_le_bytes().view_bits());
+            return Ok(());
+        }
+        self.align_to_byte();
+        write!(TextWriter(self), "{value}").map_err(failed)?;
+        self.serialize_token(Production::Str.last());
         Ok(())
     }
-    /// bytes: bytes BYTE_DELIMITER
+    /// bytes: length (u32) + padding + bytes (see [`Config::string_framing`] for the others)
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        self.data.extend(v);
-        self.serialize_token(Delimiter::Byte);
-        Ok(())
+        self.validate(|| Primitive::Bytes(v.to_vec()))?;
+        self.serialize_tag(TypeTag::Bytes);
+        self.write_framed(v, Production::Bytes.last())?;
+        self.flush()
     }
 
     /// unit: UNIT (null)
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Unit);
+        match self.config.self_describing {
+            true => self.serialize_tag(TypeTag::Unit),
+            false => {
+                self.count_value(TypeTag::Unit, 1);
+                self.serialize_token(Production::Unit.first());
+            }
+        }
         Ok(())
     }
 
     /// option:
-    /// None -> unit()
+    /// None -> 0
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_unit()
+        self.serialize_tag(TypeTag::None);
+        if !self.config.self_describing {
+            self.data.push(false);
+        }
+        Ok(())
     }
-    /// Some -> self
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error>
-    where
-        T: Serialize,
-    {
+    /// Some -> 1 + self; a struct field with option bitmaps: self
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        if std::mem::take(&mut self.implied_some) {
+            self.count_value(TypeTag::Some, 1);
+            return value.serialize(self);
+        }
+        self.serialize_tag(TypeTag::Some);
+        if !self.config.self_describing {
+            self.data.push(true);
+        }
         value.serialize(self)
     }
 
@@ -256,16 +2002,37 @@ impl<'a> Serializer for &'a mut CustomSerializer {
     fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
         self.serialize_unit()
     }
-    /// newtype_struct: self
-    fn serialize_newtype_struct<T: ?Sized>(
+    /// newtype_struct: self; an embedded payload (see [`RawRustFr`](crate::raw::RawRustFr)):
+    /// length (u32) + padding + bytes; a columnar sequence: columns()
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
         self,
-        _name: &'static str,
+        name: &'static str,
         value: &T,
-    ) -> Result<Self::Ok, Self::Error>
-    where
-        T: Serialize,
-    {
-        value.serialize(self)
+    ) -> Result<Self::Ok, Self::Error> {
+        if name == columnar::NAME && !self.config.self_describing {
+            let columns = columnar::split(value, || self.column())?;
+            return self.serialize_columns(columns);
+        }
+        if name == raw::NAME {
+            let Primitive::Bytes(bytes) = encoders::capture(value)? else {
+                return Err(Error::SerializationError(
+                    "an embedded payload must be bytes".to_string(),
+                ));
+            };
+            self.serialize_tag(TypeTag::Raw);
+            return self.serialize_custom(&bytes);
+        }
+        let encoder = match self.config.self_describing {
+            true => None,
+            false => self.encoders.and_then(|encoders| encoders.get(name)),
+        };
+        match encoder {
+            Some(encoder) => {
+                let bytes = (encoder.encode)(&encoders::capture(value)?)?;
+                self.serialize_custom(&bytes)
+            }
+            None => value.serialize(self),
+        }
     }
     /// tuple_struct: tuple()
     fn serialize_tuple_struct(
@@ -282,22 +2049,24 @@ impl<'a> Serializer for &'a mut CustomSerializer {
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        self.serialize_u32(variant_index)
+        self.serialize_tag(TypeTag::UnitVariant);
+        self.serialize_variant_index(variant_index);
+        self.serialize_variant_name(variant)?;
+        Ok(())
     }
     /// newtype_variant: variant_index self
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         value: &T,
-    ) -> Result<Self::Ok, Self::Error>
-    where
-        T: Serialize,
-    {
-        self.serialize_u32(variant_index)?;
+    ) -> Result<Self::Ok, Self::Error> {
+        self.serialize_tag(TypeTag::NewtypeVariant);
+        self.serialize_variant_index(variant_index);
+        self.serialize_variant_name(variant)?;
         value.serialize(self)
     }
     /// tuple_variant: variant_index tuple()
@@ -305,223 +2074,429 @@ impl<'a> Serializer for &'a mut CustomSerializer {
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        self.serialize_u32(variant_index)?;
-        self.serialize_seq(Some(len))
+        self.serialize_tag(TypeTag::TupleVariant);
+        self.serialize_variant_index(variant_index);
+        self.serialize_variant_name(variant)?;
+        self.begin_tuple(len)
     }
     /// struct_variant: variant_index struct()
     fn serialize_struct_variant(
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        self.serialize_u32(variant_index)?;
-        self.serialize_map(Some(len))
+        if self.validator.is_some() {
+            self.structs.push(variant);
+        }
+        self.serialize_tag(TypeTag::StructVariant);
+        self.serialize_variant_index(variant_index);
+        self.serialize_variant_name(variant)?;
+        self.begin_struct(len)
     }
 
-    /// sequences: SEQ_DELIMITER + value_1 + SEQ_VALUE_DELIMITER + value_2 + SEQ_VALUE_DELIMITER + ... SEQ_DELIMITER
+    /// sequences: SEQ_DELIMITER + SEQ_VALUE_DELIMITER + value_1 + SEQ_VALUE_DELIMITER + value_2
+    /// + ... + SEQ_DELIMITER
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
-        self.serialize_token(Delimiter::Seq);
+        self.serialize_tag(TypeTag::Seq);
+        self.serialize_token(Production::Seq.first());
+        self.begin_seq();
         Ok(self)
     }
-    /// maps: key_1 + MAP_KEY_DELIMITER + value_1 + MAP_VALUE_DELIMITER + key_2 + MAP_KEY_DELIMITER + value_2 + MAP_VALUE_DELIMITER +... MAP_DELIMITER
+    /// maps: MAP_KEY_DELIMITER + key_1 + MAP_VALUE_DELIMITER + value_1 + MAP_KEY_DELIMITER + key_2
+    /// + MAP_VALUE_DELIMITER + value_2 + ... + MAP_DELIMITER
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        self.serialize_tag(TypeTag::Map);
+        self.begin_map();
         Ok(self)
     }
 
-    /// tuples: seq()
+    /// tuples: value_1 + value_2 + ... (or seq() in the self-describing mode)
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        self.serialize_seq(Some(len))
+        self.begin_tuple(len)
     }
     /// structs: map()
     fn serialize_struct(
         self,
-        _name: &'static str,
+        name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        self.serialize_map(Some(len))
+        if self.validator.is_some() {
+            self.structs.push(name);
+        }
+        self.begin_struct(len)
     }
 }
 
-impl<'a> SerializeSeq for &'a mut CustomSerializer {
+impl<O: WireOrder> SerializeSeq for &mut CustomSerializer<'_, O> {
     type Ok = ();
     type Error = Error;
 
     /// Serialize an element of the sequence.
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
-    where
-        T: Serialize,
-    {
-        if !self.peek_token(Delimiter::Seq)? {
-            self.serialize_token(Delimiter::SeqValue);
-        }
-        value.serialize(&mut **self)
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.serialize_seq_element(value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Seq);
-        Ok(())
+        self.end_seq()
     }
 }
-impl<'a> SerializeMap for &'a mut CustomSerializer {
+impl<O: WireOrder> SerializeMap for &mut CustomSerializer<'_, O> {
     type Ok = ();
     type Error = Error;
 
     /// Serialize a key of a given element of the map.
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Self::Error>
-    where
-        T: Serialize,
-    {
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
+        self.begin_entry();
+        if self.block_key(key)? {
+            return Ok(());
+        }
+        self.begin_key();
         key.serialize(&mut **self)?;
-        self.serialize_token(Delimiter::MapKey);
-        Ok(())
+        self.end_key()
     }
 
     /// Serialize a value of a given element of the map.
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
-    where
-        T: Serialize,
-    {
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.serialize_token(Production::Map.item(1));
         value.serialize(&mut **self)?;
-        self.serialize_token(Delimiter::MapValue);
-        Ok(())
+        self.flush()
     }
 
     /// End the map serialization.
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Map);
-        Ok(())
+        self.end_map()
     }
 }
 
 // = seq()
-impl<'a> SerializeTuple for &'a mut CustomSerializer {
+impl<O: WireOrder> SerializeTuple for &mut CustomSerializer<'_, O> {
     type Ok = ();
     type Error = Error;
 
     /// Serialize an element of the tuple.
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
-    where
-        T: Serialize,
-    {
-        if !self.peek_token(Delimiter::Seq)? {
-            self.serialize_token(Delimiter::SeqValue);
-        }
-        value.serialize(&mut **self)
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.serialize_tuple_element(value)
     }
 
     /// End the tuple serialization.
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Seq);
-        Ok(())
+        self.end_tuple()
     }
 }
 // = map()
-impl<'a> SerializeStruct for &'a mut CustomSerializer {
+impl<O: WireOrder> SerializeStruct for &mut CustomSerializer<'_, O> {
     type Ok = ();
     type Error = Error;
 
     /// Serialize a field of the struct. Structs treated as a key-value pair i.e. a map.
     /// There is no difference between a struct and a map in the serialization format.
-    fn serialize_field<T: ?Sized>(
+    fn serialize_field<T: ?Sized + Serialize>(
         &mut self,
         key: &'static str,
         value: &T,
-    ) -> Result<(), Self::Error>
-    where
-        T: Serialize,
-    {
-        key.serialize(&mut **self)?;
-        self.serialize_token(Delimiter::MapKey);
-        value.serialize(&mut **self)?;
-        self.serialize_token(Delimiter::MapValue);
-        Ok(())
+    ) -> Result<(), Self::Error> {
+        self.serialize_struct_field(key, value)
+    }
+
+    /// A field that is skipped can't be told apart from the next one without the names.
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+        self.skip_struct_field(key)
     }
 
     /// End the struct serialization.
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Map);
-        Ok(())
+        self.end_struct()
     }
 }
 
-// = seq()
-impl<'a> SerializeTupleStruct for &'a mut CustomSerializer {
+// = tuple()
+impl<O: WireOrder> SerializeTupleStruct for &mut CustomSerializer<'_, O> {
     type Ok = ();
     type Error = Error;
 
-    /// Serialize an element of the tuple. Tuple structs treated as a sequence.
-    /// There is no difference between a tuple struct and a sequence in the serialization format.
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
-    where
-        T: Serialize,
-    {
-        if !self.peek_token(Delimiter::Seq)? {
-            self.serialize_token(Delimiter::SeqValue);
-        }
-        value.serialize(&mut **self)
+    /// Serialize an element of the tuple. Tuple structs treated as a tuple.
+    /// There is no difference between a tuple struct and a tuple in the serialization format.
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.serialize_tuple_element(value)
     }
 
     /// End the tuple struct serialization.
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Seq);
-        Ok(())
+        self.end_tuple()
     }
 }
 
-// = tuple() = seq()
-impl<'a> SerializeTupleVariant for &'a mut CustomSerializer {
+// = tuple()
+impl<O: WireOrder> SerializeTupleVariant for &mut CustomSerializer<'_, O> {
     type Ok = ();
     type Error = Error;
 
-    /// Serialize an element of the tuple in an enum variant. Tuple variants treated as a sequence.
-    /// There is no difference between a tuple variant and a sequence in the serialization format.
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
-    where
-        T: Serialize,
-    {
-        if self.peek_token_before_n_bits(32)? != Delimiter::Seq as u8 {
-            self.serialize_token(Delimiter::SeqValue);
-        }
-        value.serialize(&mut **self)
+    /// Serialize an element of the tuple in an enum variant. Tuple variants treated as a tuple.
+    /// There is no difference between a tuple variant and a tuple in the serialization format.
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.serialize_tuple_element(value)
     }
 
     /// End the tuple variant serialization.
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Seq);
-        Ok(())
+        self.end_tuple()
     }
 }
 
 // = struct() = map()
-impl<'a> SerializeStructVariant for &'a mut CustomSerializer {
+impl<O: WireOrder> SerializeStructVariant for &mut CustomSerializer<'_, O> {
     type Ok = ();
     type Error = Error;
 
     /// Serialize a field of the struct in an enum variant. Struct variants treated as a key-value pair i.e. a map.
     /// There is no difference between a struct variant and a map in the serialization format.
-    fn serialize_field<T: ?Sized>(
+    fn serialize_field<T: ?Sized + Serialize>(
         &mut self,
         key: &'static str,
         value: &T,
-    ) -> Result<(), Self::Error>
-    where
-        T: Serialize,
-    {
-        key.serialize(&mut **self)?;
-        self.serialize_token(Delimiter::MapKey);
-        value.serialize(&mut **self)?;
-        self.serialize_token(Delimiter::MapValue);
-        Ok(())
+    ) -> Result<(), Self::Error> {
+        self.serialize_struct_field(key, value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+        self.skip_struct_field(key)
     }
 
     /// End the struct variant serialization.
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        self.serialize_token(Delimiter::Map);
-        Ok(())
+        self.end_struct()
+    }
+}
+
+// What a value is as far as option bitmaps are concerned (see `Config::option_bitmaps`).
+#[derive(Debug, PartialEq, Eq)]
+enum Probe {
+    None,
+    Some,
+    Other,
+}
+
+// A serializer that only tells whether a value is `None`, `Some` or anything else without
+// serializing it. A newtype struct around an option is something else: serde can't fill it in
+// when its field is left out.
+struct OptionProbe;
+
+// The values a probe doesn't look for: they are `$other`.
+macro_rules! probe_other {
+    ($other:expr; $($method:ident($($arg:ty),*)),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<Self::Ok, Error> {
+                Ok($other)
+            }
+        )*
+    };
+}
+
+// Compound values fail right away in a probe so they aren't walked.
+macro_rules! probe_compounds {
+    () => {
+        type SerializeSeq = Impossible<Self::Ok, Error>;
+        type SerializeTuple = Impossible<Self::Ok, Error>;
+        type SerializeTupleStruct = Impossible<Self::Ok, Error>;
+        type SerializeTupleVariant = Impossible<Self::Ok, Error>;
+        type SerializeMap = Impossible<Self::Ok, Error>;
+        type SerializeStruct = Impossible<Self::Ok, Error>;
+        type SerializeStructVariant = Impossible<Self::Ok, Error>;
+
+        fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+            Err(Error::UnsupportedCall("probe".to_string()))
+        }
+        fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Error> {
+            Err(Error::UnsupportedCall("probe".to_string()))
+        }
+        fn serialize_tuple_struct(
+            self,
+            _: &'static str,
+            _: usize,
+        ) -> Result<Self::SerializeTupleStruct, Error> {
+            Err(Error::UnsupportedCall("probe".to_string()))
+        }
+        fn serialize_tuple_variant(
+            self,
+            _: &'static str,
+            _: u32,
+            _: &'static str,
+            _: usize,
+        ) -> Result<Self::SerializeTupleVariant, Error> {
+            Err(Error::UnsupportedCall("probe".to_string()))
+        }
+        fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Error> {
+            Err(Error::UnsupportedCall("probe".to_string()))
+        }
+        fn serialize_struct(
+            self,
+            _: &'static str,
+            _: usize,
+        ) -> Result<Self::SerializeStruct, Error> {
+            Err(Error::UnsupportedCall("probe".to_string()))
+        }
+        fn serialize_struct_variant(
+            self,
+            _: &'static str,
+            _: u32,
+            _: &'static str,
+            _: usize,
+        ) -> Result<Self::SerializeStructVariant, Error> {
+            Err(Error::UnsupportedCall("probe".to_string()))
+        }
+    };
+}
+
+impl Serializer for OptionProbe {
+    type Ok = Probe;
+    type Error = Error;
+
+    probe_compounds!();
+
+    probe_other! {
+        Probe::Other;
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+        serialize_unit_variant(&'static str, u32, &'static str),
+    }
+
+    fn serialize_none(self) -> Result<Probe, Error> {
+        Ok(Probe::None)
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> Result<Probe, Error> {
+        Ok(Probe::Some)
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Probe, Error> {
+        Ok(Probe::Other)
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Probe, Error> {
+        Ok(Probe::Other)
+    }
+}
+
+// An unsigned integer of at most 64 bits along with the type it was serialized as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Unsigned {
+    tag: TypeTag,
+    value: u64,
+}
+
+impl Unsigned {
+    // The number of bytes of the integers of a type.
+    fn size(tag: TypeTag) -> usize {
+        match tag {
+            TypeTag::U8 => 1,
+            TypeTag::U16 => 2,
+            TypeTag::U32 => 4,
+            _ => 8,
+        }
+    }
+}
+
+// A serializer that only tells whether a value is an unsigned integer of at most 64 bits
+// (e.g. the key of a map or an element of a sequence) and which, whatever its Rust type, e.g.
+// a `&u32` or a `Box<u32>`. A newtype struct around one is something else: it can't be read
+// back from a bare integer.
+struct UnsignedProbe;
+
+impl Serializer for UnsignedProbe {
+    type Ok = Option<Unsigned>;
+    type Error = Error;
+
+    probe_compounds!();
+
+    probe_other! {
+        None;
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_none(),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+        serialize_unit_variant(&'static str, u32, &'static str),
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<Self::Ok, Error> {
+        Ok(Some(Unsigned {
+            tag: TypeTag::U8,
+            value: value.into(),
+        }))
+    }
+    fn serialize_u16(self, value: u16) -> Result<Self::Ok, Error> {
+        Ok(Some(Unsigned {
+            tag: TypeTag::U16,
+            value: value.into(),
+        }))
+    }
+    fn serialize_u32(self, value: u32) -> Result<Self::Ok, Error> {
+        Ok(Some(Unsigned {
+            tag: TypeTag::U32,
+            value: value.into(),
+        }))
+    }
+    fn serialize_u64(self, value: u64) -> Result<Self::Ok, Error> {
+        Ok(Some(Unsigned {
+            tag: TypeTag::U64,
+            value,
+        }))
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Self::Ok, Error> {
+        Ok(None)
     }
 }
@@ -0,0 +1,335 @@
+//! ### Spec
+//! A module that generates the format specification from the constants used by the
+//! serializer and deserializer, so the documented format can't drift from the implemented one.
+//! The specification in the README is the output of [`generate`] and can be regenerated with
+//! `cargo run --bin gen-spec`.
+
+pub use crate::protocol::format::FORMAT_VERSION;
+use crate::{
+    config::Config,
+    protocol::format::{
+        fingerprint, Delimiter, TypeTag, ADAPTIVE_WIDTH_BITS, GRAMMAR, HEADER_WIDTH,
+        MIN_COMPATIBLE_VERSION, STRING_ESCAPE, VARIANT_INDEX_ESCAPE,
+    },
+};
+use std::fmt::Write;
+
+/// Generate the format specification as markdown.
+pub fn generate() -> String {
+    let mut spec = String::new();
+    // writing to a string never fails.
+    let _ = write_spec(&mut spec);
+    spec
+}
+
+fn write_spec(spec: &mut String) -> std::fmt::Result {
+    writeln!(
+        spec,
+        "- Format version: {FORMAT_VERSION} (fingerprint {:#018x}); data of versions \
+         {MIN_COMPATIBLE_VERSION} to {FORMAT_VERSION} can be read.",
+        fingerprint()
+    )?;
+    writeln!(
+        spec,
+        "- The format is non-self-describing unless `Config::self_describing` is set."
+    )?;
+    writeln!(
+        spec,
+        "- The format isn't human readable (`is_human_readable()` is false): types with a compact \
+         representation (e.g. `IpAddr`) use it."
+    )?;
+    writeln!(
+        spec,
+        "- Data starts with a header: the options it was serialized with (u16, {} bits), bit i set \
+         if the option i is.",
+        HEADER_WIDTH.bits()
+    )?;
+    for (i, option) in Config::OPTIONS.into_iter().enumerate() {
+        writeln!(spec, "    - {i}: `Config::{option}`")?;
+    }
+    writeln!(
+        spec,
+        "    - other bits are 0; decoders reject headers with bits they don't know."
+    )?;
+    writeln!(spec, "- Primitive types are serialized as is.")?;
+    writeln!(spec, "    - bool: 0 -> false, 1 -> true (1 bit)")?;
+    writeln!(spec, "    - i8, i16, i32, i64, i128: as is.")?;
+    writeln!(spec, "    - u8, u16, u32, u64, u128: as is.")?;
+    writeln!(spec, "    - f32, f64: as is.")?;
+    writeln!(spec, "    - char: as u32 (4 bytes)")?;
+    writeln!(
+        spec,
+        "- Delimiters are used to separate different types of data."
+    )?;
+    let (bytes, bits): (Vec<_>, Vec<_>) = Delimiter::ALL.into_iter().partition(|d| d.width() == 8);
+    writeln!(
+        spec,
+        "- {} Delimiters are 1 byte long while all other delimiters are {} bits long.",
+        join(&bytes),
+        bits[0].width()
+    )?;
+    writeln!(spec, "- Delimiters:")?;
+    for delimiter in Delimiter::ALL {
+        let value = delimiter as u8;
+        writeln!(
+            spec,
+            "    - {delimiter} = {value}; {value:#0width$b}",
+            width = delimiter.width() + 2
+        )?;
+    }
+    writeln!(
+        spec,
+        "- Grammar: the tokens framing strings and bytes (with the delimited string framing), \
+         units, sequences and maps, where (...)* repeats zero or more times:"
+    )?;
+    for rule in GRAMMAR {
+        writeln!(spec, "    - {rule}")?;
+    }
+    writeln!(spec, "- String, Bytes, Unit, Option are serialized as:")?;
+    writeln!(
+        spec,
+        "    - str: length in bytes (u32) + padding + bytes (with the default string framing, see \
+         below)"
+    )?;
+    writeln!(spec, "    - bytes: length in bytes (u32) + padding + bytes")?;
+    writeln!(
+        spec,
+        "    - padding: zero bits up to the next byte boundary so strings and bytes can be \
+         borrowed while deserializing."
+    )?;
+    writeln!(spec, "    - unit: UNIT (null)")?;
+    writeln!(
+        spec,
+        "    - option: None -> 0, Some -> 1 + self (a presence bit)"
+    )?;
+    writeln!(spec, "- Structs are serialized as:")?;
+    writeln!(spec, "    - unit_struct: unit()")?;
+    writeln!(spec, "    - newtype_struct: self")?;
+    writeln!(
+        spec,
+        "    - an embedded payload (`RawRustFr`): length (u32) + padding + bytes (the payload \
+         along with its header)"
+    )?;
+    writeln!(
+        spec,
+        "    - a columnar sequence of structs (`columnar`, outside the self-describing mode): rows \
+         (u32) + fields (u32) + name of every field (str()) + every column (padding + length in \
+         bits (u32) + the value of the field in every row)"
+    )?;
+    writeln!(spec, "    - tuple_struct: tuple()")?;
+    writeln!(spec, "- Enums are serialized as:")?;
+    writeln!(spec, "    - unit_variant: variant_index")?;
+    writeln!(spec, "    - newtype_variant: variant_index + self")?;
+    writeln!(spec, "    - tuple_variant: variant_index + tuple()")?;
+    writeln!(spec, "    - struct_variant: variant_index + struct()")?;
+    writeln!(
+        spec,
+        "    - variant_index: 1 byte below {VARIANT_INDEX_ESCAPE:#x}; otherwise \
+         {VARIANT_INDEX_ESCAPE:#x} + u32"
+    )?;
+    writeln!(spec, "- seq(): Sequences are serialized as:")?;
+    writeln!(
+        spec,
+        "    - SEQ_DELIMITER + SEQ_VALUE_DELIMITER + value_1 + SEQ_VALUE_DELIMITER + value_2 + ... \
+         + SEQ_DELIMITER"
+    )?;
+    writeln!(spec, "- map(): Maps are serialized as:")?;
+    writeln!(spec, "    - MAP_KEY_DELIMITER + key_1 +")?;
+    writeln!(spec, "      MAP_VALUE_DELIMITER + value_1 +")?;
+    writeln!(spec, "      MAP_KEY_DELIMITER + key_2 +")?;
+    writeln!(spec, "      MAP_VALUE_DELIMITER + value_2 +")?;
+    writeln!(spec, "      ... + MAP_DELIMITER")?;
+    writeln!(spec, "- Tuples and Structs are serialized as:")?;
+    writeln!(
+        spec,
+        "    - tuple: value_1 + value_2 + ... (as many as the tuple has elements)"
+    )?;
+    writeln!(spec, "    - struct: map()")?;
+    writeln!(spec, "- Self-describing mode (`Config::self_describing`):")?;
+    writeln!(
+        spec,
+        "    - Every value is prefixed with a {} bit type tag. Tags whose first {} bits are \
+         `{:#0width$b}` are never used.",
+        TypeTag::WIDTH,
+        Delimiter::Seq.width(),
+        Delimiter::Seq as u8,
+        width = Delimiter::Seq.width() + 2
+    )?;
+    writeln!(
+        spec,
+        "    - tuple: seq() (`deserialize_any` can't tell how many elements it has otherwise)"
+    )?;
+    writeln!(spec, "    - Type Tags:")?;
+    for tag in TypeTag::ALL {
+        let value = tag as u8;
+        writeln!(
+            spec,
+            "        - {tag} = {value}; {value:#0width$b}",
+            width = TypeTag::WIDTH + 2
+        )?;
+    }
+    writeln!(spec, "    - unit: UNIT_TAG")?;
+    writeln!(
+        spec,
+        "    - option: None -> NONE_TAG, Some -> SOME_TAG + self"
+    )?;
+    writeln!(
+        spec,
+        "    - variants: VARIANT_TAG + variant_index + str(variant_name) + (content)"
+    )?;
+    writeln!(spec, "- Adaptive integer mode (`Config::adaptive_ints`):")?;
+    writeln!(
+        spec,
+        "    - u64, i64: {ADAPTIVE_WIDTH_BITS} bit width tag (0 -> 1 byte, 1 -> 2 bytes, 2 -> 4 \
+         bytes, 3 -> 8 bytes) + that many bytes of the value."
+    )?;
+    writeln!(
+        spec,
+        "    - i64 is zigzag encoded first: 0, -1, 1, -2, 2... -> 0, 1, 2, 3, 4..."
+    )?;
+    writeln!(
+        spec,
+        "- Run-length encoding mode (`Config::run_length_encoding`):"
+    )?;
+    writeln!(
+        spec,
+        "    - seq(): SEQ_DELIMITER + group_1 + group_2 + ... + SEQ_DELIMITER"
+    )?;
+    writeln!(
+        spec,
+        "    - group: SEQ_VALUE_DELIMITER + value or RUN_DELIMITER + count (u32) + value, where \
+         the value repeats count times."
+    )?;
+    writeln!(spec, "- Packed u8 sequence mode (`Config::pack_u8_seqs`):")?;
+    writeln!(
+        spec,
+        "    - seq() of u8 only: PACKED_DELIMITER + length (u32) + padding + bytes"
+    )?;
+    writeln!(spec, "- Offset table mode (`Config::offset_tables`):")?;
+    writeln!(
+        spec,
+        "    - map(): count (u32) + offset of every entry (u32) + offset of the end (u32) + key_1 \
+         + ... + MAP_DELIMITER"
+    )?;
+    writeln!(spec, "    - offsets are in bits from the end of the table.")?;
+    writeln!(
+        spec,
+        "- Sorted integer key mode (`Config::sorted_int_keys`):"
+    )?;
+    writeln!(
+        spec,
+        "    - map(): 0 + MAP_KEY_DELIMITER + key_1 + MAP_VALUE_DELIMITER + value_1 + ... + \
+         MAP_DELIMITER"
+    )?;
+    writeln!(
+        spec,
+        "    - map() with u8, u16, u32 or u64 keys: 1 + count (u32) + size of a key in bytes (u8) \
+         + sorted (u8; 1 if the keys are in ascending order) + key_1 + key_2 + ... + \
+         MAP_VALUE_DELIMITER + value_1 + MAP_VALUE_DELIMITER + value_2 + ... + MAP_DELIMITER"
+    )?;
+    writeln!(
+        spec,
+        "    - the keys are little endian whatever the other modes are."
+    )?;
+    writeln!(
+        spec,
+        "    - with offset tables the bit follows the table and the offsets point to the values."
+    )?;
+    writeln!(
+        spec,
+        "- Byte-aligned bool mode (`Config::byte_aligned_bools`):"
+    )?;
+    writeln!(spec, "    - bool: 0 -> false, 1 -> true (1 byte)")?;
+    writeln!(
+        spec,
+        "- Omitted struct key mode (`Config::struct_keys = StructKeys::Omit`):"
+    )?;
+    writeln!(
+        spec,
+        "    - struct: value_1 + value_2 + ... (in field order)"
+    )?;
+    writeln!(spec, "    - in the self-describing mode: struct: tuple()")?;
+    writeln!(spec, "- Option bitmap mode (`Config::option_bitmaps`):")?;
+    writeln!(
+        spec,
+        "    - option fields of structs: None -> nothing (not even the key), Some -> self"
+    )?;
+    writeln!(
+        spec,
+        "    - with omitted struct keys: struct: bitmap + value_1 + value_2 + ... where the bitmap \
+         has a bit per field (1 -> present), padded to whole bytes"
+    )?;
+    writeln!(
+        spec,
+        "    - can't be combined with omitted struct keys in the self-describing mode"
+    )?;
+    writeln!(spec, "- MSB-first mode (`Config::msb_first`):")?;
+    writeln!(
+        spec,
+        "    - the bits are packed into bytes from the most significant bit down"
+    )?;
+    writeln!(
+        spec,
+        "    - delimiters, type tags and adaptive width tags are written most significant bit first"
+    )?;
+    writeln!(
+        spec,
+        "    - whole bytes keep their values and integers stay little endian"
+    )?;
+    writeln!(spec, "- Aligned field mode (`Config::aligned_fields`):")?;
+    writeln!(
+        spec,
+        "    - struct: padding + value_1 + padding + value_2 + ... where the padding goes up to \
+         the next byte boundary"
+    )?;
+    writeln!(
+        spec,
+        "    - needs omitted struct keys and can't be combined with the self-describing mode"
+    )?;
+    writeln!(
+        spec,
+        "- Length-prefixed key mode (`Config::length_prefixed_keys`):"
+    )?;
+    writeln!(
+        spec,
+        "    - map(): MAP_KEY_DELIMITER + length of key_1 in bits (u32) + padding + key_1 + \
+         MAP_VALUE_DELIMITER + value_1 + ... + MAP_DELIMITER"
+    )?;
+    writeln!(
+        spec,
+        "    - the padding goes up to the next byte boundary so equal keys have equal bits"
+    )?;
+    writeln!(
+        spec,
+        "    - struct field names too; keys in a block of integer keys have no length"
+    )?;
+    writeln!(spec, "- String framing (`Config::string_framing`):")?;
+    writeln!(
+        spec,
+        "    - Delimited: padding + bytes + STRING_DELIMITER (or BYTE_DELIMITER), as in the \
+         grammar; a string or bytes with the byte of its delimiter can't be read back"
+    )?;
+    writeln!(
+        spec,
+        "    - Escaped: padding + bytes + delimiter, where {STRING_ESCAPE:#x} goes in front of \
+         every byte that is the byte of the delimiter or {STRING_ESCAPE:#x}"
+    )?;
+    writeln!(
+        spec,
+        "    - LengthPrefixed (the default): str() and bytes() as above"
+    )?;
+    writeln!(spec, "    - the names of fields, variants and columns too")?;
+    Ok(())
+}
+
+// Join the names of the delimiters like "A, B & C".
+fn join(delimiters: &[Delimiter]) -> String {
+    match delimiters {
+        [] => String::new(),
+        [only] => only.to_string(),
+        [rest @ .., last] => {
+            let rest = rest.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+            format!("{} & {last}", rest.join(", "))
+        }
+    }
+}
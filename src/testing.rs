@@ -0,0 +1,264 @@
+//! ### Testing
+//! A module that generates random payloads for the integration tests and fuzzers of code built
+//! on top of rust-fr; see [`arbitrary_payload`]. It is only available with the `testing`
+//! feature.
+//!
+//! Every payload comes with the [`Value`] it is the encoding of, so a test can decode the
+//! payload and compare (or feed both to the code under test). The same seed and profile always
+//! give the same payload, so a failing case can be reproduced from its seed.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{
+//!     deserializer,
+//!     testing::{arbitrary_payload, Profile, Value},
+//! };
+//!
+//! let profile = Profile::default();
+//! for seed in 0..100 {
+//!     let (bytes, value) = arbitrary_payload(seed, &profile).unwrap();
+//!     let decoded = deserializer::from_bytes_with_config::<Value>(&bytes, &profile.config);
+//!     assert_eq!(decoded.unwrap(), value);
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    config::{Config, StringFraming},
+    error::Error,
+    protocol::format::Delimiter,
+    serializer,
+};
+
+/// A value of the shapes the format has: primitives, strings, bytes, options, sequences,
+/// maps, tuples and structs. It decodes from its payload in every config, self-describing or
+/// not.
+///
+/// The generated values are always valid: floats are never NaN (so values compare equal to
+/// themselves) and strings and bytes never contain the byte of the delimiter that ends them
+/// unless the config escapes them or prefixes them with their length.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    Str(String),
+    #[serde(with = "bytes")]
+    Bytes(Vec<u8>),
+    Option(Option<Box<Value>>),
+    Seq(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+    IntMap(BTreeMap<u32, Value>),
+    Tuple(u8, String, Box<Value>),
+    Struct {
+        id: u64,
+        name: Option<String>,
+        flags: Vec<bool>,
+        child: Option<Box<Value>>,
+    },
+}
+
+/// The shape of the generated payloads.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// The config the payloads are serialized with.
+    pub config: Config,
+    /// How deep containers are nested at most.
+    pub max_depth: u32,
+    /// The largest number of elements (or entries) of a container.
+    pub max_len: u64,
+    /// The longest string (in chars) or bytes.
+    pub max_bytes: u64,
+}
+
+impl Default for Profile {
+    /// Small values nested up to 3 containers deep, with the default config.
+    fn default() -> Self {
+        Self {
+            config: Config::default(),
+            max_depth: 3,
+            max_len: 5,
+            max_bytes: 16,
+        }
+    }
+}
+
+/// Generate a value from `seed` in the shape of `profile` and serialize it with the config of
+/// the profile. It returns the payload (with its header) along with the value. It fails if
+/// the config can't be serialized with, e.g. one with conflicting options.
+pub fn arbitrary_payload(seed: u64, profile: &Profile) -> Result<(Vec<u8>, Value), Error> {
+    let mut rng = Rng(seed);
+    let value = rng.value(profile, profile.max_depth);
+    let bytes = serializer::to_bytes_with_config(&value, &profile.config)?;
+    Ok((bytes, value))
+}
+
+// Vec<u8> as bytes instead of a sequence of u8.
+mod bytes {
+    use serde::{de::Visitor, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(bytes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        struct Bytes;
+
+        impl Visitor<'_> for Bytes {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "bytes")
+            }
+            fn visit_bytes<E>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+                Ok(bytes.to_vec())
+            }
+            fn visit_byte_buf<E>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+                Ok(bytes)
+            }
+        }
+
+        deserializer.deserialize_byte_buf(Bytes)
+    }
+}
+
+// splitmix64, so the values are the same on every platform.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // A number below `n` (0 if `n` is 0).
+    fn below(&mut self, n: u64) -> u64 {
+        self.next().checked_rem(n).unwrap_or(0)
+    }
+
+    fn chance(&mut self) -> bool {
+        self.below(2) == 0
+    }
+
+    // An integer of random magnitude, so small values are as likely as large ones.
+    fn magnitude(&mut self) -> u64 {
+        self.next() >> self.below(64)
+    }
+
+    // Characters of every width, the ones encoded with the byte of the String delimiter
+    // excepted if the delimiter isn't escaped.
+    fn string(&mut self, profile: &Profile) -> String {
+        let len = self.below(profile.max_bytes + 1) as usize;
+        let delimited = profile.config.string_framing == StringFraming::Delimited;
+        let chars = std::iter::repeat_with(|| match self.below(3) {
+            0 => char::from_u32(0x20 + self.below(95) as u32),
+            1 => char::from_u32(self.below(0x800) as u32),
+            _ => char::from_u32(self.below(0x11_0000) as u32),
+        });
+        chars
+            .flatten()
+            .filter(|c| {
+                let mut buffer = [0; 4];
+                !delimited
+                    || !c
+                        .encode_utf8(&mut buffer)
+                        .as_bytes()
+                        .contains(&(Delimiter::String as u8))
+            })
+            .take(len)
+            .collect()
+    }
+
+    // Bytes without the byte of the Byte delimiter if it isn't escaped.
+    fn bytes(&mut self, profile: &Profile) -> Vec<u8> {
+        let len = self.below(profile.max_bytes + 1);
+        let delimited = profile.config.string_framing == StringFraming::Delimited;
+        (0..len)
+            .map(|_| self.next() as u8)
+            .filter(|byte| !delimited || *byte != Delimiter::Byte as u8)
+            .collect()
+    }
+
+    fn float(&mut self) -> f64 {
+        let float = f64::from_bits(self.next());
+        match float.is_nan() {
+            true => self.magnitude() as f64,
+            false => float,
+        }
+    }
+
+    fn boxed(&mut self, profile: &Profile, depth: u32) -> Box<Value> {
+        Box::new(self.value(profile, depth - 1))
+    }
+
+    fn value(&mut self, profile: &Profile, depth: u32) -> Value {
+        // only primitives, strings and bytes at the deepest level.
+        let kinds = if depth == 0 { 17 } else { 23 };
+        match self.below(kinds) {
+            0 => Value::Unit,
+            1 => Value::Bool(self.chance()),
+            2 => Value::I8(self.next() as i8),
+            3 => Value::I16(self.next() as i16),
+            4 => Value::I32(self.magnitude() as i32),
+            5 => Value::I64(self.magnitude() as i64),
+            6 => Value::I128((self.next() as i128) << 64 | self.next() as i128),
+            7 => Value::U8(self.next() as u8),
+            8 => Value::U16(self.next() as u16),
+            9 => Value::U32(self.magnitude() as u32),
+            10 => Value::U64(self.magnitude()),
+            11 => Value::U128(u128::from(self.next()) << 64 | u128::from(self.next())),
+            12 => Value::F32(self.float() as f32),
+            13 => Value::F64(self.float()),
+            14 => Value::Char(char::from_u32(self.below(0x11_0000) as u32).unwrap_or('?')),
+            15 => Value::Str(self.string(profile)),
+            16 => Value::Bytes(self.bytes(profile)),
+            17 => Value::Option(self.chance().then(|| self.boxed(profile, depth))),
+            18 => Value::Seq(
+                (0..self.below(profile.max_len + 1))
+                    .map(|_| self.value(profile, depth - 1))
+                    .collect(),
+            ),
+            19 => Value::Map(
+                (0..self.below(profile.max_len + 1))
+                    .map(|_| (self.string(profile), self.value(profile, depth - 1)))
+                    .collect(),
+            ),
+            20 => Value::IntMap(
+                (0..self.below(profile.max_len + 1))
+                    .map(|_| (self.magnitude() as u32, self.value(profile, depth - 1)))
+                    .collect(),
+            ),
+            21 => Value::Tuple(
+                self.next() as u8,
+                self.string(profile),
+                self.boxed(profile, depth),
+            ),
+            _ => Value::Struct {
+                id: self.magnitude(),
+                name: self.chance().then(|| self.string(profile)),
+                flags: (0..self.below(profile.max_len * 4 + 1))
+                    .map(|_| self.chance())
+                    .collect(),
+                child: self.chance().then(|| self.boxed(profile, depth)),
+            },
+        }
+    }
+}
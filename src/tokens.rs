@@ -0,0 +1,614 @@
+//! ### Tokens
+//! A low-level view of self-describing data (see
+//! [`Config::self_describing`](crate::config::Config::self_describing)) as a stream of
+//! [`Event`]s, without serde's visitors. Tools that don't know the types of the data up front
+//! (e.g. indexers or converters to other formats) can walk it event by event with a
+//! [`TokenReader`], and producers that don't go through serde (e.g. a bridge to a scripting
+//! language) can write it event by event with a [`TokenWriter`].
+//!
+//! - A sequence is a [`Event::SeqStart`], its elements and a [`Event::SeqEnd`].
+//! - A map is a [`Event::MapStart`], its keys and values one after the other and a
+//!   [`Event::MapEnd`]. Structs are maps of their field names (or sequences of their fields if
+//!   the names are omitted).
+//! - An enum variant with content is a [`Event::VariantStart`], its content (a value, a
+//!   sequence or a struct) and a [`Event::VariantEnd`].
+//! - `Some` is a [`Event::Some`] followed by its value. Struct fields written without their
+//!   presence bit (see [`Config::option_bitmaps`](crate::config::Config::option_bitmaps)) are
+//!   just their value.
+//!
+//! Runs, packed sequences, offset tables, blocks of integer keys and the lengths of keys are
+//! read like any other sequence or map, so the events don't depend on those options.
+//! Containers are tracked on the heap, so deeply nested data doesn't take stack space. A
+//! [`TokenWriter`] writes the same bytes as serde for the same events, except that elements of
+//! sequences which aren't primitives (e.g. containers or `Some`) are never folded into runs and
+//! a variant whose content is a sequence (or a map) is written as a tuple (or struct) variant.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{
+//!     config::Config,
+//!     serializer,
+//!     tokens::{Event, TokenReader, TokenWriter},
+//! };
+//!
+//! #[derive(serde::Serialize)]
+//! struct Point {
+//!     x: u32,
+//!     tags: Vec<&'static str>,
+//! }
+//!
+//! let config = Config {
+//!     self_describing: true,
+//!     ..Config::default()
+//! };
+//! let point = Point { x: 7, tags: vec!["a"] };
+//! let bytes = serializer::to_bytes_with_config(&point, &config).unwrap();
+//! let events = TokenReader::new(&bytes, &config)
+//!     .unwrap()
+//!     .collect::<Result<Vec<_>, _>>()
+//!     .unwrap();
+//! assert_eq!(
+//!     events,
+//!     [
+//!         Event::MapStart,
+//!         Event::Str("x".into()),
+//!         Event::U32(7),
+//!         Event::Str("tags".into()),
+//!         Event::SeqStart,
+//!         Event::Str("a".into()),
+//!         Event::SeqEnd,
+//!         Event::MapEnd,
+//!     ]
+//! );
+//!
+//! // the same value written without serde.
+//! let mut writer = TokenWriter::new(&config).unwrap();
+//! for event in events {
+//!     writer.push(event).unwrap();
+//! }
+//! assert_eq!(writer.finish().unwrap(), bytes);
+//! ```
+
+use std::borrow::Cow;
+
+use bitvec::{prelude as bv, slice::BitSlice};
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
+use super::{
+    config::Config,
+    deserializer::{CustomDeserializer, SortedKeys},
+    error::{Container, Error},
+    protocol::format::{Production, TypeTag},
+    raw::RawRef,
+    serializer::CustomSerializer,
+};
+
+/// A piece of self-describing data; see the [module](self) docs for how values are made up
+/// of them. Strings and bytes are borrowed from the input whenever possible.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<'de> {
+    Unit,
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    Str(Cow<'de, str>),
+    Bytes(Cow<'de, [u8]>),
+    /// A payload embedded with [`RawRustFr`](crate::raw::RawRustFr), along with its header.
+    Raw(Cow<'de, [u8]>),
+    None,
+    /// `Some`; the next value is its content.
+    Some,
+    SeqStart,
+    SeqEnd,
+    MapStart,
+    MapEnd,
+    /// A variant without content.
+    UnitVariant {
+        index: u32,
+        name: Cow<'de, str>,
+    },
+    /// A variant with content; the next value is its content.
+    VariantStart {
+        index: u32,
+        name: Cow<'de, str>,
+    },
+    VariantEnd,
+}
+
+// A container (or value) that is being read.
+enum Frame<'de> {
+    // The number of elements so far and the run (the number of repetitions left and the
+    // element) being read in the run-length encoding mode.
+    Seq {
+        index: usize,
+        run: Option<(u32, &'de BitSlice<u8, bv::Lsb0>)>,
+    },
+    // A packed sequence of u8 and the number of elements so far.
+    Packed {
+        bytes: Vec<u8>,
+        index: usize,
+    },
+    // The number of entries so far, the block of keys (if any) and whether the value of the
+    // entry is next.
+    Map {
+        index: usize,
+        keys: Option<SortedKeys<'de>>,
+        value: bool,
+    },
+    // Whether the content of the variant has been read.
+    Variant {
+        read: bool,
+    },
+    // The content of a `Some`.
+    Some,
+}
+
+/// Reads self-describing data as a stream of [`Event`]s; see the [module](self) docs. It is an
+/// iterator that stops after the first error.
+pub struct TokenReader<'de> {
+    deserializer: CustomDeserializer<'de>,
+    stack: Vec<Frame<'de>>,
+    started: bool,
+    finished: bool,
+}
+
+impl<'de> TokenReader<'de> {
+    /// Read the header of `bytes` (which must match `config`) and get ready to read the
+    /// events of the value. The config must be self-describing.
+    pub fn new(bytes: &'de [u8], config: &Config) -> Result<Self, Error> {
+        if !config.self_describing {
+            return Err(Error::SelfDescribingRequired("TokenReader::new"));
+        }
+        if config.msb_first {
+            return Err(Error::UnsupportedCall(
+                "TokenReader::new with `Config::msb_first`".to_string(),
+            ));
+        }
+        let mut deserializer = CustomDeserializer::new(bytes, config.clone(), None);
+        deserializer.expect_header()?;
+        Ok(Self {
+            deserializer,
+            stack: Vec::new(),
+            started: false,
+            finished: false,
+        })
+    }
+
+    /// The number of containers (and variants) the next event is in.
+    pub fn depth(&self) -> usize {
+        self.stack
+            .iter()
+            .filter(|frame| !matches!(frame, Frame::Some))
+            .count()
+    }
+
+    /// Read the next event or `None` once the value has been read.
+    fn next_event(&mut self) -> Result<Option<Event<'de>>, Error> {
+        let Some(frame) = self.stack.last_mut() else {
+            if std::mem::replace(&mut self.started, true) {
+                return Ok(None);
+            }
+            return self.read_value().map(Some);
+        };
+        let de = &mut self.deserializer;
+        match frame {
+            Frame::Seq { index, run } => {
+                if let Some((remaining @ 1.., element)) = *run {
+                    *run = Some((remaining - 1, element));
+                    *index += 1;
+                    de.data = element;
+                    return self.read_value().map(Some);
+                }
+                if de.peek_token(Production::Seq.last())? {
+                    de.expect_token(Production::Seq.last(), *index)?;
+                    de.close();
+                    self.stack.pop();
+                    return Ok(Some(Event::SeqEnd));
+                }
+                if de.config.run_length_encoding && de.peek_token(Production::Run.first())? {
+                    de.expect_token(Production::Run.first(), *index)?;
+                    let count = de.parse_unsigned::<u32>()?;
+                    if count == 0 {
+                        return Err(Error::DeserializationError(format!(
+                            "empty run at index {index} of {}",
+                            Container::Seq
+                        )));
+                    }
+                    *run = Some((count - 1, de.data));
+                } else {
+                    de.expect_token(Production::Seq.item(0), *index)?;
+                }
+                *index += 1;
+                self.read_value().map(Some)
+            }
+            Frame::Packed { bytes, index } => match bytes.get(*index) {
+                Some(&byte) => {
+                    *index += 1;
+                    Ok(Some(Event::U8(byte)))
+                }
+                None => {
+                    self.stack.pop();
+                    Ok(Some(Event::SeqEnd))
+                }
+            },
+            Frame::Map { index, keys, value } => {
+                de.tick()?;
+                if *value {
+                    de.expect_token(Production::Map.item(1), *index)?;
+                    *value = false;
+                    *index += 1;
+                    return self.read_value().map(Some);
+                }
+                let end = match keys {
+                    Some(keys) => *index == keys.len(),
+                    None => de.peek_token(Production::Map.last())?,
+                };
+                if end {
+                    de.expect_token(Production::Map.last(), *index)?;
+                    de.close();
+                    self.stack.pop();
+                    return Ok(Some(Event::MapEnd));
+                }
+                *value = true;
+                let Some(keys) = keys else {
+                    de.expect_key(*index)?;
+                    return self.read_value().map(Some);
+                };
+                let key = keys.key(*index)?;
+                Ok(Some(match keys.size {
+                    1 => Event::U8(key as u8),
+                    2 => Event::U16(key as u16),
+                    4 => Event::U32(key as u32),
+                    _ => Event::U64(key),
+                }))
+            }
+            Frame::Variant { read: true } => {
+                self.stack.pop();
+                Ok(Some(Event::VariantEnd))
+            }
+            Frame::Variant { read } => {
+                *read = true;
+                self.read_value().map(Some)
+            }
+            Frame::Some => {
+                self.stack.pop();
+                self.read_value().map(Some)
+            }
+        }
+    }
+
+    /// Read the type tag of a value along with the value if it is a primitive, or the start
+    /// of the container.
+    fn read_value(&mut self) -> Result<Event<'de>, Error> {
+        let de = &mut self.deserializer;
+        de.tick()?;
+        let event = match de.eat_tag()? {
+            TypeTag::Unit => Event::Unit,
+            TypeTag::Bool => Event::Bool(de.parse_bool()?),
+            TypeTag::I8 => Event::I8(de.parse_signed::<i8>()?),
+            TypeTag::I16 => Event::I16(de.parse_signed::<i16>()?),
+            TypeTag::I32 => Event::I32(de.parse_signed::<i32>()?),
+            TypeTag::I64 => Event::I64(de.parse_i64()?),
+            TypeTag::I128 => Event::I128(de.parse_i128()?),
+            TypeTag::U8 => Event::U8(de.parse_unsigned::<u8>()?),
+            TypeTag::U16 => Event::U16(de.parse_unsigned::<u16>()?),
+            TypeTag::U32 => Event::U32(de.parse_unsigned::<u32>()?),
+            TypeTag::U64 => Event::U64(de.parse_u64()?),
+            TypeTag::U128 => Event::U128(de.parse_u128()?),
+            TypeTag::F32 => Event::F32(de.parse_f32()?),
+            TypeTag::F64 => Event::F64(de.parse_f64()?),
+            TypeTag::Char => Event::Char(de.parse_char()?),
+            TypeTag::Str => Event::Str(de.parse_str()?),
+            TypeTag::Bytes => Event::Bytes(de.parse_bytes()?),
+            TypeTag::Raw => Event::Raw(de.parse_raw()?),
+            TypeTag::None => Event::None,
+            TypeTag::Some => {
+                self.stack.push(Frame::Some);
+                Event::Some
+            }
+            TypeTag::Seq => {
+                de.open(Container::Seq);
+                if de.config.pack_u8_seqs && de.peek_token(Production::Packed.first())? {
+                    de.expect_token(Production::Packed.first(), 0)?;
+                    let length = de.parse_unsigned::<u32>()? as usize;
+                    de.eat_padding()?;
+                    de.length_bits(length, 1)?;
+                    let bytes = de.eat_bytes(length)?;
+                    // the bytes are handed out without reading any more tokens.
+                    de.close();
+                    self.stack.push(Frame::Packed { bytes, index: 0 });
+                } else {
+                    de.expect_token(Production::Seq.first(), 0)?;
+                    self.stack.push(Frame::Seq {
+                        index: 0,
+                        run: None,
+                    });
+                }
+                Event::SeqStart
+            }
+            TypeTag::Map => {
+                de.open(Container::Map);
+                if de.config.offset_tables {
+                    // the entries are read one after the other.
+                    de.parse_offset_table()?;
+                }
+                let keys = match de.config.sorted_int_keys {
+                    true => de.parse_sorted_keys()?,
+                    false => None,
+                };
+                self.stack.push(Frame::Map {
+                    index: 0,
+                    keys,
+                    value: false,
+                });
+                Event::MapStart
+            }
+            TypeTag::UnitVariant => {
+                let (index, name) = de.parse_variant()?;
+                Event::UnitVariant {
+                    index,
+                    name: name.unwrap_or_default(),
+                }
+            }
+            TypeTag::NewtypeVariant | TypeTag::TupleVariant | TypeTag::StructVariant => {
+                let (index, name) = de.parse_variant()?;
+                self.stack.push(Frame::Variant { read: false });
+                Event::VariantStart {
+                    index,
+                    name: name.unwrap_or_default(),
+                }
+            }
+        };
+        Ok(event)
+    }
+}
+
+impl<'de> Iterator for TokenReader<'de> {
+    type Item = Result<Event<'de>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let event = self.next_event().transpose();
+        self.finished = !matches!(event, Some(Ok(_)));
+        event
+    }
+}
+
+// A container (or value) that is being written.
+enum Open {
+    Seq,
+    // Whether the value of the entry is next.
+    Map { value: bool },
+    // The index and name of a variant until its content is written.
+    Variant { pending: Option<(u32, String)> },
+    // The content of a `Some`.
+    Some,
+}
+
+// Where the next value goes.
+enum Slot {
+    Element,
+    Key,
+    Value,
+}
+
+/// Writes self-describing data from a stream of [`Event`]s; see the [module](self) docs. Every
+/// event is checked against the containers that are open, so a value that [`finish`]es is
+/// balanced and can be read back.
+///
+/// [`finish`]: TokenWriter::finish
+pub struct TokenWriter {
+    serializer: CustomSerializer<'static>,
+    stack: Vec<Open>,
+    started: bool,
+}
+
+impl TokenWriter {
+    /// Write the header of the data and get ready for the events of the value. The config
+    /// must be self-describing.
+    pub fn new(config: &Config) -> Result<Self, Error> {
+        if !config.self_describing {
+            return Err(Error::SelfDescribingRequired("TokenWriter::new"));
+        }
+        if config.msb_first {
+            return Err(Error::UnsupportedCall(
+                "TokenWriter::new with `Config::msb_first`".to_string(),
+            ));
+        }
+        let mut serializer = CustomSerializer::new(config.clone(), None);
+        serializer.serialize_header()?;
+        Ok(Self {
+            serializer,
+            stack: Vec::new(),
+            started: false,
+        })
+    }
+
+    /// Write the next event. An event that doesn't fit where it is pushed (e.g. a
+    /// [`Event::MapEnd`] in a sequence, or a second value after the first one is complete)
+    /// is an error.
+    pub fn push(&mut self, event: Event<'_>) -> Result<(), Error> {
+        match event {
+            Event::SeqEnd => {
+                self.close(|open| matches!(open, Open::Seq), "a sequence")?;
+                return self.serializer.end_seq();
+            }
+            Event::MapEnd => {
+                self.close(|open| matches!(open, Open::Map { value: false }), "a map")?;
+                return self.serializer.end_map();
+            }
+            Event::VariantEnd => {
+                self.close(
+                    |open| matches!(open, Open::Variant { pending: None }),
+                    "a variant with its content",
+                )?;
+                return Ok(());
+            }
+            _ => {}
+        }
+        let slot = self.begin_value(&event)?;
+        let ser = &mut self.serializer;
+        if let Some(written) = write_scalar(ser, &slot, &event) {
+            return written;
+        }
+        match slot {
+            Slot::Element => ser.begin_seq_element()?,
+            Slot::Key => ser.begin_map_key()?,
+            Slot::Value => {}
+        }
+        match event {
+            Event::Some => {
+                ser.serialize_tag(TypeTag::Some);
+                self.stack.push(Open::Some);
+            }
+            Event::SeqStart => {
+                ser.serialize_seq(None)?;
+                self.stack.push(Open::Seq);
+            }
+            Event::MapStart => {
+                ser.serialize_map(None)?;
+                self.stack.push(Open::Map { value: false });
+            }
+            Event::UnitVariant { index, name } => {
+                ser.serialize_tag(TypeTag::UnitVariant);
+                ser.serialize_variant_index(index);
+                ser.serialize_variant_name(&name)?;
+            }
+            Event::VariantStart { index, name } => self.stack.push(Open::Variant {
+                pending: Some((index, name.into_owned())),
+            }),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The bytes of the data once the value is complete.
+    pub fn finish(self) -> Result<Vec<u8>, Error> {
+        if !self.started || !self.stack.is_empty() {
+            return Err(Error::SerializationError(
+                "the value isn't complete".to_string(),
+            ));
+        }
+        Ok(self.serializer.data.into_vec())
+    }
+
+    /// Close the innermost container if it is the expected one.
+    fn close(&mut self, expected: fn(&Open) -> bool, name: &str) -> Result<(), Error> {
+        match self.stack.last() {
+            Some(open) if expected(open) => {
+                self.stack.pop();
+                Ok(())
+            }
+            _ => Err(Error::SerializationError(format!(
+                "there is no open {name} to end"
+            ))),
+        }
+    }
+
+    /// Write what goes in front of a value where it is pushed and tell where it goes.
+    fn begin_value(&mut self, event: &Event<'_>) -> Result<Slot, Error> {
+        let ser = &mut self.serializer;
+        match self.stack.last_mut() {
+            None if self.started => Err(Error::SerializationError(
+                "the value is already complete".to_string(),
+            )),
+            None => {
+                self.started = true;
+                Ok(Slot::Value)
+            }
+            Some(Open::Seq) => Ok(Slot::Element),
+            Some(Open::Map { value }) => {
+                *value = !*value;
+                match *value {
+                    true => Ok(Slot::Key),
+                    false => {
+                        ser.end_key()?;
+                        ser.serialize_token(Production::Map.item(1));
+                        Ok(Slot::Value)
+                    }
+                }
+            }
+            Some(Open::Variant { pending }) => {
+                let Some((index, name)) = pending.take() else {
+                    return Err(Error::SerializationError(
+                        "a variant has a single value as its content".to_string(),
+                    ));
+                };
+                ser.serialize_tag(match event {
+                    Event::SeqStart => TypeTag::TupleVariant,
+                    Event::MapStart => TypeTag::StructVariant,
+                    _ => TypeTag::NewtypeVariant,
+                });
+                ser.serialize_variant_index(index);
+                ser.serialize_variant_name(&name)?;
+                Ok(Slot::Value)
+            }
+            Some(Open::Some) => {
+                self.stack.pop();
+                Ok(Slot::Value)
+            }
+        }
+    }
+}
+
+/// Write the event if it is a whole value on its own (a primitive, a string, bytes, a unit or
+/// `None`) the same way serde would; `None` if it isn't.
+fn write_scalar(
+    ser: &mut CustomSerializer<'_>,
+    slot: &Slot,
+    event: &Event<'_>,
+) -> Option<Result<(), Error>> {
+    macro_rules! write_to_slot {
+        ($value:expr) => {
+            Some(match slot {
+                Slot::Element => ser.serialize_seq_element($value),
+                Slot::Key => SerializeMap::serialize_key(&mut &mut *ser, $value),
+                Slot::Value => $value.serialize(&mut *ser),
+            })
+        };
+    }
+    match event {
+        Event::Unit => write_to_slot!(&()),
+        Event::Bool(v) => write_to_slot!(v),
+        Event::I8(v) => write_to_slot!(v),
+        Event::I16(v) => write_to_slot!(v),
+        Event::I32(v) => write_to_slot!(v),
+        Event::I64(v) => write_to_slot!(v),
+        Event::I128(v) => write_to_slot!(v),
+        Event::U8(v) => write_to_slot!(v),
+        Event::U16(v) => write_to_slot!(v),
+        Event::U32(v) => write_to_slot!(v),
+        Event::U64(v) => write_to_slot!(v),
+        Event::U128(v) => write_to_slot!(v),
+        Event::F32(v) => write_to_slot!(v),
+        Event::F64(v) => write_to_slot!(v),
+        Event::Char(v) => write_to_slot!(v),
+        Event::Str(v) => write_to_slot!(v.as_ref()),
+        Event::Bytes(v) => write_to_slot!(&Bytes(v)),
+        Event::Raw(v) => write_to_slot!(&RawRef(v)),
+        Event::None => write_to_slot!(&None::<()>),
+        _ => None,
+    }
+}
+
+// Bytes that serialize as bytes instead of a sequence of u8.
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
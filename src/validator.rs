@@ -0,0 +1,138 @@
+//! ### Validator
+//! A registry of constraints on the fields of structs, keyed by the name of the struct (or of
+//! the variant of a struct variant) and of the field. The constraints are checked while the
+//! value is serialized (see
+//! [`to_bytes_with_validator`](crate::serializer::to_bytes_with_validator)), so an invalid
+//! value fails with [`Error::Invalid`] without a separate pass over it.
+//!
+//! A constraint sees every primitive inside its field as a [`Primitive`]: the value of an
+//! `Option` that is `Some`, every element of a sequence and so on. Fields of structs nested in
+//! the field have their own constraints.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{config::Config, error::Error, serializer, validator::Validator};
+//!
+//! #[derive(serde::Serialize)]
+//! struct Signup {
+//!     name: String,
+//!     age: u8,
+//!     nickname: Option<String>,
+//! }
+//!
+//! let mut validator = Validator::new();
+//! validator
+//!     .max_len("Signup", "name", 16)
+//!     .max_len("Signup", "nickname", 8)
+//!     .range("Signup", "age", 13..=120);
+//!
+//! let signup = Signup {
+//!     name: "Ayush".to_string(),
+//!     age: 19,
+//!     nickname: Some("a very long nickname".to_string()),
+//! };
+//! let config = Config::default();
+//! let error = serializer::to_bytes_with_validator(&signup, &config, &validator).unwrap_err();
+//! assert!(matches!(error, Error::Invalid { field: "nickname", .. }));
+//! ```
+
+use std::{collections::HashMap, fmt, ops::RangeInclusive, sync::Arc};
+
+use super::{encoders::Primitive, error::Error};
+
+type Check = dyn Fn(&Primitive) -> Result<(), String> + Send + Sync;
+
+/// The constraints on the fields of structs by the names of the structs and the fields.
+#[derive(Clone, Default)]
+pub struct Validator {
+    checks: HashMap<(&'static str, &'static str), Vec<Arc<Check>>>,
+}
+
+impl Validator {
+    /// Create a validator without constraints.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check every primitive inside the field `field` of the structs named `name` with
+    /// `check`, which returns the reason a primitive is invalid.
+    pub fn check<F>(&mut self, name: &'static str, field: &'static str, check: F) -> &mut Self
+    where
+        F: Fn(&Primitive) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.checks
+            .entry((name, field))
+            .or_default()
+            .push(Arc::new(check));
+        self
+    }
+
+    /// Limit the strings (in characters) and bytes inside the field to `max`.
+    pub fn max_len(&mut self, name: &'static str, field: &'static str, max: usize) -> &mut Self {
+        self.check(name, field, move |value| {
+            let len = match value {
+                Primitive::Str(value) => value.chars().count(),
+                Primitive::Bytes(value) => value.len(),
+                _ => return Ok(()),
+            };
+            match len <= max {
+                true => Ok(()),
+                false => Err(format!("its length {len} is more than {max}")),
+            }
+        })
+    }
+
+    /// Limit the numbers inside the field to `range`. Floats are compared with the bounds
+    /// converted to `f64`.
+    pub fn range(
+        &mut self,
+        name: &'static str,
+        field: &'static str,
+        range: RangeInclusive<i128>,
+    ) -> &mut Self {
+        self.check(name, field, move |value| {
+            let within = match *value {
+                Primitive::I64(value) => range.contains(&value.into()),
+                Primitive::U64(value) => range.contains(&value.into()),
+                Primitive::I128(value) => range.contains(&value),
+                Primitive::U128(value) => i128::try_from(value).is_ok_and(|v| range.contains(&v)),
+                Primitive::F64(value) => {
+                    (*range.start() as f64..=*range.end() as f64).contains(&value)
+                }
+                _ => return Ok(()),
+            };
+            match within {
+                true => Ok(()),
+                false => Err(format!("{value:?} is out of the range {range:?}")),
+            }
+        })
+    }
+
+    /// Whether the field `field` of the structs named `name` has constraints.
+    pub fn contains(&self, name: &'static str, field: &'static str) -> bool {
+        self.checks.contains_key(&(name, field))
+    }
+
+    /// Check a primitive inside the field `field` of the structs named `name`.
+    pub(crate) fn validate(
+        &self,
+        name: &'static str,
+        field: &'static str,
+        value: &Primitive,
+    ) -> Result<(), Error> {
+        for check in self.checks.get(&(name, field)).into_iter().flatten() {
+            check(value).map_err(|reason| Error::Invalid {
+                name,
+                field,
+                reason,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.checks.keys()).finish()
+    }
+}
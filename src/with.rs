@@ -0,0 +1,940 @@
+//! ### With
+//! A module with adapters for `#[serde(with = "...")]`: compact encodings of standard types
+//! whose own serde representations are structs with named fields, and encodings that are text
+//! in human-readable formats (e.g. JSON) but binary in rust-fr, which isn't human readable (see
+//! `Serializer::is_human_readable`). Fields annotated with the latter keep their JSON as it was,
+//! e.g. when migrating from JSON and `serde_with`, while rust-fr gets the raw bytes.
+//!
+//! - [`duration`]: a `Duration` as a tuple of the seconds (u64) and the nanoseconds (u32).
+//!   With [`Config::adaptive_ints`](crate::config::Config::adaptive_ints) the seconds are a
+//!   varint.
+//! - [`system_time`]: a `SystemTime` as the nanoseconds since the unix epoch (i64).
+//! - [`hex`]: bytes (e.g. a `Vec<u8>` or a `[u8; 32]` hash) as a hex string in text and as
+//!   bytes in rust-fr.
+//! - [`base64`]: bytes as a base64 string (standard alphabet with padding) in text and as bytes
+//!   in rust-fr.
+//! - [`rfc3339`]: a `SystemTime` as an RFC 3339 timestamp (UTC) in text and like
+//!   [`system_time`] in rust-fr.
+//! - [`bitset`]: a `Vec<bool>` as a sequence of bools in text and as the number of bits (u64)
+//!   and the bits packed into bytes in rust-fr.
+//! - [`utf16`]: UTF-16 code units (e.g. a `Vec<u16>` from a Windows API) as a string.
+//! - [`latin1`]: Latin-1 (ISO 8859-1) bytes as a string.
+//! - [`os_str`]: an `OsString` or a `PathBuf` as a string on every platform; serializing fails
+//!   for ones that aren't valid Unicode instead of replacing their invalid parts.
+//! - [`os_bytes`]: an `OsString` or a `PathBuf` as the bytes of the platform (WTF-8 on
+//!   Windows), so every value round-trips on the platform it was written on, e.g. the paths
+//!   of an archive.
+//! - `bigint` and `biguint` (with the `bigint` feature): the big integers of `num-bigint` as a
+//!   decimal string in text and as their sign and the bytes of their magnitude in rust-fr.
+//! - `decimal` (with the `decimal` feature): a `Decimal` of `rust_decimal` as a string in text
+//!   and as its mantissa and scale in rust-fr, exactly.
+//!
+//! ### Example
+//! ```rust
+//! use rust_fr::{config::Config, deserializer, serializer};
+//! use std::time::{Duration, SystemTime};
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! struct Frame {
+//!     #[serde(with = "rust_fr::with::system_time")]
+//!     at: SystemTime,
+//!     #[serde(with = "rust_fr::with::duration")]
+//!     took: Duration,
+//! }
+//!
+//! let config = Config {
+//!     adaptive_ints: true,
+//!     ..Config::default()
+//! };
+//! let frame = Frame {
+//!     at: SystemTime::UNIX_EPOCH + Duration::from_nanos(1_700_000_000_123_456_789),
+//!     took: Duration::from_micros(1500),
+//! };
+//! let bytes = serializer::to_bytes_with_config(&frame, &config).unwrap();
+//! let decoded = deserializer::from_bytes_with_config::<Frame>(&bytes, &config).unwrap();
+//! assert_eq!(frame, decoded);
+//! ```
+//!
+//! The adapters for text formats:
+//! ```rust
+//! use rust_fr::{deserializer, serializer};
+//! use std::time::{Duration, SystemTime};
+//!
+//! #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+//! struct Commit {
+//!     #[serde(with = "rust_fr::with::hex")]
+//!     hash: [u8; 4],
+//!     #[serde(with = "rust_fr::with::base64")]
+//!     signature: Vec<u8>,
+//!     #[serde(with = "rust_fr::with::rfc3339")]
+//!     at: SystemTime,
+//! }
+//!
+//! let commit = Commit {
+//!     hash: [0xde, 0xad, 0xbe, 0xef],
+//!     signature: b"signed".to_vec(),
+//!     at: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_500),
+//! };
+//! let json = serde_json::to_string(&commit).unwrap();
+//! assert_eq!(
+//!     json,
+//!     r#"{"hash":"deadbeef","signature":"c2lnbmVk","at":"2023-11-14T22:13:20.5Z"}"#
+//! );
+//!
+//! let bytes = serializer::to_bytes(&commit).unwrap();
+//! assert!(bytes.len() < json.len());
+//! assert_eq!(deserializer::from_bytes::<Commit>(&bytes).unwrap(), commit);
+//! assert_eq!(serde_json::from_str::<Commit>(&json).unwrap(), commit);
+//! ```
+
+/// `Duration` as (seconds (u64), nanoseconds (u32)).
+pub mod duration {
+    use std::time::Duration;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+    /// Serialize a `Duration` as a tuple of its seconds and nanoseconds.
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        (duration.as_secs(), duration.subsec_nanos()).serialize(serializer)
+    }
+
+    /// Deserialize a `Duration` from a tuple of its seconds and nanoseconds.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let (secs, nanos) = <(u64, u32)>::deserialize(deserializer)?;
+        if nanos >= NANOS_PER_SEC {
+            return Err(D::Error::custom(format!(
+                "{nanos} nanoseconds are more than a second"
+            )));
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+/// `SystemTime` as the nanoseconds since the unix epoch (i64); negative before it. This
+/// covers the years 1677 to 2262.
+pub mod system_time {
+    use std::time::{Duration, SystemTime};
+
+    use serde::{
+        de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    /// Serialize a `SystemTime` as the nanoseconds since the unix epoch.
+    pub fn serialize<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        let nanos = match time.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(after) => i64::try_from(after.as_nanos()).ok(),
+            Err(before) => i64::try_from(before.duration().as_nanos())
+                .ok()
+                .map(|nanos| -nanos),
+        };
+        nanos
+            .ok_or_else(|| S::Error::custom("the time is too far from the unix epoch"))?
+            .serialize(serializer)
+    }
+
+    /// Deserialize a `SystemTime` from the nanoseconds since the unix epoch.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        let nanos = i64::deserialize(deserializer)?;
+        let since = Duration::from_nanos(nanos.unsigned_abs());
+        let time = match nanos < 0 {
+            true => SystemTime::UNIX_EPOCH.checked_sub(since),
+            false => SystemTime::UNIX_EPOCH.checked_add(since),
+        };
+        time.ok_or_else(|| D::Error::custom("the time can't be represented on this platform"))
+    }
+}
+
+/// Bytes as a lowercase hex string in human-readable formats and as bytes otherwise. Works
+/// with anything that is `AsRef<[u8]>` and `TryFrom<Vec<u8>>`, e.g. `Vec<u8>` and `[u8; N]`.
+/// Both upper and lowercase digits are read.
+pub mod hex {
+    use serde::{de::Error, Deserializer, Serializer};
+
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    /// Serialize bytes as a hex string or as bytes.
+    pub fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<[u8]>,
+        S: Serializer,
+    {
+        let bytes = bytes.as_ref();
+        if !serializer.is_human_readable() {
+            return serializer.serialize_bytes(bytes);
+        }
+        let hex: String = bytes
+            .iter()
+            .flat_map(|byte| [byte >> 4, byte & 0xf])
+            .map(|digit| DIGITS[digit as usize] as char)
+            .collect();
+        serializer.serialize_str(&hex)
+    }
+
+    /// Deserialize bytes from a hex string or from bytes.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: TryFrom<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        super::bytes::deserialize(deserializer, |hex: &str| {
+            let digit = |digit: u8| (digit as char).to_digit(16);
+            match hex.len() % 2 {
+                0 => hex
+                    .as_bytes()
+                    .chunks(2)
+                    .map(|pair| Some((digit(pair[0])? << 4 | digit(pair[1])?) as u8))
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or_else(|| D::Error::custom("invalid hex digit")),
+                _ => Err(D::Error::custom("a hex string has an odd length")),
+            }
+        })
+    }
+}
+
+/// Bytes as a base64 string (the standard alphabet with padding) in human-readable formats and
+/// as bytes otherwise. Works with anything that is `AsRef<[u8]>` and `TryFrom<Vec<u8>>`, e.g.
+/// `Vec<u8>` and `[u8; N]`.
+pub mod base64 {
+    use serde::{de::Error, Deserializer, Serializer};
+
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    /// Serialize bytes as a base64 string or as bytes.
+    pub fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<[u8]>,
+        S: Serializer,
+    {
+        let bytes = bytes.as_ref();
+        if !serializer.is_human_readable() {
+            return serializer.serialize_bytes(bytes);
+        }
+        let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+        for chunk in bytes.chunks(3) {
+            let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+                group | u32::from(*byte) << (16 - 8 * i)
+            });
+            for i in 0..4 {
+                match i <= chunk.len() {
+                    true => text.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char),
+                    false => text.push('='),
+                }
+            }
+        }
+        serializer.serialize_str(&text)
+    }
+
+    /// Deserialize bytes from a base64 string or from bytes.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: TryFrom<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        super::bytes::deserialize(deserializer, |text: &str| {
+            let invalid = || D::Error::custom("invalid base64");
+            if !text.len().is_multiple_of(4) {
+                return Err(invalid());
+            }
+            let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+            let groups = text.as_bytes().chunks(4);
+            let last = groups.len().saturating_sub(1);
+            for (index, chunk) in groups.enumerate() {
+                let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+                if padding > 2 || (padding > 0 && index != last) {
+                    return Err(invalid());
+                }
+                let mut group = 0u32;
+                for (i, c) in chunk[..4 - padding].iter().enumerate() {
+                    let value = ALPHABET.iter().position(|a| a == c).ok_or_else(invalid)?;
+                    group |= (value as u32) << (18 - 6 * i);
+                }
+                bytes.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+            }
+            Ok(bytes)
+        })
+    }
+}
+
+/// `SystemTime` as an RFC 3339 timestamp in UTC (e.g. `2023-11-14T22:13:20.5Z`) in
+/// human-readable formats and as the nanoseconds since the unix epoch (see [`system_time`])
+/// otherwise. Timestamps with an offset (e.g. `+02:00`) are read too.
+pub mod rfc3339 {
+    use std::time::{Duration, SystemTime};
+
+    use serde::{de::Error, ser::Error as _, Deserialize, Deserializer, Serializer};
+
+    const SECS_PER_DAY: i64 = 86_400;
+
+    /// Serialize a `SystemTime` as an RFC 3339 timestamp or as nanoseconds.
+    pub fn serialize<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            return super::system_time::serialize(time, serializer);
+        }
+        let (secs, nanos) = match time.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(after) => (i64::try_from(after.as_secs()).ok(), after.subsec_nanos()),
+            Err(before) => {
+                let before = before.duration();
+                let secs = i64::try_from(before.as_secs()).ok().map(|secs| -secs);
+                match before.subsec_nanos() {
+                    0 => (secs, 0),
+                    nanos => (secs.map(|secs| secs - 1), 1_000_000_000 - nanos),
+                }
+            }
+        };
+        let secs =
+            secs.ok_or_else(|| S::Error::custom("the time is too far from the unix epoch"))?;
+        let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+        if !(0..=9999).contains(&year) {
+            return Err(S::Error::custom("the year doesn't have 4 digits"));
+        }
+        let time = secs.rem_euclid(SECS_PER_DAY);
+        let mut text = format!(
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}",
+            time / 3600,
+            time / 60 % 60,
+            time % 60
+        );
+        if nanos > 0 {
+            let fraction = format!("{nanos:09}");
+            text.push('.');
+            text.push_str(fraction.trim_end_matches('0'));
+        }
+        text.push('Z');
+        serializer.serialize_str(&text)
+    }
+
+    /// Deserialize a `SystemTime` from an RFC 3339 timestamp or from nanoseconds.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        if !deserializer.is_human_readable() {
+            return super::system_time::deserialize(deserializer);
+        }
+        let text = String::deserialize(deserializer)?;
+        parse(&text).ok_or_else(|| D::Error::custom(format!("invalid RFC 3339 timestamp {text}")))
+    }
+
+    // Parse `YYYY-MM-DDTHH:MM:SS[.fraction](Z|+HH:MM|-HH:MM)`.
+    fn parse(text: &str) -> Option<SystemTime> {
+        let number = |range: std::ops::Range<usize>| -> Option<i64> {
+            let digits = text.get(range)?;
+            digits
+                .bytes()
+                .all(|b| b.is_ascii_digit())
+                .then(|| digits.parse().ok())?
+        };
+        let separators = text.as_bytes();
+        if separators.get(4) != Some(&b'-')
+            || separators.get(7) != Some(&b'-')
+            || !matches!(separators.get(10), Some(b'T' | b't' | b' '))
+            || separators.get(13) != Some(&b':')
+            || separators.get(16) != Some(&b':')
+        {
+            return None;
+        }
+        let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+        let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+        if !(1..=12).contains(&month)
+            || !(1..=days_in_month(year, month)).contains(&day)
+            || hour > 23
+            || minute > 59
+            || second > 60
+        {
+            return None;
+        }
+        let mut rest = &text[19..];
+        let mut nanos = 0u32;
+        if let Some(fraction) = rest.strip_prefix('.') {
+            let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+            if digits == 0 {
+                return None;
+            }
+            for (i, digit) in fraction.bytes().take(digits.min(9)).enumerate() {
+                nanos += u32::from(digit - b'0') * 10u32.pow(8 - i as u32);
+            }
+            rest = &fraction[digits..];
+        }
+        let offset = match rest.as_bytes() {
+            [b'Z' | b'z'] => 0,
+            [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+                let hours: i64 = rest.get(1..3)?.parse().ok()?;
+                let minutes: i64 = rest.get(4..6)?.parse().ok()?;
+                if hours > 23 || minutes > 59 {
+                    return None;
+                }
+                let offset = hours * 3600 + minutes * 60;
+                match sign {
+                    b'+' => offset,
+                    _ => -offset,
+                }
+            }
+            _ => return None,
+        };
+        let secs =
+            days_from_civil(year, month, day) * SECS_PER_DAY + hour * 3600 + minute * 60 + second
+                - offset;
+        let since = Duration::new(secs.unsigned_abs(), 0);
+        let time = match secs < 0 {
+            true => SystemTime::UNIX_EPOCH.checked_sub(since)?,
+            false => SystemTime::UNIX_EPOCH.checked_add(since)?,
+        };
+        time.checked_add(Duration::from_nanos(nanos.into()))
+    }
+
+    fn days_in_month(year: i64, month: i64) -> i64 {
+        match month {
+            2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    // The number of days since 1970-01-01 of a date of the proleptic Gregorian calendar.
+    fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    // The date of the proleptic Gregorian calendar a number of days since 1970-01-01 falls on.
+    fn civil_from_days(days: i64) -> (i64, i64, i64) {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        (year, month, day)
+    }
+}
+
+/// `Vec<bool>` as a sequence of bools in human-readable formats and otherwise as a tuple of
+/// the number of bits (u64) and the bits packed into bytes, least significant bit first, e.g.
+/// for flags or presence masks. The bits after the last one must be zero.
+pub mod bitset {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialize bits as a sequence of bools or packed into bytes.
+    pub fn serialize<S: Serializer>(bits: &[bool], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return bits.serialize(serializer);
+        }
+        let mut bytes = vec![0u8; bits.len().div_ceil(8)];
+        for (i, _) in bits.iter().enumerate().filter(|(_, &bit)| bit) {
+            bytes[i / 8] |= 1 << (i % 8);
+        }
+        (bits.len() as u64, Packed(&bytes)).serialize(serializer)
+    }
+
+    /// Deserialize bits from a sequence of bools or from bytes.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<bool>, D::Error> {
+        if deserializer.is_human_readable() {
+            return Vec::deserialize(deserializer);
+        }
+        let (len, Unpacked(bytes)) = <(u64, Unpacked)>::deserialize(deserializer)?;
+        let len = usize::try_from(len)
+            .ok()
+            .filter(|len| len.div_ceil(8) == bytes.len())
+            .ok_or_else(|| D::Error::custom("the number of bits doesn't match the bytes"))?;
+        if len % 8 != 0 && bytes.last().is_some_and(|last| last >> (len % 8) != 0) {
+            return Err(D::Error::custom("the bits after the last one aren't zero"));
+        }
+        Ok((0..len)
+            .map(|i| bytes[i / 8] & (1 << (i % 8)) != 0)
+            .collect())
+    }
+
+    struct Packed<'a>(&'a [u8]);
+
+    impl Serialize for Packed<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    struct Unpacked(Vec<u8>);
+
+    impl<'de> Deserialize<'de> for Unpacked {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            super::bytes::deserialize(deserializer, |_: &str| {
+                Err(D::Error::custom("expected bytes"))
+            })
+            .map(Unpacked)
+        }
+    }
+}
+
+/// UTF-16 code units as a string, so text from UTF-16 sources (e.g. the wide strings of Windows
+/// APIs or Java and JavaScript strings) is written as the same string as its UTF-8. Works with
+/// anything that is `AsRef<[u16]>` and `From<Vec<u16>>`, e.g. `Vec<u16>`. Serializing fails for
+/// an unpaired surrogate, which has no string.
+pub mod utf16 {
+    use serde::{ser::Error as _, Deserialize, Deserializer, Serializer};
+
+    /// Serialize UTF-16 code units as a string.
+    pub fn serialize<T, S>(units: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<[u16]>,
+        S: Serializer,
+    {
+        let text = String::from_utf16(units.as_ref())
+            .map_err(|_| S::Error::custom("the UTF-16 has an unpaired surrogate"))?;
+        serializer.serialize_str(&text)
+    }
+
+    /// Deserialize UTF-16 code units from a string.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: From<Vec<u16>>,
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        Ok(T::from(text.encode_utf16().collect::<Vec<u16>>()))
+    }
+}
+
+/// Latin-1 (ISO 8859-1) bytes as a string, every byte being the char of the same number. Works
+/// with anything that is `AsRef<[u8]>` and `TryFrom<Vec<u8>>`, e.g. `Vec<u8>` and `[u8; N]`.
+/// Deserializing fails for a string with chars above U+00FF.
+pub mod latin1 {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    /// Serialize Latin-1 bytes as a string.
+    pub fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<[u8]>,
+        S: Serializer,
+    {
+        let text: String = bytes
+            .as_ref()
+            .iter()
+            .map(|&byte| char::from(byte))
+            .collect();
+        serializer.serialize_str(&text)
+    }
+
+    /// Deserialize Latin-1 bytes from a string.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: TryFrom<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        let bytes = text
+            .chars()
+            .map(|c| u8::try_from(c).map_err(|_| D::Error::custom(format!("{c:?} isn't Latin-1"))))
+            .collect::<Result<Vec<u8>, _>>()?;
+        let len = bytes.len();
+        T::try_from(bytes).map_err(|_| D::Error::custom(format!("unexpected length {len}")))
+    }
+}
+
+/// An `OsString` or a `PathBuf` (or anything that is `AsRef<OsStr>` and `From<OsString>`) as a
+/// string. The own serde representation of `OsString` is the platform's (the bytes on Unix and
+/// the UTF-16 on Windows) and can't be read on the other platforms; a string can. Serializing
+/// fails for a value that isn't valid Unicode (e.g. a non-UTF-8 path on Unix) rather than
+/// writing another one; see [`os_bytes`] for those.
+pub mod os_str {
+    use std::ffi::{OsStr, OsString};
+
+    use serde::{ser::Error as _, Deserialize, Deserializer, Serializer};
+
+    /// Serialize an `OsStr` as a string.
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<OsStr> + ?Sized,
+        S: Serializer,
+    {
+        let value = value.as_ref();
+        let text = value
+            .to_str()
+            .ok_or_else(|| S::Error::custom(format!("{value:?} isn't valid Unicode")))?;
+        serializer.serialize_str(text)
+    }
+
+    /// Deserialize an `OsString` from a string.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: From<OsString>,
+        D: Deserializer<'de>,
+    {
+        Ok(T::from(OsString::from(String::deserialize(deserializer)?)))
+    }
+}
+
+/// An `OsString` or a `PathBuf` (or anything that is `AsRef<OsStr>` and `From<OsString>`) as
+/// bytes in every format: the bytes of the value on Unix and its WTF-8 on Windows (the UTF-8
+/// of its UTF-16, where unpaired surrogates are encoded like other code points), so every
+/// value round-trips on the platform it was written on, valid Unicode or not. Values that are
+/// valid Unicode have the bytes of their UTF-8 on both and can be read on either; deserializing
+/// fails for bytes that aren't WTF-8 on Windows and that aren't UTF-8 on other platforms.
+/// Raw bytes may contain the byte of the delimiter after bytes; set
+/// [`Config::string_framing`](crate::config::Config::string_framing) so it is escaped (or not
+/// needed).
+pub mod os_bytes {
+    use std::ffi::{OsStr, OsString};
+
+    use serde::{Deserializer, Serializer};
+
+    /// Serialize an `OsStr` as the bytes of the platform.
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<OsStr> + ?Sized,
+        S: Serializer,
+    {
+        let value = value.as_ref();
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+
+            serializer.serialize_bytes(value.as_bytes())
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::ffi::OsStrExt;
+
+            let units = value.encode_wide().collect::<Vec<u16>>();
+            serializer.serialize_bytes(&encode_wtf8(&units))
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            use serde::ser::Error as _;
+
+            let text = value
+                .to_str()
+                .ok_or_else(|| S::Error::custom(format!("{value:?} isn't valid Unicode")))?;
+            serializer.serialize_bytes(text.as_bytes())
+        }
+    }
+
+    /// Deserialize an `OsString` from the bytes of the platform.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: From<OsString>,
+        D: Deserializer<'de>,
+    {
+        let bytes = super::bytes::byte_buf(deserializer)?;
+        #[cfg(unix)]
+        let value = {
+            use std::os::unix::ffi::OsStringExt;
+
+            OsString::from_vec(bytes)
+        };
+        #[cfg(windows)]
+        let value = {
+            use serde::de::Error as _;
+            use std::os::windows::ffi::OsStringExt;
+
+            let units = decode_wtf8(&bytes).ok_or_else(|| D::Error::custom("invalid WTF-8"))?;
+            OsString::from_wide(&units)
+        };
+        #[cfg(not(any(unix, windows)))]
+        let value = {
+            use serde::de::Error as _;
+
+            OsString::from(String::from_utf8(bytes).map_err(|_| D::Error::custom("invalid UTF-8"))?)
+        };
+        Ok(T::from(value))
+    }
+
+    /// The WTF-8 of UTF-16 code units (e.g. the wide string of a Windows API) on any platform:
+    /// their UTF-8, with every unpaired surrogate encoded as if it were a char.
+    pub fn encode_wtf8(units: &[u16]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(units.len());
+        for unit in char::decode_utf16(units.iter().copied()) {
+            match unit {
+                Ok(c) => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                Err(error) => {
+                    let surrogate = error.unpaired_surrogate();
+                    bytes.extend([
+                        0xe0 | (surrogate >> 12) as u8,
+                        0x80 | (surrogate >> 6 & 0x3f) as u8,
+                        0x80 | (surrogate & 0x3f) as u8,
+                    ]);
+                }
+            }
+        }
+        bytes
+    }
+
+    /// The UTF-16 code units of WTF-8 bytes, or `None` if they aren't WTF-8 (e.g. a surrogate
+    /// pair encoded as two surrogates, which WTF-8 encodes as one char).
+    pub fn decode_wtf8(bytes: &[u8]) -> Option<Vec<u16>> {
+        let mut units = Vec::with_capacity(bytes.len());
+        let mut rest = bytes;
+        // whether the last unit is a leading surrogate on its own.
+        let mut leading = false;
+        while let Some((&first, tail)) = rest.split_first() {
+            let (width, min, initial) = match first {
+                0x00..=0x7f => (0, 0, u32::from(first)),
+                0xc2..=0xdf => (1, 0x80, u32::from(first & 0x1f)),
+                0xe0..=0xef => (2, 0x800, u32::from(first & 0x0f)),
+                0xf0..=0xf4 => (3, 0x1_0000, u32::from(first & 0x07)),
+                _ => return None,
+            };
+            let continuation = tail.get(..width)?;
+            let code = continuation.iter().try_fold(initial, |code, &byte| {
+                (byte & 0xc0 == 0x80).then_some(code << 6 | u32::from(byte & 0x3f))
+            })?;
+            if code < min || code > 0x10_ffff {
+                return None;
+            }
+            rest = &tail[width..];
+            let trailing = (0xdc00..=0xdfff).contains(&code);
+            if leading && trailing {
+                return None;
+            }
+            leading = (0xd800..=0xdbff).contains(&code);
+            match char::from_u32(code) {
+                Some(c) => units.extend_from_slice(c.encode_utf16(&mut [0; 2])),
+                None => units.push(code as u16),
+            }
+        }
+        Some(units)
+    }
+}
+
+/// `BigInt` (of `num-bigint`) as a decimal string in human-readable formats and otherwise as a
+/// tuple of its sign (i8: -1, 0 or 1) and the bytes of its magnitude, least significant first
+/// and without trailing zeros, so it takes as many bytes as it needs. Only available with the
+/// `bigint` feature.
+#[cfg(feature = "bigint")]
+pub mod bigint {
+    use num_bigint::{BigInt, Sign};
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::sign_magnitude::SignMagnitude;
+
+    /// Serialize a `BigInt` as a string or as its sign and magnitude.
+    pub fn serialize<S: Serializer>(value: &BigInt, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.collect_str(value);
+        }
+        let (sign, magnitude) = value.to_bytes_le();
+        let sign = match sign {
+            Sign::Minus => -1,
+            Sign::NoSign => 0,
+            Sign::Plus => 1,
+        };
+        SignMagnitude::new(sign, magnitude).serialize(serializer)
+    }
+
+    /// Deserialize a `BigInt` from a string or from its sign and magnitude.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigInt, D::Error> {
+        if deserializer.is_human_readable() {
+            let text = String::deserialize(deserializer)?;
+            return text.parse().map_err(D::Error::custom);
+        }
+        let SignMagnitude { sign, magnitude } = SignMagnitude::deserialize(deserializer)?;
+        let sign = match sign {
+            -1 => Sign::Minus,
+            0 => Sign::NoSign,
+            _ => Sign::Plus,
+        };
+        Ok(BigInt::from_bytes_le(sign, &magnitude))
+    }
+}
+
+/// `BigUint` (of `num-bigint`) like a [`bigint`]: as a decimal string in human-readable formats
+/// and otherwise as its sign (0 or 1) and the bytes of its magnitude, so the two read each
+/// other's non-negative values. Only available with the `bigint` feature.
+#[cfg(feature = "bigint")]
+pub mod biguint {
+    use num_bigint::BigUint;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::sign_magnitude::SignMagnitude;
+
+    /// Serialize a `BigUint` as a string or as its sign and magnitude.
+    pub fn serialize<S: Serializer>(value: &BigUint, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.collect_str(value);
+        }
+        let magnitude = value.to_bytes_le();
+        SignMagnitude::new(i8::from(value.bits() != 0), magnitude).serialize(serializer)
+    }
+
+    /// Deserialize a `BigUint` from a string or from its sign and magnitude.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigUint, D::Error> {
+        if deserializer.is_human_readable() {
+            let text = String::deserialize(deserializer)?;
+            return text.parse().map_err(D::Error::custom);
+        }
+        let SignMagnitude { sign, magnitude } = SignMagnitude::deserialize(deserializer)?;
+        if sign < 0 {
+            return Err(D::Error::custom("a BigUint can't be negative"));
+        }
+        Ok(BigUint::from_bytes_le(&magnitude))
+    }
+}
+
+/// `Decimal` (of `rust_decimal`) as a string in human-readable formats and otherwise as a tuple
+/// of its mantissa, as the sign and magnitude of a `bigint`, and its scale (u8), the number
+/// of digits after the point. The value is exact and keeps its scale, e.g. `1.50` stays `1.50`.
+/// Only available with the `decimal` feature.
+#[cfg(feature = "decimal")]
+pub mod decimal {
+    use rust_decimal::Decimal;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::sign_magnitude::SignMagnitude;
+
+    /// Serialize a `Decimal` as a string or as its mantissa and scale.
+    pub fn serialize<S: Serializer>(value: &Decimal, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.collect_str(value);
+        }
+        let mantissa = value.mantissa();
+        let magnitude = mantissa.unsigned_abs().to_le_bytes().to_vec();
+        let mantissa = SignMagnitude::new(mantissa.signum() as i8, magnitude);
+        // the scale of a decimal is at most 28.
+        (mantissa, value.scale() as u8).serialize(serializer)
+    }
+
+    /// Deserialize a `Decimal` from a string or from its mantissa and scale.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decimal, D::Error> {
+        if deserializer.is_human_readable() {
+            let text = String::deserialize(deserializer)?;
+            return text.parse().map_err(D::Error::custom);
+        }
+        let (SignMagnitude { sign, magnitude }, scale) =
+            <(SignMagnitude, u8)>::deserialize(deserializer)?;
+        let mut bytes = [0u8; 16];
+        bytes
+            .get_mut(..magnitude.len())
+            .ok_or_else(|| D::Error::custom("the mantissa of a decimal is too large"))?
+            .copy_from_slice(&magnitude);
+        let mantissa = i128::try_from(u128::from_le_bytes(bytes))
+            .map_err(|_| D::Error::custom("the mantissa of a decimal is too large"))?;
+        Decimal::try_from_i128_with_scale(mantissa * i128::from(sign), u32::from(scale))
+            .map_err(D::Error::custom)
+    }
+}
+
+// A sign (-1, 0 or 1) and the bytes of a magnitude, least significant first: a tuple of the
+// sign (i8) and the bytes. Every number has one encoding: the magnitude has no trailing zeros
+// and is empty exactly when the sign is 0.
+#[cfg(any(feature = "bigint", feature = "decimal"))]
+mod sign_magnitude {
+    use serde::{de::Error, ser::SerializeTuple, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(super) struct SignMagnitude {
+        pub(super) sign: i8,
+        pub(super) magnitude: Vec<u8>,
+    }
+
+    impl SignMagnitude {
+        // The sign and the magnitude with its trailing zeros removed.
+        pub(super) fn new(sign: i8, mut magnitude: Vec<u8>) -> Self {
+            while magnitude.last() == Some(&0) {
+                magnitude.pop();
+            }
+            Self { sign, magnitude }
+        }
+    }
+
+    impl Serialize for SignMagnitude {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            struct Magnitude<'a>(&'a [u8]);
+
+            impl Serialize for Magnitude<'_> {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_bytes(self.0)
+                }
+            }
+
+            let mut tuple = serializer.serialize_tuple(2)?;
+            tuple.serialize_element(&self.sign)?;
+            tuple.serialize_element(&Magnitude(&self.magnitude))?;
+            tuple.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SignMagnitude {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct Magnitude(Vec<u8>);
+
+            impl<'de> Deserialize<'de> for Magnitude {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    super::bytes::byte_buf(deserializer).map(Magnitude)
+                }
+            }
+
+            let (sign, Magnitude(magnitude)) = <(i8, Magnitude)>::deserialize(deserializer)?;
+            if !(-1..=1).contains(&sign) {
+                return Err(D::Error::custom(format!("{sign} isn't a sign")));
+            }
+            if magnitude.last() == Some(&0) {
+                return Err(D::Error::custom("the magnitude has trailing zeros"));
+            }
+            if (sign == 0) != magnitude.is_empty() {
+                return Err(D::Error::custom("the sign doesn't match the magnitude"));
+            }
+            Ok(Self { sign, magnitude })
+        }
+    }
+}
+
+// Deserializing bytes that are text in human-readable formats.
+pub(crate) mod bytes {
+    use std::fmt;
+
+    use serde::de::{Deserializer, Error, SeqAccess, Visitor};
+
+    /// Deserialize bytes from a string (decoded with `decode`) in human-readable formats and
+    /// from bytes (or a sequence of u8) otherwise, then convert them to `T`.
+    pub(super) fn deserialize<'de, T, D, F>(deserializer: D, decode: F) -> Result<T, D::Error>
+    where
+        T: TryFrom<Vec<u8>>,
+        D: Deserializer<'de>,
+        F: FnOnce(&str) -> Result<Vec<u8>, D::Error>,
+    {
+        let bytes = match deserializer.is_human_readable() {
+            true => decode(&<String as serde::Deserialize>::deserialize(deserializer)?)?,
+            false => byte_buf(deserializer)?,
+        };
+        let len = bytes.len();
+        T::try_from(bytes).map_err(|_| D::Error::custom(format!("unexpected length {len}")))
+    }
+
+    /// Deserialize bytes (or a sequence of u8).
+    pub(crate) fn byte_buf<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        deserializer.deserialize_byte_buf(BytesVisitor)
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("bytes")
+        }
+
+        fn visit_bytes<E: Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(bytes.to_vec())
+        }
+
+        fn visit_byte_buf<E: Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(bytes)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+}
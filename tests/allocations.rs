@@ -0,0 +1,289 @@
+//! The number of allocations of representative encodings and decodings, counted by a global
+//! allocator. The library forbids `unsafe` code, which an allocator needs, so the tests live
+//! in their own crate.
+//!
+//! The bounds don't grow with the number of elements: a change that allocates for every
+//! string, byte buffer or element (e.g. in `eat_bytes`, `parse_str` or the visitors) fails
+//! them.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    collections::HashMap,
+};
+
+use rust_fr::{
+    config::{Config, StringFraming},
+    deserializer, serializer,
+};
+use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
+
+// Counts the allocations (and reallocations) of the current thread, so tests running in
+// parallel don't count each other's.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count() {
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// The number of allocations `f` makes; its result is dropped before counting ends.
+fn allocations<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    drop(f());
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+// Check that `f` allocates at most `bound` times.
+fn assert_allocations<T>(what: &str, bound: usize, f: impl FnOnce() -> T) {
+    let count = allocations(f);
+    assert!(
+        count <= bound,
+        "{what} allocated {count} times, at most {bound} expected"
+    );
+}
+
+// The allocations of a call that doesn't allocate for every element: the output, the stack of
+// open containers, and the growth of both.
+const FEW: usize = 32;
+
+const ELEMENTS: usize = 1_000;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Record<'a> {
+    id: u64,
+    active: bool,
+    score: f64,
+    name: &'a str,
+    tags: [u16; 4],
+}
+
+fn records(names: &[String]) -> Vec<Record<'_>> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| Record {
+            id: i as u64,
+            active: i % 2 == 0,
+            score: i as f64 / 3.0,
+            name,
+            tags: [i as u16; 4],
+        })
+        .collect()
+}
+
+// Bytes borrowed from the data (a `&[u8]` is a sequence of u8 in serde).
+#[derive(Debug, PartialEq)]
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Bytes<'de> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Borrowed;
+
+        impl<'de> Visitor<'de> for Borrowed {
+            type Value = Bytes<'de>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "borrowed bytes")
+            }
+            fn visit_borrowed_bytes<E>(self, bytes: &'de [u8]) -> Result<Bytes<'de>, E> {
+                Ok(Bytes(bytes))
+            }
+        }
+
+        deserializer.deserialize_bytes(Borrowed)
+    }
+}
+
+fn names() -> Vec<String> {
+    (0..ELEMENTS).map(|i| format!("name-{i}")).collect()
+}
+
+fn configs() -> [Config; 2] {
+    [
+        Config::default(),
+        Config {
+            self_describing: true,
+            ..Config::default()
+        },
+    ]
+}
+
+#[test]
+fn encoding() {
+    let names = names();
+    let records = records(&names);
+    for config in configs() {
+        assert_allocations("encoding records", FEW, || {
+            serializer::to_bytes_with_config(&records, &config).unwrap()
+        });
+        // a buffer that is large enough already is reused as it is.
+        let mut buffer = serializer::to_bytes_with_config(&records, &config).unwrap();
+        assert_allocations("encoding records into a buffer", 2, || {
+            serializer::to_bytes_into_with_config(&records, &mut buffer, &config).unwrap()
+        });
+    }
+}
+
+#[test]
+fn strings() {
+    let names = names();
+    let strs = names.iter().map(String::as_str).collect::<Vec<_>>();
+    for config in configs() {
+        let bytes = serializer::to_bytes_with_config(&strs, &config).unwrap();
+        assert_allocations("decoding borrowed strings", FEW, || {
+            deserializer::from_bytes_with_config::<Vec<&str>>(&bytes, &config).unwrap()
+        });
+        // one allocation for every owned string and none on the way to it.
+        assert_allocations("decoding owned strings", ELEMENTS + FEW, || {
+            deserializer::from_bytes_with_config::<Vec<String>>(&bytes, &config).unwrap()
+        });
+    }
+}
+
+#[test]
+fn records_decoding() {
+    let names = names();
+    let records = records(&names);
+    for config in configs() {
+        let bytes = serializer::to_bytes_with_config(&records, &config).unwrap();
+        assert_allocations("decoding records", FEW, || {
+            deserializer::from_bytes_with_config::<Vec<Record>>(&bytes, &config).unwrap()
+        });
+    }
+}
+
+#[test]
+fn maps() {
+    let names = names();
+    let map = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.as_str(), i as u32))
+        .collect::<HashMap<_, _>>();
+    for config in configs() {
+        let bytes = serializer::to_bytes_with_config(&map, &config).unwrap();
+        assert_allocations("decoding a map", FEW, || {
+            deserializer::from_bytes_with_config::<HashMap<&str, u32>>(&bytes, &config).unwrap()
+        });
+    }
+}
+
+#[test]
+fn bytes() {
+    // the packed bytes are taken in one piece (see `eat_bytes`).
+    let packed = Config {
+        pack_u8_seqs: true,
+        ..Config::default()
+    };
+    let data = (0..ELEMENTS * 10).map(|i| i as u8).collect::<Vec<_>>();
+    let bytes = serializer::to_bytes_with_config(&data, &packed).unwrap();
+    assert_allocations("decoding packed bytes", 4, || {
+        deserializer::from_bytes_with_config::<Vec<u8>>(&bytes, &packed).unwrap()
+    });
+
+    // escaped bytes without escapes in them are borrowed.
+    let escaped = Config {
+        string_framing: StringFraming::Escaped,
+        ..Config::default()
+    };
+    let names = names();
+    let blobs = names
+        .iter()
+        .map(|name| Bytes(name.as_bytes()))
+        .collect::<Vec<_>>();
+    let bytes = serializer::to_bytes_with_config(&blobs, &escaped).unwrap();
+    assert_allocations("decoding escaped bytes", FEW, || {
+        deserializer::from_bytes_with_config::<Vec<Bytes>>(&bytes, &escaped).unwrap()
+    });
+}
+
+#[cfg(feature = "allocator-api2")]
+#[test]
+fn encoding_in_an_allocator() {
+    use allocator_api2::alloc::{AllocError, Allocator};
+    use rust_fr::error::Error;
+    use std::ptr::NonNull;
+
+    // Hands out at most `limit` bytes at once from the system allocator, which isn't counted,
+    // like the arena of a plugin host.
+    struct Arena {
+        used: Cell<usize>,
+        limit: usize,
+    }
+
+    unsafe impl Allocator for Arena {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            let used = self.used.get() + layout.size();
+            if used > self.limit {
+                return Err(AllocError);
+            }
+            let ptr = NonNull::new(unsafe { System.alloc(layout) }).ok_or(AllocError)?;
+            self.used.set(used);
+            Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.used.set(self.used.get() - layout.size());
+            System.dealloc(ptr.as_ptr(), layout)
+        }
+    }
+
+    let names = names();
+    let records = records(&names);
+    for config in configs() {
+        let expected = serializer::to_bytes_with_config(&records, &config).unwrap();
+        let arena = Arena {
+            used: Cell::new(0),
+            limit: usize::MAX,
+        };
+        // the output is in the arena; the serializer only holds a few KiB of its own.
+        assert_allocations("encoding records in an arena", FEW, || {
+            let bytes = serializer::to_bytes_in(&records, &config, &arena).unwrap();
+            assert_eq!(bytes[..], expected[..]);
+            assert!(arena.used.get() >= expected.len());
+        });
+
+        let arena = Arena {
+            used: Cell::new(0),
+            limit: expected.len() / 2,
+        };
+        assert!(matches!(
+            serializer::to_bytes_in(&records, &config, &arena),
+            Err(Error::SerializationError(_))
+        ));
+    }
+}